@@ -0,0 +1,147 @@
+//! Account names and their hierarchy.
+//!
+//! An [`Account`] is a colon-separated name such as `Assets:Bank:Checking`.
+//! Each component names one level of the hierarchy, starting from a root
+//! account type (`Assets`, `Liabilities`, `Equity`, `Income` or `Expenses`).
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::InternedStr;
+
+/// Separator between the components of an account name.
+const SEPARATOR: char = ':';
+
+/// A hierarchical account name.
+///
+/// # Examples
+///
+/// ```
+/// use rustledger_core::Account;
+///
+/// let checking = Account::new("Assets:Bank:Checking");
+/// assert_eq!(
+///     checking.components().collect::<Vec<_>>(),
+///     ["Assets", "Bank", "Checking"]
+/// );
+///
+/// let bank = checking.parent().unwrap();
+/// assert_eq!(bank.as_str(), "Assets:Bank");
+/// assert!(checking.is_child_of(&bank));
+/// assert!(checking.is_child_of(&Account::new("Assets")));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Account(InternedStr);
+
+impl Account {
+    /// Create an account from its full name.
+    #[must_use]
+    pub fn new(name: impl Into<InternedStr>) -> Self {
+        Self(name.into())
+    }
+
+    /// Get the full account name.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Iterate over the components of the name, from the root down.
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.as_str().split(SEPARATOR)
+    }
+
+    /// Get the account one level up, or `None` for a root account.
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        self.as_str()
+            .rsplit_once(SEPARATOR)
+            .map(|(parent, _)| Self::new(parent))
+    }
+
+    /// Check whether this account is nested anywhere below `ancestor`.
+    ///
+    /// An account is not a child of itself, and `Assets:BankFees` is not a
+    /// child of `Assets:Bank`.
+    #[must_use]
+    pub fn is_child_of(&self, ancestor: &Self) -> bool {
+        self.as_str()
+            .strip_prefix(ancestor.as_str())
+            .is_some_and(|rest| rest.starts_with(SEPARATOR))
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AsRef<str> for Account {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Account {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Account {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<InternedStr> for Account {
+    fn from(name: InternedStr) -> Self {
+        Self(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_components() {
+        let account = Account::new("Assets:Bank:Checking");
+        assert_eq!(
+            account.components().collect::<Vec<_>>(),
+            vec!["Assets", "Bank", "Checking"]
+        );
+
+        let root = Account::new("Assets");
+        assert_eq!(root.components().collect::<Vec<_>>(), vec!["Assets"]);
+    }
+
+    #[test]
+    fn test_parent() {
+        let account = Account::new("Assets:Bank:Checking");
+        let parent = account.parent().unwrap();
+        assert_eq!(parent, Account::new("Assets:Bank"));
+        assert_eq!(parent.parent(), Some(Account::new("Assets")));
+
+        // Root accounts have no parent
+        assert_eq!(Account::new("Assets").parent(), None);
+    }
+
+    #[test]
+    fn test_is_child_of() {
+        let checking = Account::new("Assets:Bank:Checking");
+        let bank = Account::new("Assets:Bank");
+        let assets = Account::new("Assets");
+
+        assert!(checking.is_child_of(&bank));
+        assert!(checking.is_child_of(&assets));
+        assert!(bank.is_child_of(&assets));
+
+        assert!(!bank.is_child_of(&checking));
+        assert!(!assets.is_child_of(&assets));
+        assert!(!Account::new("Assets:BankFees").is_child_of(&bank));
+        assert!(!Account::new("Expenses:Bank").is_child_of(&assets));
+    }
+}
@@ -153,6 +153,9 @@ impl std::error::Error for BookingError {}
 /// It tracks all positions for an account and supports booking operations
 /// for adding and reducing positions.
 ///
+/// Units are accumulated exactly as [`Decimal`]s, never converted to
+/// floating point; rounding is left to display.
+///
 /// # Examples
 ///
 /// ```
@@ -300,6 +303,13 @@ impl Inventory {
         self.positions.push(position);
     }
 
+    /// Add an amount held without a cost basis.
+    ///
+    /// Shorthand for adding a [`Position::simple`].
+    pub fn add_amount(&mut self, amount: &Amount) {
+        self.add(Position::simple(amount.clone()));
+    }
+
     /// Reduce positions from the inventory using the specified booking method.
     ///
     /// # Arguments
@@ -927,6 +937,32 @@ mod tests {
         assert_eq!(inv.units("USD"), dec!(150));
     }
 
+    #[test]
+    fn test_add_accumulates_exact_decimals() {
+        let mut inv = Inventory::new();
+        for _ in 0..10 {
+            inv.add(Position::simple(Amount::new(dec!(0.1), "USD")));
+        }
+
+        // 0.1 has no exact binary representation; ten f64 additions give
+        // 0.9999999999999999
+        assert_eq!(inv.units("USD"), dec!(1.0));
+        assert_eq!(inv.units("USD").to_string(), "1.0");
+    }
+
+    #[test]
+    fn test_add_amount_different_currencies() {
+        let mut inv = Inventory::new();
+        inv.add_amount(&Amount::new(dec!(100), "USD"));
+        inv.add_amount(&Amount::new(dec!(50), "EUR"));
+        inv.add_amount(&Amount::new(dec!(25), "USD"));
+
+        assert_eq!(inv.len(), 2);
+        assert_eq!(inv.units("USD"), dec!(125));
+        assert_eq!(inv.units("EUR"), dec!(50));
+        assert_eq!(inv.currencies(), vec!["EUR", "USD"]);
+    }
+
     #[test]
     fn test_add_with_cost_no_merge() {
         let mut inv = Inventory::new();
@@ -971,6 +1007,23 @@ mod tests {
         assert_eq!(result.cost_basis.unwrap().number, dec!(750.00)); // 5 * 150
     }
 
+    #[test]
+    fn test_reduce_to_zero_removes_position() {
+        let mut inv = Inventory::new();
+        let cost = Cost::new(dec!(150.00), "USD").with_date(date(2024, 1, 1));
+        inv.add(Position::with_cost(Amount::new(dec!(10), "AAPL"), cost));
+        inv.add(Position::simple(Amount::new(dec!(100), "USD")));
+
+        inv.reduce(&Amount::new(dec!(-10), "AAPL"), None, BookingMethod::Strict)
+            .unwrap();
+        inv.reduce(&Amount::new(dec!(-100), "USD"), None, BookingMethod::Strict)
+            .unwrap();
+
+        assert_eq!(inv.len(), 0);
+        assert!(inv.is_empty());
+        assert_eq!(format!("{inv}"), "(empty)");
+    }
+
     #[test]
     fn test_reduce_strict_ambiguous() {
         let mut inv = Inventory::new();
@@ -2,6 +2,7 @@
 //!
 //! This crate provides the fundamental types used throughout the rustledger project:
 //!
+//! - [`Account`] - A hierarchical account name
 //! - [`Amount`] - A decimal number with a currency
 //! - [`Cost`] - Acquisition cost of a position (lot)
 //! - [`CostSpec`] - Specification for matching or creating costs
@@ -42,6 +43,7 @@
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
+pub mod account;
 pub mod amount;
 pub mod cost;
 pub mod directive;
@@ -50,6 +52,7 @@ pub mod intern;
 pub mod inventory;
 pub mod position;
 
+pub use account::Account;
 pub use amount::{Amount, IncompleteAmount};
 pub use cost::{Cost, CostSpec};
 pub use directive::{
@@ -30,6 +30,8 @@ pub struct CsvConfig {
     pub date_column: ColumnSpec,
     /// The date format (strftime-style).
     pub date_format: String,
+    /// Additional date formats tried in order when `date_format` does not match.
+    pub alternate_date_formats: Vec<String>,
     /// The column name or index for the narration/description.
     pub narration_column: Option<ColumnSpec>,
     /// The column name or index for the payee.
@@ -48,6 +50,9 @@ pub struct CsvConfig {
     pub skip_rows: usize,
     /// Whether to invert the sign of amounts.
     pub invert_sign: bool,
+    /// The contra account for every row (defaults to `Expenses:Unknown` or
+    /// `Income:Unknown` depending on the sign of the amount).
+    pub expense_account: Option<String>,
 }
 
 impl Default for CsvConfig {
@@ -55,6 +60,7 @@ impl Default for CsvConfig {
         Self {
             date_column: ColumnSpec::Name("Date".to_string()),
             date_format: "%Y-%m-%d".to_string(),
+            alternate_date_formats: Vec::new(),
             narration_column: Some(ColumnSpec::Name("Description".to_string())),
             payee_column: None,
             amount_column: Some(ColumnSpec::Name("Amount".to_string())),
@@ -64,6 +70,7 @@ impl Default for CsvConfig {
             delimiter: ',',
             skip_rows: 0,
             invert_sign: false,
+            expense_account: None,
         }
     }
 }
@@ -151,6 +158,12 @@ impl CsvConfigBuilder {
         self
     }
 
+    /// Add a fallback date format, tried after the primary format.
+    pub fn alternate_date_format(mut self, format: impl Into<String>) -> Self {
+        self.config.alternate_date_formats.push(format.into());
+        self
+    }
+
     /// Set the narration/description column by name.
     pub fn narration_column(mut self, name: impl Into<String>) -> Self {
         self.config.narration_column = Some(ColumnSpec::Name(name.into()));
@@ -223,6 +236,12 @@ impl CsvConfigBuilder {
         self
     }
 
+    /// Set the default contra account for imported transactions.
+    pub fn expense_account(mut self, account: impl Into<String>) -> Self {
+        self.config.expense_account = Some(account.into());
+        self
+    }
+
     /// Build the importer configuration.
     pub fn build(self) -> ImporterConfig {
         ImporterConfig {
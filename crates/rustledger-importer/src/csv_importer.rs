@@ -38,10 +38,19 @@ impl CsvImporter {
 
     /// Extract transactions from string content.
     pub fn extract_string(&self, content: &str, csv_config: &CsvConfig) -> Result<ImportResult> {
+        self.extract_reader(content.as_bytes(), csv_config)
+    }
+
+    /// Extract transactions from any reader producing CSV data.
+    pub fn extract_reader<R: Read>(
+        &self,
+        source: R,
+        csv_config: &CsvConfig,
+    ) -> Result<ImportResult> {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(csv_config.has_header)
             .delimiter(csv_config.delimiter as u8)
-            .from_reader(content.as_bytes());
+            .from_reader(source);
 
         // Build column name to index map from headers
         let header_map: HashMap<String, usize> = if csv_config.has_header {
@@ -101,7 +110,9 @@ impl CsvImporter {
             return Ok(None); // Skip empty rows
         }
 
-        let date = NaiveDate::parse_from_str(date_str.trim(), &csv_config.date_format)
+        let date = std::iter::once(&csv_config.date_format)
+            .chain(&csv_config.alternate_date_formats)
+            .find_map(|format| NaiveDate::parse_from_str(date_str.trim(), format).ok())
             .with_context(|| {
                 format!(
                     "Row {}: failed to parse date '{}' with format '{}'",
@@ -150,10 +161,10 @@ impl CsvImporter {
         let posting = Posting::new(&self.config.account, amount);
 
         // Create balancing posting (auto-interpolated)
-        let contra_account = if final_amount < Decimal::ZERO {
-            "Income:Unknown"
-        } else {
-            "Expenses:Unknown"
+        let contra_account = match &csv_config.expense_account {
+            Some(account) => account.as_str(),
+            None if final_amount < Decimal::ZERO => "Income:Unknown",
+            None => "Expenses:Unknown",
         };
         let contra_posting = Posting::auto(contra_account);
 
@@ -749,6 +760,7 @@ not-a-date,Coffee,-5.00
         let csv_config = CsvConfig {
             date_column: ColumnSpec::Name("Date".to_string()),
             date_format: "%Y-%m-%d".to_string(),
+            alternate_date_formats: Vec::new(),
             narration_column: Some(ColumnSpec::Name("Description".to_string())),
             payee_column: None,
             amount_column: None,
@@ -758,6 +770,7 @@ not-a-date,Coffee,-5.00
             delimiter: ',',
             skip_rows: 0,
             invert_sign: false,
+            expense_account: None,
         };
 
         let importer = CsvImporter::new(ImporterConfig {
@@ -869,4 +882,48 @@ not-a-date,Coffee,-5.00
             assert_eq!(amount.number, Decimal::from(100));
         }
     }
+
+    #[test]
+    fn test_csv_import_alternate_date_format() {
+        let config = ImporterConfig::csv()
+            .account("Assets:Bank")
+            .currency("USD")
+            .date_format("%Y-%m-%d")
+            .alternate_date_format("%m/%d/%Y")
+            .build();
+
+        let csv_content = r"Date,Description,Amount
+2024-01-15,Coffee,-5.00
+01/16/2024,Lunch,-12.00
+";
+
+        let result = config.extract_from_string(csv_content).unwrap();
+        assert_eq!(result.directives.len(), 2);
+        assert_eq!(result.warnings.len(), 0);
+        assert_eq!(
+            result.directives[1].date(),
+            NaiveDate::from_ymd_opt(2024, 1, 16).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_csv_import_expense_account() {
+        let config = ImporterConfig::csv()
+            .account("Assets:Bank")
+            .currency("USD")
+            .expense_account("Expenses:Uncategorized")
+            .build();
+
+        let csv_content = r"Date,Description,Amount
+2024-01-15,Coffee,-5.00
+2024-01-16,Refund,5.00
+";
+
+        let result = config.extract_from_string(csv_content).unwrap();
+        for directive in &result.directives {
+            if let Directive::Transaction(txn) = directive {
+                assert_eq!(txn.postings[1].account.as_str(), "Expenses:Uncategorized");
+            }
+        }
+    }
 }
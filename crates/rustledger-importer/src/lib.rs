@@ -37,6 +37,7 @@ pub mod registry;
 
 use anyhow::Result;
 use rustledger_core::Directive;
+use std::io::Read;
 use std::path::Path;
 
 pub use config::ImporterConfig;
@@ -109,6 +110,21 @@ pub fn extract_from_string(content: &str, config: &ImporterConfig) -> Result<Imp
     config.extract_from_string(content)
 }
 
+/// Import CSV data from a reader into transaction directives.
+///
+/// The `mapping` describes which columns hold the date, amount, payee, and
+/// narration, along with the asset account and the contra account. Rows that
+/// cannot be parsed are skipped; use [`ImporterConfig::extract_from_string`]
+/// to also receive the per-row warnings.
+pub fn import_csv<R: Read>(reader: R, mapping: &ImporterConfig) -> Result<Vec<Directive>> {
+    match &mapping.importer_type {
+        config::ImporterType::Csv(csv_config) => {
+            let importer = csv_importer::CsvImporter::new(mapping.clone());
+            Ok(importer.extract_reader(reader, csv_config)?.directives)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -214,4 +230,53 @@ mod tests {
         assert_eq!(result.warnings.len(), 1);
         assert_eq!(cloned.warnings.len(), 1);
     }
+
+    // ========== import_csv Tests ==========
+
+    #[test]
+    fn test_import_csv_three_rows() {
+        let config = ImporterConfig::csv()
+            .account("Assets:Bank:Checking")
+            .currency("USD")
+            .date_column("Date")
+            .date_format("%d/%m/%Y")
+            .payee_column("Payee")
+            .narration_column("Memo")
+            .amount_column("Amount")
+            .expense_account("Expenses:Uncategorized")
+            .delimiter(';')
+            .build();
+
+        let csv_content = "Date;Payee;Memo;Amount\n\
+            15/01/2024;\"Joe's \"\"Diner\"\"\";Lunch;-12.50\n\
+            16/01/2024;Employer;Salary;2500.00\n\
+            17/01/2024;Grocer;Food;(40.25)\n";
+
+        let directives = import_csv(csv_content.as_bytes(), &config).unwrap();
+        assert_eq!(directives.len(), 3);
+
+        let expected = [
+            Decimal::from_str("-12.50").unwrap(),
+            Decimal::from_str("2500.00").unwrap(),
+            Decimal::from_str("-40.25").unwrap(),
+        ];
+        for (directive, amount) in directives.iter().zip(expected) {
+            let Directive::Transaction(txn) = directive else {
+                panic!("expected a transaction");
+            };
+            // One explicit posting balanced by one interpolated posting
+            assert_eq!(txn.postings.len(), 2);
+            assert_eq!(txn.postings[0].account.as_str(), "Assets:Bank:Checking");
+            assert_eq!(txn.postings[0].amount().unwrap().number, amount);
+            assert_eq!(txn.postings[1].account.as_str(), "Expenses:Uncategorized");
+            assert!(txn.postings[1].units.is_none());
+        }
+
+        // Quotes in the payee survive and are escaped when formatted
+        let formatted = rustledger_core::format_directive(
+            &directives[0],
+            &rustledger_core::FormatConfig::default(),
+        );
+        assert!(formatted.starts_with("2024-01-15 * \"Joe's \\\"Diner\\\"\" \"Lunch\""));
+    }
 }
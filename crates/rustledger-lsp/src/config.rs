@@ -0,0 +1,116 @@
+//! Server configuration.
+//!
+//! Read from the client's `initializationOptions` at startup and replaced
+//! on `workspace/didChangeConfiguration`. Settings may be sent either
+//! directly or nested under an `rledger` key.
+
+use crate::handlers::diagnostics::DiagnosticsConfig;
+use lsp_types::InitializeParams;
+use serde::Deserialize;
+
+/// User-configurable server settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Number of spaces postings are indented by.
+    pub indent_width: usize,
+    /// Column amounts are right-aligned to when formatting.
+    pub alignment_column: usize,
+    /// Whether formatting inserts (`true`) or strips (`false`) comma
+    /// thousands separators in amounts. Unset leaves numbers as written.
+    pub thousands_separators: Option<bool>,
+    /// Operating currency to assume in addition to any declared by the
+    /// ledger's `operating_currency` option.
+    pub operating_currency: Option<String>,
+    /// Account conversion postings are booked to when balancing a
+    /// transaction that mixes currencies. Defaults to `Equity:Conversions`.
+    pub conversions_account: String,
+    /// Directory relative `document` paths are resolved against, itself
+    /// relative to the ledger file. Defaults to the ledger file's directory.
+    pub document_base: Option<String>,
+    /// Which diagnostics are enabled.
+    pub diagnostics: DiagnosticsConfig,
+    /// Quiet period after an edit before diagnostics are recomputed, in
+    /// milliseconds. Defaults to 250.
+    pub diagnostics_debounce_ms: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            indent_width: 2,
+            alignment_column: 50,
+            thousands_separators: None,
+            operating_currency: None,
+            conversions_account: "Equity:Conversions".to_string(),
+            document_base: None,
+            diagnostics: DiagnosticsConfig::default(),
+            diagnostics_debounce_ms: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read configuration from initialize params, ignoring invalid options.
+    pub fn from_params(params: &InitializeParams) -> Self {
+        params
+            .initialization_options
+            .as_ref()
+            .and_then(Self::from_value)
+            .unwrap_or_default()
+    }
+
+    /// Read configuration from a settings object.
+    ///
+    /// Returns `None` (after logging) if the settings are invalid.
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        let value = value.get("rledger").unwrap_or(value);
+        serde_json::from_value(value.clone())
+            .map_err(|e| tracing::warn!("Ignoring invalid configuration: {}", e))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_from_value() {
+        let config = Config::from_value(&json!({
+            "indentWidth": 4,
+            "alignmentColumn": 60,
+            "thousandsSeparators": true,
+            "operatingCurrency": "CAD",
+            "conversionsAccount": "Equity:Trading",
+            "documentBase": "receipts",
+            "diagnostics": { "undefinedAccounts": false, "flaggedEntries": true }
+        }))
+        .unwrap();
+
+        assert_eq!(config.indent_width, 4);
+        assert_eq!(config.alignment_column, 60);
+        assert_eq!(config.thousands_separators, Some(true));
+        assert_eq!(config.operating_currency.as_deref(), Some("CAD"));
+        assert_eq!(config.conversions_account, "Equity:Trading");
+        assert_eq!(config.document_base.as_deref(), Some("receipts"));
+        assert!(!config.diagnostics.undefined_accounts);
+        assert!(config.diagnostics.flagged_entries);
+        // Unset options keep their defaults
+        assert!(config.diagnostics.metadata_types);
+        assert!(!config.diagnostics.duplicate_transactions);
+        assert_eq!(config.diagnostics_debounce_ms, None);
+    }
+
+    #[test]
+    fn test_config_nested_under_section() {
+        let config = Config::from_value(&json!({ "rledger": { "indentWidth": 4 } })).unwrap();
+        assert_eq!(config.indent_width, 4);
+    }
+
+    #[test]
+    fn test_invalid_config_is_ignored() {
+        assert!(Config::from_value(&json!({ "indentWidth": "wide" })).is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! JSON export of parsed ledgers.
+
+use rustledger_core::{
+    Amount, CostSpec, Directive, IncompleteAmount, MetaValue, Metadata, Posting, PriceAnnotation,
+};
+use rustledger_parser::ParseResult;
+use serde_json::{Map, Value, json};
+
+/// Serialize a parsed ledger to JSON.
+///
+/// The result is an object with `options`, `includes`, `plugins`, and
+/// `directives` keys. Each directive is an object with a `type` and `date`
+/// plus the fields of that directive type. Numbers are emitted as strings
+/// to preserve their exact decimal representation, and metadata keys are
+/// sorted so the output is stable.
+pub fn to_json(parse_result: &ParseResult) -> Value {
+    let options: Map<String, Value> = parse_result
+        .options
+        .iter()
+        .map(|(key, value, _)| (key.clone(), Value::String(value.clone())))
+        .collect();
+
+    let includes: Vec<Value> = parse_result
+        .includes
+        .iter()
+        .map(|(path, _)| Value::String(path.clone()))
+        .collect();
+
+    let plugins: Vec<Value> = parse_result
+        .plugins
+        .iter()
+        .map(|(name, config, _)| json!({ "name": name, "config": config }))
+        .collect();
+
+    let directives: Vec<Value> = parse_result
+        .directives
+        .iter()
+        .map(|spanned| directive_to_json(&spanned.value))
+        .collect();
+
+    json!({
+        "options": options,
+        "includes": includes,
+        "plugins": plugins,
+        "directives": directives,
+    })
+}
+
+/// Serialize a single directive to JSON.
+pub fn directive_to_json(directive: &Directive) -> Value {
+    let mut value = match directive {
+        Directive::Transaction(txn) => json!({
+            "type": "transaction",
+            "flag": txn.flag.to_string(),
+            "payee": txn.payee.as_ref().map(ToString::to_string),
+            "narration": txn.narration.to_string(),
+            "tags": txn.tags.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "links": txn.links.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "postings": txn.postings.iter().map(posting_to_json).collect::<Vec<_>>(),
+        }),
+        Directive::Balance(bal) => json!({
+            "type": "balance",
+            "account": bal.account.to_string(),
+            "amount": amount_to_json(&bal.amount),
+            "tolerance": bal.tolerance.map(|t| t.to_string()),
+        }),
+        Directive::Open(open) => json!({
+            "type": "open",
+            "account": open.account.to_string(),
+            "currencies": open.currencies.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "booking": open.booking,
+        }),
+        Directive::Close(close) => json!({
+            "type": "close",
+            "account": close.account.to_string(),
+        }),
+        Directive::Commodity(comm) => json!({
+            "type": "commodity",
+            "currency": comm.currency.to_string(),
+        }),
+        Directive::Pad(pad) => json!({
+            "type": "pad",
+            "account": pad.account.to_string(),
+            "source_account": pad.source_account.to_string(),
+        }),
+        Directive::Event(event) => json!({
+            "type": "event",
+            "event_type": event.event_type,
+            "value": event.value,
+        }),
+        Directive::Query(query) => json!({
+            "type": "query",
+            "name": query.name,
+            "query": query.query,
+        }),
+        Directive::Note(note) => json!({
+            "type": "note",
+            "account": note.account.to_string(),
+            "comment": note.comment,
+        }),
+        Directive::Document(doc) => json!({
+            "type": "document",
+            "account": doc.account.to_string(),
+            "path": doc.path,
+            "tags": doc.tags.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            "links": doc.links.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        }),
+        Directive::Price(price) => json!({
+            "type": "price",
+            "currency": price.currency.to_string(),
+            "amount": amount_to_json(&price.amount),
+        }),
+        Directive::Custom(custom) => json!({
+            "type": "custom",
+            "custom_type": custom.custom_type,
+            "values": custom.values.iter().map(meta_value_to_json).collect::<Vec<_>>(),
+        }),
+    };
+
+    if let Value::Object(fields) = &mut value {
+        fields.insert("date".to_string(), json!(directive.date().to_string()));
+        fields.insert("meta".to_string(), metadata_to_json(directive.meta()));
+    }
+
+    value
+}
+
+/// Serialize a posting to JSON.
+fn posting_to_json(posting: &Posting) -> Value {
+    json!({
+        "account": posting.account.to_string(),
+        "flag": posting.flag.map(|f| f.to_string()),
+        "units": posting.units.as_ref().map(incomplete_amount_to_json),
+        "cost": posting.cost.as_ref().map(cost_spec_to_json),
+        "price": posting.price.as_ref().map(price_to_json),
+        "meta": metadata_to_json(&posting.meta),
+    })
+}
+
+/// Serialize an amount as `{"number": "...", "currency": "..."}`.
+fn amount_to_json(amount: &Amount) -> Value {
+    json!({
+        "number": amount.number.to_string(),
+        "currency": amount.currency.to_string(),
+    })
+}
+
+/// Serialize a possibly incomplete amount; missing parts become `null`.
+fn incomplete_amount_to_json(amount: &IncompleteAmount) -> Value {
+    json!({
+        "number": amount.number().map(|n| n.to_string()),
+        "currency": amount.currency(),
+    })
+}
+
+/// Serialize a cost specification.
+fn cost_spec_to_json(cost: &CostSpec) -> Value {
+    json!({
+        "number_per": cost.number_per.map(|n| n.to_string()),
+        "number_total": cost.number_total.map(|n| n.to_string()),
+        "currency": cost.currency.as_ref().map(ToString::to_string),
+        "date": cost.date.map(|d| d.to_string()),
+        "label": cost.label,
+        "merge": cost.merge,
+    })
+}
+
+/// Serialize a price annotation (`@` is per unit, `@@` is total).
+fn price_to_json(price: &PriceAnnotation) -> Value {
+    let (kind, amount) = match price {
+        PriceAnnotation::Unit(a) => ("unit", Some(amount_to_json(a))),
+        PriceAnnotation::Total(a) => ("total", Some(amount_to_json(a))),
+        PriceAnnotation::UnitIncomplete(a) => ("unit", Some(incomplete_amount_to_json(a))),
+        PriceAnnotation::TotalIncomplete(a) => ("total", Some(incomplete_amount_to_json(a))),
+        PriceAnnotation::UnitEmpty => ("unit", None),
+        PriceAnnotation::TotalEmpty => ("total", None),
+    };
+    json!({ "kind": kind, "amount": amount })
+}
+
+/// Serialize metadata with sorted keys.
+fn metadata_to_json(meta: &Metadata) -> Value {
+    let mut entries: Vec<(&String, &MetaValue)> = meta.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    Value::Object(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.clone(), meta_value_to_json(value)))
+            .collect(),
+    )
+}
+
+/// Serialize a metadata value as `{"type": "...", "value": ...}`.
+fn meta_value_to_json(value: &MetaValue) -> Value {
+    let (kind, value) = match value {
+        MetaValue::String(s) => ("string", json!(s)),
+        MetaValue::Account(s) => ("account", json!(s)),
+        MetaValue::Currency(s) => ("currency", json!(s)),
+        MetaValue::Tag(s) => ("tag", json!(s)),
+        MetaValue::Link(s) => ("link", json!(s)),
+        MetaValue::Date(d) => ("date", json!(d.to_string())),
+        MetaValue::Number(n) => ("number", json!(n.to_string())),
+        MetaValue::Bool(b) => ("bool", json!(b)),
+        MetaValue::Amount(a) => ("amount", amount_to_json(a)),
+        MetaValue::None => ("none", Value::Null),
+    };
+    json!({ "type": kind, "value": value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustledger_parser::parse;
+
+    #[test]
+    fn test_to_json_open_and_transaction() {
+        let source = r#"option "title" "Test"
+2024-01-01 open Assets:Bank USD
+2024-01-15 * "Store" "Groceries" #food ^receipt-1
+  invoice: "INV-1"
+  Assets:Bank  -5.00 USD
+  Expenses:Food  1 APPLE {5.00 USD} @ 5.00 USD
+"#;
+        let result = parse(source);
+        let json = to_json(&result);
+
+        assert_eq!(json["options"]["title"], "Test");
+
+        let directives = json["directives"].as_array().unwrap();
+        assert_eq!(directives.len(), 2);
+
+        let open = &directives[0];
+        assert_eq!(open["type"], "open");
+        assert_eq!(open["date"], "2024-01-01");
+        assert_eq!(open["account"], "Assets:Bank");
+        assert_eq!(open["currencies"][0], "USD");
+
+        let txn = &directives[1];
+        assert_eq!(txn["type"], "transaction");
+        assert_eq!(txn["date"], "2024-01-15");
+        assert_eq!(txn["payee"], "Store");
+        assert_eq!(txn["narration"], "Groceries");
+        assert_eq!(txn["tags"][0], "food");
+        assert_eq!(txn["links"][0], "receipt-1");
+        assert_eq!(txn["meta"]["invoice"]["value"], "INV-1");
+
+        let postings = txn["postings"].as_array().unwrap();
+        assert_eq!(postings[0]["account"], "Assets:Bank");
+        assert_eq!(postings[0]["units"]["number"], "-5.00");
+        assert_eq!(postings[0]["units"]["currency"], "USD");
+        assert_eq!(postings[1]["cost"]["number_per"], "5.00");
+        assert_eq!(postings[1]["cost"]["currency"], "USD");
+        assert_eq!(postings[1]["price"]["kind"], "unit");
+        assert_eq!(postings[1]["price"]["amount"]["number"], "5.00");
+    }
+}
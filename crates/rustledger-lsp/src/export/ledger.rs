@@ -0,0 +1,347 @@
+//! Ledger-CLI export of parsed ledgers.
+//!
+//! Beancount concepts without a Ledger equivalent (open, close, pad, note,
+//! event, query, document, and custom directives, plus options and
+//! includes) are kept as `;` comments so no information is silently lost.
+
+use std::fmt::Write;
+
+use rustledger_core::{
+    Amount, CostSpec, Decimal, Directive, IncompleteAmount, MetaValue, Metadata, NaiveDate,
+    Posting, PriceAnnotation, Transaction,
+};
+use rustledger_parser::ParseResult;
+
+/// Convert a parsed ledger to Ledger-CLI syntax.
+///
+/// Transactions use `YYYY/MM/DD` dates with indented postings, prices become
+/// `P` lines, and balance assertions become a zero-amount posting carrying a
+/// `= amount` assertion. Directives are separated by blank lines.
+///
+/// Beancount checks a balance at the start of its date, while Ledger checks
+/// an assertion against everything before it in the file. Directives are
+/// therefore written in date order with each day's balance assertions
+/// first, dated the day before.
+pub fn to_ledger(parse_result: &ParseResult) -> String {
+    let mut out = String::new();
+
+    for (key, value, _) in &parse_result.options {
+        let _ = writeln!(out, "; option \"{key}\" \"{value}\"");
+    }
+    for (path, _) in &parse_result.includes {
+        let _ = writeln!(out, "; include \"{path}\"");
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    let mut directives: Vec<&Directive> = parse_result
+        .directives
+        .iter()
+        .map(|spanned| &spanned.value)
+        .collect();
+    directives.sort_by_key(|directive| {
+        (
+            directive.date(),
+            !matches!(directive, Directive::Balance(_)),
+        )
+    });
+    let blocks: Vec<String> = directives.into_iter().map(directive_to_ledger).collect();
+    out.push_str(&blocks.join("\n"));
+
+    out
+}
+
+/// Convert a single directive to one or more Ledger lines.
+fn directive_to_ledger(directive: &Directive) -> String {
+    let date = ledger_date(directive.date());
+    let mut out = String::new();
+
+    match directive {
+        Directive::Transaction(txn) => write_transaction(&mut out, txn),
+        Directive::Balance(bal) => {
+            let date = ledger_date(bal.date.pred_opt().unwrap_or(bal.date));
+            let _ = writeln!(out, "{date} * Balance assertion");
+            let _ = writeln!(
+                out,
+                "  {}  0 {} = {}",
+                bal.account,
+                ledger_commodity(&bal.amount.currency),
+                ledger_amount(&bal.amount)
+            );
+        }
+        Directive::Price(price) => {
+            let _ = writeln!(
+                out,
+                "P {date} {} {}",
+                ledger_commodity(&price.currency),
+                ledger_amount(&price.amount)
+            );
+        }
+        Directive::Commodity(comm) => {
+            let _ = writeln!(out, "commodity {}", ledger_commodity(&comm.currency));
+        }
+        Directive::Open(open) => {
+            let mut line = format!("; {date} open {}", open.account);
+            for currency in &open.currencies {
+                line.push(' ');
+                line.push_str(currency);
+            }
+            let _ = writeln!(out, "{line}");
+        }
+        Directive::Close(close) => {
+            let _ = writeln!(out, "; {date} close {}", close.account);
+        }
+        Directive::Pad(pad) => {
+            let _ = writeln!(out, "; {date} pad {} {}", pad.account, pad.source_account);
+        }
+        Directive::Note(note) => {
+            let _ = writeln!(out, "; {date} note {} \"{}\"", note.account, note.comment);
+        }
+        Directive::Event(event) => {
+            let _ = writeln!(
+                out,
+                "; {date} event \"{}\" \"{}\"",
+                event.event_type, event.value
+            );
+        }
+        Directive::Query(query) => {
+            let _ = writeln!(out, "; {date} query \"{}\" \"{}\"", query.name, query.query);
+        }
+        Directive::Document(doc) => {
+            let _ = writeln!(out, "; {date} document {} \"{}\"", doc.account, doc.path);
+        }
+        Directive::Custom(custom) => {
+            let _ = writeln!(out, "; {date} custom \"{}\"", custom.custom_type);
+        }
+    }
+
+    out
+}
+
+/// Write a transaction with its tags, links, metadata, and postings.
+fn write_transaction(out: &mut String, txn: &Transaction) {
+    let mut header = ledger_date(txn.date);
+    // Ledger only knows cleared (`*`) and pending (`!`) states
+    if matches!(txn.flag, '*' | '!') {
+        header.push(' ');
+        header.push(txn.flag);
+    }
+    let description = match &txn.payee {
+        Some(payee) if !txn.narration.is_empty() => format!("{payee} | {}", txn.narration),
+        Some(payee) => payee.to_string(),
+        None => txn.narration.to_string(),
+    };
+    if !description.is_empty() {
+        header.push(' ');
+        header.push_str(&description);
+    }
+    let _ = writeln!(out, "{header}");
+
+    if !txn.tags.is_empty() {
+        let tags: String = txn.tags.iter().map(|t| format!("{t}:")).collect();
+        let _ = writeln!(out, "  ; :{tags}");
+    }
+    for link in &txn.links {
+        let _ = writeln!(out, "  ; Link: {link}");
+    }
+    write_metadata(out, &txn.meta, "  ");
+
+    for posting in &txn.postings {
+        write_posting(out, posting);
+    }
+}
+
+/// Write a single posting line followed by its metadata.
+fn write_posting(out: &mut String, posting: &Posting) {
+    let mut line = String::from("  ");
+    if let Some(flag) = posting.flag {
+        if matches!(flag, '*' | '!') {
+            line.push(flag);
+            line.push(' ');
+        }
+    }
+    line.push_str(&posting.account);
+
+    if let Some(amount) = posting.units.as_ref().and_then(complete_amount) {
+        line.push_str("  ");
+        line.push_str(&ledger_amount(&amount));
+        if let Some(cost) = posting.cost.as_ref().and_then(ledger_cost) {
+            line.push(' ');
+            line.push_str(&cost);
+        }
+        if let Some(price) = posting.price.as_ref().and_then(ledger_price) {
+            line.push(' ');
+            line.push_str(&price);
+        }
+    }
+
+    let _ = writeln!(out, "{line}");
+    write_metadata(out, &posting.meta, "    ");
+}
+
+/// Write metadata as Ledger `; key: value` comment tags, sorted by key.
+fn write_metadata(out: &mut String, meta: &Metadata, indent: &str) {
+    let mut entries: Vec<(&String, &MetaValue)> = meta.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (key, value) in entries {
+        let _ = writeln!(out, "{indent}; {key}: {}", meta_value_text(value));
+    }
+}
+
+/// Render a metadata value without Beancount quoting.
+fn meta_value_text(value: &MetaValue) -> String {
+    match value {
+        MetaValue::String(s)
+        | MetaValue::Account(s)
+        | MetaValue::Currency(s)
+        | MetaValue::Tag(s)
+        | MetaValue::Link(s) => s.clone(),
+        MetaValue::Date(d) => ledger_date(*d),
+        MetaValue::Number(n) => n.to_string(),
+        MetaValue::Bool(b) => b.to_string(),
+        MetaValue::Amount(a) => ledger_amount(a),
+        MetaValue::None => String::new(),
+    }
+}
+
+/// Only complete amounts can be written; Ledger infers elided ones.
+fn complete_amount(amount: &IncompleteAmount) -> Option<Amount> {
+    match amount {
+        IncompleteAmount::Complete(a) => Some(a.clone()),
+        _ => None,
+    }
+}
+
+/// Render a per-unit or total cost as a Ledger lot price.
+fn ledger_cost(cost: &CostSpec) -> Option<String> {
+    let currency = cost.currency.as_ref()?;
+    let lot_date = cost
+        .date
+        .map(|d| format!(" [{}]", ledger_date(d)))
+        .unwrap_or_default();
+    if let Some(number) = cost.number_per {
+        Some(format!(
+            "{{{}}}{lot_date}",
+            ledger_number_with_commodity(number, currency)
+        ))
+    } else {
+        cost.number_total.map(|number| {
+            format!(
+                "{{{{{}}}}}{lot_date}",
+                ledger_number_with_commodity(number, currency)
+            )
+        })
+    }
+}
+
+/// Render a price annotation (`@` per unit, `@@` total).
+fn ledger_price(price: &PriceAnnotation) -> Option<String> {
+    match price {
+        PriceAnnotation::Unit(a) => Some(format!("@ {}", ledger_amount(a))),
+        PriceAnnotation::Total(a) => Some(format!("@@ {}", ledger_amount(a))),
+        _ => None,
+    }
+}
+
+/// Format a date the way Ledger expects (`YYYY/MM/DD`).
+fn ledger_date(date: NaiveDate) -> String {
+    date.format("%Y/%m/%d").to_string()
+}
+
+/// Format an amount with the commodity after the number.
+fn ledger_amount(amount: &Amount) -> String {
+    ledger_number_with_commodity(amount.number, &amount.currency)
+}
+
+fn ledger_number_with_commodity(number: Decimal, currency: &str) -> String {
+    format!("{number} {}", ledger_commodity(currency))
+}
+
+/// Quote commodities that Ledger would otherwise misread.
+///
+/// Ledger treats digits, periods, and dashes as part of the amount, so
+/// commodities such as `VWO2` or `BRK.B` must be quoted.
+fn ledger_commodity(currency: &str) -> String {
+    if currency.chars().all(|c| c.is_ascii_alphabetic()) {
+        currency.to_string()
+    } else {
+        format!("\"{currency}\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustledger_parser::parse;
+
+    #[test]
+    fn test_to_ledger_golden() {
+        let source = r#"option "title" "Test"
+2024-01-01 open Assets:Bank USD
+2024-01-15 * "Store" "Groceries" #food
+  invoice: "INV-1"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+2024-01-20 * "Buy shares"
+  Assets:Broker  10 VWO2 {50.00 USD} @ 51.00 USD
+  Assets:Bank  -500.00 USD
+2024-01-21 price VWO2 51.00 USD
+2024-01-31 balance Assets:Bank 95.00 USD
+"#;
+        let expected = r#"; option "title" "Test"
+
+; 2024/01/01 open Assets:Bank USD
+
+2024/01/15 * Store | Groceries
+  ; :food:
+  ; invoice: INV-1
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+
+2024/01/20 * Buy shares
+  Assets:Broker  10 "VWO2" {50.00 USD} @ 51.00 USD
+  Assets:Bank  -500.00 USD
+
+P 2024/01/21 "VWO2" 51.00 USD
+
+2024/01/30 * Balance assertion
+  Assets:Bank  0 USD = 95.00 USD
+"#;
+        let result = parse(source);
+        assert_eq!(to_ledger(&result), expected);
+    }
+
+    #[test]
+    fn test_to_ledger_balance_before_same_day_transaction() {
+        let source = r#"2024-01-31 * "Fee"
+  Assets:Bank  -1.00 USD
+  Expenses:Fees
+2024-01-31 balance Assets:Bank 95.00 USD
+"#;
+        let expected = r#"2024/01/30 * Balance assertion
+  Assets:Bank  0 USD = 95.00 USD
+
+2024/01/31 * Fee
+  Assets:Bank  -1.00 USD
+  Expenses:Fees
+"#;
+        let result = parse(source);
+        assert_eq!(to_ledger(&result), expected);
+    }
+
+    #[test]
+    fn test_to_ledger_pending_flag_and_links() {
+        let source = r#"2024-02-01 ! "Refund" ^order-42
+  Assets:Bank  12.50 EUR
+  Income:Refunds  -12.50 EUR
+"#;
+        let expected = r#"2024/02/01 ! Refund
+  ; Link: order-42
+  Assets:Bank  12.50 EUR
+  Income:Refunds  -12.50 EUR
+"#;
+        let result = parse(source);
+        assert_eq!(to_ledger(&result), expected);
+    }
+}
@@ -0,0 +1,11 @@
+//! Export of parsed ledgers to other formats.
+//!
+//! Provides:
+//! - JSON export with a stable schema for external tooling
+//! - Ledger-CLI export for migrating between tools
+
+mod json;
+mod ledger;
+
+pub use json::{directive_to_json, to_json};
+pub use ledger::to_ledger;
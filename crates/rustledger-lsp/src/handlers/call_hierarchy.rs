@@ -9,22 +9,24 @@
 use lsp_types::{
     CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams, CallHierarchyItem,
     CallHierarchyOutgoingCall, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
-    Position, Range, SymbolKind, Uri,
+    Position, Range, SymbolKind,
 };
 use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 use std::collections::HashMap;
 
 use super::utils::{byte_offset_to_position, get_word_at_position, is_account_like};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a prepare call hierarchy request.
 /// Returns the account at the cursor position as a CallHierarchyItem.
 pub fn handle_prepare_call_hierarchy(
     params: &CallHierarchyPrepareParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<CallHierarchyItem>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let position = params.text_document_position_params.position;
     let line_idx = position.line as usize;
     let lines: Vec<&str> = source.lines().collect();
@@ -67,10 +69,11 @@ pub fn handle_prepare_call_hierarchy(
 /// Returns all transactions that post to this account.
 pub fn handle_incoming_calls(
     params: &CallHierarchyIncomingCallsParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<CallHierarchyIncomingCall>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let account = params
         .item
         .data
@@ -155,10 +158,11 @@ pub fn handle_incoming_calls(
 /// For a transaction (identified by data): returns all accounts it posts to.
 pub fn handle_outgoing_calls(
     params: &CallHierarchyOutgoingCallsParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<CallHierarchyOutgoingCall>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     // Check if this is a transaction
     let data = params.item.data.as_ref()?;
     let item_type = data.get("type").and_then(|v| v.as_str())?;
@@ -294,7 +298,7 @@ fn account_exists(account: &str, parse_result: &ParseResult) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
+    use lsp_types::Uri;
 
     #[test]
     fn test_prepare_call_hierarchy() {
@@ -303,7 +307,6 @@ mod tests {
   Assets:Bank:Checking  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         let params = CallHierarchyPrepareParams {
@@ -314,7 +317,8 @@ mod tests {
             work_done_progress_params: Default::default(),
         };
 
-        let items = handle_prepare_call_hierarchy(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let items = handle_prepare_call_hierarchy(&params, &snapshot);
         assert!(items.is_some());
 
         let items = items.unwrap();
@@ -333,7 +337,6 @@ mod tests {
   Assets:Bank:Checking  -10.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         let item = CallHierarchyItem {
@@ -353,7 +356,8 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let calls = handle_incoming_calls(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let calls = handle_incoming_calls(&params, &snapshot);
         assert!(calls.is_some());
 
         let calls = calls.unwrap();
@@ -368,7 +372,6 @@ mod tests {
   Assets:Bank:Checking  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         let item = CallHierarchyItem {
@@ -391,7 +394,8 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let calls = handle_outgoing_calls(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let calls = handle_outgoing_calls(&params, &snapshot);
         assert!(calls.is_some());
 
         let calls = calls.unwrap();
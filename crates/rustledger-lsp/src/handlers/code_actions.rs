@@ -1,9 +1,17 @@
 //! Code actions handler for quick fixes and refactorings.
 //!
 //! Provides code actions for:
-//! - Adding missing account open directives
+//! - Adding missing account open directives, one at a time or all at once
+//! - Adding missing commodity directives
+//! - Fixing misspelled accounts and currencies
+//! - Adding conversion postings to balance a transaction mixing currencies
+//! - Normalizing posting indentation and replacing inline tabs
+//! - Rounding amounts to their commodity's declared precision
 //! - Balancing transaction postings
 //! - Formatting amounts consistently
+//! - Documenting a repeated posting pattern as a template comment
+//! - Moving a long narration into a `desc:` metadata line
+//! - Sorting a transaction's postings into a canonical order
 //!
 //! Supports resolve for lazy-loading workspace edits.
 
@@ -11,18 +19,31 @@ use lsp_types::{
     CodeAction, CodeActionKind, CodeActionParams, CodeActionResponse, Position, Range, TextEdit,
     Uri, WorkspaceEdit,
 };
-use rustledger_core::Directive;
+use rustledger_core::{Account, Directive};
 use rustledger_parser::ParseResult;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use super::diagnostics::{
+    ACCOUNT_CAPITALIZATION_CODE, EXCESS_PRECISION_CODE, INDENTATION_CODE, INLINE_TAB_CODE,
+    PostingLine, UNDECLARED_CURRENCY_CODE, UNDEFINED_ACCOUNT_CODE, UNRELATED_CURRENCIES_CODE,
+    used_accounts,
+};
 use super::utils::byte_offset_to_position;
+use crate::snapshot::WorldSnapshot;
+
+/// Narrations longer than this (in characters) can be moved to `desc:`.
+const LONG_NARRATION_CHARS: usize = 80;
+
+/// Maximum length of the narration left on the header after moving it.
+const SHORT_NARRATION_CHARS: usize = 40;
 
 /// Handle a code action request.
 pub fn handle_code_actions(
     params: &CodeActionParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<CodeActionResponse> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let mut actions = Vec::new();
 
     let range = params.range;
@@ -41,10 +62,33 @@ pub fn handle_code_actions(
         .collect();
 
     // If there are undefined accounts, offer to create open directives
-    for account in undefined_accounts {
+    for account in &undefined_accounts {
         // Check if this account is on or near the selected range
-        if is_account_in_range(source, &account, range, parse_result) {
-            let action = create_open_directive_action(&uri, &account);
+        if is_account_in_range(source, account, range, parse_result) {
+            let action = create_open_directive_action(&uri, account);
+            actions.push(action);
+        }
+    }
+
+    if !undefined_accounts.is_empty() {
+        actions.push(create_all_open_directives_action(&uri));
+    }
+
+    // Offer to declare currencies flagged as undeclared
+    for diagnostic in &params.context.diagnostics {
+        if let Some(action) = create_fix_typo_action(&uri, diagnostic) {
+            actions.push(action);
+        }
+        if let Some(action) = create_commodity_directive_action(&uri, diagnostic) {
+            actions.push(action);
+        }
+        if let Some(action) = create_replace_line_action(&uri, source, diagnostic) {
+            actions.push(action);
+        }
+        if let Some(action) = create_round_precision_action(&uri, diagnostic) {
+            actions.push(action);
+        }
+        if let Some(action) = create_conversion_postings_action(&uri, diagnostic) {
             actions.push(action);
         }
     }
@@ -54,6 +98,18 @@ pub fn handle_code_actions(
         actions.push(action);
     }
 
+    if let Some(action) = create_template_comment_action(&uri, range, source, parse_result) {
+        actions.push(action);
+    }
+
+    if let Some(action) = create_move_narration_action(&uri, range, source, parse_result) {
+        actions.push(action);
+    }
+
+    if let Some(action) = create_sort_postings_action(&uri, range, source, parse_result) {
+        actions.push(action);
+    }
+
     if actions.is_empty() {
         None
     } else {
@@ -76,76 +132,67 @@ fn collect_defined_accounts(parse_result: &ParseResult) -> HashSet<String> {
 
 /// Collect all accounts used in the document.
 fn collect_used_accounts(parse_result: &ParseResult) -> HashSet<String> {
-    let mut accounts = HashSet::new();
-
-    for spanned in &parse_result.directives {
-        match &spanned.value {
-            Directive::Transaction(txn) => {
-                for posting in &txn.postings {
-                    accounts.insert(posting.account.to_string());
-                }
-            }
-            Directive::Balance(bal) => {
-                accounts.insert(bal.account.to_string());
-            }
-            Directive::Pad(pad) => {
-                accounts.insert(pad.account.to_string());
-                accounts.insert(pad.source_account.to_string());
-            }
-            Directive::Note(note) => {
-                accounts.insert(note.account.to_string());
-            }
-            Directive::Document(doc) => {
-                accounts.insert(doc.account.to_string());
-            }
-            Directive::Close(close) => {
-                accounts.insert(close.account.to_string());
-            }
-            _ => {}
-        }
-    }
-
-    accounts
+    parse_result
+        .directives
+        .iter()
+        .flat_map(|spanned| used_accounts(&spanned.value))
+        .map(str::to_string)
+        .collect()
 }
 
-/// Check if an account is mentioned in or near the given range.
+/// Check if an account is mentioned in the given range, or used by a
+/// directive overlapping it.
+///
+/// Directive spans cover every posting and metadata line, so overlap with
+/// a span is enough to find the directive under the selection.
 fn is_account_in_range(
     source: &str,
     account: &str,
     range: Range,
     parse_result: &ParseResult,
 ) -> bool {
-    // Find the line at the range start
-    let lines: Vec<&str> = source.lines().collect();
-    let start_line = range.start.line as usize;
-
-    // Check a few lines around the selection
-    for line_idx in start_line.saturating_sub(3)..=(start_line + 10).min(lines.len() - 1) {
-        if let Some(line) = lines.get(line_idx) {
-            if line.contains(account) {
-                return true;
-            }
-        }
+    let selected_lines = source
+        .lines()
+        .skip(range.start.line as usize)
+        .take(range.end.line.saturating_sub(range.start.line) as usize + 1);
+    if selected_lines
+        .into_iter()
+        .any(|line| line.contains(account))
+    {
+        return true;
     }
 
-    // Also check if we're inside a transaction that uses this account
-    for spanned in &parse_result.directives {
-        if let Directive::Transaction(txn) = &spanned.value {
-            let (dir_line, _) = byte_offset_to_position(source, spanned.span.start);
-            let (end_line, _) = byte_offset_to_position(source, spanned.span.end);
-
-            // Check if range overlaps with transaction
-            if (range.start.line <= end_line) && (range.end.line >= dir_line) {
-                for posting in &txn.postings {
-                    if posting.account.as_ref() == account {
-                        return true;
-                    }
-                }
+    parse_result.directives.iter().any(|spanned| {
+        let (start_line, _) = byte_offset_to_position(source, spanned.span.start);
+        let (end_line, _) = byte_offset_to_position(source, spanned.span.end);
+        let overlaps = range.start.line <= end_line && range.end.line >= start_line;
+        overlaps
+            && match &spanned.value {
+                Directive::Transaction(txn) => txn
+                    .postings
+                    .iter()
+                    .any(|posting| posting.account.as_ref() == account),
+                _ => false,
             }
-        }
-    }
+    })
+}
 
-    false
+/// Create a source action adding open directives for every undefined
+/// account in the file. The edit is deferred to the resolve phase.
+fn create_all_open_directives_action(uri: &Uri) -> CodeAction {
+    CodeAction {
+        title: "Add open directives for all undefined accounts".to_string(),
+        kind: Some(CodeActionKind::SOURCE),
+        diagnostics: None,
+        edit: None, // Resolved lazily
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: Some(serde_json::json!({
+            "kind": "add_all_open_directives",
+            "uri": uri.as_str(),
+        })),
+    }
 }
 
 /// Create a code action to add an open directive for an account.
@@ -170,15 +217,495 @@ fn create_open_directive_action(uri: &Uri, account: &str) -> CodeAction {
     }
 }
 
-/// Handle a code action resolve request.
-/// Computes the workspace edit for a code action.
+/// Create a quickfix adding a commodity directive for an undeclared-currency diagnostic.
+/// The edit is deferred to the resolve phase like the open directive action.
+fn create_commodity_directive_action(
+    uri: &Uri,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    let is_undeclared_currency = matches!(
+        &diagnostic.code,
+        Some(lsp_types::NumberOrString::String(code)) if code == UNDECLARED_CURRENCY_CODE
+    );
+    if !is_undeclared_currency {
+        return None;
+    }
+
+    let data = diagnostic.data.as_ref()?;
+    let currency = data.get("currency")?.as_str()?;
+    let date = data.get("date")?.as_str()?;
+
+    Some(CodeAction {
+        title: format!("Add 'commodity {}' directive", currency),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: None, // Resolved lazily
+        command: None,
+        is_preferred: Some(true),
+        disabled: None,
+        data: Some(serde_json::json!({
+            "kind": "add_commodity_directive",
+            "currency": currency,
+            "date": date,
+            "uri": uri.as_str(),
+        })),
+    })
+}
+
+/// Create a quickfix replacing a line with the normalized line carried
+/// by its indentation or inline tab diagnostic.
 #[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
-pub fn handle_code_action_resolve(
-    action: CodeAction,
+fn create_replace_line_action(
+    uri: &Uri,
+    source: &str,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    let title = match &diagnostic.code {
+        Some(lsp_types::NumberOrString::String(code)) if code == INDENTATION_CODE => {
+            "Normalize posting indentation"
+        }
+        Some(lsp_types::NumberOrString::String(code)) if code == INLINE_TAB_CODE => {
+            "Replace tabs with spaces"
+        }
+        _ => return None,
+    };
+
+    let replacement = diagnostic.data.as_ref()?.get("replacement")?.as_str()?;
+    let line_num = diagnostic.range.start.line;
+    let line = source.lines().nth(line_num as usize)?;
+    let line_end = line.trim_end_matches('\r').encode_utf16().count() as u32;
+
+    let edit = TextEdit {
+        range: Range::new(
+            Position::new(line_num, 0),
+            Position::new(line_num, line_end),
+        ),
+        new_text: replacement.to_string(),
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: Some(true),
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Create a quickfix replacing a misspelled account or currency with the
+/// known name suggested by its undefined-account or undeclared-currency
+/// diagnostic, or a mis-capitalized account with its capitalized form.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn create_fix_typo_action(uri: &Uri, diagnostic: &lsp_types::Diagnostic) -> Option<CodeAction> {
+    let is_typo_candidate = matches!(
+        &diagnostic.code,
+        Some(lsp_types::NumberOrString::String(code))
+            if code == UNDEFINED_ACCOUNT_CODE
+                || code == UNDECLARED_CURRENCY_CODE
+                || code == ACCOUNT_CAPITALIZATION_CODE
+    );
+    if !is_typo_candidate {
+        return None;
+    }
+
+    let suggestion = diagnostic.data.as_ref()?.get("suggestion")?.as_str()?;
+    let edit = TextEdit {
+        range: diagnostic.range,
+        new_text: suggestion.to_string(),
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: format!("Change to '{suggestion}'"),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: Some(true),
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Create a quickfix rounding an amount to its commodity's precision,
+/// using the rounded number carried by its diagnostic.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn create_round_precision_action(
+    uri: &Uri,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    let is_excess_precision = matches!(
+        &diagnostic.code,
+        Some(lsp_types::NumberOrString::String(code)) if code == EXCESS_PRECISION_CODE
+    );
+    if !is_excess_precision {
+        return None;
+    }
+
+    let replacement = diagnostic.data.as_ref()?.get("replacement")?.as_str()?;
+    let edit = TextEdit {
+        range: diagnostic.range,
+        new_text: replacement.to_string(),
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: format!("Round to {replacement}"),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: Some(true),
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Create a quickfix adding postings to the conversions account so each
+/// currency of a transaction nets to zero, using the postings and insert
+/// position carried by its unrelated-currencies diagnostic.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn create_conversion_postings_action(
+    uri: &Uri,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<CodeAction> {
+    let is_unrelated_currencies = matches!(
+        &diagnostic.code,
+        Some(lsp_types::NumberOrString::String(code)) if code == UNRELATED_CURRENCIES_CODE
+    );
+    if !is_unrelated_currencies {
+        return None;
+    }
+
+    let data = diagnostic.data.as_ref()?;
+    let account = data.get("account")?.as_str()?;
+    let insertion = data.get("insertion")?.as_str()?;
+    let position: Position = serde_json::from_value(data.get("position")?.clone()).ok()?;
+    let edit = TextEdit {
+        range: Range::new(position, position),
+        new_text: insertion.to_string(),
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: format!("Add conversion postings to {account}"),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Create a refactor moving a long narration into a `desc:` metadata line.
+///
+/// Offered when the cursor is on the header of a transaction whose
+/// narration is longer than [`LONG_NARRATION_CHARS`]. The header keeps a
+/// shortened narration, cut at a word boundary.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn create_move_narration_action(
+    uri: &Uri,
+    range: Range,
     source: &str,
     parse_result: &ParseResult,
+) -> Option<CodeAction> {
+    let (line_num, txn) = parse_result.directives.iter().find_map(|spanned| {
+        let Directive::Transaction(txn) = &spanned.value else {
+            return None;
+        };
+        let (line, _) = byte_offset_to_position(source, spanned.span.start);
+        (line == range.start.line).then_some((line, txn))
+    })?;
+    let narration = txn.narration.as_str();
+    if narration.chars().count() <= LONG_NARRATION_CHARS {
+        return None;
+    }
+
+    let header = source
+        .lines()
+        .nth(line_num as usize)?
+        .trim_end_matches('\r');
+    // The narration is the last string on the header
+    let (start, end) = header_strings(header).last().copied()?;
+    let new_text = format!(
+        "{}{}\n  desc: {}",
+        quote_string(&shorten_narration(narration)),
+        &header[end..],
+        quote_string(narration)
+    );
+    let edit = TextEdit {
+        range: Range::new(
+            Position::new(line_num, header[..start].encode_utf16().count() as u32),
+            Position::new(line_num, header.encode_utf16().count() as u32),
+        ),
+        new_text,
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: "Move narration to `desc:` metadata".to_string(),
+        kind: Some(CodeActionKind::REFACTOR),
+        diagnostics: None,
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Create a refactor sorting the postings of the transaction under the
+/// cursor: assets, liabilities, equity, income, then expenses, each by
+/// account name. A posting with an elided amount stays last, and each
+/// posting keeps the metadata and comment lines beneath it.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn create_sort_postings_action(
     uri: &Uri,
-) -> CodeAction {
+    range: Range,
+    source: &str,
+    parse_result: &ParseResult,
+) -> Option<CodeAction> {
+    let (first_line, last_line, txn) = parse_result.directives.iter().find_map(|spanned| {
+        let Directive::Transaction(txn) = &spanned.value else {
+            return None;
+        };
+        let (first, _) = byte_offset_to_position(source, spanned.span.start);
+        let (end, end_col) = byte_offset_to_position(source, spanned.span.end);
+        let last = if end_col == 0 {
+            end.saturating_sub(1)
+        } else {
+            end
+        };
+        (first <= range.start.line && range.start.line <= last).then_some((first, last, txn))
+    })?;
+    if txn.postings.len() < 2 {
+        return None;
+    }
+
+    // Group the lines after the header into one block per posting
+    let lines: Vec<&str> = source
+        .lines()
+        .skip(first_line as usize + 1)
+        .take((last_line - first_line) as usize)
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+    let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+    let mut first_posting_line = None;
+    for (i, line) in lines.iter().enumerate() {
+        let next = txn.postings.get(blocks.len());
+        let is_next_posting = PostingLine::parse(line)
+            .zip(next)
+            .is_some_and(|(parsed, posting)| parsed.account == posting.account.as_str());
+        if is_next_posting {
+            first_posting_line.get_or_insert(i);
+            blocks.push((blocks.len(), vec![line]));
+        } else if let Some((_, block)) = blocks.last_mut() {
+            block.push(line);
+        }
+    }
+    if blocks.len() != txn.postings.len() {
+        return None;
+    }
+
+    let sort_key = |index: usize| {
+        let posting = &txn.postings[index];
+        let account = posting.account.as_str();
+        let rank = ["Assets", "Liabilities", "Equity", "Income", "Expenses"]
+            .iter()
+            .position(|root| Account::new(account).components().next() == Some(*root))
+            .unwrap_or(5);
+        (posting.units.is_none(), rank, account)
+    };
+    let mut sorted = blocks.clone();
+    sorted.sort_by_key(|(index, _)| sort_key(*index));
+    if sorted
+        .iter()
+        .map(|(i, _)| i)
+        .eq(blocks.iter().map(|(i, _)| i))
+    {
+        return None;
+    }
+
+    let start_line = first_line + 1 + first_posting_line? as u32;
+    let last = *blocks.last()?.1.last()?;
+    let new_text = sorted
+        .iter()
+        .flat_map(|(_, block)| block.iter().copied())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let edit = TextEdit {
+        range: Range::new(
+            Position::new(start_line, 0),
+            Position::new(last_line, last.encode_utf16().count() as u32),
+        ),
+        new_text,
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: "Sort postings".to_string(),
+        kind: Some(CodeActionKind::REFACTOR),
+        diagnostics: None,
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Byte ranges of the quoted strings on a line, including the quotes,
+/// stopping at a `;` comment.
+fn header_strings(line: &str) -> Vec<(usize, usize)> {
+    let mut strings = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (open, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(start), '"') => {
+                strings.push((start, i + 1));
+                open = None;
+            }
+            (None, '"') => open = Some(i),
+            (None, ';') => break,
+            _ => {}
+        }
+    }
+    strings
+}
+
+/// Shorten a narration to at most [`SHORT_NARRATION_CHARS`], cutting at
+/// the last word boundary and marking the cut with `...`.
+fn shorten_narration(narration: &str) -> String {
+    let limit = narration
+        .char_indices()
+        .nth(SHORT_NARRATION_CHARS)
+        .map_or(narration.len(), |(i, _)| i);
+    let cut = narration[..limit]
+        .rfind(char::is_whitespace)
+        .filter(|&i| i > 0)
+        .unwrap_or(limit);
+    format!("{}...", narration[..cut].trim_end())
+}
+
+/// Quote a string for Beancount, escaping backslashes and quotes.
+fn quote_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Create a refactor inserting a `; template:` comment above a selection of
+/// transactions that all post to the same set of accounts.
+///
+/// Only offered when at least two transactions start inside the selection
+/// and every one of them has an identical account set.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn create_template_comment_action(
+    uri: &Uri,
+    range: Range,
+    source: &str,
+    parse_result: &ParseResult,
+) -> Option<CodeAction> {
+    let mut selected = parse_result.directives.iter().filter_map(|spanned| {
+        let Directive::Transaction(txn) = &spanned.value else {
+            return None;
+        };
+        let (line, _) = byte_offset_to_position(source, spanned.span.start);
+        (range.start.line <= line && line <= range.end.line).then_some((line, txn))
+    });
+
+    let (first_line, first) = selected.next()?;
+    let accounts: Vec<String> = first
+        .postings
+        .iter()
+        .map(|p| p.account.to_string())
+        .collect();
+    let account_set: BTreeSet<&str> = accounts.iter().map(String::as_str).collect();
+    if account_set.len() < 2 {
+        return None;
+    }
+
+    let mut count = 1;
+    for (_, txn) in selected {
+        let other: BTreeSet<&str> = txn.postings.iter().map(|p| p.account.as_str()).collect();
+        if other != account_set {
+            return None;
+        }
+        count += 1;
+    }
+    if count < 2 {
+        return None;
+    }
+
+    // Accounts in the order the first transaction posts them
+    let mut seen = HashSet::new();
+    let ordered: Vec<&str> = accounts
+        .iter()
+        .map(String::as_str)
+        .filter(|a| seen.insert(*a))
+        .collect();
+
+    let position = Position::new(first_line, 0);
+    let edit = TextEdit {
+        range: Range::new(position, position),
+        new_text: format!("; template: {}\n", ordered.join(", ")),
+    };
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), vec![edit]);
+
+    Some(CodeAction {
+        title: format!("Document template shared by {} transactions", count),
+        kind: Some(CodeActionKind::REFACTOR),
+        diagnostics: None,
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }),
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    })
+}
+
+/// Handle a code action resolve request.
+/// Computes the workspace edit for a code action.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+pub fn handle_code_action_resolve(action: CodeAction, snapshot: &WorldSnapshot) -> CodeAction {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let mut resolved = action.clone();
 
     if let Some(data) = &action.data {
@@ -192,6 +719,22 @@ pub fn handle_code_action_resolve(
                 ));
             }
         }
+        if data.get("kind").and_then(|v| v.as_str()) == Some("add_all_open_directives") {
+            resolved.edit = Some(compute_all_open_directives_edit(uri, source, parse_result));
+        }
+        if data.get("kind").and_then(|v| v.as_str()) == Some("add_commodity_directive") {
+            let currency = data.get("currency").and_then(|v| v.as_str());
+            let date = data.get("date").and_then(|v| v.as_str());
+            if let (Some(currency), Some(date)) = (currency, date) {
+                resolved.edit = Some(compute_commodity_directive_edit(
+                    uri,
+                    source,
+                    currency,
+                    date,
+                    parse_result,
+                ));
+            }
+        }
     }
 
     resolved
@@ -205,14 +748,64 @@ fn compute_open_directive_edit(
     account: &str,
     parse_result: &ParseResult,
 ) -> WorkspaceEdit {
-    // Find the earliest date in the file or use a default
-    let earliest_date =
-        find_earliest_date(parse_result).unwrap_or_else(|| "2000-01-01".to_string());
+    // Date the open at the account's first use, falling back to the
+    // earliest date in the file or a default
+    let earliest_date = first_use_dates(parse_result)
+        .get(account)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .or_else(|| find_earliest_date(parse_result))
+        .unwrap_or_else(|| "2000-01-01".to_string());
 
     // Find where to insert the open directive
     let insert_position = find_open_directive_position(source, parse_result);
 
-    let new_text = format!("{} open {}\n", earliest_date, account);
+    let new_text = format!(
+        "{} {}\n",
+        earliest_date,
+        open_directive_body(parse_result, account)
+    );
+
+    let mut changes = HashMap::new();
+    changes.insert(
+        uri.clone(),
+        vec![TextEdit {
+            range: Range {
+                start: insert_position,
+                end: insert_position,
+            },
+            new_text,
+        }],
+    );
+
+    WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+        change_annotations: None,
+    }
+}
+
+/// Compute the workspace edit adding open directives for every undefined
+/// account, sorted by account and each dated at the account's first use.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn compute_all_open_directives_edit(
+    uri: &Uri,
+    source: &str,
+    parse_result: &ParseResult,
+) -> WorkspaceEdit {
+    let defined_accounts = collect_defined_accounts(parse_result);
+
+    let new_text: String = first_use_dates(parse_result)
+        .iter()
+        .filter(|(account, _)| !defined_accounts.contains(**account))
+        .map(|(account, date)| {
+            format!(
+                "{} {}\n",
+                date.format("%Y-%m-%d"),
+                open_directive_body(parse_result, account)
+            )
+        })
+        .collect();
+    let insert_position = find_open_directive_position(source, parse_result);
 
     let mut changes = HashMap::new();
     changes.insert(
@@ -233,6 +826,93 @@ fn compute_open_directive_edit(
     }
 }
 
+/// The `open` directive for an account without its date, constrained to
+/// the currencies the account is posted in, in order of first appearance.
+fn open_directive_body(parse_result: &ParseResult, account: &str) -> String {
+    let mut currencies: Vec<&str> = Vec::new();
+    for spanned in &parse_result.directives {
+        let Directive::Transaction(txn) = &spanned.value else {
+            continue;
+        };
+        for posting in &txn.postings {
+            if posting.account.as_str() != account {
+                continue;
+            }
+            let currency = posting.units.as_ref().and_then(|units| units.currency());
+            if let Some(currency) = currency {
+                if !currencies.contains(&currency) {
+                    currencies.push(currency);
+                }
+            }
+        }
+    }
+
+    if currencies.is_empty() {
+        format!("open {account}")
+    } else {
+        format!("open {account} {}", currencies.join(","))
+    }
+}
+
+/// The earliest date each account is used on, keyed by account.
+fn first_use_dates(parse_result: &ParseResult) -> BTreeMap<&str, chrono::NaiveDate> {
+    let mut first_use: BTreeMap<&str, chrono::NaiveDate> = BTreeMap::new();
+    for spanned in &parse_result.directives {
+        let date = spanned.value.date();
+        for account in used_accounts(&spanned.value) {
+            first_use
+                .entry(account)
+                .and_modify(|earliest| *earliest = (*earliest).min(date))
+                .or_insert(date);
+        }
+    }
+    first_use
+}
+
+/// Compute the workspace edit for adding a commodity directive.
+#[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
+fn compute_commodity_directive_edit(
+    uri: &Uri,
+    source: &str,
+    currency: &str,
+    date: &str,
+    parse_result: &ParseResult,
+) -> WorkspaceEdit {
+    // Insert after the last commodity directive, or before the first directive
+    let last_commodity_end = parse_result
+        .directives
+        .iter()
+        .rev()
+        .find(|spanned| matches!(&spanned.value, Directive::Commodity(_)))
+        .map(|spanned| spanned.span.end);
+    let insert_position = match last_commodity_end {
+        Some(offset) => Position::new(byte_offset_to_position(source, offset).0 + 1, 0),
+        None => parse_result
+            .directives
+            .first()
+            .map(|spanned| Position::new(byte_offset_to_position(source, spanned.span.start).0, 0))
+            .unwrap_or_else(|| Position::new(0, 0)),
+    };
+
+    let mut changes = HashMap::new();
+    changes.insert(
+        uri.clone(),
+        vec![TextEdit {
+            range: Range {
+                start: insert_position,
+                end: insert_position,
+            },
+            new_text: format!("{} commodity {}\n", date, currency),
+        }],
+    );
+
+    WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+        change_annotations: None,
+    }
+}
+
 /// Find the earliest date in the document.
 fn find_earliest_date(parse_result: &ParseResult) -> Option<String> {
     let mut earliest: Option<chrono::NaiveDate> = None;
@@ -370,7 +1050,6 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         // Create a code action with data (as returned by handle_code_actions)
@@ -389,7 +1068,8 @@ mod tests {
             })),
         };
 
-        let resolved = handle_code_action_resolve(action, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let resolved = handle_code_action_resolve(action, &snapshot);
 
         // Should now have an edit
         assert!(resolved.edit.is_some());
@@ -401,6 +1081,622 @@ mod tests {
         // Should insert an open directive
         assert_eq!(edits.len(), 1);
         assert!(edits[0].new_text.contains("open Expenses:Food"));
-        assert!(edits[0].new_text.contains("2024-01-01")); // Earliest date
+        assert!(edits[0].new_text.contains("2024-01-15")); // First use of the account
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_open_directive_dated_at_first_use() {
+        let source = r#"2024-03-12 * "Rent"
+  Expenses:Rent  1200.00 USD
+  Assets:Checking
+2024-04-12 * "Rent"
+  Expenses:Rent  1200.00 USD
+  Assets:Checking
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+
+        let action = create_open_directive_action(&uri, "Expenses:Rent");
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let resolved = handle_code_action_resolve(action, &snapshot);
+        let changes = resolved.edit.unwrap().changes.unwrap();
+        assert_eq!(
+            changes.get(&uri).unwrap()[0].new_text,
+            "2024-03-12 open Expenses:Rent USD\n"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_open_directive_infers_currencies() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 * "Dinner"
+  Expenses:Food  30.00 USD
+  Assets:Bank
+2024-02-15 * "Dinner abroad"
+  Expenses:Food  25.00 EUR
+  Assets:Bank  -27.00 USD @@ 25.00 EUR
+2024-03-15 * "Groceries"
+  Expenses:Food  40.00 USD
+  Assets:Bank
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+
+        let action = create_open_directive_action(&uri, "Expenses:Food");
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let resolved = handle_code_action_resolve(action, &snapshot);
+        let changes = resolved.edit.unwrap().changes.unwrap();
+        assert_eq!(
+            changes.get(&uri).unwrap()[0].new_text,
+            "2024-01-15 open Expenses:Food USD,EUR\n"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_add_commodity_directive_quickfix() {
+        use super::super::diagnostics::undeclared_currency_diagnostics;
+
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = undeclared_currency_diagnostics(&result, source, &HashSet::new());
+        assert_eq!(diagnostics.len(), 1);
+
+        let params = CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            range: diagnostics[0].range,
+            context: lsp_types::CodeActionContext {
+                diagnostics: diagnostics.clone(),
+                only: None,
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(&params, &snapshot).unwrap();
+        let action = actions
+            .into_iter()
+            .find_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a)
+                    if a.title == "Add 'commodity USD' directive" =>
+                {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .expect("commodity quickfix");
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let resolved = handle_code_action_resolve(action, &snapshot);
+        let changes = resolved.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "2024-01-01 commodity USD\n");
+        assert_eq!(edits[0].range.start, Position::new(0, 0));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_normalize_tab_indented_posting() {
+        use super::super::diagnostics::indentation_diagnostics;
+
+        let source =
+            "2024-01-05 * \"Lunch\"\n  Expenses:Food    12.00 USD\n\tAssets:Cash     -12.00 USD\n";
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = indentation_diagnostics(&result, source, 2);
+        assert_eq!(diagnostics.len(), 1);
+
+        let params = CodeActionParams {
+            context: lsp_types::CodeActionContext {
+                diagnostics: diagnostics.clone(),
+                only: None,
+                trigger_kind: None,
+            },
+            ..refactor_params(&uri, diagnostics[0].range)
+        };
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(&params, &snapshot).unwrap();
+        let action = actions
+            .into_iter()
+            .find_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a)
+                    if a.title == "Normalize posting indentation" =>
+                {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .expect("indentation quickfix");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 0), Position::new(2, 27))
+        );
+        // Indented with spaces, number still ending under the first posting's
+        assert_eq!(edits[0].new_text, "  Assets:Cash     -12.00 USD");
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_add_all_open_directives() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-02-10 * "Groceries"
+  Assets:Bank  -40.00 USD
+  Expenses:Food
+2024-01-05 * "Lunch"
+  Expenses:Food  12.00 USD
+  Liabilities:Card
+2024-03-01 balance Assets:Savings 0 USD
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(
+            &refactor_params(&uri, Range::new(Position::new(0, 0), Position::new(0, 0))),
+            &snapshot,
+        )
+        .unwrap();
+        let action = actions
+            .into_iter()
+            .find_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a)
+                    if a.kind == Some(CodeActionKind::SOURCE) =>
+                {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .expect("source action");
+        assert_eq!(
+            action.title,
+            "Add open directives for all undefined accounts"
+        );
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let resolved = handle_code_action_resolve(action, &snapshot);
+        let changes = resolved.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range.start, Position::new(1, 0));
+        // Sorted by account, each dated at its first use
+        assert_eq!(
+            edits[0].new_text,
+            "2024-03-01 open Assets:Savings\n\
+             2024-01-05 open Expenses:Food USD\n\
+             2024-01-05 open Liabilities:Card\n"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_sort_postings_moves_metadata() {
+        let source = r#"2024-01-15 * "Dinner"
+  trip: "berlin"
+  Expenses:Food  30.00 EUR
+    receipt: "r-12.pdf"
+  Assets:Cash
+  Liabilities:Card  -30.00 EUR
+    ; split later
+2024-01-16 * "Next"
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(
+            &refactor_params(&uri, Range::new(Position::new(2, 4), Position::new(2, 4))),
+            &snapshot,
+        )
+        .unwrap();
+        let action = refactor_actions(actions)
+            .into_iter()
+            .find(|a| a.title == "Sort postings")
+            .expect("sort postings refactor");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 0), Position::new(6, 17))
+        );
+        // Liabilities before expenses; the elided posting stays last
+        assert_eq!(
+            edits[0].new_text,
+            "  Liabilities:Card  -30.00 EUR\n    ; split later\n  Expenses:Food  30.00 EUR\n    receipt: \"r-12.pdf\"\n  Assets:Cash"
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_sort_postings_not_offered_when_sorted() {
+        let source = "2024-01-15 * \"Coffee\"\n  Assets:Cash  -5.00 USD\n  Expenses:Food\n";
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let range = Range::new(Position::new(0, 0), Position::new(0, 0));
+        assert!(create_sort_postings_action(&uri, range, source, &result).is_none());
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_fix_account_typo() {
+        use super::super::diagnostics::undefined_account_diagnostics;
+
+        let source = "2024-01-01 open Assets:Bank\n2024-01-02 balance Assets:Banl 0 USD\n";
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = undefined_account_diagnostics(&result, source, &HashSet::new());
+        assert_eq!(diagnostics.len(), 1);
+
+        let params = CodeActionParams {
+            context: lsp_types::CodeActionContext {
+                diagnostics: diagnostics.clone(),
+                only: None,
+                trigger_kind: None,
+            },
+            ..refactor_params(&uri, diagnostics[0].range)
+        };
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(&params, &snapshot).unwrap();
+        let action = actions
+            .into_iter()
+            .find_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a)
+                    if a.title == "Change to 'Assets:Bank'" =>
+                {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .expect("typo quickfix");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 19), Position::new(1, 30))
+        );
+        assert_eq!(edits[0].new_text, "Assets:Bank");
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_fix_currency_typo() {
+        use super::super::diagnostics::undeclared_currency_diagnostics;
+
+        let source = "2024-01-01 commodity USD\n2024-01-02 price EUR 1.08 USF\n";
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = undeclared_currency_diagnostics(&result, source, &HashSet::new());
+        let typo = diagnostics
+            .iter()
+            .find(|d| d.data.as_ref().unwrap()["currency"] == "USF")
+            .unwrap();
+        let action = create_fix_typo_action(&uri, typo).expect("typo quickfix");
+        assert_eq!(action.title, "Change to 'USD'");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 26), Position::new(1, 29))
+        );
+        assert_eq!(edits[0].new_text, "USD");
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_add_conversion_postings() {
+        use super::super::diagnostics::unrelated_currencies_diagnostics;
+
+        let source = "2024-01-15 * \"Exchange\"\n    Assets:Bank:USD  -100 USD\n    Assets:Bank:EUR  90 EUR\n";
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = unrelated_currencies_diagnostics(&result, source, "Equity:Trading");
+        let action =
+            create_conversion_postings_action(&uri, &diagnostics[0]).expect("conversion quickfix");
+        assert_eq!(action.title, "Add conversion postings to Equity:Trading");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edit = &changes.get(&uri).unwrap()[0];
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(2, 27), Position::new(2, 27))
+        );
+        assert_eq!(
+            edit.new_text,
+            "\n    Equity:Trading  100 USD\n    Equity:Trading  -90 EUR"
+        );
+
+        // The postings net both currencies to zero
+        let fixed = format!("{}{}\n", source.trim_end(), edit.new_text);
+        let fixed_result = parse(&fixed);
+        assert!(
+            unrelated_currencies_diagnostics(&fixed_result, &fixed, "Equity:Trading").is_empty()
+        );
+        let Directive::Transaction(txn) = &fixed_result.directives[0].value else {
+            panic!("expected transaction");
+        };
+        assert!(
+            rustledger_booking::calculate_residual(txn)
+                .values()
+                .all(|residual| residual.is_zero())
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_fix_account_capitalization() {
+        use super::super::diagnostics::account_capitalization_diagnostics;
+
+        let source = "2024-01-05 * \"Lunch\"\n  expenses:food  12.00 USD\n  Assets:Cash\n";
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = account_capitalization_diagnostics(source);
+        let action = create_fix_typo_action(&uri, &diagnostics[0]).expect("capitalization fix");
+        assert_eq!(action.title, "Change to 'Expenses:Food'");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 2), Position::new(1, 15))
+        );
+        assert_eq!(edits[0].new_text, "Expenses:Food");
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_replace_inline_tab() {
+        use super::super::diagnostics::inline_tab_diagnostics;
+
+        let source = "2024-01-05 * \"Lunch\"\n  Expenses:Food\t12.00 USD\n  Assets:Cash\n";
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = inline_tab_diagnostics(source, 2);
+        assert_eq!(diagnostics.len(), 1);
+
+        let params = CodeActionParams {
+            context: lsp_types::CodeActionContext {
+                diagnostics: diagnostics.clone(),
+                only: None,
+                trigger_kind: None,
+            },
+            ..refactor_params(&uri, diagnostics[0].range)
+        };
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(&params, &snapshot).unwrap();
+        let action = actions
+            .into_iter()
+            .find_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a)
+                    if a.title == "Replace tabs with spaces" =>
+                {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .expect("tab quickfix");
+
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 0), Position::new(1, 25))
+        );
+        assert_eq!(edits[0].new_text, "  Expenses:Food 12.00 USD");
+    }
+
+    #[test]
+    fn test_account_in_range_uses_full_transaction_span() {
+        let source = r#"2024-01-15 * "Coffee"
+  receipt: "r-1"
+  Assets:Bank  -5.00 USD
+  note: "a"
+  note2: "b"
+  Expenses:Food
+2024-01-16 * "Other"
+  Assets:Bank  -1.00 USD
+  Assets:Cash
+"#;
+        let result = parse(source);
+        // A cursor on the metadata line sits inside the first transaction
+        let on_metadata = Range::new(Position::new(3, 2), Position::new(3, 2));
+        assert!(is_account_in_range(
+            source,
+            "Expenses:Food",
+            on_metadata,
+            &result
+        ));
+        // The second transaction does not use it, however close it is
+        let in_second = Range::new(Position::new(7, 2), Position::new(7, 2));
+        assert!(!is_account_in_range(
+            source,
+            "Expenses:Food",
+            in_second,
+            &result
+        ));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_move_long_narration_to_desc() {
+        let narration = "Quarterly reimbursement for conference travel ".repeat(5);
+        let narration = narration.trim_end();
+        assert!(narration.chars().count() > 200);
+        let source = format!(
+            "2024-01-15 * \"Acme\" \"{narration}\" #work\n  Assets:Bank  100.00 USD\n  Income:Reimbursed\n"
+        );
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = refactor_params(&uri, Range::new(Position::new(0, 3), Position::new(0, 3)));
+
+        let snapshot = WorldSnapshot::new(uri.clone(), &source);
+        let actions = refactor_actions(handle_code_actions(&params, &snapshot).unwrap());
+        let action = actions
+            .iter()
+            .find(|a| a.title == "Move narration to `desc:` metadata")
+            .unwrap();
+        let changes = action.edit.clone().unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(edits.len(), 1);
+
+        let header = source.lines().next().unwrap();
+        let start = edits[0].range.start.character as usize;
+        let end = edits[0].range.end.character as usize;
+        let edited = format!(
+            "{}{}{}",
+            &header[..start],
+            edits[0].new_text,
+            &header[end..]
+        );
+        assert_eq!(
+            edited,
+            format!(
+                "2024-01-15 * \"Acme\" \"Quarterly reimbursement for conference...\" #work\n  desc: \"{narration}\""
+            )
+        );
+
+        // The result still parses, with the full text in metadata
+        let rewritten = source.replacen(header, &edited, 1);
+        let reparsed = parse(&rewritten);
+        assert!(reparsed.errors.is_empty());
+        let Directive::Transaction(txn) = &reparsed.directives[0].value else {
+            panic!("expected transaction");
+        };
+        assert_eq!(
+            txn.meta.get("desc"),
+            Some(&rustledger_core::MetaValue::String(narration.to_string()))
+        );
+        assert_eq!(txn.tags.len(), 1);
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_round_excess_precision_quickfix() {
+        use super::super::diagnostics::excess_precision_diagnostics;
+
+        let source = r#"2024-01-01 commodity USD
+  precision: 2
+2024-01-05 * "Import"
+  Expenses:Food  5.12345 USD
+  Assets:Cash
+"#;
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = excess_precision_diagnostics(&result, source);
+        let params = CodeActionParams {
+            context: lsp_types::CodeActionContext {
+                diagnostics: diagnostics.clone(),
+                only: None,
+                trigger_kind: None,
+            },
+            ..refactor_params(&uri, diagnostics[0].range)
+        };
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(&params, &snapshot).unwrap();
+        let action = actions
+            .into_iter()
+            .find_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a) if a.title == "Round to 5.12" => {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .expect("precision quickfix");
+        let changes = action.edit.unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(edits[0].range, diagnostics[0].range);
+        assert_eq!(edits[0].new_text, "5.12");
+    }
+
+    #[test]
+    fn test_shorten_narration() {
+        assert_eq!(
+            shorten_narration("Quarterly reimbursement for conference travel"),
+            "Quarterly reimbursement for conference..."
+        );
+        assert_eq!(quote_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+
+    fn refactor_params(uri: &Uri, range: Range) -> CodeActionParams {
+        CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            range,
+            context: lsp_types::CodeActionContext {
+                diagnostics: vec![],
+                only: None,
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    fn refactor_actions(response: CodeActionResponse) -> Vec<CodeAction> {
+        response
+            .into_iter()
+            .filter_map(|a| match a {
+                lsp_types::CodeActionOrCommand::CodeAction(a)
+                    if a.kind == Some(CodeActionKind::REFACTOR) =>
+                {
+                    Some(a)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_template_comment_for_matching_transactions() {
+        let source = r#"2024-01-01 open Assets:Cash
+2024-01-01 open Expenses:Food
+2024-01-05 * "Lunch"
+  Expenses:Food  12.00 USD
+  Assets:Cash
+2024-01-06 * "Dinner"
+  Assets:Cash  -20.00 USD
+  Expenses:Food
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = refactor_params(&uri, Range::new(Position::new(2, 0), Position::new(7, 0)));
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = refactor_actions(handle_code_actions(&params, &snapshot).unwrap());
+        assert_eq!(actions.len(), 1);
+        let changes = actions[0].edit.clone().unwrap().changes.unwrap();
+        let edits = changes.get(&uri).unwrap();
+        assert_eq!(edits[0].range.start, Position::new(2, 0));
+        assert_eq!(
+            edits[0].new_text,
+            "; template: Expenses:Food, Assets:Cash\n"
+        );
+    }
+
+    #[test]
+    fn test_template_comment_requires_identical_accounts() {
+        let source = r#"2024-01-05 * "Lunch"
+  Expenses:Food  12.00 USD
+  Assets:Cash
+2024-01-06 * "Fuel"
+  Expenses:Car  30.00 USD
+  Assets:Cash
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = refactor_params(&uri, Range::new(Position::new(0, 0), Position::new(5, 0)));
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let actions = handle_code_actions(&params, &snapshot)
+            .map(refactor_actions)
+            .unwrap_or_default();
+        assert!(actions.is_empty());
     }
 }
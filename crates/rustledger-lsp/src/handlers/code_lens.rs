@@ -3,122 +3,73 @@
 //! Provides code lenses above:
 //! - Account open directives (showing transaction count)
 //! - Transactions (showing posting count and currencies)
-//! - Balance assertions (with verification status)
+//! - Balance assertions (with the difference from the computed balance)
 //!
-//! Supports resolve for lazy-loading expensive balance calculations.
+//! Lenses are returned with only a range and `data` naming the
+//! [`DirectiveId`] they were made for; their titles are computed on
+//! resolve, so only lenses the client shows are paid for. Balance lenses
+//! are the exception: every assertion is checked in one pass over the
+//! running balances, so they come back titled.
 
 use lsp_types::{CodeLens, CodeLensParams, Command, Position, Range};
-use rustledger_core::{Decimal, Directive};
+use rustledger_core::{Balance, Decimal, Directive, Open, Transaction};
 use rustledger_parser::ParseResult;
 use std::collections::HashMap;
 
-use super::utils::LineIndex;
+use super::utils::{DirectiveId, account_transaction_count};
+use crate::report::{balance_assertion_actuals, balances};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a code lens request.
 pub fn handle_code_lens(
     params: &CodeLensParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<CodeLens>> {
-    let line_index = LineIndex::new(source);
+    let parse_result = &*snapshot.parse_result;
+    let line_index = &snapshot.line_index;
     let mut lenses = Vec::new();
     let uri = params.text_document.uri.as_str();
+    let actuals = balance_assertion_actuals(parse_result);
 
-    // Collect account usage statistics
-    let account_stats = collect_account_stats(parse_result);
-
-    for spanned in &parse_result.directives {
+    for (index, spanned) in parse_result.directives.iter().enumerate() {
         let (line, _) = line_index.offset_to_position(spanned.span.start);
+        let id = DirectiveId(index);
 
-        match &spanned.value {
+        // Store data for resolve - titles are deferred
+        let mut command = None;
+        let data = match &spanned.value {
             Directive::Open(open) => {
-                let account = open.account.to_string();
-                let stats = account_stats.get(&account);
-
-                let txn_count = stats.map(|s| s.transaction_count).unwrap_or(0);
-                let currencies: Vec<String> =
-                    open.currencies.iter().map(|c| c.to_string()).collect();
-
-                let title = if txn_count > 0 {
-                    if currencies.is_empty() {
-                        format!("{} transactions", txn_count)
-                    } else {
-                        format!("{} transactions | {}", txn_count, currencies.join(", "))
-                    }
-                } else if !currencies.is_empty() {
-                    currencies.join(", ")
-                } else {
-                    "No transactions".to_string()
-                };
-
-                lenses.push(CodeLens {
-                    range: Range {
-                        start: Position::new(line, 0),
-                        end: Position::new(line, 0),
-                    },
-                    command: Some(Command {
-                        title,
-                        command: "rledger.showAccountDetails".to_string(),
-                        arguments: Some(vec![serde_json::json!(account)]),
-                    }),
-                    data: Some(serde_json::json!({ "uri": uri })),
-                });
+                let mut data = lens_data(uri, id, "open");
+                data["account"] = open.account.to_string().into();
+                data
             }
             Directive::Transaction(txn) => {
-                let posting_count = txn.postings.len();
-                let currencies: Vec<String> = txn
-                    .postings
-                    .iter()
-                    .filter_map(|p| {
-                        p.units
-                            .as_ref()
-                            .and_then(|u| u.currency().map(String::from))
-                    })
-                    .collect::<std::collections::HashSet<_>>()
-                    .into_iter()
-                    .collect();
-
-                let title = if currencies.is_empty() {
-                    format!("{} postings", posting_count)
-                } else {
-                    format!("{} postings | {}", posting_count, currencies.join(", "))
-                };
-
-                lenses.push(CodeLens {
-                    range: Range {
-                        start: Position::new(line, 0),
-                        end: Position::new(line, 0),
-                    },
-                    command: Some(Command {
-                        title,
-                        command: "rledger.showTransactionDetails".to_string(),
-                        arguments: None,
-                    }),
-                    data: Some(serde_json::json!({ "uri": uri })),
-                });
+                let mut data = lens_data(uri, id, "transaction");
+                data["date"] = txn.date.to_string().into();
+                data["narration"] = txn.narration.to_string().into();
+                data
             }
             Directive::Balance(bal) => {
-                // Store data for resolve - verification is deferred
-                let data = serde_json::json!({
-                    "uri": uri,
-                    "kind": "balance",
-                    "account": bal.account.to_string(),
-                    "date": bal.date.to_string(),
-                    "expected_amount": bal.amount.number.to_string(),
-                    "expected_currency": bal.amount.currency.to_string(),
-                });
-
-                lenses.push(CodeLens {
-                    range: Range {
-                        start: Position::new(line, 0),
-                        end: Position::new(line, 0),
-                    },
-                    command: None, // Resolved lazily
-                    data: Some(data),
-                });
+                let mut data = lens_data(uri, id, "balance");
+                data["account"] = bal.account.to_string().into();
+                data["date"] = bal.date.to_string().into();
+                data["expected_amount"] = bal.amount.number.to_string().into();
+                data["expected_currency"] = bal.amount.currency.to_string().into();
+                let actual = actuals.get(&index).copied().unwrap_or_default();
+                command = Some(balance_command(bal, actual));
+                data
             }
-            _ => {}
-        }
+            _ => continue,
+        };
+
+        lenses.push(CodeLens {
+            range: Range {
+                start: Position::new(line, 0),
+                end: Position::new(line, 0),
+            },
+            command,
+            data: Some(data),
+        });
     }
 
     if lenses.is_empty() {
@@ -128,122 +79,199 @@ pub fn handle_code_lens(
     }
 }
 
+/// The `data` shared by every lens: the document, the directive, and the
+/// kind of lens.
+fn lens_data(uri: &str, id: DirectiveId, kind: &str) -> serde_json::Value {
+    let mut data = serde_json::json!({ "uri": uri, "kind": kind });
+    id.store(&mut data);
+    data
+}
+
 /// Handle a code lens resolve request.
-/// Computes expensive balance verification on demand.
-pub fn handle_code_lens_resolve(lens: CodeLens, parse_result: &ParseResult) -> CodeLens {
+/// Computes the lens title, including expensive balance verification, on demand.
+///
+/// The directive is found through the lens's [`DirectiveId`] as long as the
+/// account, date or narration stored in `data` still match it; after an edit
+/// moves directives around, open and balance lenses fall back to the copy of
+/// the directive stored in `data`, and transaction lenses get no command.
+pub fn handle_code_lens_resolve(lens: CodeLens, snapshot: &WorldSnapshot) -> CodeLens {
+    let parse_result = &*snapshot.parse_result;
     let mut resolved = lens.clone();
-
-    if let Some(data) = &lens.data {
-        if data.get("kind").and_then(|v| v.as_str()) == Some("balance") {
-            let account = data.get("account").and_then(|v| v.as_str()).unwrap_or("");
-            let date_str = data.get("date").and_then(|v| v.as_str()).unwrap_or("");
-            let expected_amount = data
-                .get("expected_amount")
-                .and_then(|v| v.as_str())
-                .and_then(|s| s.parse::<Decimal>().ok())
-                .unwrap_or_default();
-            let expected_currency = data
-                .get("expected_currency")
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-
-            // Parse the date
-            let date = chrono::NaiveDate::parse_from_str(date_str, "%Y-%m-%d").ok();
-
-            // Calculate actual balance up to this date
-            let actual_balance = calculate_balance_at_date(parse_result, account, date);
-            let actual_amount = actual_balance
-                .get(expected_currency)
-                .copied()
-                .unwrap_or_default();
-
-            // Check if balance matches
-            let (title, status) = if actual_amount == expected_amount {
-                (
-                    format!("✓ Balance: {} {}", expected_amount, expected_currency),
-                    "verified",
-                )
-            } else {
-                let diff = actual_amount - expected_amount;
-                (
-                    format!(
-                        "✗ Balance: expected {} {}, actual {} {} (diff: {})",
-                        expected_amount, expected_currency, actual_amount, expected_currency, diff
-                    ),
-                    "mismatch",
-                )
-            };
-
-            resolved.command = Some(Command {
-                title,
-                command: "rledger.showBalanceDetails".to_string(),
-                arguments: Some(vec![serde_json::json!({
-                    "account": account,
-                    "status": status,
-                    "expected": format!("{} {}", expected_amount, expected_currency),
-                    "actual": format!("{} {}", actual_amount, expected_currency),
-                })]),
-            });
+    let Some(data) = &lens.data else {
+        return resolved;
+    };
+    let directive = DirectiveId::from_data(data)
+        .and_then(|id| id.get(parse_result))
+        .map(|spanned| &spanned.value);
+    let stored = |key: &str| data.get(key).and_then(|v| v.as_str());
+
+    resolved.command = match (stored("kind"), directive) {
+        (Some("open"), Some(Directive::Open(open)))
+            if stored("account") == Some(open.account.as_str()) =>
+        {
+            Some(resolve_open(open, parse_result))
         }
-    }
+        (Some("open"), _) => {
+            stored_open(data, parse_result).map(|open| resolve_open(open, parse_result))
+        }
+        (Some("transaction"), Some(Directive::Transaction(txn)))
+            if stored("date") == Some(txn.date.to_string().as_str())
+                && stored("narration") == Some(txn.narration.as_str()) =>
+        {
+            Some(resolve_transaction(txn))
+        }
+        (Some("transaction"), _) => None,
+        (Some("balance"), Some(Directive::Balance(bal)))
+            if stored("account") == Some(bal.account.as_str())
+                && stored("date") == Some(bal.date.to_string().as_str()) =>
+        {
+            Some(resolve_balance(bal, parse_result))
+        }
+        (Some("balance"), _) => stored_balance(data).map(|bal| resolve_balance(&bal, parse_result)),
+        // If nothing to resolve, return as-is
+        _ => resolved.command,
+    };
 
-    // If no data to resolve, return as-is (already has command)
     resolved
 }
 
-/// Calculate the balance of an account at a specific date.
-fn calculate_balance_at_date(
-    parse_result: &ParseResult,
-    account: &str,
-    date: Option<chrono::NaiveDate>,
-) -> HashMap<String, Decimal> {
-    let mut balances: HashMap<String, Decimal> = HashMap::new();
-
-    for spanned in &parse_result.directives {
-        if let Directive::Transaction(txn) = &spanned.value {
-            // Only include transactions before the balance date
-            if let Some(d) = date {
-                if txn.date >= d {
-                    continue;
-                }
-            }
-
-            for posting in &txn.postings {
-                if posting.account.as_ref() == account {
-                    if let Some(units) = &posting.units {
-                        if let Some(number) = units.number() {
-                            let currency = units.currency().unwrap_or("???").to_string();
-                            *balances.entry(currency).or_default() += number;
-                        }
-                    }
-                }
-            }
+/// Title an open directive's lens with its transaction count and currencies.
+fn resolve_open(open: &Open, parse_result: &ParseResult) -> Command {
+    let account = open.account.to_string();
+    let txn_count = account_transaction_count(parse_result, &account);
+    let currencies: Vec<String> = open.currencies.iter().map(|c| c.to_string()).collect();
+
+    let title = if txn_count > 0 {
+        if currencies.is_empty() {
+            format!("{} transactions", txn_count)
+        } else {
+            format!("{} transactions | {}", txn_count, currencies.join(", "))
         }
+    } else if !currencies.is_empty() {
+        currencies.join(", ")
+    } else {
+        "No transactions".to_string()
+    };
+
+    Command {
+        title,
+        command: "rledger.showAccountDetails".to_string(),
+        arguments: Some(vec![serde_json::json!(account)]),
+    }
+}
+
+/// Title a transaction's lens with its posting count and currencies.
+fn resolve_transaction(txn: &Transaction) -> Command {
+    let posting_count = txn.postings.len();
+    let currencies: Vec<String> = txn
+        .postings
+        .iter()
+        .filter_map(|p| {
+            p.units
+                .as_ref()
+                .and_then(|u| u.currency().map(String::from))
+        })
+        .collect::<std::collections::HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let title = if currencies.is_empty() {
+        format!("{} postings", posting_count)
+    } else {
+        format!("{} postings | {}", posting_count, currencies.join(", "))
+    };
+
+    Command {
+        title,
+        command: "rledger.showTransactionDetails".to_string(),
+        arguments: None,
     }
+}
 
-    balances
+/// Find the open directive for the account stored in a lens's `data`.
+fn stored_open<'a>(data: &serde_json::Value, parse_result: &'a ParseResult) -> Option<&'a Open> {
+    let account = data.get("account").and_then(|v| v.as_str())?;
+    parse_result
+        .directives
+        .iter()
+        .find_map(|spanned| match &spanned.value {
+            Directive::Open(open) if open.account.as_str() == account => Some(open),
+            _ => None,
+        })
 }
 
-/// Statistics for an account.
-#[derive(Default)]
-struct AccountStats {
-    transaction_count: usize,
+/// Rebuild a balance assertion from the copy stored in a lens's `data`.
+fn stored_balance(data: &serde_json::Value) -> Option<Balance> {
+    let field = |key: &str| data.get(key).and_then(|v| v.as_str());
+    let date = chrono::NaiveDate::parse_from_str(field("date")?, "%Y-%m-%d").ok()?;
+    let amount = field("expected_amount")?.parse::<Decimal>().ok()?;
+    Some(Balance::new(
+        date,
+        field("account")?,
+        rustledger_core::Amount::new(amount, field("expected_currency")?),
+    ))
 }
 
-/// Collect statistics about account usage.
-fn collect_account_stats(parse_result: &ParseResult) -> HashMap<String, AccountStats> {
-    let mut stats: HashMap<String, AccountStats> = HashMap::new();
+/// Verify a balance assertion against the account's balance at its date.
+fn resolve_balance(bal: &Balance, parse_result: &ParseResult) -> Command {
+    // Calculate actual balance up to this date
+    let actual_balance =
+        calculate_balance_at_date(parse_result, bal.account.as_str(), Some(bal.date));
+    let actual_amount = actual_balance
+        .get(bal.amount.currency.as_str())
+        .copied()
+        .unwrap_or_default();
+
+    balance_command(bal, actual_amount)
+}
 
-    for spanned in &parse_result.directives {
-        if let Directive::Transaction(txn) = &spanned.value {
-            for posting in &txn.postings {
-                let account = posting.account.to_string();
-                stats.entry(account).or_default().transaction_count += 1;
-            }
+/// Title a balance assertion's lens with how far the computed balance is
+/// from the asserted one, e.g. `✓ matches` or `off by -3.00 USD`.
+fn balance_command(bal: &Balance, actual_amount: Decimal) -> Command {
+    let account = bal.account.as_str();
+    let expected_amount = bal.amount.number;
+    let expected_currency = bal.amount.currency.as_str();
+
+    let (title, status) = if actual_amount == expected_amount {
+        ("✓ matches".to_string(), "verified")
+    } else {
+        let diff = actual_amount - expected_amount;
+        (format!("off by {} {}", diff, expected_currency), "mismatch")
+    };
+
+    Command {
+        title,
+        command: "rledger.showBalanceDetails".to_string(),
+        arguments: Some(vec![serde_json::json!({
+            "account": account,
+            "status": status,
+            "expected": format!("{} {}", expected_amount, expected_currency),
+            "actual": format!("{} {}", actual_amount, expected_currency),
+        })]),
+    }
+}
+
+/// Calculate the balance of an account at a specific date.
+///
+/// Only transactions strictly before `date` count, matching balance
+/// assertion semantics. Pads are resolved by [`balances`].
+fn calculate_balance_at_date(
+    parse_result: &ParseResult,
+    account: &str,
+    date: Option<chrono::NaiveDate>,
+) -> HashMap<String, Decimal> {
+    let as_of = date.and_then(|d| d.pred_opt());
+    let mut totals: HashMap<String, Decimal> = HashMap::new();
+
+    if let Some(inventory) = balances(parse_result, as_of).get(account) {
+        for position in inventory.positions() {
+            *totals
+                .entry(position.units.currency.to_string())
+                .or_default() += position.units.number;
         }
     }
 
-    stats
+    totals
 }
 
 #[cfg(test)]
@@ -258,10 +286,12 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 2024-01-16 * "Lunch"
-  Assets:Bank  -10.00 USD
+  Assets:Bank  -6.00 USD
+  Assets:Bank  -4.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = CodeLensParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -269,23 +299,22 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let lenses = handle_code_lens(&params, source, &result);
+        let lenses = handle_code_lens(&params, &snapshot);
         assert!(lenses.is_some());
 
         let lenses = lenses.unwrap();
         // Should have: 1 open + 2 transactions = 3 lenses
         assert_eq!(lenses.len(), 3);
 
-        // First lens is for the open directive
-        assert!(
-            lenses[0]
-                .command
-                .as_ref()
-                .unwrap()
-                .title
-                .contains("2 transactions")
-        );
+        // Titles are left for resolve
+        assert!(lenses.iter().all(|lens| lens.command.is_none()));
+
+        // First lens is for the open directive; the split lunch counts once
+        let open = handle_code_lens_resolve(lenses[0].clone(), &snapshot);
+        assert_eq!(open.command.unwrap().title, "2 transactions | USD");
+
+        let txn = handle_code_lens_resolve(lenses[1].clone(), &snapshot);
+        assert_eq!(txn.command.unwrap().title, "2 postings | USD");
     }
 
     #[test]
@@ -293,7 +322,8 @@ mod tests {
         let source = r#"2024-01-01 open Assets:Bank USD
 2024-01-31 balance Assets:Bank 100 USD
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = CodeLensParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -301,8 +331,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let lenses = handle_code_lens(&params, source, &result);
+        let lenses = handle_code_lens(&params, &snapshot);
         assert!(lenses.is_some());
 
         let lenses = lenses.unwrap();
@@ -315,11 +344,51 @@ mod tests {
                 == Some("balance")
         });
         assert!(balance_lens.is_some());
-        assert!(balance_lens.unwrap().command.is_none());
+        // Balance lenses are titled up front; nothing was deposited
+        let command = balance_lens.unwrap().command.as_ref().unwrap();
+        assert_eq!(command.title, "off by -100 USD");
     }
 
     #[test]
-    fn test_code_lens_resolve_balance_match() {
+    fn test_code_lens_balance_shows_difference() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Deposit"
+  Assets:Bank  97.00 USD
+  Income:Salary
+2024-01-31 balance Assets:Bank 100.00 USD
+2024-02-10 * "Deposit"
+  Assets:Bank  3.00 USD
+  Income:Salary
+2024-02-28 balance Assets:Bank 100.00 USD
+"#;
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let params = CodeLensParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let lenses = handle_code_lens(&params, &snapshot).unwrap();
+        let titles: Vec<&str> = lenses
+            .iter()
+            .filter_map(|lens| lens.command.as_ref())
+            .map(|command| command.title.as_str())
+            .collect();
+        assert_eq!(titles, ["off by -3.00 USD", "✓ matches"]);
+
+        let mismatch = lenses
+            .iter()
+            .find_map(|lens| lens.command.as_ref())
+            .unwrap();
+        let details = &mismatch.arguments.as_ref().unwrap()[0];
+        assert_eq!(details["status"], "mismatch");
+        assert_eq!(details["actual"], "97.00 USD");
+    }
+
+    #[test]
+    fn test_code_lens_directive_id_round_trips() {
         let source = r#"2024-01-01 open Assets:Bank USD
 2024-01-15 * "Deposit"
   Assets:Bank  100.00 USD
@@ -327,6 +396,115 @@ mod tests {
 2024-01-31 balance Assets:Bank 100 USD
 "#;
         let result = parse(source);
+        let params = CodeLensParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let lenses = handle_code_lens(&params, &snapshot).unwrap();
+        let balance_lens = lenses.into_iter().nth(2).unwrap();
+
+        // The client hands the lens back as JSON
+        let json = serde_json::to_string(&balance_lens).unwrap();
+        let returned: CodeLens = serde_json::from_str(&json).unwrap();
+
+        let id = DirectiveId::from_data(returned.data.as_ref().unwrap()).unwrap();
+        assert_eq!(id, DirectiveId(2));
+        assert!(matches!(
+            id.get(&result).map(|s| &s.value),
+            Some(Directive::Balance(bal)) if bal.account.as_str() == "Assets:Bank"
+        ));
+
+        let resolved = handle_code_lens_resolve(returned, &snapshot);
+        assert!(resolved.command.unwrap().title.contains("✓ matches"));
+    }
+
+    #[test]
+    fn test_code_lens_resolve_ignores_stale_directive_id() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+2024-01-31 balance Assets:Bank 100 USD
+"#;
+        let params = CodeLensParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let balance_lens = handle_code_lens(&params, &snapshot)
+            .unwrap()
+            .into_iter()
+            .nth(2)
+            .unwrap();
+
+        // An edit inserts another balance assertion where the lens's id points
+        let edited = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+2024-01-20 balance Assets:Bank 50 USD
+2024-01-31 balance Assets:Bank 100 USD
+"#;
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), edited);
+        let resolved = handle_code_lens_resolve(balance_lens, &snapshot);
+        let command = resolved.command.unwrap();
+        assert!(command.title.contains("✓ matches"), "{}", command.title);
+    }
+
+    #[test]
+    fn test_code_lens_resolve_ignores_stale_transaction_id() {
+        let source = r#"2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+"#;
+        let params = CodeLensParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let lens = handle_code_lens(&params, &snapshot)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+
+        // An edit inserts another transaction where the lens's id points
+        let edited = r#"2024-01-10 * "Transfer"
+  Assets:Bank  10.00 EUR
+  Assets:Cash
+2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+"#;
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), edited);
+        let resolved = handle_code_lens_resolve(lens.clone(), &snapshot);
+        assert!(resolved.command.is_none());
+
+        // Unedited, the lens resolves
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        assert!(handle_code_lens_resolve(lens, &snapshot).command.is_some());
+    }
+
+    #[test]
+    fn test_code_lens_resolve_balance_match() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+2024-01-31 balance Assets:Bank 100 USD
+"#;
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
 
         // Create a code lens like what handle_code_lens would return
         let lens = CodeLens {
@@ -344,12 +522,11 @@ mod tests {
             })),
         };
 
-        let resolved = handle_code_lens_resolve(lens, &result);
+        let resolved = handle_code_lens_resolve(lens, &snapshot);
         assert!(resolved.command.is_some());
 
         let cmd = resolved.command.unwrap();
-        assert!(cmd.title.contains("✓")); // Should show checkmark for match
-        assert!(cmd.title.contains("100"));
+        assert_eq!(cmd.title, "✓ matches");
     }
 
     #[test]
@@ -360,7 +537,7 @@ mod tests {
   Income:Salary
 2024-01-31 balance Assets:Bank 100 USD
 "#;
-        let result = parse(source);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
 
         let lens = CodeLens {
             range: Range {
@@ -377,11 +554,48 @@ mod tests {
             })),
         };
 
-        let resolved = handle_code_lens_resolve(lens, &result);
+        let resolved = handle_code_lens_resolve(lens, &snapshot);
         assert!(resolved.command.is_some());
 
         let cmd = resolved.command.unwrap();
-        assert!(cmd.title.contains("✗")); // Should show X for mismatch
-        assert!(cmd.title.contains("diff"));
+        assert_eq!(cmd.title, "off by -50.00 USD");
+    }
+
+    #[test]
+    fn test_code_lens_resolve_balance_with_pad() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-01 open Equity:Opening-Balances
+2024-01-15 * "Deposit"
+  Assets:Bank  40.00 USD
+  Income:Salary
+2024-01-20 pad Assets:Bank Equity:Opening-Balances
+2024-01-31 balance Assets:Bank 100 USD
+"#;
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+
+        let lens = CodeLens {
+            range: Range {
+                start: Position::new(6, 0),
+                end: Position::new(6, 0),
+            },
+            command: None,
+            data: Some(serde_json::json!({
+                "kind": "balance",
+                "account": "Assets:Bank",
+                "date": "2024-01-31",
+                "expected_amount": "100",
+                "expected_currency": "USD",
+            })),
+        };
+
+        let resolved = handle_code_lens_resolve(lens, &snapshot);
+        let cmd = resolved.command.unwrap();
+        assert!(cmd.title.contains("✓")); // Padding makes the assertion pass
+
+        // The difference is taken from the pad's source account
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 31);
+        let source_balance =
+            calculate_balance_at_date(&snapshot.parse_result, "Equity:Opening-Balances", date);
+        assert_eq!(source_balance.get("USD"), Some(&Decimal::new(-60, 0)));
     }
 }
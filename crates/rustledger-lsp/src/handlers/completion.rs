@@ -1,16 +1,34 @@
 //! Completion handler for autocompletion.
 //!
 //! Provides context-aware completions for:
-//! - Account names (after posting indentation or in directives)
+//! - Account names (after posting indentation or in directives), with
+//!   accounts that usually pair with the first posting ranked first
+//! - Account aliases from `custom "alias"` directives, expanded to the full account
 //! - Currencies (after amounts)
+//! - Cost currencies and lot dates (inside `{...}` cost annotations)
 //! - Directives (after dates)
 //! - Payees and narrations (in transaction headers)
+//! - Metadata keys (on indented lines, ranked by usage)
+//! - `custom` directive names used elsewhere in the file
+//! - Booking methods in `open` directives
+//! - `balance` amounts, prefilled with the account's computed balance
+//! - `include` paths, from Beancount files and directories on disk
+//! - `document` paths, from files on disk and the account's documents directory
 
+use chrono::{Datelike, Months};
 use lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse, Position,
+    CompletionItem, CompletionItemKind, CompletionItemTag, CompletionParams, CompletionResponse,
+    CompletionTextEdit, InsertTextFormat, Position, Range, TextEdit, Uri,
 };
-use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
+use rustledger_core::{Account, Directive, NaiveDate};
+use rustledger_parser::{ParseResult, Spanned};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::diagnostics::{document_base_dir, resolve_include, resolve_path};
+use super::utils::{BOOKING_METHODS, account_aliases, position_to_offset, uri_to_path};
+use crate::report::balances;
+use crate::snapshot::WorldSnapshot;
 
 /// Standard Beancount account types.
 const ACCOUNT_TYPES: &[&str] = &["Assets", "Liabilities", "Equity", "Income", "Expenses"];
@@ -52,6 +70,39 @@ pub enum CompletionContext {
     },
     /// After an amount (expecting currency)
     ExpectingCurrency,
+    /// Inside a `{...}` cost annotation (expecting cost currency or lot date)
+    InsideCost,
+    /// After `custom` (expecting a custom directive name)
+    CustomName {
+        /// The name typed so far, including its opening quote
+        typed: String,
+    },
+    /// Inside the quoted booking method of an `open` directive
+    BookingMethod {
+        /// The method typed so far, including its opening quote
+        typed: String,
+    },
+    /// After the account of a `balance` directive (expecting the amount)
+    BalanceAmount {
+        /// The directive's date
+        date: NaiveDate,
+        /// The account being asserted
+        account: String,
+        /// The amount typed so far
+        typed: String,
+    },
+    /// Inside the path of an `include` directive
+    IncludePath {
+        /// The path typed so far, after the opening quote
+        typed: String,
+    },
+    /// Inside the path of a `document` directive
+    DocumentPath {
+        /// The document's account
+        account: String,
+        /// The path typed so far, after the opening quote
+        typed: String,
+    },
     /// Inside a string (payee/narration)
     InsideString,
     /// Unknown context
@@ -59,33 +110,75 @@ pub enum CompletionContext {
 }
 
 /// Handle a completion request.
+///
+/// The snapshot's workspace documents supply names used across the ledger.
 pub fn handle_completion(
     params: &CompletionParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
+) -> Option<CompletionResponse> {
+    let today = chrono::Local::now().date_naive();
+    handle_completion_at(params, snapshot, today)
+}
+
+/// Handle a completion request, resolving relative dates against `today`.
+pub fn handle_completion_at(
+    params: &CompletionParams,
+    snapshot: &WorldSnapshot,
+    today: NaiveDate,
 ) -> Option<CompletionResponse> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let position = params.text_document_position.position;
     let uri = &params.text_document_position.text_document.uri;
     let context = detect_context(source, position);
+    let account_prefix = match &context {
+        CompletionContext::AccountSegment { prefix } => prefix.clone(),
+        _ => String::new(),
+    };
 
     tracing::debug!("Completion context: {:?} at {:?}", context, position);
 
     let mut items = match context {
-        CompletionContext::LineStart => complete_line_start(),
+        CompletionContext::LineStart => complete_line_start(source, position, parse_result, today),
         CompletionContext::AfterDate => complete_after_date(),
-        CompletionContext::ExpectingAccount => complete_account_start(parse_result),
+        CompletionContext::ExpectingAccount => {
+            let closed = closed_accounts_at(snapshot, position);
+            let mut items = complete_account_start(parse_result, &closed);
+            rank_counterpart_accounts(&mut items, source, position, parse_result);
+            items.extend(complete_account_aliases(source, position, parse_result));
+            if is_metadata_key_position(source, position) {
+                items.extend(complete_metadata_key(snapshot, position));
+            }
+            items
+        }
         CompletionContext::AccountSegment { prefix } => {
-            complete_account_segment(&prefix, parse_result)
+            let closed = closed_accounts_at(snapshot, position);
+            complete_account_segment(&prefix, parse_result, &closed)
         }
         CompletionContext::ExpectingCurrency => complete_currency(parse_result),
+        CompletionContext::InsideCost => complete_cost(snapshot, position, today),
+        CompletionContext::CustomName { typed } => {
+            let documents =
+                std::iter::once(parse_result).chain(snapshot.workspace.iter().map(|p| &**p));
+            complete_custom_name(&typed, position, documents)
+        }
+        CompletionContext::BookingMethod { typed } => complete_booking_method(&typed, position),
+        CompletionContext::BalanceAmount {
+            date,
+            account,
+            typed,
+        } => complete_balance_amount(date, &account, &typed, position, parse_result),
+        CompletionContext::IncludePath { typed } => complete_include_path(&typed, position, uri),
+        CompletionContext::DocumentPath { account, typed } => {
+            complete_document_path(&account, &typed, position, snapshot)
+        }
         CompletionContext::InsideString => complete_payee(parse_result),
         CompletionContext::Unknown => return None,
     };
 
-    // Add URI to each item's data for resolve
-    let uri_data = serde_json::json!({ "uri": uri.as_str() });
+    // Documentation is filled in lazily by completionItem/resolve
     for item in &mut items {
-        item.data = Some(uri_data.clone());
+        item.data = Some(resolve_data(uri, item, &account_prefix));
     }
 
     if items.is_empty() {
@@ -95,17 +188,34 @@ pub fn handle_completion(
     }
 }
 
+/// Data stored on a completion item for `completionItem/resolve`.
+///
+/// Account, currency and payee items name what they complete so resolve
+/// can document it; `account_prefix` is the account typed before a
+/// segment item.
+fn resolve_data(uri: &Uri, item: &CompletionItem, account_prefix: &str) -> serde_json::Value {
+    let mut data = serde_json::json!({ "uri": uri.as_str() });
+    match item.kind {
+        Some(CompletionItemKind::VARIABLE) => {
+            data["kind"] = "account".into();
+            data["account"] = format!("{account_prefix}{}", item.label).into();
+        }
+        Some(CompletionItemKind::UNIT) => {
+            data["kind"] = "currency".into();
+            data["currency"] = item.label.clone().into();
+        }
+        Some(CompletionItemKind::TEXT) => {
+            data["kind"] = "payee".into();
+            data["payee"] = item.label.clone().into();
+        }
+        _ => {}
+    }
+    data
+}
+
 /// Detect the completion context from cursor position.
 fn detect_context(source: &str, position: Position) -> CompletionContext {
-    let line = get_line(source, position.line as usize);
-
-    // Get text before cursor
-    let col = position.character as usize;
-    let before_cursor = if col <= line.len() {
-        &line[..col]
-    } else {
-        line
-    };
+    let before_cursor = line_before_cursor(source, position);
 
     let trimmed = before_cursor.trim_start();
 
@@ -120,6 +230,13 @@ fn detect_context(source: &str, position: Position) -> CompletionContext {
         // Inside a posting - could be account or amount
         let posting_content = trimmed;
 
+        // Inside an unclosed `{` cost annotation
+        if let Some(brace_pos) = posting_content.rfind('{') {
+            if !posting_content[brace_pos..].contains('}') {
+                return CompletionContext::InsideCost;
+            }
+        }
+
         // Check if there's already an account (contains colon and space after)
         if posting_content.contains(':') && posting_content.contains(' ') {
             // After account, might be expecting amount or currency
@@ -152,6 +269,30 @@ fn detect_context(source: &str, position: Position) -> CompletionContext {
         return CompletionContext::LineStart;
     }
 
+    // The path of an `include`, up to its closing quote
+    if let Some(rest) = before_cursor.strip_prefix("include") {
+        if let Some(typed) = rest.trim_start().strip_prefix('"') {
+            if !typed.contains('"') {
+                return CompletionContext::IncludePath {
+                    typed: typed.to_string(),
+                };
+            }
+        }
+    }
+
+    // The path of a `document`, up to its closing quote
+    if let Some((account, typed)) = document_path_prefix(before_cursor) {
+        return CompletionContext::DocumentPath {
+            account: account.to_string(),
+            typed: typed.to_string(),
+        };
+    }
+
+    // A partial date or `txn` at line start can still become a new entry
+    if is_entry_start_prefix(before_cursor) {
+        return CompletionContext::LineStart;
+    }
+
     // Check for date at line start (YYYY-MM-DD pattern)
     if trimmed.len() >= 10 && is_date_like(&trimmed[..10]) {
         let after_date = trimmed[10..].trim_start();
@@ -159,6 +300,42 @@ fn detect_context(source: &str, position: Position) -> CompletionContext {
             return CompletionContext::AfterDate;
         }
 
+        // The name of a custom directive, up to its closing quote
+        if let Some(rest) = after_date.strip_prefix("custom ") {
+            let typed = rest.trim_start();
+            if typed.is_empty() || (typed.starts_with('"') && typed.matches('"').count() == 1) {
+                return CompletionContext::CustomName {
+                    typed: typed.to_string(),
+                };
+            }
+        }
+
+        // The booking method of an `open`, up to its closing quote
+        if let Some(rest) = after_date.strip_prefix("open ") {
+            if rest.matches('"').count() == 1 {
+                let typed = &rest[rest.find('"').unwrap_or_default()..];
+                return CompletionContext::BookingMethod {
+                    typed: typed.to_string(),
+                };
+            }
+        }
+
+        // The amount of a `balance`, once its account is typed
+        if let Some(rest) = after_date.strip_prefix("balance ") {
+            let rest = rest.trim_start();
+            if let Some((account, after_account)) = rest.split_once(char::is_whitespace) {
+                let typed = after_account.trim_start();
+                let date = NaiveDate::parse_from_str(&trimmed[..10], "%Y-%m-%d");
+                if let (Ok(date), true) = (date, typed.chars().all(is_number_char)) {
+                    return CompletionContext::BalanceAmount {
+                        date,
+                        account: account.to_string(),
+                        typed: typed.to_string(),
+                    };
+                }
+            }
+        }
+
         // Check for directive keywords
         for directive in DIRECTIVES {
             if let Some(rest) = after_date.strip_prefix(directive) {
@@ -193,6 +370,15 @@ fn detect_context(source: &str, position: Position) -> CompletionContext {
     CompletionContext::Unknown
 }
 
+/// The text of the cursor's line up to the cursor.
+///
+/// The cursor column counts UTF-16 code units, so it is converted to a byte
+/// offset rather than used to slice the line.
+fn line_before_cursor(source: &str, position: Position) -> &str {
+    let line_start = position_to_offset(source, Position::new(position.line, 0));
+    &source[line_start..position_to_offset(source, position)]
+}
+
 /// Get a specific line from source.
 fn get_line(source: &str, line_num: usize) -> &str {
     source.lines().nth(line_num).unwrap_or("")
@@ -215,16 +401,124 @@ fn is_date_like(s: &str) -> bool {
         })
 }
 
-/// Complete at line start (date template).
-fn complete_line_start() -> Vec<CompletionItem> {
-    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
-    vec![CompletionItem {
-        label: today.clone(),
-        kind: Some(CompletionItemKind::VALUE),
-        detail: Some("Today's date".to_string()),
-        insert_text: Some(format!("{} ", today)),
+/// Check if text typed at line start is a partial date, date keyword, or `txn`.
+fn is_entry_start_prefix(s: &str) -> bool {
+    if s.is_empty() {
+        return false;
+    }
+    ["txn", "today", "yesterday"]
+        .iter()
+        .any(|keyword| keyword.starts_with(s))
+        || (s.len() < 10
+            && s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_digit() || c == '-'))
+}
+
+/// Complete at line start (dates and transaction skeleton).
+fn complete_line_start(
+    source: &str,
+    position: Position,
+    parse_result: &ParseResult,
+    today: NaiveDate,
+) -> Vec<CompletionItem> {
+    let typed = line_before_cursor(source, position);
+
+    let mut items = complete_dates(typed, position, parse_result, today);
+    items.push(transaction_snippet(
+        &today.format("%Y-%m-%d").to_string(),
+        typed,
+        position,
+    ));
+    items
+}
+
+/// Complete relative date keywords, each inserting a literal date.
+fn complete_dates(
+    typed: &str,
+    position: Position,
+    parse_result: &ParseResult,
+    today: NaiveDate,
+) -> Vec<CompletionItem> {
+    let month_start = today.with_day(1).unwrap_or(today);
+    let month_end = month_start
+        .checked_add_months(Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .unwrap_or(today);
+
+    let mut dates = vec![
+        ("today", today),
+        ("yesterday", today.pred_opt().unwrap_or(today)),
+        ("start of month", month_start),
+        ("end of month", month_end),
+    ];
+    if let Some(last_used) = parse_result.directives.iter().map(|d| d.value.date()).max() {
+        dates.push(("last used", last_used));
+    }
+
+    let range = typed_range(typed, position);
+    dates
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (keyword, date))| {
+            let date = date.format("%Y-%m-%d").to_string();
+            CompletionItem {
+                label: keyword.to_string(),
+                kind: Some(CompletionItemKind::VALUE),
+                detail: Some(date.clone()),
+                // Typing digits filters on the date itself, letters on the keyword
+                filter_text: Some(if typed.starts_with(|c: char| c.is_ascii_digit()) {
+                    date.clone()
+                } else {
+                    keyword.to_string()
+                }),
+                sort_text: Some(format!("{rank}")),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: format!("{date} "),
+                })),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// The range covering what was typed before the cursor on this line.
+fn typed_range(typed: &str, position: Position) -> Range {
+    Range {
+        start: Position::new(
+            position.line,
+            position
+                .character
+                .saturating_sub(typed.encode_utf16().count() as u32),
+        ),
+        end: position,
+    }
+}
+
+/// Build the transaction skeleton snippet, replacing what was typed so far.
+fn transaction_snippet(today: &str, typed: &str, position: Position) -> CompletionItem {
+    let snippet = format!(
+        "${{1:{today}}} * \"${{2:payee}}\" \"${{3:narration}}\"\n  ${{4:Account}}  ${{5:amount}}\n  ${{6:Account}}"
+    );
+    let range = typed_range(typed, position);
+
+    CompletionItem {
+        label: "txn".to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: Some("Transaction skeleton".to_string()),
+        // Match whatever was typed (a partial date or `txn`)
+        filter_text: Some(if typed.is_empty() {
+            "txn".to_string()
+        } else {
+            typed.to_string()
+        }),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: snippet,
+        })),
         ..Default::default()
-    }]
+    }
 }
 
 /// Complete after a date (directive keywords).
@@ -260,7 +554,13 @@ fn complete_after_date() -> Vec<CompletionItem> {
 }
 
 /// Complete account name start (account types).
-fn complete_account_start(parse_result: &ParseResult) -> Vec<CompletionItem> {
+///
+/// Accounts in `closed` are still offered, but tagged as deprecated and
+/// sorted after open accounts.
+fn complete_account_start(
+    parse_result: &ParseResult,
+    closed: &HashMap<String, NaiveDate>,
+) -> Vec<CompletionItem> {
     // First, offer standard account types
     let mut items: Vec<CompletionItem> = ACCOUNT_TYPES
         .iter()
@@ -275,19 +575,61 @@ fn complete_account_start(parse_result: &ParseResult) -> Vec<CompletionItem> {
     // Also offer known accounts from the file
     let known_accounts = extract_accounts(parse_result);
     for account in known_accounts.iter().take(20) {
-        items.push(CompletionItem {
+        let mut item = CompletionItem {
             label: account.clone(),
             kind: Some(CompletionItemKind::VARIABLE),
             detail: Some("Known account".to_string()),
             ..Default::default()
-        });
+        };
+        if let Some(close_date) = closed.get(account) {
+            mark_closed(&mut item, *close_date);
+        }
+        items.push(item);
     }
 
     items
 }
 
+/// Complete account aliases, replacing the alias typed so far with the
+/// account it stands for.
+fn complete_account_aliases(
+    source: &str,
+    position: Position,
+    parse_result: &ParseResult,
+) -> Vec<CompletionItem> {
+    let typed = line_before_cursor(source, position)
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default();
+
+    let mut aliases: Vec<_> = account_aliases(parse_result).into_iter().collect();
+    aliases.sort_unstable();
+
+    aliases
+        .into_iter()
+        .map(|(alias, account)| CompletionItem {
+            label: alias.to_string(),
+            kind: Some(CompletionItemKind::REFERENCE),
+            detail: Some(format!("Alias for {account}")),
+            filter_text: Some(alias.to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: typed_range(typed, position),
+                new_text: account.to_string(),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
 /// Complete account segment after colon.
-fn complete_account_segment(prefix: &str, parse_result: &ParseResult) -> Vec<CompletionItem> {
+///
+/// A segment is tagged as deprecated when every account it leads to is
+/// in `closed`.
+fn complete_account_segment(
+    prefix: &str,
+    parse_result: &ParseResult,
+    closed: &HashMap<String, NaiveDate>,
+) -> Vec<CompletionItem> {
     let known_accounts = extract_accounts(parse_result);
 
     // Find accounts that start with this prefix
@@ -321,7 +663,21 @@ fn complete_account_segment(prefix: &str, parse_result: &ParseResult) -> Vec<Com
             let has_more = matching
                 .iter()
                 .any(|a| a.starts_with(&format!("{}:", full)));
-            CompletionItem {
+            let nested = format!("{full}:");
+            let reachable: Vec<&&String> = matching
+                .iter()
+                .filter(|a| ***a == full || a.starts_with(&nested))
+                .collect();
+            let latest_close = if reachable.iter().all(|a| closed.contains_key(a.as_str())) {
+                reachable
+                    .iter()
+                    .filter_map(|a| closed.get(a.as_str()))
+                    .max()
+                    .copied()
+            } else {
+                None
+            };
+            let mut item = CompletionItem {
                 label: seg.clone(),
                 kind: Some(if has_more {
                     CompletionItemKind::FOLDER
@@ -335,7 +691,148 @@ fn complete_account_segment(prefix: &str, parse_result: &ParseResult) -> Vec<Com
                 }),
                 insert_text: Some(if has_more { format!("{}:", seg) } else { seg }),
                 ..Default::default()
+            };
+            if let Some(close_date) = latest_close {
+                mark_closed(&mut item, close_date);
+            }
+            item
+        })
+        .collect()
+}
+
+/// Rank accounts that historically pair with the transaction's first
+/// posting ahead of other account completions.
+///
+/// Only applies when the cursor is on the second posting, i.e. exactly one
+/// posting account has been written above it.
+fn rank_counterpart_accounts(
+    items: &mut Vec<CompletionItem>,
+    source: &str,
+    position: Position,
+    parse_result: &ParseResult,
+) {
+    let preceding = preceding_posting_accounts(source, position);
+    let [first] = preceding.as_slice() else {
+        return;
+    };
+    let counterparts = account_cooccurrences(parse_result)
+        .remove(first.as_str())
+        .unwrap_or_default();
+
+    let mut ranked: Vec<(String, usize)> = counterparts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    for (rank, (account, count)) in ranked.into_iter().enumerate() {
+        let index = match items.iter().position(|i| i.label == account) {
+            Some(index) => index,
+            None => {
+                items.push(CompletionItem {
+                    label: account.clone(),
+                    kind: Some(CompletionItemKind::VARIABLE),
+                    ..Default::default()
+                });
+                items.len() - 1
+            }
+        };
+        let item = &mut items[index];
+        if item.tags.is_some() {
+            // Closed accounts stay at the bottom
+            continue;
+        }
+        item.detail = Some(format!("Paired with {first} ({})", times(count)));
+        item.sort_text = Some(format!("0{rank:04}"));
+    }
+}
+
+/// Describe how often something was used, such as "once" or "3 times".
+fn times(count: usize) -> String {
+    if count == 1 {
+        "once".to_string()
+    } else {
+        format!("{count} times")
+    }
+}
+
+/// Accounts posted to on the lines above the cursor in the current entry.
+fn preceding_posting_accounts(source: &str, position: Position) -> Vec<String> {
+    let mut accounts: Vec<String> = source
+        .lines()
+        .take(position.line as usize)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .take_while(|line| line.starts_with(' ') || line.starts_with('\t'))
+        .filter_map(|line| {
+            let word = line.split_whitespace().next()?;
+            let is_account = word.contains(':')
+                && !word.ends_with(':')
+                && word.chars().next().is_some_and(|c| c.is_ascii_uppercase());
+            is_account.then(|| word.to_string())
+        })
+        .collect();
+    accounts.reverse();
+    accounts
+}
+
+/// Count how often each pair of accounts appears together in a
+/// two-account transaction, keyed by either account of the pair.
+fn account_cooccurrences(parse_result: &ParseResult) -> HashMap<&str, HashMap<String, usize>> {
+    let mut counts: HashMap<&str, HashMap<String, usize>> = HashMap::new();
+    for spanned in &parse_result.directives {
+        let Directive::Transaction(txn) = &spanned.value else {
+            continue;
+        };
+        let mut accounts: Vec<&str> = txn.postings.iter().map(|p| p.account.as_str()).collect();
+        accounts.sort_unstable();
+        accounts.dedup();
+        if let [a, b] = accounts[..] {
+            *counts
+                .entry(a)
+                .or_default()
+                .entry(b.to_string())
+                .or_default() += 1;
+            *counts
+                .entry(b)
+                .or_default()
+                .entry(a.to_string())
+                .or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Tag a completion item for an account closed on `close_date`.
+fn mark_closed(item: &mut CompletionItem, close_date: NaiveDate) {
+    item.tags = Some(vec![CompletionItemTag::DEPRECATED]);
+    item.detail = Some(format!("Closed account (closed {close_date})"));
+    item.sort_text = Some(format!("~{}", item.label));
+}
+
+/// Find accounts already closed at the cursor, with their close dates.
+///
+/// The reference date is the date on the cursor's line if it starts a
+/// directive, otherwise the date of the directive the cursor is under.
+/// Accounts closed before that date are returned; without a date, nothing is.
+fn closed_accounts_at(snapshot: &WorldSnapshot, position: Position) -> HashMap<String, NaiveDate> {
+    let line = get_line(&snapshot.text, position.line as usize);
+    let line_date = line
+        .get(..10)
+        .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok());
+    let date = line_date
+        .or_else(|| enclosing_directive(snapshot, position).map(|spanned| spanned.value.date()));
+    let Some(date) = date else {
+        return HashMap::new();
+    };
+
+    snapshot
+        .parse_result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Close(close) if close.date < date => {
+                Some((close.account.to_string(), close.date))
             }
+            _ => None,
         })
         .collect()
 }
@@ -355,6 +852,33 @@ fn complete_currency(parse_result: &ParseResult) -> Vec<CompletionItem> {
         .collect()
 }
 
+/// Complete inside a cost annotation: cost currencies and a lot date.
+///
+/// The lot date placeholder defaults to the date of the transaction being
+/// edited, or `today` outside of one.
+fn complete_cost(
+    snapshot: &WorldSnapshot,
+    position: Position,
+    today: NaiveDate,
+) -> Vec<CompletionItem> {
+    let mut items = complete_currency(&snapshot.parse_result);
+
+    let date =
+        enclosing_directive(snapshot, position).map_or(today, |spanned| spanned.value.date());
+    items.push(CompletionItem {
+        label: "lot date".to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        detail: Some("Acquisition date of the lot".to_string()),
+        insert_text: Some(format!("${{1:{}}}", date.format("%Y-%m-%d"))),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        // After the currencies
+        sort_text: Some("~lot date".to_string()),
+        ..Default::default()
+    });
+
+    items
+}
+
 /// Complete payee/narration inside string.
 fn complete_payee(parse_result: &ParseResult) -> Vec<CompletionItem> {
     let payees = extract_payees(parse_result);
@@ -371,6 +895,343 @@ fn complete_payee(parse_result: &ParseResult) -> Vec<CompletionItem> {
         .collect()
 }
 
+/// Complete a `custom` directive name from those already used in
+/// `documents`, most used first.
+fn complete_custom_name<'a>(
+    typed: &str,
+    position: Position,
+    documents: impl Iterator<Item = &'a ParseResult>,
+) -> Vec<CompletionItem> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for spanned in documents.flat_map(|document| &document.directives) {
+        if let Directive::Custom(custom) = &spanned.value {
+            *counts.entry(custom.custom_type.as_str()).or_default() += 1;
+        }
+    }
+
+    let mut names: Vec<_> = counts.into_iter().collect();
+    names.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    let range = typed_range(typed, position);
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (name, _))| {
+            let quoted = format!("\"{}\"", name);
+            CompletionItem {
+                label: quoted.clone(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                detail: Some("Custom directive".to_string()),
+                sort_text: Some(format!("{:04}", rank)),
+                filter_text: Some(quoted.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: quoted,
+                })),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Complete the booking method of an `open` directive.
+///
+/// The method typed so far, opening quote included, is replaced.
+fn complete_booking_method(typed: &str, position: Position) -> Vec<CompletionItem> {
+    let range = typed_range(typed, position);
+    BOOKING_METHODS
+        .iter()
+        .enumerate()
+        .map(|(rank, (name, description))| {
+            let quoted = format!("\"{}\"", name);
+            CompletionItem {
+                label: quoted.clone(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                detail: Some(description.to_string()),
+                sort_text: Some(format!("{:04}", rank)),
+                filter_text: Some(quoted.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: quoted,
+                })),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Whether a character can appear in a number being typed.
+fn is_number_char(c: char) -> bool {
+    c.is_ascii_digit() || matches!(c, '.' | ',' | '-')
+}
+
+/// Complete the amount of a `balance` directive with the account's balance
+/// in each currency it holds, as of the start of `date`.
+///
+/// The number is a snippet placeholder, so the computed balance is accepted
+/// with one keystroke or typed over. The amount typed so far is replaced.
+fn complete_balance_amount(
+    date: NaiveDate,
+    account: &str,
+    typed: &str,
+    position: Position,
+    parse_result: &ParseResult,
+) -> Vec<CompletionItem> {
+    // Balance assertions check the balance before any of the day's entries
+    let balances = balances(parse_result, date.pred_opt());
+    let Some(inventory) = balances.get(account) else {
+        return Vec::new();
+    };
+
+    let range = typed_range(typed, position);
+    let mut currencies = inventory.currencies();
+    currencies.sort_unstable();
+    currencies.dedup();
+    currencies
+        .into_iter()
+        .filter_map(|currency| {
+            let number = inventory.units(currency);
+            if number.is_zero() {
+                return None;
+            }
+            Some(CompletionItem {
+                label: format!("{} {}", number, currency),
+                kind: Some(CompletionItemKind::SNIPPET),
+                detail: Some(format!("Balance of {} on {}", account, date)),
+                filter_text: Some(number.to_string()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: format!("${{1:{}}} {}", number, currency),
+                })),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// File extensions offered when completing `include` paths.
+const BEANCOUNT_EXTENSIONS: &[&str] = &["beancount", "bean"];
+
+/// Complete an `include` path with the Beancount files and directories in
+/// the directory typed so far, relative to the current file.
+///
+/// Only the last path component is replaced. Hidden entries are offered
+/// once a `.` has been typed.
+fn complete_include_path(typed: &str, position: Position, uri: &Uri) -> Vec<CompletionItem> {
+    let (dir_part, name_part) = split_typed_path(typed);
+    let dir = uri_to_path(uri).and_then(|current| resolve_include(&current, dir_part));
+    complete_path_entries(dir.as_deref(), name_part, position, |name| {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| BEANCOUNT_EXTENSIONS.contains(&ext))
+    })
+}
+
+/// The account and path typed so far when the cursor is inside the quoted
+/// path of a `document` directive.
+fn document_path_prefix(before_cursor: &str) -> Option<(&str, &str)> {
+    if !before_cursor.get(..10).is_some_and(is_date_like) {
+        return None;
+    }
+    let rest = before_cursor[10..].trim_start().strip_prefix("document ")?;
+    let (account, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    let typed = rest.trim_start().strip_prefix('"')?;
+    (!typed.contains('"')).then_some((account, typed))
+}
+
+/// Complete a `document` path with the files and directories in the
+/// directory typed so far, relative to the document base directory.
+///
+/// Before a directory is typed, the account's directory under each
+/// `option "documents"` root is offered too, if it exists.
+fn complete_document_path(
+    account: &str,
+    typed: &str,
+    position: Position,
+    snapshot: &WorldSnapshot,
+) -> Vec<CompletionItem> {
+    let Some(file) = uri_to_path(&snapshot.uri) else {
+        return Vec::new();
+    };
+    let Some(base_dir) = document_base_dir(&file, snapshot.config.document_base.as_deref()) else {
+        return Vec::new();
+    };
+    let (dir_part, name_part) = split_typed_path(typed);
+    let dir = resolve_path(&base_dir, dir_part);
+    let mut items = complete_path_entries(dir.as_deref(), name_part, position, |_| true);
+
+    if dir_part.is_empty() {
+        let account_dir = Account::new(account)
+            .components()
+            .collect::<Vec<_>>()
+            .join("/");
+        for (_, root, _) in snapshot
+            .parse_result
+            .options
+            .iter()
+            .filter(|(key, _, _)| key == "documents")
+        {
+            let Some(dir) = resolve_include(&file, &format!("{root}/{account_dir}")) else {
+                continue;
+            };
+            if !dir.is_dir() {
+                continue;
+            }
+            let new_text = match dir.strip_prefix(&base_dir) {
+                Ok(relative) => format!("{}/", relative.display()),
+                Err(_) => format!("{}/", dir.display()),
+            };
+            if !new_text.starts_with(typed) {
+                continue;
+            }
+            items.insert(
+                0,
+                CompletionItem {
+                    label: new_text.clone(),
+                    kind: Some(CompletionItemKind::FOLDER),
+                    detail: Some(format!("Documents for {account}")),
+                    sort_text: Some(format!("0{new_text}")),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range: typed_range(typed, position),
+                        new_text,
+                    })),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+    items
+}
+
+/// Split a typed path into its directory part, with a trailing `/`, and
+/// the name being typed.
+fn split_typed_path(typed: &str) -> (&str, &str) {
+    typed
+        .rsplit_once('/')
+        .map_or(("", typed), |(dir, name)| (&typed[..dir.len() + 1], name))
+}
+
+/// Complete the last component of a path with the entries of `dir`.
+///
+/// Directories are always offered; files only if `keep_file` accepts
+/// their name. Hidden entries are offered once a `.` has been typed.
+fn complete_path_entries(
+    dir: Option<&Path>,
+    name_part: &str,
+    position: Position,
+    keep_file: impl Fn(&str) -> bool,
+) -> Vec<CompletionItem> {
+    let Some(Ok(entries)) = dir.map(std::fs::read_dir) else {
+        return Vec::new();
+    };
+
+    let range = typed_range(name_part, position);
+    let mut items: Vec<CompletionItem> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().to_str()?.to_string();
+            if name.starts_with('.') && !name_part.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.file_type().ok()?.is_dir();
+            if !is_dir && !keep_file(&name) {
+                return None;
+            }
+
+            let new_text = if is_dir { format!("{name}/") } else { name };
+            Some(CompletionItem {
+                label: new_text.clone(),
+                kind: Some(if is_dir {
+                    CompletionItemKind::FOLDER
+                } else {
+                    CompletionItemKind::FILE
+                }),
+                // Files first, then directories
+                sort_text: Some(format!("{}{}", u8::from(is_dir), new_text)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+                ..Default::default()
+            })
+        })
+        .collect();
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    items
+}
+
+/// Check if the cursor is on an indented line where a metadata key could start.
+fn is_metadata_key_position(source: &str, position: Position) -> bool {
+    let before_cursor = line_before_cursor(source, position);
+    let typed = before_cursor.trim_start();
+    before_cursor.len() > typed.len()
+        && typed
+            .chars()
+            .next()
+            .map_or(true, |c| c.is_ascii_lowercase())
+        && typed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Complete metadata keys seen on directives of the same type.
+///
+/// Keys are ranked by how often they are used. For transactions, keys from
+/// posting metadata are included as well.
+fn complete_metadata_key(snapshot: &WorldSnapshot, position: Position) -> Vec<CompletionItem> {
+    let parse_result = &*snapshot.parse_result;
+    let Some(current) = enclosing_directive(snapshot, position) else {
+        return Vec::new();
+    };
+    let kind = std::mem::discriminant(&current.value);
+
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for spanned in &parse_result.directives {
+        if std::mem::discriminant(&spanned.value) != kind {
+            continue;
+        }
+        for key in spanned.value.meta().keys() {
+            *counts.entry(key.as_str()).or_default() += 1;
+        }
+        if let Directive::Transaction(txn) = &spanned.value {
+            for posting in &txn.postings {
+                for key in posting.meta.keys() {
+                    *counts.entry(key.as_str()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut keys: Vec<(&str, usize)> = counts.into_iter().collect();
+    keys.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    keys.into_iter()
+        .enumerate()
+        .map(|(rank, (key, count))| CompletionItem {
+            label: format!("{key}:"),
+            kind: Some(CompletionItemKind::PROPERTY),
+            detail: Some(format!("Metadata key (used {})", times(count))),
+            insert_text: Some(format!("{key}: ")),
+            sort_text: Some(format!("0{rank:04}")),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Find the directive the cursor is under: the last one starting at or above it.
+fn enclosing_directive(
+    snapshot: &WorldSnapshot,
+    position: Position,
+) -> Option<&Spanned<Directive>> {
+    snapshot
+        .parse_result
+        .directives
+        .iter()
+        .take_while(|spanned| {
+            snapshot.line_index.offset_to_position(spanned.span.start).0 <= position.line
+        })
+        .last()
+}
+
 /// Extract all account names from parse result.
 fn extract_accounts(parse_result: &ParseResult) -> Vec<String> {
     let mut accounts = Vec::new();
@@ -464,6 +1325,7 @@ fn extract_payees(parse_result: &ParseResult) -> Vec<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::handlers::utils::path_to_uri;
 
     #[test]
     fn test_is_date_like() {
@@ -506,4 +1368,514 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_complete_metadata_keys_on_indented_line() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+  institution: "Big Bank"
+2024-01-15 * "Coffee"
+  category: "food"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+2024-01-16 * "Lunch"
+  category: "food"
+  invoice: "INV-2"
+  Assets:Bank  -10.00 USD
+  Expenses:Food
+2024-01-17 * "Dinner"
+  
+"#;
+        let position = Position::new(12, 2);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::ExpectingAccount
+        );
+        assert!(is_metadata_key_position(source, position));
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let items = complete_metadata_key(&snapshot, position);
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        // Most frequently used first; keys from open directives are not offered
+        assert_eq!(labels, vec!["category:", "invoice:"]);
+    }
+
+    #[test]
+    fn test_closed_accounts_marked_deprecated() {
+        let source = r#"2023-01-01 open Assets:Bank:Old
+2023-01-01 open Assets:Bank:Checking
+2023-06-30 close Assets:Bank:Old
+2024-01-15 * "Coffee"
+  Assets:Bank:
+"#;
+        let result = rustledger_parser::parse(source);
+        let position = Position::new(4, 14);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let closed = closed_accounts_at(&snapshot, position);
+        assert_eq!(
+            closed.get("Assets:Bank:Old"),
+            NaiveDate::from_ymd_opt(2023, 6, 30).as_ref()
+        );
+
+        let items = complete_account_segment("Assets:Bank:", &result, &closed);
+        let old = items.iter().find(|i| i.label == "Old").unwrap();
+        assert_eq!(old.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+        let checking = items.iter().find(|i| i.label == "Checking").unwrap();
+        assert_eq!(checking.tags, None);
+
+        let items = complete_account_start(&result, &closed);
+        let old = items.iter().find(|i| i.label == "Assets:Bank:Old").unwrap();
+        assert_eq!(old.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+    }
+
+    #[test]
+    fn test_second_posting_ranks_paired_account_first() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Books
+2024-01-01 open Income:Salary
+2024-01-05 * "Groceries"
+  Assets:Bank  -20.00 USD
+  Expenses:Food
+2024-01-06 * "Salary"
+  Income:Salary  -100.00 USD
+  Expenses:Books
+2024-01-10 * "Groceries"
+  Assets:Bank  -15.00 USD
+  
+"#;
+        let params = CompletionParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier {
+                    uri: "file:///test.beancount".parse().unwrap(),
+                },
+                position: Position::new(12, 2),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let Some(CompletionResponse::Array(mut items)) = handle_completion(&params, &snapshot)
+        else {
+            panic!("expected completion items");
+        };
+
+        items.sort_by(|a, b| {
+            let key = |i: &CompletionItem| i.sort_text.clone().unwrap_or(i.label.clone());
+            key(a).cmp(&key(b))
+        });
+        assert_eq!(items[0].label, "Expenses:Food");
+        assert_eq!(
+            items[0].detail.as_deref(),
+            Some("Paired with Assets:Bank (once)")
+        );
+    }
+
+    #[test]
+    fn test_account_segment_resolves_documentation() {
+        use super::super::completion_resolve::handle_completion_resolve;
+        use lsp_types::Documentation;
+
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+2024-01-20 * "Coffee"
+  Expenses:Food  5.00 USD
+  Assets:
+"#;
+        let params = CompletionParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier {
+                    uri: "file:///test.beancount".parse().unwrap(),
+                },
+                position: Position::new(6, 9),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let Some(CompletionResponse::Array(items)) = handle_completion(&params, &snapshot) else {
+            panic!("expected completion items");
+        };
+
+        // The list itself carries no documentation
+        let bank = items.into_iter().find(|i| i.label == "Bank").unwrap();
+        assert!(bank.documentation.is_none());
+        let data = bank.data.clone().unwrap();
+        assert_eq!(data["kind"], "account");
+        assert_eq!(data["account"], "Assets:Bank");
+
+        let resolved = handle_completion_resolve(bank, &snapshot);
+        let Some(Documentation::MarkupContent(content)) = resolved.documentation else {
+            panic!("expected markdown documentation");
+        };
+        assert!(content.value.contains("**Assets:Bank**"));
+        assert!(content.value.contains("100.00 USD"));
+    }
+
+    #[test]
+    fn test_account_not_closed_before_its_close_date() {
+        let source = r#"2023-01-01 open Assets:Bank:Old
+2023-06-30 close Assets:Bank:Old
+2023-03-01 * "Coffee"
+  Assets:Bank:
+"#;
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let closed = closed_accounts_at(&snapshot, Position::new(3, 14));
+        assert_eq!(closed.len(), 0);
+    }
+
+    #[test]
+    fn test_complete_inside_cost() {
+        let source = r#"2024-01-01 commodity AAPL
+2024-01-01 commodity USD
+2024-01-15 * "Buy"
+  Assets:Broker  10 AAPL {
+"#;
+        let position = Position::new(3, 26);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::InsideCost
+        );
+
+        let today = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let items = complete_cost(&snapshot, position, today);
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"USD"));
+        assert!(labels.contains(&"AAPL"));
+
+        let date = items.iter().find(|i| i.label == "lot date").unwrap();
+        assert_eq!(date.insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert_eq!(date.insert_text.as_deref(), Some("${1:2024-01-15}"));
+    }
+
+    #[test]
+    fn test_closed_cost_is_not_cost_context() {
+        let position = Position::new(0, 38);
+        assert_ne!(
+            detect_context("  Assets:Broker  10 AAPL {150.00 USD} ", position),
+            CompletionContext::InsideCost
+        );
+    }
+
+    #[test]
+    fn test_complete_custom_names_from_history() {
+        let source = r#"2024-01-01 custom "budget" Expenses:Food "monthly" 500 USD
+2024-01-01 custom "fava-option" "language" "en"
+2024-02-01 custom "budget" Expenses:Rent "monthly" 900 USD
+2024-03-01 custom "#;
+        let position = Position::new(3, 18);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::CustomName {
+                typed: String::new()
+            }
+        );
+
+        let result = rustledger_parser::parse(source);
+        let items = complete_custom_name("", position, std::iter::once(&result));
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["\"budget\"", "\"fava-option\""]);
+
+        // Names used in other workspace files are offered too
+        let other = rustledger_parser::parse(
+            "2024-01-01 custom \"fava-extension\" \"fava_investor\"\n\
+             2024-01-01 custom \"fava-extension\" \"fava_dashboards\"\n",
+        );
+        let items = complete_custom_name("", position, [&result, &other].into_iter());
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec!["\"budget\"", "\"fava-extension\"", "\"fava-option\""]
+        );
+
+        // A partially typed name is replaced, opening quote included
+        let source = "2024-03-01 custom \"bu";
+        let position = Position::new(0, 21);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::CustomName {
+                typed: "\"bu".to_string()
+            }
+        );
+        let items = complete_custom_name("\"bu", position, std::iter::once(&result));
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.range.start, Position::new(0, 18));
+        assert_eq!(edit.new_text, "\"budget\"");
+    }
+
+    #[test]
+    fn test_completion_after_multibyte_characters() {
+        // The cursor column counts UTF-16 code units, not bytes
+        let source = "2024-01-01 open Assets:Bank\n  note: \"café\"\né";
+        let after_cafe = Position::new(1, 13);
+        assert!(!is_metadata_key_position(source, after_cafe));
+        assert_eq!(
+            detect_context(source, after_cafe),
+            CompletionContext::Unknown
+        );
+
+        let result = rustledger_parser::parse(source);
+        assert!(complete_account_aliases(source, after_cafe, &result).is_empty());
+
+        let after_e = Position::new(2, 1);
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let items = complete_line_start(source, after_e, &result, today);
+        let Some(CompletionTextEdit::Edit(edit)) = &items.last().unwrap().text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.range.start, Position::new(2, 0));
+    }
+
+    #[test]
+    fn test_complete_booking_method() {
+        let source = "2024-01-01 open Assets:Stocks AAPL \"FI";
+        let position = Position::new(0, 38);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::BookingMethod {
+                typed: "\"FI".to_string()
+            }
+        );
+
+        let items = complete_booking_method("\"FI", position);
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"\"FIFO\""));
+        assert!(labels.contains(&"\"STRICT\""));
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.range.start, Position::new(0, 35));
+    }
+
+    #[test]
+    fn test_complete_balance_amount() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Deposit"
+  Assets:Bank  100.00 USD
+  Income:Salary
+2024-02-10 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+2024-03-01 * "Lunch"
+  Assets:Bank  -12.00 USD
+  Expenses:Food
+2024-03-01 balance Assets:Bank "#;
+        let position = Position::new(10, 31);
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::BalanceAmount {
+                date,
+                account: "Assets:Bank".to_string(),
+                typed: String::new(),
+            }
+        );
+
+        // The day's own lunch is not part of the asserted balance
+        let result = rustledger_parser::parse(source);
+        let items = complete_balance_amount(date, "Assets:Bank", "", position, &result);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "95.00 USD");
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.new_text, "${1:95.00} USD");
+        assert_eq!(edit.range.start, position);
+    }
+
+    #[test]
+    fn test_complete_account_alias_expands_to_account() {
+        let source = r#"2024-01-01 open Expenses:Food:Groceries
+2024-01-01 custom "alias" "groc" Expenses:Food:Groceries
+2024-01-05 * "Market"
+  gr"#;
+        let position = Position::new(3, 4);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::ExpectingAccount
+        );
+
+        let result = rustledger_parser::parse(source);
+        let items = complete_account_aliases(source, position, &result);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "groc");
+        assert_eq!(
+            items[0].detail.as_deref(),
+            Some("Alias for Expenses:Food:Groceries")
+        );
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.range.start, Position::new(3, 2));
+        assert_eq!(edit.new_text, "Expenses:Food:Groceries");
+    }
+
+    #[test]
+    fn test_complete_include_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("years")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join("accounts.beancount"), "").unwrap();
+        std::fs::write(dir.join("prices.beancount"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::fs::write(dir.join("years").join("2024.beancount"), "").unwrap();
+        let uri = path_to_uri(&dir.join("main.beancount")).unwrap();
+
+        let source = "include \"";
+        let position = Position::new(0, 9);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::IncludePath {
+                typed: String::new()
+            }
+        );
+        let items = complete_include_path("", position, &uri);
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            vec!["accounts.beancount", "prices.beancount", "years/"]
+        );
+
+        // Within a subdirectory only the last component is replaced
+        let source = "include \"years/20";
+        let position = Position::new(0, 17);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::IncludePath {
+                typed: "years/20".to_string()
+            }
+        );
+        let items = complete_include_path("years/20", position, &uri);
+        assert_eq!(items.len(), 1);
+        let Some(CompletionTextEdit::Edit(edit)) = &items[0].text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.range.start, Position::new(0, 15));
+        assert_eq!(edit.new_text, "2024.beancount");
+    }
+
+    #[test]
+    fn test_complete_document_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let account_dir = dir.join("docs").join("Assets").join("Bank");
+        std::fs::create_dir_all(&account_dir).unwrap();
+        std::fs::write(dir.join("statement.pdf"), "").unwrap();
+        std::fs::write(account_dir.join("2024-01-31.statement.pdf"), "").unwrap();
+        let uri = path_to_uri(&dir.join("main.beancount")).unwrap();
+
+        let source = "option \"documents\" \"docs\"\n2024-01-31 document Assets:Bank \"";
+        let position = Position::new(1, 33);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::DocumentPath {
+                account: "Assets:Bank".to_string(),
+                typed: String::new()
+            }
+        );
+        let snapshot = WorldSnapshot::new(uri, source);
+        let items = complete_document_path("Assets:Bank", "", position, &snapshot);
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        // The account's documents directory comes first, then any file
+        assert_eq!(labels, vec!["docs/Assets/Bank/", "statement.pdf", "docs/"]);
+
+        let items = complete_document_path(
+            "Assets:Bank",
+            "docs/Assets/Bank/",
+            Position::new(1, 50),
+            &snapshot,
+        );
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["2024-01-31.statement.pdf"]);
+    }
+
+    #[test]
+    fn test_transaction_snippet_completion() {
+        let source = "txn";
+        let position = Position::new(0, 3);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::LineStart
+        );
+
+        let today = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let result = rustledger_parser::parse(source);
+        let items = complete_line_start(source, position, &result, today);
+        let snippet = items.iter().find(|i| i.label == "txn").unwrap();
+        assert_eq!(snippet.insert_text_format, Some(InsertTextFormat::SNIPPET));
+
+        let Some(CompletionTextEdit::Edit(edit)) = &snippet.text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.range.start, Position::new(0, 0));
+        assert!(edit.new_text.starts_with("${1:2024-03-09} * "));
+        for placeholder in [
+            "\"${2:payee}\"",
+            "\"${3:narration}\"",
+            "\n  ${4:Account}  ${5:amount}",
+            "\n  ${6:Account}",
+        ] {
+            assert!(edit.new_text.contains(placeholder), "missing {placeholder}");
+        }
+    }
+
+    #[test]
+    fn test_transaction_snippet_after_partial_date() {
+        let source = "2024-0";
+        let position = Position::new(0, 6);
+        assert_eq!(
+            detect_context(source, position),
+            CompletionContext::LineStart
+        );
+
+        let today = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let result = rustledger_parser::parse(source);
+        let items = complete_line_start(source, position, &result, today);
+        let snippet = items.iter().find(|i| i.label == "txn").unwrap();
+        assert_eq!(snippet.filter_text.as_deref(), Some("2024-0"));
+    }
+
+    #[test]
+    fn test_relative_date_completion_with_fixed_clock() {
+        let source = "2024-02-20 open Assets:Bank\n2024-02-25 close Assets:Bank\nto";
+        let today = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        let params = CompletionParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier {
+                    uri: "file:///test.beancount".parse().unwrap(),
+                },
+                position: Position::new(2, 2),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let Some(CompletionResponse::Array(items)) =
+            handle_completion_at(&params, &snapshot, today)
+        else {
+            panic!("expected completion items");
+        };
+        let resolved = |label: &str| {
+            let item = items.iter().find(|i| i.label == label).unwrap();
+            match &item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+                _ => panic!("expected a text edit"),
+            }
+        };
+
+        assert_eq!(resolved("today"), "2024-03-09 ");
+        assert_eq!(resolved("yesterday"), "2024-03-08 ");
+        assert_eq!(resolved("start of month"), "2024-03-01 ");
+        assert_eq!(resolved("end of month"), "2024-03-31 ");
+        assert_eq!(resolved("last used"), "2024-02-25 ");
+    }
 }
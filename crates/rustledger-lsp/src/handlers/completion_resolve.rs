@@ -11,14 +11,13 @@ use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 use std::collections::HashMap;
 
-use super::utils::{is_account_like, is_currency_like_simple};
+use super::utils::{account_transaction_count, is_account_like, is_currency_like_simple};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a completion item resolve request.
 /// This adds detailed documentation to completion items.
-pub fn handle_completion_resolve(
-    item: CompletionItem,
-    parse_result: &ParseResult,
-) -> CompletionItem {
+pub fn handle_completion_resolve(item: CompletionItem, snapshot: &WorldSnapshot) -> CompletionItem {
+    let parse_result = &*snapshot.parse_result;
     let mut resolved = item.clone();
 
     // Check what kind of completion this is based on data field
@@ -68,7 +67,7 @@ pub fn handle_completion_resolve(
 /// Resolve documentation for an account completion.
 fn resolve_account_documentation(account: &str, parse_result: &ParseResult) -> Documentation {
     let mut balances: HashMap<String, Decimal> = HashMap::new();
-    let mut transaction_count = 0;
+    let transaction_count = account_transaction_count(parse_result, account);
     let mut first_date: Option<chrono::NaiveDate> = None;
     let mut last_date: Option<chrono::NaiveDate> = None;
 
@@ -76,8 +75,6 @@ fn resolve_account_documentation(account: &str, parse_result: &ParseResult) -> D
         if let Directive::Transaction(txn) = &spanned.value {
             for posting in &txn.postings {
                 if posting.account.as_ref() == account {
-                    transaction_count += 1;
-
                     // Track dates
                     if first_date.is_none() || Some(txn.date) < first_date {
                         first_date = Some(txn.date);
@@ -239,7 +236,6 @@ fn resolve_payee_documentation(payee: &str, parse_result: &ParseResult) -> Docum
 mod tests {
     use super::super::utils::{is_account_like, is_currency_like_simple};
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_resolve_account_completion() {
@@ -251,14 +247,14 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
 
         let item = CompletionItem {
             label: "Assets:Bank".to_string(),
             ..Default::default()
         };
 
-        let resolved = handle_completion_resolve(item, &result);
+        let resolved = handle_completion_resolve(item, &snapshot);
         assert!(resolved.documentation.is_some());
 
         if let Some(Documentation::MarkupContent(content)) = resolved.documentation {
@@ -276,14 +272,14 @@ mod tests {
   Assets:Brokerage  10 AAPL
   Assets:Bank  -1500 USD
 "#;
-        let result = parse(source);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
 
         let item = CompletionItem {
             label: "AAPL".to_string(),
             ..Default::default()
         };
 
-        let resolved = handle_completion_resolve(item, &result);
+        let resolved = handle_completion_resolve(item, &snapshot);
         assert!(resolved.documentation.is_some());
 
         if let Some(Documentation::MarkupContent(content)) = resolved.documentation {
@@ -11,10 +11,10 @@ pub use super::definition::handle_goto_definition as handle_goto_declaration;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::snapshot::WorldSnapshot;
     use lsp_types::{
         GotoDefinitionParams, Position, TextDocumentIdentifier, TextDocumentPositionParams,
     };
-    use rustledger_parser::parse;
 
     #[test]
     fn test_goto_declaration_is_goto_definition() {
@@ -23,7 +23,6 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
 
         let params = GotoDefinitionParams {
@@ -35,7 +34,8 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let result = handle_goto_declaration(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let result = handle_goto_declaration(&params, &snapshot);
         assert!(result.is_some());
     }
 }
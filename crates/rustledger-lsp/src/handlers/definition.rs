@@ -2,25 +2,69 @@
 //!
 //! Provides navigation to symbol definitions:
 //! - Account → Open directive
-//! - Currency → Commodity directive
+//! - Account alias → Open directive of the aliased account
+//! - Currency → Commodity directive, `operating_currency` option, or Open directive
+//! - Posting → its account or currency, depending on which part is under the cursor
+//! - `note`/`document` account → Open directive (their text and path are not references)
+//! - Include path → Included file
 
 use lsp_types::{GotoDefinitionParams, GotoDefinitionResponse, Location, Position, Range, Uri};
 use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 
+use super::diagnostics::resolve_include;
 use super::utils::{
-    byte_offset_to_position, get_word_at_source_position, is_account_type, is_currency_like_simple,
+    account_aliases, amount_token_at, byte_offset_to_position, get_word_at_source_position,
+    is_account_type, is_currency_like_simple, line_words, path_to_uri, position_to_offset,
+    uri_to_path,
 };
+use crate::snapshot::WorldSnapshot;
+
+/// The part of a posting under the cursor.
+#[derive(Debug, PartialEq, Eq)]
+enum PostingPart<'a> {
+    Account(&'a str),
+    Number,
+    Currency(&'a str),
+}
 
 /// Handle a go-to-definition request.
 pub fn handle_goto_definition(
     params: &GotoDefinitionParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<GotoDefinitionResponse> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let position = params.text_document_position_params.position;
 
+    // Check if the cursor is on an include path
+    if let Some(location) = find_include_target(position, parse_result, source, uri) {
+        return Some(GotoDefinitionResponse::Scalar(location));
+    }
+
+    // On a posting, the parsed transaction tells us exactly what is under the cursor
+    if let Some(part) = find_posting_part(position, source, parse_result) {
+        let location = match part {
+            PostingPart::Account(account) => {
+                find_account_definition(account, parse_result, source, uri)
+            }
+            PostingPart::Currency(currency) => {
+                find_currency_definition(currency, parse_result, source, uri)
+            }
+            // A number has no definition; its currency is its type definition
+            PostingPart::Number => None,
+        };
+        return location.map(GotoDefinitionResponse::Scalar);
+    }
+
+    // Likewise for the account of a note or document, whose quoted text is not a reference
+    if let Some(account) = find_note_or_document_account(position, source, parse_result) {
+        return account
+            .and_then(|account| find_account_definition(account, parse_result, source, uri))
+            .map(GotoDefinitionResponse::Scalar);
+    }
+
     // Get the word at the cursor position
     let word = get_word_at_source_position(source, position)?;
 
@@ -33,6 +77,13 @@ pub fn handle_goto_definition(
         }
     }
 
+    // Check if it's an account alias
+    if let Some(account) = account_aliases(parse_result).get(word.as_str()) {
+        if let Some(location) = find_account_definition(account, parse_result, source, uri) {
+            return Some(GotoDefinitionResponse::Scalar(location));
+        }
+    }
+
     // Check if it's a currency
     if is_currency_like_simple(&word) {
         if let Some(location) = find_currency_definition(&word, parse_result, source, uri) {
@@ -43,6 +94,122 @@ pub fn handle_goto_definition(
     None
 }
 
+/// Find the file targeted by an include directive under the cursor.
+///
+/// The path is resolved like other include paths, relative to the current
+/// file's directory with `~` expanded. Returns None if the cursor is not on
+/// an include path or the file doesn't exist.
+fn find_include_target(
+    position: Position,
+    parse_result: &ParseResult,
+    source: &str,
+    uri: &Uri,
+) -> Option<Location> {
+    let offset = position_to_offset(source, position);
+    let path = parse_result.includes.iter().find_map(|(path, span)| {
+        let path_start = span.start + source.get(span.start..span.end)?.find(path.as_str())?;
+        (path_start..=path_start + path.len())
+            .contains(&offset)
+            .then_some(path)
+    })?;
+
+    let target = resolve_include(&uri_to_path(uri)?, path)?;
+    if !target.exists() {
+        return None;
+    }
+
+    Some(Location {
+        uri: path_to_uri(&target)?,
+        range: Range {
+            start: Position::new(0, 0),
+            end: Position::new(0, 0),
+        },
+    })
+}
+
+/// Find which part of a posting the cursor is on.
+///
+/// The posting line is matched against the postings of the parsed
+/// transaction enclosing the cursor, so a signed number such as `-5.00`
+/// is never mistaken for part of a word. Returns None when the cursor is
+/// not on a posting's account, number, or currency.
+fn find_posting_part<'a>(
+    position: Position,
+    source: &'a str,
+    parse_result: &ParseResult,
+) -> Option<PostingPart<'a>> {
+    let offset = position_to_offset(source, position);
+    let txn = parse_result
+        .directives
+        .iter()
+        .find_map(|spanned| match &spanned.value {
+            Directive::Transaction(txn)
+                if spanned.span.start <= offset && offset <= spanned.span.end =>
+            {
+                Some(txn)
+            }
+            _ => None,
+        })?;
+
+    let line_start = position_to_offset(source, Position::new(position.line, 0));
+    let line = source[line_start..].lines().next()?;
+    let col = offset - line_start;
+
+    let (account_start, account) = line_words(line).into_iter().find(|(_, word)| {
+        txn.postings
+            .iter()
+            .any(|posting| posting.account.as_str() == *word)
+    })?;
+    if (account_start..=account_start + account.len()).contains(&col) {
+        return Some(PostingPart::Account(account));
+    }
+
+    let (start, number, currency) =
+        amount_token_at(line, col).filter(|(start, _, _)| *start > account_start)?;
+    let number_end = start + number.len();
+    if col <= number_end {
+        Some(PostingPart::Number)
+    } else if col >= number_end + line[number_end..].find(currency)? {
+        Some(PostingPart::Currency(currency))
+    } else {
+        None
+    }
+}
+
+/// Find the account of a `note` or `document` directive on the cursor's line.
+///
+/// Returns `Some(None)` when the cursor is elsewhere on such a line, e.g.
+/// in the note's text or the document's path, so that account-like words
+/// there are not taken for references.
+fn find_note_or_document_account<'a>(
+    position: Position,
+    source: &'a str,
+    parse_result: &ParseResult,
+) -> Option<Option<&'a str>> {
+    let line_start = position_to_offset(source, Position::new(position.line, 0));
+    let line = source[line_start..].lines().next()?;
+    let on_line = line_start..=line_start + line.len();
+
+    let account = parse_result
+        .directives
+        .iter()
+        .filter(|spanned| on_line.contains(&spanned.span.start))
+        .find_map(|spanned| match &spanned.value {
+            Directive::Note(note) => Some(note.account.as_str()),
+            Directive::Document(doc) => Some(doc.account.as_str()),
+            _ => None,
+        })?;
+
+    let col = position_to_offset(source, position) - line_start;
+
+    Some(
+        line_words(line)
+            .into_iter()
+            .find(|(start, word)| *word == account && (*start..=start + word.len()).contains(&col))
+            .map(|(_, word)| word),
+    )
+}
+
 /// Find the definition of an account (the Open directive).
 fn find_account_definition(
     account: &str,
@@ -73,30 +240,223 @@ fn find_account_definition(
     None
 }
 
-/// Find the definition of a currency (the Commodity directive).
-fn find_currency_definition(
+/// Find the definition of a currency.
+///
+/// Prefers the Commodity directive, then falls back to an
+/// `option "operating_currency"` line, then to the first Open directive
+/// that lists the currency as a constraint.
+pub(crate) fn find_currency_definition(
     currency: &str,
     parse_result: &ParseResult,
     source: &str,
     uri: &Uri,
 ) -> Option<Location> {
-    for spanned_directive in &parse_result.directives {
-        if let Directive::Commodity(comm) = &spanned_directive.value {
-            if comm.currency.as_ref() == currency {
-                let (start_line, start_col) =
-                    byte_offset_to_position(source, spanned_directive.span.start);
-                let (end_line, end_col) =
-                    byte_offset_to_position(source, spanned_directive.span.end);
+    let commodity_span = parse_result
+        .directives
+        .iter()
+        .find_map(|spanned| match &spanned.value {
+            Directive::Commodity(comm) if comm.currency.as_ref() == currency => Some(spanned.span),
+            _ => None,
+        });
 
-                return Some(Location {
-                    uri: uri.clone(),
-                    range: Range {
-                        start: Position::new(start_line, start_col),
-                        end: Position::new(end_line, end_col),
-                    },
-                });
+    let option_span = || {
+        parse_result
+            .options
+            .iter()
+            .find(|(key, value, _)| key == "operating_currency" && value == currency)
+            .map(|(_, _, span)| *span)
+    };
+
+    let open_span = || {
+        parse_result
+            .directives
+            .iter()
+            .find_map(|spanned| match &spanned.value {
+                Directive::Open(open) if open.currencies.iter().any(|c| c.as_ref() == currency) => {
+                    Some(spanned.span)
+                }
+                _ => None,
+            })
+    };
+
+    let span = commodity_span.or_else(option_span).or_else(open_span)?;
+    let (start_line, start_col) = byte_offset_to_position(source, span.start);
+    let (end_line, end_col) = byte_offset_to_position(source, span.end);
+
+    Some(Location {
+        uri: uri.clone(),
+        range: Range {
+            start: Position::new(start_line, start_col),
+            end: Position::new(end_line, end_col),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{TextDocumentIdentifier, TextDocumentPositionParams};
+    use rustledger_parser::parse;
+
+    fn make_params(uri: &Uri, line: u32, character: u32) -> GotoDefinitionParams {
+        GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(line, character),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_goto_definition_include_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("accounts.beancount"), "").unwrap();
+
+        let source = "include \"accounts.beancount\"\n";
+        let uri = path_to_uri(&dir.join("main.beancount")).unwrap();
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let response = handle_goto_definition(&make_params(&uri, 0, 12), &snapshot);
+        let expected = path_to_uri(&dir.join("accounts.beancount")).unwrap();
+        match response {
+            Some(GotoDefinitionResponse::Scalar(location)) => {
+                assert_eq!(location.uri, expected);
+                assert_eq!(location.range.start, Position::new(0, 0));
             }
+            other => panic!("expected scalar location, got {:?}", other),
         }
     }
-    None
+
+    #[test]
+    fn test_goto_definition_include_resolves_like_links() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("ledger")).unwrap();
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(dir.join("shared/💰.beancount"), "").unwrap();
+
+        let source = "include \"../shared/./💰.beancount\"\n";
+        let uri = path_to_uri(&dir.join("ledger/main.beancount")).unwrap();
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+
+        // The end of the path, in UTF-16 columns: the emoji takes two
+        let end = 9 + "../shared/./💰.beancount".encode_utf16().count() as u32;
+        let response = handle_goto_definition(&make_params(&uri, 0, end), &snapshot);
+        let Some(GotoDefinitionResponse::Scalar(location)) = response else {
+            panic!("expected scalar location, got {:?}", response);
+        };
+        assert_eq!(
+            location.uri,
+            path_to_uri(&dir.join("shared/💰.beancount")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_goto_definition_include_missing_file() {
+        let source = "include \"does-not-exist.beancount\"\n";
+        let uri: Uri = "file:///nonexistent-dir/main.beancount".parse().unwrap();
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let response = handle_goto_definition(&make_params(&uri, 0, 12), &snapshot);
+        assert!(response.is_none());
+    }
+
+    fn definition_line(source: &str, line: u32, character: u32) -> Option<u32> {
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        match handle_goto_definition(&make_params(&uri, line, character), &snapshot) {
+            Some(GotoDefinitionResponse::Scalar(location)) => Some(location.range.start.line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_goto_definition_currency_commodity() {
+        let source = r#"option "operating_currency" "USD"
+2024-01-01 open Assets:Bank USD
+2024-01-01 commodity USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        assert_eq!(definition_line(source, 4, 22), Some(2));
+    }
+
+    #[test]
+    fn test_goto_definition_currency_operating_currency_option() {
+        let source = r#"option "operating_currency" "USD"
+2024-01-01 open Assets:Bank USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        assert_eq!(definition_line(source, 3, 22), Some(0));
+    }
+
+    #[test]
+    fn test_goto_definition_currency_open_constraint() {
+        let source = r#"2024-01-01 open Expenses:Food
+2024-01-01 open Assets:Bank USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        assert_eq!(definition_line(source, 3, 22), Some(1));
+    }
+
+    #[test]
+    fn test_goto_definition_posting_parts() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-01 commodity USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        // On the currency of `-5.00 USD`
+        assert_eq!(definition_line(source, 3, 22), Some(1));
+        // On the sign and digits of the number
+        assert_eq!(definition_line(source, 3, 15), None);
+        assert_eq!(definition_line(source, 3, 17), None);
+        // On the account
+        assert_eq!(definition_line(source, 3, 5), Some(0));
+
+        let result = parse(source);
+        assert_eq!(
+            find_posting_part(Position::new(3, 21), source, &result),
+            Some(PostingPart::Currency("USD"))
+        );
+        assert_eq!(
+            find_posting_part(Position::new(3, 16), source, &result),
+            Some(PostingPart::Number)
+        );
+    }
+
+    #[test]
+    fn test_goto_definition_account_alias() {
+        let source = r#"2024-01-01 open Expenses:Food:Groceries
+2024-01-01 custom "alias" "groc" Expenses:Food:Groceries
+2024-01-05 * "Market"
+  groc
+"#;
+        assert_eq!(definition_line(source, 3, 3), Some(0));
+        assert_eq!(definition_line(source, 1, 28), Some(0));
+    }
+
+    #[test]
+    fn test_goto_definition_note_and_document_account() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Assets:Savings
+2024-02-01 note Assets:Bank "Moved funds to Assets:Savings"
+2024-02-01 document Assets:Bank "Assets:Savings/statement.pdf"
+"#;
+        // On the account of the note and of the document
+        assert_eq!(definition_line(source, 2, 20), Some(0));
+        assert_eq!(definition_line(source, 3, 24), Some(0));
+        // An account named in the note's text or the document's path is not a reference
+        assert_eq!(definition_line(source, 2, 50), None);
+        assert_eq!(definition_line(source, 3, 40), None);
+    }
 }
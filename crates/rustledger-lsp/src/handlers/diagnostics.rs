@@ -1,9 +1,237 @@
 //! Diagnostics handler for publishing parse errors.
+//!
+//! Also provides checks that go beyond what the parser reports:
+//! - Accounts used without an `open` directive (here or in an included file)
+//! - Account components that are not capitalized (`Expenses:food`)
+//! - Currencies used without a `commodity` directive
+//! - `pad` directives with no later `balance` assertion
+//! - `!`-flagged transactions and postings awaiting review
+//! - Transactions mixing currencies with no price or cost to relate them
+//! - Balance assertions on a currency the account never held
+//! - Postings in a currency their account's `open` directive does not allow
+//! - Accounts marked `allow_negative: FALSE` whose balance goes negative
+//! - Sales at cost reducing a lot the account does not hold enough of
+//! - Well-known metadata keys holding a value of the wrong type
+//! - Metadata keys repeated on the same directive or posting
+//! - Transactions repeating an earlier one exactly, as from a double import
+//! - Amounts with more decimals than their commodity's declared `precision`
+//! - `price` directives pricing a commodity in itself
+//! - `custom "budget"` directives overlapping an earlier budget for the account
+//! - Files starting with a UTF-8 byte order mark, which is ignored
+//! - `document` directives whose file does not exist
+//! - `open` directives naming an unknown booking method
 
-use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
-use rustledger_parser::{ParseError, ParseResult};
+use chrono::{Datelike, Months, NaiveDate};
+use lsp_types::{
+    Diagnostic, DiagnosticOptions, DiagnosticRelatedInformation, DiagnosticServerCapabilities,
+    DiagnosticSeverity, Location, Position, Range, Uri,
+};
+use rustledger_booking::{calculate_residual, interpolate, merge_with_padding, process_pads};
+use rustledger_core::{
+    Account, BookingError, BookingMethod, Decimal, Directive, Inventory, MetaValue, Posting,
+    Transaction,
+};
+use rustledger_parser::{ParseError, ParseResult, Spanned};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 
-use super::utils::LineIndex;
+use super::utils::{
+    BOOKING_METHODS, LineIndex, amount_tokens, declared_precisions, is_account_type, line_words,
+    units_amount_token,
+};
+use crate::report::BookingMethods;
+use crate::vfs::parse_document;
+
+// Checks the validator also performs share its `E` codes. Checks only the
+// language server performs use `L` codes numbered by the same categories,
+// with `L0xxx` for file formatting and `L9xxx` for custom directives.
+
+/// Diagnostic code for an account used without an `open` directive.
+pub const UNDEFINED_ACCOUNT_CODE: &str = "E1001";
+
+/// Diagnostic code for a currency used without a `commodity` directive.
+pub const UNDECLARED_CURRENCY_CODE: &str = "E5001";
+
+/// Diagnostic code for a posting in a currency its account's `open`
+/// directive does not list.
+pub const CURRENCY_NOT_ALLOWED_CODE: &str = "E5002";
+
+/// Diagnostic code for a pad directive without a subsequent balance assertion.
+pub const PAD_WITHOUT_BALANCE_CODE: &str = "E2003";
+
+/// Diagnostic code for a balance assertion on a currency the account never held.
+///
+/// This is distinct from the validator's failed balance assertion (E2001),
+/// which covers accounts holding the currency in a different amount.
+pub const BALANCE_CURRENCY_NEVER_HELD_CODE: &str = "L2001";
+
+/// Diagnostic code for a transaction taking an `allow_negative: FALSE`
+/// account below zero.
+pub const NEGATIVE_BALANCE_CODE: &str = "L2002";
+
+/// Diagnostic code for a transaction whose currencies need a price or cost to balance.
+///
+/// This is a more specific form of the validator's unbalanced transaction
+/// error (E3001).
+pub const UNRELATED_CURRENCIES_CODE: &str = "L3001";
+
+/// Diagnostic code for a reduction at cost matching no lot held by the account.
+pub const NO_MATCHING_LOT_CODE: &str = "E4001";
+
+/// Diagnostic code for a reduction at cost larger than the matching lots.
+pub const INSUFFICIENT_UNITS_CODE: &str = "E4002";
+
+/// Diagnostic code for a STRICT reduction at cost matching several lots.
+pub const AMBIGUOUS_LOT_CODE: &str = "E4003";
+
+/// Diagnostic code for a `!`-flagged transaction or posting.
+pub const FLAGGED_ENTRY_CODE: &str = "L3003";
+
+/// Diagnostic code for a metadata key repeated on the same directive or posting.
+pub const DUPLICATE_METADATA_CODE: &str = "E6001";
+
+/// Diagnostic code for a transaction that repeats an earlier one.
+pub const DUPLICATE_TRANSACTION_CODE: &str = "L3002";
+
+/// Diagnostic code for a well-known metadata key with a value of the wrong type.
+pub const METADATA_TYPE_CODE: &str = "E6002";
+
+/// Diagnostic code for a posting indented with tabs or out of step with the
+/// other postings of its transaction.
+pub const INDENTATION_CODE: &str = "L0002";
+
+/// Diagnostic code for an amount with more decimals than its commodity's
+/// declared `precision`.
+pub const EXCESS_PRECISION_CODE: &str = "L5001";
+
+/// Diagnostic code for a `price` directive quoting a commodity in itself.
+pub const SELF_PRICE_CODE: &str = "L5002";
+
+/// Diagnostic code for a tab between the tokens of a directive line.
+pub const INLINE_TAB_CODE: &str = "L0003";
+
+/// Diagnostic code for an account with a component that does not start
+/// with an uppercase letter or digit, a form of invalid account name.
+pub const ACCOUNT_CAPITALIZATION_CODE: &str = "E1005";
+
+/// Diagnostic code for a `custom "budget"` overlapping an earlier budget
+/// for the same account.
+pub const BUDGET_OVERLAP_CODE: &str = "L9001";
+
+/// Diagnostic code for a `document` directive whose file does not exist,
+/// matching the validator's document not found error.
+pub const MISSING_DOCUMENT_CODE: &str = "E8001";
+
+/// Diagnostic code for an `open` directive naming an unknown booking method.
+pub const UNKNOWN_BOOKING_CODE: &str = "L4001";
+
+/// Diagnostic code for a file that starts with a UTF-8 byte order mark.
+pub const BOM_CODE: &str = "L0001";
+
+/// Get the pull diagnostics capabilities.
+///
+/// Diagnostics depend on included files, and the workspace report covers
+/// files that are not open.
+pub fn get_capabilities() -> DiagnosticServerCapabilities {
+    DiagnosticServerCapabilities::Options(DiagnosticOptions {
+        identifier: Some("rustledger".to_string()),
+        inter_file_dependencies: true,
+        workspace_diagnostics: true,
+        work_done_progress_options: Default::default(),
+    })
+}
+
+/// Whether the client pulls diagnostics, in which case they are not pushed.
+pub fn client_pulls_diagnostics(params: &lsp_types::InitializeParams) -> bool {
+    params
+        .capabilities
+        .text_document
+        .as_ref()
+        .is_some_and(|text_document| text_document.diagnostic.is_some())
+}
+
+/// Value types expected for well-known metadata keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MetaType {
+    String,
+    Number,
+    Amount,
+}
+
+impl MetaType {
+    fn matches(self, value: &MetaValue) -> bool {
+        matches!(
+            (self, value),
+            (Self::String, MetaValue::String(_))
+                | (Self::Number, MetaValue::Number(_))
+                | (Self::Amount, MetaValue::Amount(_))
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Number => "a number",
+            Self::Amount => "an amount",
+        }
+    }
+}
+
+/// Built-in schema: (directive keyword, metadata key, expected type).
+const METADATA_SCHEMA: &[(&str, &str, MetaType)] = &[
+    ("commodity", "precision", MetaType::Number),
+    ("commodity", "name", MetaType::String),
+    ("commodity", "price", MetaType::String),
+    ("open", "budget", MetaType::Amount),
+];
+
+/// Toggles for diagnostics. Opt-in checks are disabled by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiagnosticsConfig {
+    /// Report accounts used without an `open` directive.
+    pub undefined_accounts: bool,
+    /// Warn on currencies used without a `commodity` directive.
+    pub undeclared_currencies: bool,
+    /// List `!`-flagged transactions and postings as information diagnostics.
+    pub flagged_entries: bool,
+    /// Warn when well-known metadata keys hold a value of the wrong type.
+    pub metadata_types: bool,
+    /// Warn on postings indented with tabs or an inconsistent number of spaces.
+    pub indentation: bool,
+    /// Warn on tabs between the tokens of a directive line.
+    pub inline_tabs: bool,
+    /// Warn on transactions that repeat an earlier one, as from importing
+    /// the same statement twice.
+    pub duplicate_transactions: bool,
+    /// Warn on `document` directives whose file does not exist on disk.
+    pub missing_documents: bool,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            undefined_accounts: true,
+            undeclared_currencies: false,
+            flagged_entries: false,
+            metadata_types: true,
+            indentation: false,
+            inline_tabs: true,
+            duplicate_transactions: false,
+            missing_documents: true,
+        }
+    }
+}
+
+/// Directives and file paths reachable through a document's includes.
+#[derive(Debug, Default)]
+pub struct IncludedFiles {
+    /// Directives from all transitively included files.
+    pub directives: Vec<Directive>,
+    /// Paths of all transitively included files, readable or not.
+    pub paths: HashSet<PathBuf>,
+}
 
 /// Convert parse errors to LSP diagnostics.
 pub fn parse_errors_to_diagnostics(result: &ParseResult, source: &str) -> Vec<Diagnostic> {
@@ -39,9 +267,2036 @@ pub fn parse_error_to_diagnostic(error: &ParseError, line_index: &LineIndex) ->
     }
 }
 
+/// Report accounts used without an `open` directive.
+///
+/// `opened_elsewhere` holds accounts opened in included files. Every usage
+/// of an undefined account produces an error at the account name.
+pub fn undefined_account_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    opened_elsewhere: &HashSet<String>,
+) -> Vec<Diagnostic> {
+    let opened: HashSet<&str> = result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Open(open) => Some(open.account.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let line_index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for spanned in &result.directives {
+        for account in used_accounts(&spanned.value) {
+            if opened.contains(account) || opened_elsewhere.contains(account) {
+                continue;
+            }
+            let candidates = opened
+                .iter()
+                .copied()
+                .chain(opened_elsewhere.iter().map(String::as_str));
+            let suggestion = closest_match(account, candidates);
+            let range = find_word_range(
+                source,
+                spanned.span.start,
+                spanned.span.end,
+                account,
+                &line_index,
+            );
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(lsp_types::NumberOrString::String(
+                    UNDEFINED_ACCOUNT_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: match suggestion {
+                    Some(suggestion) => format!(
+                        "Account {} is used without an open directive; did you mean `{}`?",
+                        account, suggestion
+                    ),
+                    None => format!("Account {} is used without an open directive", account),
+                },
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: Some(match suggestion {
+                    Some(suggestion) => {
+                        serde_json::json!({ "account": account, "suggestion": suggestion })
+                    }
+                    None => serde_json::json!({ "account": account }),
+                }),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Maximum edit distance for a known name to be suggested as a fix for an
+/// undefined account or undeclared currency.
+///
+/// Short names such as currency codes allow a single edit; otherwise `CAD`
+/// would be two edits from `USD`.
+fn max_typo_distance(name: &str) -> usize {
+    if name.chars().count() <= 4 { 1 } else { 2 }
+}
+
+/// The candidate closest to `name` by edit distance, if within
+/// [`max_typo_distance`]. Ties go to the alphabetically first.
+fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = max_typo_distance(name);
+    candidates
+        .filter_map(|candidate| {
+            let distance = levenshtein(name, candidate);
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Accounts referenced by a directive, other than the one an `open` declares.
+pub(crate) fn used_accounts(directive: &Directive) -> Vec<&str> {
+    let mut accounts = Vec::new();
+    match directive {
+        Directive::Transaction(txn) => {
+            for posting in &txn.postings {
+                if !accounts.contains(&posting.account.as_str()) {
+                    accounts.push(posting.account.as_str());
+                }
+            }
+        }
+        Directive::Balance(bal) => accounts.push(bal.account.as_str()),
+        Directive::Pad(pad) => {
+            accounts.push(pad.account.as_str());
+            accounts.push(pad.source_account.as_str());
+        }
+        Directive::Note(note) => accounts.push(note.account.as_str()),
+        Directive::Document(doc) => accounts.push(doc.account.as_str()),
+        Directive::Close(close) => accounts.push(close.account.as_str()),
+        _ => {}
+    }
+    accounts
+}
+
+/// Warn on currencies that are used but never declared with a `commodity` directive.
+///
+/// `declared_elsewhere` holds currencies declared in included files. Each
+/// undeclared currency produces one warning at its earliest usage; the
+/// diagnostic data carries the currency and that date for the quickfix.
+pub fn undeclared_currency_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    declared_elsewhere: &HashSet<String>,
+) -> Vec<Diagnostic> {
+    let declared: HashSet<&str> = result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Commodity(comm) => Some(comm.currency.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    // Earliest usage per currency: (date, directive span)
+    let mut usages: HashMap<String, (NaiveDate, usize, usize)> = HashMap::new();
+    for spanned in &result.directives {
+        let date = spanned.value.date();
+        for currency in used_currencies(&spanned.value) {
+            if declared.contains(currency.as_str()) || declared_elsewhere.contains(&currency) {
+                continue;
+            }
+            let usage = (date, spanned.span.start, spanned.span.end);
+            usages
+                .entry(currency)
+                .and_modify(|existing| {
+                    if date < existing.0 {
+                        *existing = usage;
+                    }
+                })
+                .or_insert(usage);
+        }
+    }
+
+    let line_index = LineIndex::new(source);
+    let mut diagnostics: Vec<_> = usages
+        .into_iter()
+        .map(|(currency, (date, start, end))| {
+            let range = find_word_range(source, start, end, &currency, &line_index);
+            let candidates = declared
+                .iter()
+                .copied()
+                .chain(declared_elsewhere.iter().map(String::as_str));
+            let suggestion = closest_match(&currency, candidates);
+            let mut data = serde_json::json!({
+                "currency": currency,
+                "date": date.format("%Y-%m-%d").to_string(),
+            });
+            if let Some(suggestion) = suggestion {
+                data["suggestion"] = serde_json::json!(suggestion);
+            }
+            Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    UNDECLARED_CURRENCY_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: match suggestion {
+                    Some(suggestion) => format!(
+                        "Currency {} is used without a commodity directive; did you mean {}?",
+                        currency, suggestion
+                    ),
+                    None => format!(
+                        "Currency {} is used without a commodity directive",
+                        currency
+                    ),
+                },
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: Some(data),
+            }
+        })
+        .collect();
+
+    diagnostics.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+    diagnostics
+}
+
+/// Currencies referenced by a directive's amounts, costs, and prices.
+fn used_currencies(directive: &Directive) -> Vec<String> {
+    let mut currencies = Vec::new();
+    match directive {
+        Directive::Transaction(txn) => {
+            for posting in &txn.postings {
+                if let Some(currency) = posting.units.as_ref().and_then(|u| u.currency()) {
+                    currencies.push(currency.to_string());
+                }
+                if let Some(currency) = posting.cost.as_ref().and_then(|c| c.currency.as_ref()) {
+                    currencies.push(currency.to_string());
+                }
+                if let Some(amount) = posting.price.as_ref().and_then(|p| p.amount()) {
+                    currencies.push(amount.currency.to_string());
+                }
+            }
+        }
+        Directive::Balance(bal) => currencies.push(bal.amount.currency.to_string()),
+        Directive::Price(price) => {
+            currencies.push(price.currency.to_string());
+            currencies.push(price.amount.currency.to_string());
+        }
+        _ => {}
+    }
+    currencies
+}
+
+/// Warn on `pad` directives that have no later `balance` on the padded account.
+///
+/// A pad only takes effect when a subsequent balance assertion exists to
+/// compute the padding amount; otherwise it is a no-op. Balances in
+/// `included` directives (from included files) are taken into account.
+pub fn pad_without_balance_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    included: &[Directive],
+) -> Vec<Diagnostic> {
+    let balances: Vec<(&str, NaiveDate)> = result
+        .directives
+        .iter()
+        .map(|spanned| &spanned.value)
+        .chain(included)
+        .filter_map(|directive| match directive {
+            Directive::Balance(bal) => Some((bal.account.as_str(), bal.date)),
+            _ => None,
+        })
+        .collect();
+
+    let line_index = LineIndex::new(source);
+    result
+        .directives
+        .iter()
+        .filter_map(|spanned| {
+            let Directive::Pad(pad) = &spanned.value else {
+                return None;
+            };
+            let has_balance = balances
+                .iter()
+                .any(|(account, date)| *account == pad.account.as_str() && *date > pad.date);
+            if has_balance {
+                return None;
+            }
+
+            let (line, col) = line_index.offset_to_position(spanned.span.start);
+            let line_end = source[spanned.span.start..]
+                .find('\n')
+                .map_or(source.len(), |i| spanned.span.start + i);
+            let (_, end_col) = line_index.offset_to_position(line_end);
+            Some(Diagnostic {
+                range: Range {
+                    start: Position::new(line, col),
+                    end: Position::new(line, end_col),
+                },
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    PAD_WITHOUT_BALANCE_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "Pad for {} has no effect: pad requires a later balance assertion on the account to compute the padding amount",
+                    pad.account
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Warn on `custom "budget"` directives that start partway through the
+/// period of an earlier budget for the same account.
+///
+/// Budgets recur (`custom "budget" Expenses:Food "monthly" 500.00 USD`) and
+/// a later budget replaces the earlier one from its date. Starting on one of
+/// the earlier budget's period boundaries hands over cleanly; starting on
+/// the same date or mid-period leaves both budgets covering that period.
+/// Budgets in `included` directives are taken into account.
+pub fn budget_overlap_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    included: &[Directive],
+) -> Vec<Diagnostic> {
+    let mut budgets: Vec<(NaiveDate, &str, &str)> = included.iter().filter_map(budget_of).collect();
+
+    let line_index = LineIndex::new(source);
+    let mut local: Vec<_> = result
+        .directives
+        .iter()
+        .filter_map(|spanned| Some((budget_of(&spanned.value)?, spanned.span)))
+        .collect();
+    local.sort_by_key(|((date, _, _), span)| (*date, span.start));
+
+    let mut diagnostics = Vec::new();
+    for ((date, account, period), span) in local {
+        // The budget in effect when this one starts
+        let previous = budgets
+            .iter()
+            .filter(|(d, a, _)| *a == account && *d <= date)
+            .max_by_key(|(d, _, _)| *d);
+        if let Some(&(start, _, previous_period)) = previous {
+            if start == date || !on_period_boundary(start, previous_period, date) {
+                let (line, col) = line_index.offset_to_position(span.start);
+                let line_end = source[span.start..]
+                    .find('\n')
+                    .map_or(source.len(), |i| span.start + i);
+                let (_, end_col) = line_index.offset_to_position(line_end);
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, col),
+                        end: Position::new(line, end_col),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(lsp_types::NumberOrString::String(
+                        BUDGET_OVERLAP_CODE.to_string(),
+                    )),
+                    source: Some("rustledger".to_string()),
+                    message: format!(
+                        "Budget for {} overlaps the {} budget starting {}",
+                        account, previous_period, start
+                    ),
+                    related_information: None,
+                    tags: None,
+                    code_description: None,
+                    data: None,
+                });
+            }
+        }
+        budgets.push((date, account, period));
+    }
+    diagnostics
+}
+
+/// The date, account, and period of a `custom "budget"` directive.
+fn budget_of(directive: &Directive) -> Option<(NaiveDate, &str, &str)> {
+    let Directive::Custom(custom) = directive else {
+        return None;
+    };
+    if custom.custom_type != "budget" {
+        return None;
+    }
+    match custom.values.as_slice() {
+        [MetaValue::Account(account), MetaValue::String(period), ..] => {
+            Some((custom.date, account.as_str(), period.as_str()))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `date` starts one of the periods of a budget recurring from `start`.
+///
+/// Unknown periods are treated as always on a boundary.
+fn on_period_boundary(start: NaiveDate, period: &str, date: NaiveDate) -> bool {
+    let months = match period {
+        "daily" => return true,
+        "weekly" => return (date - start).num_days() % 7 == 0,
+        "monthly" => 1,
+        "quarterly" => 3,
+        "yearly" => 12,
+        _ => return true,
+    };
+    let elapsed = (date.year() - start.year()) * 12 + date.month() as i32 - start.month() as i32;
+    elapsed % months == 0
+        && u32::try_from(elapsed)
+            .ok()
+            .and_then(|n| start.checked_add_months(Months::new(n)))
+            == Some(date)
+}
+
+/// Warn on balance assertions for a currency the account has never held.
+///
+/// Only accounts that held some other currency before the assertion are
+/// checked, and currencies listed on the account's `open` directive are
+/// accepted. Pads and elided posting amounts are resolved first, and
+/// `included` directives (from included files) are taken into account.
+pub fn balance_currency_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    included: &[Directive],
+) -> Vec<Diagnostic> {
+    let directives: Vec<Directive> = result
+        .directives
+        .iter()
+        .map(|spanned| spanned.value.clone())
+        .chain(included.iter().cloned())
+        .collect();
+
+    let mut declared: HashMap<&str, Vec<&str>> = HashMap::new();
+    for directive in &directives {
+        if let Directive::Open(open) = directive {
+            declared
+                .entry(open.account.as_str())
+                .or_default()
+                .extend(open.currencies.iter().map(|c| c.as_str()));
+        }
+    }
+
+    // First date each account held each currency
+    let mut held: HashMap<String, HashMap<String, NaiveDate>> = HashMap::new();
+    for directive in merge_with_padding(&directives) {
+        let Directive::Transaction(txn) = directive else {
+            continue;
+        };
+        let txn = interpolate(&txn).map_or(txn, |interpolated| interpolated.transaction);
+        for posting in &txn.postings {
+            let Some(amount) = posting.amount() else {
+                continue;
+            };
+            let first = held
+                .entry(posting.account.to_string())
+                .or_default()
+                .entry(amount.currency.to_string())
+                .or_insert(txn.date);
+            *first = (*first).min(txn.date);
+        }
+    }
+
+    let line_index = LineIndex::new(source);
+    result
+        .directives
+        .iter()
+        .filter_map(|spanned| {
+            let Directive::Balance(bal) = &spanned.value else {
+                return None;
+            };
+            let currency = bal.amount.currency.as_str();
+            // Balances are checked at the start of their date
+            let held_before: Vec<&str> = held
+                .get(bal.account.as_str())?
+                .iter()
+                .filter(|(_, first)| **first < bal.date)
+                .map(|(c, _)| c.as_str())
+                .collect();
+            if held_before.is_empty()
+                || held_before.contains(&currency)
+                || declared
+                    .get(bal.account.as_str())
+                    .is_some_and(|currencies| currencies.contains(&currency))
+            {
+                return None;
+            }
+
+            let mut held_before = held_before;
+            held_before.sort_unstable();
+            Some(Diagnostic {
+                range: find_word_range(
+                    source,
+                    spanned.span.start,
+                    spanned.span.end,
+                    currency,
+                    &line_index,
+                ),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    BALANCE_CURRENCY_NEVER_HELD_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "{} has never held {} (it holds {}); is this the right currency?",
+                    bal.account,
+                    currency,
+                    held_before.join(", ")
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Report postings whose currency is not among those listed on their
+/// account's `open` directive.
+///
+/// Accounts opened without a currency list accept any currency. Only
+/// explicit posting amounts are checked; `included` directives supply
+/// `open` directives from included files.
+pub fn currency_constraint_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    included: &[Directive],
+) -> Vec<Diagnostic> {
+    let local_opens = result.directives.iter().map(|spanned| &spanned.value);
+    let mut allowed: HashMap<&str, Vec<&str>> = HashMap::new();
+    for directive in local_opens.chain(included) {
+        if let Directive::Open(open) = directive {
+            if !open.currencies.is_empty() {
+                allowed
+                    .entry(open.account.as_str())
+                    .or_default()
+                    .extend(open.currencies.iter().map(|c| c.as_str()));
+            }
+        }
+    }
+    if allowed.is_empty() {
+        return Vec::new();
+    }
+
+    let line_index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+    for spanned in &result.directives {
+        let Directive::Transaction(txn) = &spanned.value else {
+            continue;
+        };
+        let line_starts = posting_line_spans(txn, spanned, source);
+        for (posting, (line_start, line_end)) in txn.postings.iter().zip(line_starts) {
+            let Some(currencies) = allowed.get(posting.account.as_str()) else {
+                continue;
+            };
+            let Some(currency) = posting.units.as_ref().and_then(|units| units.currency()) else {
+                continue;
+            };
+            if currencies.contains(&currency) {
+                continue;
+            }
+            // Skip the account itself when locating the currency
+            let account_end = source[line_start..line_end]
+                .find(posting.account.as_str())
+                .map_or(line_start, |i| line_start + i + posting.account.len());
+            diagnostics.push(Diagnostic {
+                range: find_word_range(source, account_end, line_end, currency, &line_index),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(lsp_types::NumberOrString::String(
+                    CURRENCY_NOT_ALLOWED_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "Currency {} is not allowed in {} (opened with {})",
+                    currency,
+                    posting.account,
+                    currencies.join(", ")
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Warn on transactions that take an account below zero when its `open`
+/// directive carries `allow_negative: FALSE`.
+///
+/// Balances are run in date order across this document, its includes and
+/// any padding; only transactions in this document are reported, once per
+/// account and currency each time the balance crosses below zero.
+pub fn negative_balance_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    included: &[Directive],
+) -> Vec<Diagnostic> {
+    let directives: Vec<Directive> = result
+        .directives
+        .iter()
+        .map(|spanned| spanned.value.clone())
+        .chain(included.iter().cloned())
+        .collect();
+
+    let guarded: HashSet<&str> = directives
+        .iter()
+        .filter_map(|directive| match directive {
+            Directive::Open(open)
+                if matches!(
+                    open.meta.get("allow_negative"),
+                    Some(MetaValue::Bool(false))
+                ) =>
+            {
+                Some(open.account.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    if guarded.is_empty() {
+        return Vec::new();
+    }
+
+    // Transactions in date order, with spans for those in this document
+    let padding = process_pads(&directives).padding_transactions;
+    let mut entries: Vec<(&Transaction, Option<&Spanned<Directive>>)> = result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Transaction(txn) => Some((txn, Some(spanned))),
+            _ => None,
+        })
+        .chain(included.iter().filter_map(|directive| match directive {
+            Directive::Transaction(txn) => Some((txn, None)),
+            _ => None,
+        }))
+        .chain(padding.iter().map(|txn| (txn, None)))
+        .collect();
+    entries.sort_by_key(|(txn, _)| txn.date);
+
+    let line_index = LineIndex::new(source);
+    let mut running: HashMap<(String, String), Decimal> = HashMap::new();
+    let mut diagnostics = Vec::new();
+
+    for (txn, spanned) in entries {
+        let interpolated = interpolate(txn).map(|i| i.transaction);
+        let txn = interpolated.as_ref().unwrap_or(txn);
+
+        // Net change per guarded account and currency for the whole transaction
+        let mut changes: Vec<((String, String), Decimal)> = Vec::new();
+        for posting in &txn.postings {
+            let Some(amount) = posting.amount() else {
+                continue;
+            };
+            if !guarded.contains(posting.account.as_str()) {
+                continue;
+            }
+            let key = (posting.account.to_string(), amount.currency.to_string());
+            match changes.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, change)) => *change += amount.number,
+                None => changes.push((key, amount.number)),
+            }
+        }
+
+        for (key, change) in changes {
+            let balance = running.entry(key.clone()).or_default();
+            let was_negative = *balance < Decimal::ZERO;
+            *balance += change;
+            if was_negative || *balance >= Decimal::ZERO {
+                continue;
+            }
+            let Some(spanned) = spanned else {
+                continue;
+            };
+            let (account, currency) = key;
+            diagnostics.push(Diagnostic {
+                range: find_word_range(
+                    source,
+                    spanned.span.start,
+                    spanned.span.end,
+                    &account,
+                    &line_index,
+                ),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    NEGATIVE_BALANCE_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "{} goes negative ({} {}) but is marked `allow_negative: FALSE`",
+                    account, balance, currency
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Report sales at cost that reduce more units than the account holds.
+///
+/// Each account's lots are tracked by currency and cost (including its
+/// date and label) by running transactions in date order across this
+/// document and its includes, booked like the balance reports: with the
+/// method from the account's `open` directive, the `booking_method` option
+/// or STRICT. A negative posting with a `{}` cost is a reduction; it is
+/// reported when no held lot matches its cost, the matching lots are too
+/// small, or STRICT booking finds several matching lots. Only postings in
+/// this document are reported.
+pub fn lot_reduction_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    included: &[Directive],
+) -> Vec<Diagnostic> {
+    let local = result
+        .directives
+        .iter()
+        .map(|spanned| (&spanned.value, Some(spanned)));
+    let all = || local.clone().chain(included.iter().map(|d| (d, None)));
+    let booking = BookingMethods::new(result, all().map(|(directive, _)| directive));
+    let mut entries: Vec<(&Transaction, Option<&Spanned<Directive>>)> = all()
+        .filter_map(|(directive, spanned)| match directive {
+            Directive::Transaction(txn) => Some((txn, spanned)),
+            _ => None,
+        })
+        .collect();
+    if !entries
+        .iter()
+        .any(|(txn, _)| txn.postings.iter().any(|p| p.cost.is_some()))
+    {
+        return Vec::new();
+    }
+    entries.sort_by_key(|(txn, _)| txn.date);
+
+    let line_index = LineIndex::new(source);
+    let mut inventories: HashMap<&str, Inventory> = HashMap::new();
+    let mut diagnostics = Vec::new();
+
+    for (txn, spanned) in entries {
+        let line_spans = spanned.map(|spanned| posting_line_spans(txn, spanned, source));
+        for (index, posting) in txn.postings.iter().enumerate() {
+            let Some(units) = posting.amount() else {
+                continue;
+            };
+            let account = posting.account.as_str();
+            let inventory = inventories.entry(account).or_default();
+
+            let Some(cost) = posting.cost.as_ref() else {
+                inventory.add_amount(units);
+                continue;
+            };
+            if !units.number.is_sign_negative() {
+                inventory.add(match cost.resolve(units.number, txn.date) {
+                    Some(cost) => rustledger_core::Position::with_cost(units.clone(), cost),
+                    None => rustledger_core::Position::simple(units.clone()),
+                });
+                continue;
+            }
+
+            let method = booking.get(account);
+            let (code, message) = match inventory.reduce(units, Some(cost), method) {
+                Err(BookingError::NoMatchingLot { currency, .. }) => (
+                    NO_MATCHING_LOT_CODE,
+                    format!("No lot of {currency} held in {account} matches {cost}"),
+                ),
+                Err(BookingError::InsufficientUnits {
+                    currency,
+                    requested,
+                    available,
+                }) => (
+                    INSUFFICIENT_UNITS_CODE,
+                    format!(
+                        "Cannot reduce {requested} {currency} from {account}: only {available} held at {cost}"
+                    ),
+                ),
+                Err(BookingError::AmbiguousMatch {
+                    num_matches,
+                    currency,
+                }) => (
+                    AMBIGUOUS_LOT_CODE,
+                    format!(
+                        "{num_matches} lots of {currency} held in {account} match {cost}; STRICT booking needs a unique match"
+                    ),
+                ),
+                _ => continue,
+            };
+            // Only postings in this file, located on their own line
+            let Some(&(line_start, line_end)) = line_spans.as_ref().and_then(|s| s.get(index))
+            else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range: find_word_range(source, line_start, line_end, account, &line_index),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(lsp_types::NumberOrString::String(code.to_string())),
+                source: Some("rustledger".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Warn on well-known metadata keys whose value has the wrong type.
+///
+/// For example, a commodity's `precision` must be a number and an account's
+/// `budget` must be an amount. Unknown keys are not checked.
+pub fn metadata_type_diagnostics(result: &ParseResult, source: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+
+    for spanned in &result.directives {
+        let keyword = match &spanned.value {
+            Directive::Commodity(_) => "commodity",
+            Directive::Open(_) => "open",
+            _ => continue,
+        };
+        let meta = spanned.value.meta();
+
+        for &(_, key, expected) in METADATA_SCHEMA.iter().filter(|(k, _, _)| *k == keyword) {
+            let Some(value) = meta.get(key) else {
+                continue;
+            };
+            if expected.matches(value) {
+                continue;
+            }
+
+            let range = metadata_line_range(source, spanned.span.start, spanned.span.end, key)
+                .map_or_else(
+                    || {
+                        find_word_range(
+                            source,
+                            spanned.span.start,
+                            spanned.span.end,
+                            key,
+                            &line_index,
+                        )
+                    },
+                    |(start, end)| {
+                        let (start_line, start_col) = line_index.offset_to_position(start);
+                        let (end_line, end_col) = line_index.offset_to_position(end);
+                        Range {
+                            start: Position::new(start_line, start_col),
+                            end: Position::new(end_line, end_col),
+                        }
+                    },
+                );
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    METADATA_TYPE_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "Metadata `{}` on {} directives should be {}, found `{}`",
+                    key,
+                    keyword,
+                    expected.name(),
+                    value
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Find the byte range of the `key: value` line for a metadata key in a directive.
+fn metadata_line_range(
+    source: &str,
+    start: usize,
+    end: usize,
+    key: &str,
+) -> Option<(usize, usize)> {
+    let text = &source[start..end.min(source.len())];
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end();
+        let indent = content.len() - content.trim_start().len();
+        let is_key_line = indent > 0
+            && content[indent..]
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.starts_with(':'));
+        if is_key_line {
+            return Some((
+                start + line_start + indent,
+                start + line_start + content.len(),
+            ));
+        }
+        line_start += line.len();
+    }
+    None
+}
+
+/// Warn on transactions that look like duplicates of an earlier one.
+///
+/// Two transactions are duplicates when they share a date, payee and
+/// narration and their postings match exactly, in any order: accounts,
+/// amounts, costs and prices. A matching payee alone is not enough, so the
+/// same purchase made twice for different amounts is not reported. The later transaction is
+/// flagged, with related information pointing at the earlier one.
+pub fn duplicate_transaction_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    uri: &Uri,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+    let header_range = |start: usize| {
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let (line, col) = line_index.offset_to_position(start);
+        let (_, end_col) = line_index.offset_to_position(line_end);
+        Range::new(Position::new(line, col), Position::new(line, end_col))
+    };
+
+    // Earlier transactions by date, payee and narration
+    let mut seen: HashMap<_, Vec<(&Transaction, Range)>> = HashMap::new();
+    let mut diagnostics = Vec::new();
+    for spanned in &result.directives {
+        let Directive::Transaction(txn) = &spanned.value else {
+            continue;
+        };
+        let range = header_range(spanned.span.start);
+        let earlier = seen
+            .entry((txn.date, txn.payee.as_deref(), txn.narration.as_str()))
+            .or_default();
+        let Some(first) = earlier
+            .iter()
+            .find(|(other, _)| same_postings(&txn.postings, &other.postings))
+            .map(|(_, range)| *range)
+        else {
+            earlier.push((txn, range));
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(lsp_types::NumberOrString::String(
+                DUPLICATE_TRANSACTION_CODE.to_string(),
+            )),
+            source: Some("rustledger".to_string()),
+            message: format!(
+                "Possible duplicate of the transaction on line {}",
+                first.start.line + 1
+            ),
+            related_information: Some(vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri: uri.clone(),
+                    range: first,
+                },
+                message: "Earlier transaction with the same postings".to_string(),
+            }]),
+            tags: None,
+            code_description: None,
+            data: None,
+        });
+    }
+
+    diagnostics
+}
+
+/// Whether two lists of postings match in any order, comparing accounts,
+/// amounts, costs and prices.
+fn same_postings(a: &[Posting], b: &[Posting]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut unmatched: Vec<&Posting> = b.iter().collect();
+    a.iter().all(|posting| {
+        let found = unmatched.iter().position(|other| {
+            posting.account == other.account
+                && posting.units == other.units
+                && posting.cost == other.cost
+                && posting.price == other.price
+        });
+        found.map(|i| unmatched.swap_remove(i)).is_some()
+    })
+}
+
+/// Report metadata keys that appear twice on the same directive or posting.
+///
+/// Metadata is parsed into a map, so duplicates are found by scanning the
+/// directive's lines: a key line belongs to the posting above it when it is
+/// indented deeper than that posting, and to the directive otherwise. The
+/// second occurrence is flagged, with related information pointing at the
+/// first.
+pub fn duplicate_metadata_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    uri: &Uri,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+
+    for spanned in &result.directives {
+        let (first_line, _) = line_index.offset_to_position(spanned.span.start);
+        let text = &source[spanned.span.start..spanned.span.end.min(source.len())];
+
+        let mut directive_keys: HashMap<&str, Range> = HashMap::new();
+        // Indentation of the current posting and the keys seen under it
+        let mut posting: Option<(usize, HashMap<&str, Range>)> = None;
+
+        for (n, line) in text.lines().enumerate().skip(1) {
+            let body = line.trim_start();
+            let indent = line.len() - body.len();
+            if body.is_empty() || body.starts_with(';') {
+                continue;
+            }
+
+            let Some(key) = metadata_key(body) else {
+                if PostingLine::parse(line).is_some() {
+                    posting = Some((indent, HashMap::new()));
+                }
+                continue;
+            };
+
+            let line_num = first_line + n as u32;
+            let col = line[..indent].encode_utf16().count() as u32;
+            let range = Range {
+                start: Position::new(line_num, col),
+                end: Position::new(line_num, col + key.len() as u32),
+            };
+            let keys = match &mut posting {
+                Some((posting_indent, keys)) if indent > *posting_indent => keys,
+                _ => &mut directive_keys,
+            };
+            let Some(first) = keys.get(key) else {
+                keys.insert(key, range);
+                continue;
+            };
+
+            diagnostics.push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(lsp_types::NumberOrString::String(
+                    DUPLICATE_METADATA_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!("Duplicate metadata key `{key}`"),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: *first,
+                    },
+                    message: format!("`{key}` first set here"),
+                }]),
+                tags: None,
+                code_description: None,
+                data: None,
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// The key of a `key: value` metadata line, given the line without its
+/// indentation.
+fn metadata_key(body: &str) -> Option<&str> {
+    let (key, _) = body.split_once(':')?;
+    let mut chars = key.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(key)
+}
+
+/// Warn on posting units and balance amounts written with more decimal
+/// places than the `precision` declared on their commodity.
+///
+/// Prices and costs are not checked: they may need more precision than the
+/// commodity is displayed with, and rounding them changes weights.
+///
+/// Each diagnostic covers the number and carries it rounded to the declared
+/// precision as `replacement` in its data.
+pub fn excess_precision_diagnostics(result: &ParseResult, source: &str) -> Vec<Diagnostic> {
+    let precisions = declared_precisions(result);
+    if precisions.is_empty() {
+        return Vec::new();
+    }
+
+    let mut diagnostics = Vec::new();
+    for (line_num, line) in source.lines().enumerate() {
+        if let Some((start, number, currency)) = units_amount_token(line) {
+            let Some(&precision) = precisions.get(currency) else {
+                continue;
+            };
+            let decimals = number.split_once('.').map_or(0, |(_, f)| f.len());
+            if decimals <= precision as usize {
+                continue;
+            }
+            let Some(rounded) = round_to_precision(number, precision) else {
+                continue;
+            };
+
+            let col = line[..start].encode_utf16().count() as u32;
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line_num as u32, col),
+                    end: Position::new(line_num as u32, col + number.len() as u32),
+                },
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    EXCESS_PRECISION_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "{number} {currency} has {decimals} decimal places; {currency} declares a precision of {precision}"
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: Some(serde_json::json!({ "replacement": rounded })),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Warn on `document` directives whose file does not exist.
+///
+/// Relative paths are resolved against `base_dir`. Each diagnostic covers
+/// the path as written.
+pub fn missing_document_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    base_dir: &Path,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+
+    result
+        .directives
+        .iter()
+        .filter_map(|spanned| {
+            let Directive::Document(doc) = &spanned.value else {
+                return None;
+            };
+            let path = resolve_path(base_dir, &doc.path)?;
+            if path.exists() {
+                return None;
+            }
+
+            let range = find_word_range(
+                source,
+                spanned.span.start,
+                spanned.span.end,
+                &doc.path,
+                &line_index,
+            );
+
+            Some(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    MISSING_DOCUMENT_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!("Document file not found: {}", path.display()),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Note that a document started with a byte order mark.
+///
+/// The mark is skipped when parsing, so this is informational: it
+/// flags files that other tools may not read the same way.
+pub fn bom_diagnostic() -> Diagnostic {
+    Diagnostic {
+        range: Range::default(),
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(lsp_types::NumberOrString::String(BOM_CODE.to_string())),
+        source: Some("rustledger".to_string()),
+        message: "File starts with a UTF-8 byte order mark, which is ignored".to_string(),
+        related_information: None,
+        tags: None,
+        code_description: None,
+        data: None,
+    }
+}
+
+/// Warn on `price` directives whose commodity is also the quote currency,
+/// such as `price USD 1.00 USD`.
+///
+/// Each diagnostic covers the directive's amount.
+pub fn self_price_diagnostics(result: &ParseResult, source: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+
+    result
+        .directives
+        .iter()
+        .filter_map(|spanned| {
+            let Directive::Price(price) = &spanned.value else {
+                return None;
+            };
+            if price.currency != price.amount.currency {
+                return None;
+            }
+
+            let (line_num, _) = line_index.offset_to_position(spanned.span.start);
+            let line = source.lines().nth(line_num as usize)?;
+            let range = match amount_tokens(line).first() {
+                Some(&(start, number, currency)) => {
+                    let number_end = start + number.len();
+                    let end = line[number_end..]
+                        .find(currency)
+                        .map_or(number_end, |i| number_end + i + currency.len());
+                    Range {
+                        start: Position::new(line_num, line[..start].encode_utf16().count() as u32),
+                        end: Position::new(line_num, line[..end].encode_utf16().count() as u32),
+                    }
+                }
+                None => find_word_range(
+                    source,
+                    spanned.span.start,
+                    spanned.span.end,
+                    &price.currency,
+                    &line_index,
+                ),
+            };
+
+            Some(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    SELF_PRICE_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!("{} is priced in itself", price.currency),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Warn on `open` directives whose booking method is not one Beancount
+/// knows, such as `"FIFOO"`.
+///
+/// Each diagnostic covers the quoted method. Such accounts are booked with
+/// the default method instead.
+pub fn unknown_booking_diagnostics(result: &ParseResult, source: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+
+    result
+        .directives
+        .iter()
+        .filter_map(|spanned| {
+            let Directive::Open(open) = &spanned.value else {
+                return None;
+            };
+            let booking = open.booking.as_deref()?;
+            if booking.parse::<BookingMethod>().is_ok() {
+                return None;
+            }
+
+            let quoted = format!("\"{}\"", booking);
+            let range = match source[spanned.span.start..spanned.span.end].find(&quoted) {
+                Some(i) => {
+                    let start = spanned.span.start + i;
+                    let (start_line, start_col) = line_index.offset_to_position(start);
+                    let (end_line, end_col) = line_index.offset_to_position(start + quoted.len());
+                    Range {
+                        start: Position::new(start_line, start_col),
+                        end: Position::new(end_line, end_col),
+                    }
+                }
+                None => find_word_range(
+                    source,
+                    spanned.span.start,
+                    spanned.span.end,
+                    &open.account,
+                    &line_index,
+                ),
+            };
+            let known: Vec<&str> = BOOKING_METHODS.iter().map(|(name, _)| *name).collect();
+
+            Some(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    UNKNOWN_BOOKING_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "Unknown booking method \"{}\"; expected one of {}",
+                    booking,
+                    known.join(", ")
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// Report account names with a component starting with a lowercase letter.
+///
+/// Beancount requires each component to start with an uppercase letter or a
+/// digit, so `expenses:food` and `Expenses:food` are both invalid. Words
+/// whose first component is a root account type in any case are checked;
+/// strings and comments are skipped. The capitalized account is carried in
+/// `data` as the quickfix suggestion.
+pub fn account_capitalization_diagnostics(source: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (line_num, line) in source.lines().enumerate() {
+        for (start, word) in line_words(line) {
+            let Some(fixed) = capitalize_account(word) else {
+                continue;
+            };
+
+            let col = line[..start].encode_utf16().count() as u32;
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line_num as u32, col),
+                    end: Position::new(line_num as u32, col + word.encode_utf16().count() as u32),
+                },
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(lsp_types::NumberOrString::String(
+                    ACCOUNT_CAPITALIZATION_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "Account components must start with an uppercase letter or digit; did you mean `{fixed}`?"
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: Some(serde_json::json!({ "suggestion": fixed })),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Capitalize the components of an account-like word, or `None` if the word
+/// is not an account or is already capitalized.
+fn capitalize_account(word: &str) -> Option<String> {
+    let account = Account::new(word);
+    let components: Vec<&str> = account.components().collect();
+    let is_account_like = components.len() >= 2
+        && components
+            .iter()
+            .all(|c| !c.is_empty() && c.chars().all(|ch| ch.is_alphanumeric() || ch == '-'));
+    if !is_account_like {
+        return None;
+    }
+
+    let capitalized: Vec<String> = components
+        .iter()
+        .map(|component| {
+            let mut chars = component.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+    let fixed = capitalized.join(":");
+    (is_account_type(&capitalized[0]) && fixed != word).then_some(fixed)
+}
+
+/// Round a plain decimal number to `precision` places, half-to-even, and
+/// format it with exactly that many decimals.
+pub fn round_to_precision(number: &str, precision: u32) -> Option<String> {
+    let mut rounded = number.parse::<Decimal>().ok()?.round_dp(precision);
+    rounded.rescale(precision);
+    Some(rounded.to_string())
+}
+
+/// Report transactions that mix currencies without any price or cost.
+///
+/// When every posting has an explicit amount, none carries a `@` price or
+/// `{}` cost, and the per-currency sums are not all zero, the transaction
+/// can never balance. Transactions with an elided amount are skipped since
+/// interpolation fills in one posting per currency.
+///
+/// The data carries postings to `conversions_account` that net each
+/// currency to zero, and the position after the last posting to insert
+/// them at.
+pub fn unrelated_currencies_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    conversions_account: &str,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+
+    result
+        .directives
+        .iter()
+        .filter_map(|spanned| {
+            let Directive::Transaction(txn) = &spanned.value else {
+                return None;
+            };
+            if txn
+                .postings
+                .iter()
+                .any(|p| p.amount().is_none() || p.cost.is_some() || p.price.is_some())
+            {
+                return None;
+            }
+
+            let sums = transaction_residuals(txn);
+            if sums.len() < 2 || sums.iter().all(|(_, sum)| sum.is_zero()) {
+                return None;
+            }
+
+            let currencies: Vec<&str> = sums.iter().map(|(c, _)| c.as_str()).collect();
+            let (line, col) = line_index.offset_to_position(spanned.span.start);
+            let line_end = source[spanned.span.start..]
+                .find('\n')
+                .map_or(source.len(), |i| spanned.span.start + i);
+            let (_, end_col) = line_index.offset_to_position(line_end);
+
+            // Conversion postings, indented like the line after the header
+            let text = &source[spanned.span.start..spanned.span.end.min(source.len())];
+            let indent = text
+                .lines()
+                .nth(1)
+                .map(|posting| &posting[..posting.len() - posting.trim_start().len()])
+                .filter(|indent| !indent.is_empty())
+                .unwrap_or("  ");
+            let insertion: String = sums
+                .iter()
+                .filter(|(_, sum)| !sum.is_zero())
+                .map(|(currency, sum)| format!("\n{indent}{conversions_account}  {} {currency}", -sum))
+                .collect();
+            let (insert_line, insert_col) =
+                line_index.offset_to_position(spanned.span.start + text.trim_end().len());
+
+            Some(Diagnostic {
+                range: Range {
+                    start: Position::new(line, col),
+                    end: Position::new(line, end_col),
+                },
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(lsp_types::NumberOrString::String(
+                    UNRELATED_CURRENCIES_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message: format!(
+                    "Postings in {} cannot balance: add a `@` price or `{{}}` cost to convert between them",
+                    currencies.join(" and ")
+                ),
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: Some(serde_json::json!({
+                    "account": conversions_account,
+                    "insertion": insertion,
+                    "position": Position::new(insert_line, insert_col),
+                })),
+            })
+        })
+        .collect()
+}
+
+/// Compute the per-currency residual (sum of posting weights) of a transaction.
+///
+/// Postings with a cost or price are weighed in the cost or price currency.
+/// Currencies are listed in the order postings first mention them.
+pub fn transaction_residuals(txn: &Transaction) -> Vec<(String, Decimal)> {
+    let mut residuals: Vec<(String, Decimal)> = calculate_residual(txn)
+        .into_iter()
+        .map(|(currency, residual)| (currency.to_string(), residual))
+        .collect();
+    let first_mention = |currency: &str| {
+        txn.postings
+            .iter()
+            .position(|p| {
+                let units = p.units.as_ref().and_then(|u| u.currency());
+                let cost = p.cost.as_ref().and_then(|c| c.currency.as_deref());
+                units == Some(currency) || cost == Some(currency)
+            })
+            .unwrap_or(usize::MAX)
+    };
+    residuals.sort_by(|a, b| {
+        first_mention(&a.0)
+            .cmp(&first_mention(&b.0))
+            .then_with(|| a.0.cmp(&b.0))
+    });
+    residuals
+}
+
+/// Report `!`-flagged transactions and postings as a review queue.
+///
+/// Each flag produces one information diagnostic whose range covers the
+/// `!` character itself.
+pub fn flagged_entry_diagnostics(result: &ParseResult, source: &str) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+
+    for spanned in &result.directives {
+        let Directive::Transaction(txn) = &spanned.value else {
+            continue;
+        };
+        let text = &source[spanned.span.start..spanned.span.end.min(source.len())];
+        let mut lines = text.split_inclusive('\n');
+        let mut line_start = spanned.span.start;
+
+        // Transaction flag: the first `!` on the header line
+        if let Some(header) = lines.next() {
+            if txn.flag == '!' {
+                if let Some(col) = header.find('!') {
+                    let message = match &txn.payee {
+                        Some(payee) => format!("Flagged transaction: {} {}", payee, txn.narration),
+                        None => format!("Flagged transaction: {}", txn.narration),
+                    };
+                    diagnostics.push(flag_diagnostic(line_start + col, &line_index, message));
+                }
+            }
+            line_start += header.len();
+        }
+
+        // Posting flags: a leading `!` before the account on a posting line
+        for line in lines {
+            let trimmed = line.trim_start();
+            if let Some(rest) = trimmed.strip_prefix('!') {
+                let account = rest.split_whitespace().next().unwrap_or_default();
+                let is_flagged_posting = txn
+                    .postings
+                    .iter()
+                    .any(|p| p.flag == Some('!') && p.account.as_str() == account);
+                if is_flagged_posting {
+                    let col = line.len() - trimmed.len();
+                    diagnostics.push(flag_diagnostic(
+                        line_start + col,
+                        &line_index,
+                        format!("Flagged posting: {account}"),
+                    ));
+                }
+            }
+            line_start += line.len();
+        }
+    }
+
+    diagnostics
+}
+
+/// Build an information diagnostic covering the `!` at `offset`.
+fn flag_diagnostic(offset: usize, line_index: &LineIndex, message: String) -> Diagnostic {
+    let (line, col) = line_index.offset_to_position(offset);
+    Diagnostic {
+        range: Range {
+            start: Position::new(line, col),
+            end: Position::new(line, col + 1),
+        },
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(lsp_types::NumberOrString::String(
+            FLAGGED_ENTRY_CODE.to_string(),
+        )),
+        source: Some("rustledger".to_string()),
+        message,
+        related_information: None,
+        tags: None,
+        code_description: None,
+        data: None,
+    }
+}
+
+/// Warn on posting lines indented with tabs, or with a number of spaces
+/// other than `indent_width` when the transaction's postings disagree.
+///
+/// Each diagnostic covers the leading whitespace and carries the normalized
+/// line as `replacement` in its data. The normalized line keeps its amount
+/// aligned (by the end of the number) with the transaction's well-indented
+/// postings, or where it was if there are none.
+pub fn indentation_diagnostics(
+    result: &ParseResult,
+    source: &str,
+    indent_width: usize,
+) -> Vec<Diagnostic> {
+    let line_index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+
+    for spanned in &result.directives {
+        let Directive::Transaction(txn) = &spanned.value else {
+            continue;
+        };
+        let (first_line, _) = line_index.offset_to_position(spanned.span.start);
+        let text = &source[spanned.span.start..spanned.span.end.min(source.len())];
+
+        let postings: Vec<(u32, PostingLine<'_>)> = text
+            .lines()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(n, line)| {
+                let posting = PostingLine::parse(line)?;
+                let is_posting = txn
+                    .postings
+                    .iter()
+                    .any(|p| p.account.as_str() == posting.account);
+                is_posting.then_some((first_line + n as u32, posting))
+            })
+            .collect();
+
+        let mut space_indents: Vec<usize> = postings
+            .iter()
+            .filter(|(_, p)| !p.indent.contains('\t'))
+            .map(|(_, p)| p.indent.len())
+            .collect();
+        space_indents.sort_unstable();
+        space_indents.dedup();
+        let inconsistent = space_indents.len() > 1;
+        let is_misindented = |p: &PostingLine<'_>| {
+            p.indent.contains('\t') || (inconsistent && p.indent.len() != indent_width)
+        };
+
+        let aligned_end = postings
+            .iter()
+            .filter(|(_, p)| !is_misindented(p))
+            .find_map(|(_, p)| p.number_end_column());
+
+        for (line, posting) in &postings {
+            if !is_misindented(posting) {
+                continue;
+            }
+            let message = if posting.indent.contains('\t') {
+                format!("Posting indented with a tab; expected {indent_width} spaces")
+            } else {
+                format!(
+                    "Posting indented with {} spaces; expected {indent_width}",
+                    posting.indent.len()
+                )
+            };
+            let number_end = aligned_end.or_else(|| posting.number_end_column());
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(*line, 0),
+                    end: Position::new(*line, posting.indent.len() as u32),
+                },
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(lsp_types::NumberOrString::String(
+                    INDENTATION_CODE.to_string(),
+                )),
+                source: Some("rustledger".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                code_description: None,
+                data: Some(serde_json::json!({
+                    "replacement": posting.normalized(indent_width, number_end),
+                })),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Byte spans of the lines holding a transaction's postings, in posting order.
+///
+/// Lines are matched to postings by account, so metadata and comments
+/// between postings are skipped.
+fn posting_line_spans(
+    txn: &Transaction,
+    spanned: &Spanned<Directive>,
+    source: &str,
+) -> Vec<(usize, usize)> {
+    let text = &source[spanned.span.start..spanned.span.end.min(source.len())];
+    let mut spans = Vec::new();
+    let mut offset = spanned.span.start;
+    for line in text.split_inclusive('\n') {
+        let next = txn.postings.get(spans.len());
+        let is_next_posting = PostingLine::parse(line)
+            .zip(next)
+            .is_some_and(|(parsed, posting)| parsed.account == posting.account.as_str());
+        if is_next_posting {
+            spans.push((offset, offset + line.len()));
+        }
+        offset += line.len();
+    }
+    spans
+}
+
+/// A posting line split into its indentation, account and the rest.
+pub(crate) struct PostingLine<'a> {
+    indent: &'a str,
+    /// Optional flag and the account, e.g. `! Assets:Cash`.
+    head: &'a str,
+    pub(crate) account: &'a str,
+    /// Whitespace between the account and the amount.
+    gap: &'a str,
+    /// Amount, cost, price and comment, without trailing whitespace.
+    tail: &'a str,
+}
+
+impl<'a> PostingLine<'a> {
+    pub(crate) fn parse(line: &'a str) -> Option<Self> {
+        let line = line.trim_end();
+        let body = line.trim_start();
+        let indent = &line[..line.len() - body.len()];
+        if indent.is_empty() {
+            return None;
+        }
+
+        let (flag_len, after_flag) = match body.chars().next()? {
+            c if c.is_ascii_uppercase() => (0, body),
+            _ => {
+                let rest = body.get(1..)?;
+                let trimmed = rest.trim_start();
+                (body.len() - trimmed.len(), trimmed)
+            }
+        };
+        let account_len = after_flag
+            .find(char::is_whitespace)
+            .unwrap_or(after_flag.len());
+        let account = &after_flag[..account_len];
+        if !account.contains(':') || account.ends_with(':') {
+            return None;
+        }
+
+        let head = &body[..flag_len + account_len];
+        let rest = &body[head.len()..];
+        let tail = rest.trim_start();
+        Some(Self {
+            indent,
+            head,
+            account,
+            gap: &rest[..rest.len() - tail.len()],
+            tail,
+        })
+    }
+
+    /// Character column just past the amount's number, if the posting has one.
+    fn number_end_column(&self) -> Option<usize> {
+        if self.tail.is_empty() || self.tail.starts_with(';') {
+            return None;
+        }
+        let number = self.tail.split_whitespace().next()?;
+        Some(
+            self.indent.chars().count()
+                + self.head.chars().count()
+                + self.gap.chars().count()
+                + number.chars().count(),
+        )
+    }
+
+    /// The line re-indented with `indent_width` spaces, with the amount's
+    /// number ending at `number_end` (keeping at least two spaces before it).
+    fn normalized(&self, indent_width: usize, number_end: Option<usize>) -> String {
+        let indent = " ".repeat(indent_width);
+        if self.tail.is_empty() {
+            return format!("{indent}{}", self.head);
+        }
+        let number = self.tail.split_whitespace().next().unwrap_or_default();
+        let used = indent_width + self.head.chars().count() + number.chars().count();
+        let gap = match number_end {
+            Some(end) if self.number_end_column().is_some() => end.saturating_sub(used).max(2),
+            _ => self.gap.chars().count(),
+        };
+        format!("{indent}{}{}{}", self.head, " ".repeat(gap), self.tail)
+    }
+}
+
+/// Warn on tabs between the tokens of a directive line, such as between
+/// an account and its amount.
+///
+/// Tabs in leading indentation are left to the indentation check, and tabs
+/// inside strings or comments are ignored. Each diagnostic covers the
+/// line's inline tabs and carries the line with its tabs expanded to
+/// `tab_width` stops as `replacement` in its data, so the text keeps the
+/// alignment it had when displayed with that tab width.
+pub fn inline_tab_diagnostics(source: &str, tab_width: usize) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (line_num, line) in source.lines().enumerate() {
+        let tabs = inline_tab_offsets(line);
+        let (Some(&first), Some(&last)) = (tabs.first(), tabs.last()) else {
+            continue;
+        };
+        let column = |offset: usize| line[..offset].encode_utf16().count() as u32;
+        diagnostics.push(Diagnostic {
+            range: Range {
+                start: Position::new(line_num as u32, column(first)),
+                end: Position::new(line_num as u32, column(last) + 1),
+            },
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(lsp_types::NumberOrString::String(
+                INLINE_TAB_CODE.to_string(),
+            )),
+            source: Some("rustledger".to_string()),
+            message: "Tab character between tokens; use spaces".to_string(),
+            related_information: None,
+            tags: None,
+            code_description: None,
+            data: Some(serde_json::json!({
+                "replacement": expand_tabs(line.trim_end_matches('\r'), tab_width),
+            })),
+        });
+    }
+
+    diagnostics
+}
+
+/// Byte offsets of tabs after the first token of a line, outside strings
+/// and comments.
+fn inline_tab_offsets(line: &str) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut seen_token = false;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (offset, c) in line.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            ';' => break,
+            '"' => {
+                in_string = true;
+                seen_token = true;
+            }
+            '\t' if seen_token => offsets.push(offset),
+            c if !c.is_whitespace() => seen_token = true,
+            _ => {}
+        }
+    }
+
+    offsets
+}
+
+/// Expand tabs outside strings and comments to the next multiple of
+/// `tab_width` columns.
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut expanded = String::with_capacity(line.len());
+    let mut column = 0;
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut escaped = false;
+
+    for c in line.chars() {
+        if c == '\t' && !in_string && !in_comment {
+            let spaces = tab_width - column % tab_width;
+            expanded.push_str(&" ".repeat(spaces));
+            column += spaces;
+            continue;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' && !in_comment {
+            in_string = true;
+        } else if c == ';' {
+            in_comment = true;
+        }
+        expanded.push(c);
+        column += 1;
+    }
+
+    expanded
+}
+
+/// Collect currencies declared with `commodity` directives in included directives.
+pub fn collect_included_commodities(included: &[Directive]) -> HashSet<String> {
+    included
+        .iter()
+        .filter_map(|directive| match directive {
+            Directive::Commodity(comm) => Some(comm.currency.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collect accounts opened in included directives.
+pub fn collect_included_accounts(included: &[Directive]) -> HashSet<String> {
+    included
+        .iter()
+        .filter_map(|directive| match directive {
+            Directive::Open(open) => Some(open.account.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Load directives from every file transitively included by `result`.
+///
+/// Include paths are resolved relative to the including file. Files that
+/// cannot be read are skipped, and include cycles are followed only once.
+pub fn load_included_directives(path: &Path, result: &ParseResult) -> Vec<Directive> {
+    load_included_files(path, result, |p| std::fs::read_to_string(p).ok()).directives
+}
+
+/// Load included files using `read` to fetch their contents.
+///
+/// This lets callers prefer unsaved editor buffers over the files on disk.
+pub fn load_included_files(
+    path: &Path,
+    result: &ParseResult,
+    read: impl Fn(&Path) -> Option<String>,
+) -> IncludedFiles {
+    let mut included = IncludedFiles::default();
+    let mut visited = HashSet::new();
+    visited.insert(path.to_path_buf());
+
+    let mut pending: Vec<PathBuf> = result
+        .includes
+        .iter()
+        .filter_map(|(include, _)| resolve_include(path, include))
+        .collect();
+
+    while let Some(include_path) = pending.pop() {
+        if !visited.insert(include_path.clone()) {
+            continue;
+        }
+        // Record the dependency even if the file can't be read yet
+        included.paths.insert(include_path.clone());
+        let Some(content) = read(&include_path) else {
+            continue;
+        };
+        let parsed = parse_document(&content);
+        pending.extend(
+            parsed
+                .includes
+                .iter()
+                .filter_map(|(include, _)| resolve_include(&include_path, include)),
+        );
+        included
+            .directives
+            .extend(parsed.directives.into_iter().map(|spanned| spanned.value));
+    }
+
+    included
+}
+
+/// Resolve an include path relative to the including file.
+///
+/// A leading `~` is expanded to the home directory, and `.` and `..`
+/// components are removed so the result can be compared with paths of open
+/// documents.
+pub(crate) fn resolve_include(from: &Path, include: &str) -> Option<PathBuf> {
+    resolve_path(from.parent()?, include)
+}
+
+/// Resolve a path relative to a directory, the way include paths are.
+pub(crate) fn resolve_path(dir: &Path, path: &str) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let joined = match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ if Path::new(path).is_absolute() => PathBuf::from(path),
+        _ => dir.join(path),
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    Some(normalized)
+}
+
+/// The directory relative `document` paths in `file` are resolved against.
+///
+/// This is the configured `document_base`, itself relative to the file's
+/// directory, or the file's directory when none is configured.
+pub(crate) fn document_base_dir(file: &Path, document_base: Option<&str>) -> Option<PathBuf> {
+    match document_base {
+        Some(base) => resolve_include(file, base),
+        None => file.parent().map(Path::to_path_buf),
+    }
+}
+
+/// Find the first whole-word occurrence of `word` within a byte span.
+///
+/// Falls back to the start of the span if the word isn't found.
+fn find_word_range(
+    source: &str,
+    start: usize,
+    end: usize,
+    word: &str,
+    line_index: &LineIndex,
+) -> Range {
+    let text = &source[start..end.min(source.len())];
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || matches!(c, '\'' | '.' | '_' | '-');
+    let found = text.match_indices(word).map(|(i, _)| i).find(|&i| {
+        !text[..i].chars().next_back().is_some_and(is_ident)
+            && !text[i + word.len()..].chars().next().is_some_and(is_ident)
+    });
+
+    let (word_start, word_end) = match found {
+        Some(i) => (start + i, start + i + word.len()),
+        None => (start, start),
+    };
+    let (start_line, start_col) = line_index.offset_to_position(word_start);
+    let (end_line, end_col) = line_index.offset_to_position(word_end);
+    Range {
+        start: Position::new(start_line, start_col),
+        end: Position::new(end_line, end_col),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rustledger_parser::parse;
 
     #[test]
     fn test_line_index_offset_to_position() {
@@ -53,4 +2308,894 @@ mod tests {
         assert_eq!(line_index.offset_to_position(6), (1, 0));
         assert_eq!(line_index.offset_to_position(12), (2, 0));
     }
+
+    #[test]
+    fn test_undeclared_currency_warning() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 commodity EUR
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food  5.00 USD
+2024-02-01 balance Assets:Bank 10 EUR
+"#;
+        let result = parse(source);
+        let diagnostics = undeclared_currency_diagnostics(&result, source, &HashSet::new());
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostic.range.start, Position::new(3, 21));
+        assert_eq!(diagnostic.range.end, Position::new(3, 24));
+        let data = diagnostic.data.as_ref().unwrap();
+        assert_eq!(data["currency"], "USD");
+        assert_eq!(data["date"], "2024-01-15");
+    }
+
+    #[test]
+    fn test_undeclared_currency_suggests_near_miss() {
+        let source = r#"2024-01-01 commodity USD
+2024-01-01 commodity EUR
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USF
+  Expenses:Food  5.00 USF
+2024-01-16 price CAD 0.73 USD
+"#;
+        let result = parse(source);
+        let diagnostics = undeclared_currency_diagnostics(&result, source, &HashSet::new());
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "Currency USF is used without a commodity directive; did you mean USD?"
+        );
+        assert_eq!(diagnostics[0].data.as_ref().unwrap()["suggestion"], "USD");
+        // CAD is two edits from USD, too many for a short name
+        assert_eq!(diagnostics[1].data.as_ref().unwrap()["currency"], "CAD");
+        assert!(
+            diagnostics[1]
+                .data
+                .as_ref()
+                .unwrap()
+                .get("suggestion")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_currency_not_allowed_by_open() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-01 open Expenses:Travel
+2024-01-15 * "Hotel"
+  Expenses:Travel  90.00 EUR
+  Assets:Bank  -90.00 EUR
+"#;
+        let result = parse(source);
+        let diagnostics = currency_constraint_diagnostics(&result, source, &[]);
+
+        // Expenses:Travel lists no currencies, so anything is allowed there
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                start: Position::new(4, 22),
+                end: Position::new(4, 25),
+            }
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "Currency EUR is not allowed in Assets:Bank (opened with USD)"
+        );
+    }
+
+    #[test]
+    fn test_currency_allowed_when_open_lists_none() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 * "Exchange"
+  Assets:Bank  -100.00 USD
+  Assets:Bank  92.00 EUR @@ 100.00 USD
+"#;
+        let result = parse(source);
+        assert!(currency_constraint_diagnostics(&result, source, &[]).is_empty());
+
+        // An open in an included file constrains the account too
+        let included = parse("2024-01-01 open Assets:Cash USD\n");
+        let included: Vec<Directive> = included.directives.into_iter().map(|s| s.value).collect();
+        let source = "2024-01-15 * \"Cash\"\n  Assets:Cash  -5.00 CAD\n  Assets:Bank\n";
+        let result = parse(source);
+        assert_eq!(
+            currency_constraint_diagnostics(&result, source, &included).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_undeclared_currency_declared_in_include() {
+        let source = "2024-01-15 price BTC 40000 USD\n";
+        let result = parse(source);
+        let declared: HashSet<String> = ["USD".to_string(), "BTC".to_string()].into();
+        assert!(undeclared_currency_diagnostics(&result, source, &declared).is_empty());
+    }
+
+    #[test]
+    fn test_collect_included_commodities() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(
+            dir.join("commodities.beancount"),
+            "include \"nested/more.beancount\"\n2024-01-01 commodity USD\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("nested/more.beancount"),
+            "2024-01-01 commodity EUR\n",
+        )
+        .unwrap();
+
+        let main = dir.join("main.beancount");
+        let source = "include \"commodities.beancount\"\n";
+        let result = parse(source);
+        let included = load_included_directives(&main, &result);
+        let commodities = collect_included_commodities(&included);
+
+        assert!(commodities.contains("USD"));
+        assert!(commodities.contains("EUR"));
+    }
+
+    #[test]
+    fn test_overlapping_budgets() {
+        let source = r#"2024-01-01 custom "budget" Expenses:Food "monthly" 500.00 USD
+2024-01-15 custom "budget" Expenses:Food "monthly" 600.00 USD
+2024-03-15 custom "budget" Expenses:Food "monthly" 650.00 USD
+2024-01-15 custom "budget" Expenses:Rent "monthly" 900.00 USD
+"#;
+        let result = parse(source);
+        let diagnostics = budget_overlap_diagnostics(&result, source, &[]);
+
+        // The March budget starts on a period boundary of the January one it replaces
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 0));
+        assert_eq!(
+            diagnostics[0].message,
+            "Budget for Expenses:Food overlaps the monthly budget starting 2024-01-01"
+        );
+    }
+
+    #[test]
+    fn test_account_capitalization() {
+        let source = r#"2024-01-15 * "Lunch at assets:bank" ; expenses:note
+  expenses:food  5.00 USD
+  Assets:Bank:2024-savings
+  Liabilities:CreditCard
+"#;
+        let diagnostics = account_capitalization_diagnostics(source);
+
+        // Strings, comments, and components starting with a digit are fine
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 2));
+        assert_eq!(diagnostics[0].range.end, Position::new(1, 15));
+        assert_eq!(
+            diagnostics[0].data.as_ref().unwrap()["suggestion"],
+            "Expenses:Food"
+        );
+
+        // A lowercase component below a capitalized root
+        let diagnostics =
+            account_capitalization_diagnostics("2024-01-01 open Equity:opening-balances\n");
+        assert_eq!(
+            diagnostics[0].data.as_ref().unwrap()["suggestion"],
+            "Equity:Opening-balances"
+        );
+    }
+
+    #[test]
+    fn test_pad_without_balance() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Equity:Opening
+2024-01-01 pad Assets:Bank Equity:Opening
+"#;
+        let result = parse(source);
+        let diagnostics = pad_without_balance_diagnostics(&result, source, &[]);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 0));
+        assert!(diagnostics[0].message.contains("requires a later balance"));
+    }
+
+    #[test]
+    fn test_pad_without_balance_crlf_range() {
+        let source = "2024-01-01 open Assets:Bank\r\n2024-01-01 pad Assets:Bank Equity:Opening\r\n";
+        let result = parse(source);
+        let diagnostics = pad_without_balance_diagnostics(&result, source, &[]);
+
+        // The range ends at the last character, not on the `\r`
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 0));
+        assert_eq!(diagnostics[0].range.end, Position::new(1, 41));
+    }
+
+    #[test]
+    fn test_pad_with_balance() {
+        let source = r#"2024-01-01 pad Assets:Bank Equity:Opening
+2024-01-02 balance Assets:Bank 100 USD
+"#;
+        let result = parse(source);
+        assert!(pad_without_balance_diagnostics(&result, source, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_pad_with_balance_in_included_file() {
+        let source = "2024-01-01 pad Assets:Bank Equity:Opening\n";
+        let result = parse(source);
+        let included = parse("2024-01-02 balance Assets:Bank 100 USD\n");
+        let included: Vec<Directive> = included.directives.into_iter().map(|s| s.value).collect();
+        assert!(pad_without_balance_diagnostics(&result, source, &included).is_empty());
+    }
+
+    #[test]
+    fn test_flagged_transaction() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 ! "Check this"
+  Assets:Bank  -5.00 USD
+  Expenses:Food  5.00 USD
+2024-01-16 * "Fine"
+  Assets:Bank  -5.00 USD
+  Expenses:Food  5.00 USD
+"#;
+        let result = parse(source);
+        let diagnostics = flagged_entry_diagnostics(&result, source);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+        assert_eq!(diagnostics[0].range.start, Position::new(1, 11));
+        assert_eq!(diagnostics[0].range.end, Position::new(1, 12));
+    }
+
+    #[test]
+    fn test_flagged_posting() {
+        let source = r#"2024-01-16 * "Groceries"
+  Assets:Bank  -5.00 USD
+  ! Expenses:Food  5.00 USD
+"#;
+        let result = parse(source);
+        let diagnostics = flagged_entry_diagnostics(&result, source);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 2));
+        assert!(diagnostics[0].message.contains("Expenses:Food"));
+    }
+
+    #[test]
+    fn test_unrelated_currencies_need_price() {
+        let source = r#"2024-01-15 * "Exchange"
+  Assets:Bank:USD  -100 USD
+  Assets:Bank:EUR  90 EUR
+"#;
+        let result = parse(source);
+        let diagnostics = unrelated_currencies_diagnostics(&result, source, "Equity:Conversions");
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostic.code,
+            Some(lsp_types::NumberOrString::String(
+                UNRELATED_CURRENCIES_CODE.to_string()
+            ))
+        );
+        assert_eq!(diagnostic.range.start, Position::new(0, 0));
+        assert!(diagnostic.message.contains("USD and EUR"));
+        assert!(diagnostic.message.contains("`@` price"));
+    }
+
+    #[test]
+    fn test_related_currencies_are_fine() {
+        let source = r#"2024-01-15 * "Exchange with price"
+  Assets:Bank:USD  -100 USD
+  Assets:Bank:EUR  90 EUR @ 1.11 USD
+2024-01-16 * "Elided"
+  Assets:Bank:USD  -100 USD
+  Assets:Bank:EUR  90 EUR
+  Equity:Conversions
+"#;
+        let result = parse(source);
+        assert!(unrelated_currencies_diagnostics(&result, source, "Equity:Conversions").is_empty());
+    }
+
+    #[test]
+    fn test_balance_currency_never_held() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Income:Salary
+2024-01-05 * "Salary"
+  Assets:Bank  1000.00 USD
+  Income:Salary
+2024-02-01 balance Assets:Bank 0 JPY
+2024-02-01 balance Assets:Bank 900.00 USD
+"#;
+        let result = parse(source);
+        let diagnostics = balance_currency_diagnostics(&result, source, &[]);
+
+        // Only the JPY assertion; the USD one is an ordinary (amount) mismatch
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(5, 33),
+                end: Position::new(5, 36),
+            }
+        );
+        assert!(diagnostic.message.contains("never held JPY"));
+        assert!(diagnostic.message.contains("USD"));
+    }
+
+    #[test]
+    fn test_negative_balance_on_guarded_account() {
+        let source = r#"2024-01-01 open Assets:Savings
+  allow_negative: FALSE
+2024-01-01 open Assets:Checking
+2024-01-01 open Income:Salary
+2024-01-05 * "Deposit"
+  Assets:Savings  100.00 USD
+  Income:Salary
+2024-01-10 * "Withdraw too much"
+  Assets:Checking  150.00 USD
+  Assets:Savings
+2024-01-12 * "Still overdrawn"
+  Assets:Checking  10.00 USD
+  Assets:Savings
+2024-01-15 * "Overdraft on checking is fine"
+  Assets:Checking  -500.00 USD
+  Income:Salary
+"#;
+        let result = parse(source);
+        let diagnostics = negative_balance_diagnostics(&result, source, &[]);
+
+        // Reported where the balance first crosses below zero
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(9, 2),
+                end: Position::new(9, 16),
+            }
+        );
+        assert!(diagnostic.message.contains("-50.00 USD"));
+    }
+
+    #[test]
+    fn test_lot_reduction_beyond_holdings() {
+        let source = r#"2024-01-01 open Assets:Stock
+2024-01-01 open Assets:Cash
+2024-01-05 * "Buy"
+  Assets:Stock  5 AAPL {150 USD}
+  Assets:Cash  -750 USD
+2024-02-01 * "Sell some"
+  Assets:Stock  -3 AAPL {150 USD}
+  Assets:Cash  450 USD
+2024-03-01 * "Sell more than held"
+  Assets:Stock  -10 AAPL {150 USD}
+  Assets:Cash  1500 USD
+2024-04-01 * "Sell an unknown lot"
+  Assets:Stock  -1 AAPL {160 USD}
+  Assets:Cash  160 USD
+"#;
+        let result = parse(source);
+        let diagnostics = lot_reduction_diagnostics(&result, source, &[]);
+
+        // The valid sale of 3 is not reported
+        assert_eq!(diagnostics.len(), 2);
+        let oversized = &diagnostics[0];
+        assert_eq!(oversized.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            oversized.code,
+            Some(lsp_types::NumberOrString::String(
+                INSUFFICIENT_UNITS_CODE.to_string()
+            ))
+        );
+        assert_eq!(
+            oversized.range,
+            Range {
+                start: Position::new(9, 2),
+                end: Position::new(9, 14),
+            }
+        );
+        assert!(
+            oversized.message.contains("only 2 held"),
+            "{}",
+            oversized.message
+        );
+
+        assert_eq!(diagnostics[1].range.start.line, 12);
+        assert_eq!(
+            diagnostics[1].code,
+            Some(lsp_types::NumberOrString::String(
+                NO_MATCHING_LOT_CODE.to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_lot_reduction_ambiguous_under_strict_booking() {
+        let source = r#"2024-01-01 open Assets:Stock
+2024-01-01 open Assets:Cash
+2024-01-05 * "Buy"
+  Assets:Stock  5 AAPL {150 USD}
+  Assets:Cash  -750 USD
+2024-01-10 * "Buy again"
+  Assets:Stock  5 AAPL {160 USD}
+  Assets:Cash  -800 USD
+2024-02-01 * "Sell"
+  Assets:Stock  -1 AAPL {}
+  Assets:Cash  160 USD
+"#;
+        let result = parse(source);
+        let diagnostics = lot_reduction_diagnostics(&result, source, &[]);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 9);
+        assert_eq!(
+            diagnostics[0].code,
+            Some(lsp_types::NumberOrString::String(
+                AMBIGUOUS_LOT_CODE.to_string()
+            ))
+        );
+
+        // The ledger's default booking method picks a lot instead
+        let source = format!("option \"booking_method\" \"FIFO\"\n{source}");
+        let result = parse(&source);
+        assert!(lot_reduction_diagnostics(&result, &source, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_lot_reduction_range_is_the_reducing_posting() {
+        let source = r#"2024-01-01 open Assets:Stock
+2024-01-01 open Assets:Cash
+2024-01-05 * "Rebalance Assets:Stock"
+  Assets:Stock  1 AAPL {150 USD}
+  Assets:Stock  -10 AAPL {150 USD}
+  Assets:Cash  1350 USD
+"#;
+        let result = parse(source);
+        let diagnostics = lot_reduction_diagnostics(&result, source, &[]);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                start: Position::new(4, 2),
+                end: Position::new(4, 14),
+            }
+        );
+    }
+
+    #[test]
+    fn test_balance_currency_from_pad_or_open_is_fine() {
+        let source = r#"2024-01-01 open Assets:Bank USD,JPY
+2024-01-01 open Assets:Wallet
+2024-01-01 open Equity:Opening
+2024-01-05 * "Deposit"
+  Assets:Bank  1000.00 USD
+  Equity:Opening
+2024-01-05 * "Cash"
+  Assets:Wallet  20.00 USD
+  Equity:Opening
+2024-01-06 pad Assets:Wallet Equity:Opening
+2024-02-01 balance Assets:Bank 0 JPY
+2024-02-01 balance Assets:Wallet 5000 JPY
+"#;
+        let result = parse(source);
+        assert_eq!(balance_currency_diagnostics(&result, source, &[]).len(), 0);
+    }
+
+    #[test]
+    fn test_indentation_flags_tab_indented_posting() {
+        let source = "2024-01-05 * \"Lunch\"\n  Expenses:Food          12.00 USD\n\tAssets:Cash           -12.00 USD\n";
+        let result = parse(source);
+        let diagnostics = indentation_diagnostics(&result, source, 2);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                start: Position::new(2, 0),
+                end: Position::new(2, 1),
+            }
+        );
+        let replacement = diagnostics[0].data.as_ref().unwrap()["replacement"]
+            .as_str()
+            .unwrap();
+        // The number stays aligned with the other posting's
+        assert_eq!(replacement, "  Assets:Cash           -12.00 USD");
+        let first_posting = source.lines().nth(1).unwrap();
+        assert_eq!(replacement.find(" USD"), first_posting.find(" USD"));
+    }
+
+    #[test]
+    fn test_indentation_consistent_spaces_not_flagged() {
+        let source = r#"2024-01-05 * "Lunch"
+    Expenses:Food  12.00 USD
+    Assets:Cash
+2024-01-06 * "Dinner"
+  Expenses:Food  20.00 USD
+   Assets:Cash
+"#;
+        let result = parse(source);
+        let diagnostics = indentation_diagnostics(&result, source, 2);
+
+        // A uniform four-space block is left alone; the stray three-space
+        // posting in the second transaction is flagged
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, Position::new(5, 0));
+        assert_eq!(
+            diagnostics[0].data.as_ref().unwrap()["replacement"],
+            "  Assets:Cash"
+        );
+    }
+
+    #[test]
+    fn test_inline_tab_between_account_and_amount() {
+        let source =
+            "2024-01-05 * \"Lunch\"\n  Expenses:Food\t12.00 USD\n  Assets:Cash\t-12.00 USD\n";
+        let diagnostics = inline_tab_diagnostics(source, 4);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range {
+                start: Position::new(1, 15),
+                end: Position::new(1, 16),
+            }
+        );
+        let replacements: Vec<_> = diagnostics
+            .iter()
+            .map(|d| d.data.as_ref().unwrap()["replacement"].as_str().unwrap())
+            .collect();
+        // Both amounts land on the same tab stop
+        assert_eq!(
+            replacements,
+            vec!["  Expenses:Food 12.00 USD", "  Assets:Cash   -12.00 USD"]
+        );
+    }
+
+    #[test]
+    fn test_inline_tab_ignores_indentation_strings_and_comments() {
+        let source = "2024-01-05 * \"Tab\there\"\n\tExpenses:Food  12.00 USD ;\tnote\n";
+        assert!(inline_tab_diagnostics(source, 2).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_transactions() {
+        let source = r#"2024-01-05 * "Cafe" "Coffee"
+  Expenses:Food  4.50 USD
+  Assets:Cash
+2024-01-05 * "Cafe" "Coffee"
+  Expenses:Food  4.50 USD
+  Assets:Cash
+2024-01-05 * "Cafe" "Coffee"
+  Expenses:Food  5.00 USD
+  Assets:Cash
+2024-01-05 * "Cafe" "Coffee"
+  Expenses:Food  4.50 USD
+  Liabilities:Card
+2024-01-06 * "Cafe" "Coffee"
+  Expenses:Food  4.50 USD
+  Assets:Cash
+2024-01-05 * "Cafe" "Muffin"
+  Expenses:Food  4.50 USD
+  Assets:Cash
+"#;
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = duplicate_transaction_diagnostics(&result, source, &uri);
+
+        // Only the byte-identical copy; other amounts, accounts, days or
+        // narrations differ
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostic.range.start, Position::new(3, 0));
+        assert!(diagnostic.message.contains("line 1"));
+        let related = diagnostic.related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start, Position::new(0, 0));
+    }
+
+    #[test]
+    fn test_duplicate_metadata_key_on_posting() {
+        let source = r#"2024-01-15 * "Coffee"
+  category: "treat"
+  Expenses:Food  5.00 USD
+    category: "food"
+    category: "drinks"
+  Assets:Cash
+    category: "cash"
+"#;
+        let result = parse(source);
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let diagnostics = duplicate_metadata_diagnostics(&result, source, &uri);
+
+        // Only the repeat on the `Expenses:Food` posting
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(4, 4),
+                end: Position::new(4, 12),
+            }
+        );
+        let related = diagnostic.related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.uri, uri);
+        assert_eq!(related[0].location.range.start, Position::new(3, 4));
+    }
+
+    #[test]
+    fn test_excess_precision() {
+        let source = r#"2024-01-01 commodity USD
+  precision: 2
+2024-01-05 * "Import"
+  Expenses:Food  5.12345 USD
+  Assets:Cash  -5.12 USD
+  Assets:Wallet  1.23456 EUR @ 1.08754 USD
+2024-01-06 price EUR 1.08754 USD
+"#;
+        let result = parse(source);
+        let diagnostics = excess_precision_diagnostics(&result, source);
+
+        // The price and the price directive are not held to the precision
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(3, 17),
+                end: Position::new(3, 24),
+            }
+        );
+        assert_eq!(diagnostic.data.as_ref().unwrap()["replacement"], "5.12");
+    }
+
+    #[test]
+    fn test_missing_document() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("statement.pdf"), "").unwrap();
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-31 document Assets:Bank "statement.pdf"
+2024-02-29 document Assets:Bank "missing.pdf"
+"#;
+        let result = parse(source);
+        let diagnostics = missing_document_diagnostics(&result, source, dir);
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(2, 33),
+                end: Position::new(2, 44),
+            }
+        );
+        assert_eq!(
+            diagnostic.message,
+            format!(
+                "Document file not found: {}",
+                dir.join("missing.pdf").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_self_price() {
+        let source = r#"2024-01-01 price USD 1.00 USD
+2024-01-01 price EUR 1.10 USD
+"#;
+        let result = parse(source);
+        let diagnostics = self_price_diagnostics(&result, source);
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(0, 21),
+                end: Position::new(0, 29),
+            }
+        );
+        assert_eq!(diagnostic.message, "USD is priced in itself");
+    }
+
+    #[test]
+    fn test_unknown_booking_method() {
+        let source = r#"2024-01-01 open Assets:Stocks AAPL "FIFOO"
+2024-01-01 open Assets:Funds VTI "FIFO"
+2024-01-01 open Assets:Bank USD
+"#;
+        let result = parse(source);
+        let diagnostics = unknown_booking_diagnostics(&result, source);
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.range,
+            Range {
+                start: Position::new(0, 35),
+                end: Position::new(0, 42),
+            }
+        );
+        assert!(
+            diagnostic
+                .message
+                .starts_with("Unknown booking method \"FIFOO\"; expected one of STRICT")
+        );
+    }
+
+    #[test]
+    fn test_metadata_type_mismatch() {
+        let source = r#"2024-01-01 commodity USD
+  precision: "two"
+2024-01-01 commodity EUR
+  precision: 2
+  name: "Euro"
+2024-01-01 open Expenses:Food
+  budget: 300.00 USD
+2024-01-01 open Expenses:Fun
+  budget: "lots"
+"#;
+        let result = parse(source);
+        let diagnostics = metadata_type_diagnostics(&result, source);
+
+        assert_eq!(diagnostics.len(), 2);
+        let precision = &diagnostics[0];
+        assert_eq!(precision.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            precision.code,
+            Some(lsp_types::NumberOrString::String(
+                METADATA_TYPE_CODE.to_string()
+            ))
+        );
+        assert_eq!(
+            precision.range,
+            Range {
+                start: Position::new(1, 2),
+                end: Position::new(1, 18),
+            }
+        );
+        assert!(precision.message.contains("should be a number"));
+
+        assert_eq!(diagnostics[1].range.start, Position::new(8, 2));
+        assert!(diagnostics[1].message.contains("should be an amount"));
+    }
+
+    #[test]
+    fn test_undefined_account() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food  5.00 USD
+"#;
+        let result = parse(source);
+        let diagnostics = undefined_account_diagnostics(&result, source, &HashSet::new());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].range.start, Position::new(3, 2));
+        assert_eq!(diagnostics[0].range.end, Position::new(3, 15));
+
+        let opened: HashSet<String> = ["Expenses:Food".to_string()].into();
+        assert!(undefined_account_diagnostics(&result, source, &opened).is_empty());
+    }
+
+    #[test]
+    fn test_undefined_account_suggests_near_miss() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Assets:Cash
+2024-01-15 * "Coffee"
+  Assets:Banl  -5.00 USD
+  Expenses:Food  5.00 USD
+"#;
+        let result = parse(source);
+        let diagnostics = undefined_account_diagnostics(&result, source, &HashSet::new());
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "Account Assets:Banl is used without an open directive; did you mean `Assets:Bank`?"
+        );
+        assert_eq!(
+            diagnostics[0].data.as_ref().unwrap()["suggestion"],
+            "Assets:Bank"
+        );
+        // Nothing opened is within two edits of Expenses:Food
+        assert!(
+            diagnostics[1]
+                .data
+                .as_ref()
+                .unwrap()
+                .get("suggestion")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("Assets:Bank", "Assets:Bank"), 0);
+        assert_eq!(levenshtein("Assets:Banl", "Assets:Bank"), 1);
+        assert_eq!(levenshtein("Assets:Bnk", "Assets:Bank"), 1);
+        assert_eq!(levenshtein("Asets:Bnk", "Assets:Bank"), 2);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_closest_match_scales_with_length() {
+        let currencies = ["EUR", "USD"];
+        assert_eq!(closest_match("USF", currencies.into_iter()), Some("USD"));
+        assert_eq!(closest_match("CAD", currencies.into_iter()), None);
+        assert_eq!(closest_match("SGD", currencies.into_iter()), None);
+
+        let accounts = ["Assets:Bank", "Assets:Cash"];
+        assert_eq!(
+            closest_match("Asets:Bnk", accounts.into_iter()),
+            Some("Assets:Bank")
+        );
+    }
+
+    #[test]
+    fn test_load_included_files_with_reader() {
+        let source = "include \"sub/../accounts.beancount\"\n";
+        let result = parse(source);
+        let included = load_included_files(Path::new("/ledger/main.beancount"), &result, |p| {
+            (p == Path::new("/ledger/accounts.beancount"))
+                .then(|| "2024-01-01 open Assets:Bank\n".to_string())
+        });
+
+        assert_eq!(included.directives.len(), 1);
+        assert!(
+            included
+                .paths
+                .contains(Path::new("/ledger/accounts.beancount"))
+        );
+        assert!(collect_included_accounts(&included.directives).contains("Assets:Bank"));
+    }
+
+    #[test]
+    fn test_resolve_include_paths() {
+        let from = Path::new("/ledger/years/2024.beancount");
+        assert_eq!(
+            resolve_include(from, "../accounts.beancount"),
+            Some(PathBuf::from("/ledger/accounts.beancount"))
+        );
+        assert_eq!(
+            resolve_include(from, "/abs/prices.beancount"),
+            Some(PathBuf::from("/abs/prices.beancount"))
+        );
+
+        // `~` expands to the home directory, but not within a name
+        if let Some(home) = std::env::var_os("HOME") {
+            assert_eq!(
+                resolve_include(from, "~/ledger/main.beancount"),
+                Some(PathBuf::from(home).join("ledger/main.beancount"))
+            );
+        }
+        assert_eq!(
+            resolve_include(from, "~backup.beancount"),
+            Some(PathBuf::from("/ledger/years/~backup.beancount"))
+        );
+    }
 }
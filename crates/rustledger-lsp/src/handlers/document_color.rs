@@ -4,13 +4,13 @@
 //! - Negative amounts: red
 //! - Positive amounts: green
 //! - Zero amounts: gray
+//! - Hex color metadata values (e.g. `color: "#ff8800"`): the color itself
 
 use lsp_types::{
     Color, ColorInformation, ColorPresentation, ColorPresentationParams, DocumentColorParams,
-    Position, Range,
+    Position, Range, TextEdit,
 };
-use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
+use rustledger_core::{Directive, MetaValue};
 
 use super::utils::byte_offset_to_position;
 
@@ -37,13 +37,15 @@ const COLOR_ZERO: Color = Color {
     blue: 0.5,
     alpha: 1.0,
 };
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a document color request.
 pub fn handle_document_color(
     _params: &DocumentColorParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<ColorInformation>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let mut colors = Vec::new();
 
     for spanned in &parse_result.directives {
@@ -107,6 +109,8 @@ pub fn handle_document_color(
             }
             _ => {}
         }
+
+        collect_metadata_colors(source, spanned.span.start, &spanned.value, &mut colors);
     }
 
     if colors.is_empty() {
@@ -117,10 +121,36 @@ pub fn handle_document_color(
 }
 
 /// Handle a color presentation request.
-/// This is called when the user wants to change a color (not really applicable for amounts).
-pub fn handle_color_presentation(params: &ColorPresentationParams) -> Vec<ColorPresentation> {
-    // We don't support changing colors - amounts are data, not colors
-    // Just return the current representation
+///
+/// For hex color metadata values, offers a `#RRGGBB` edit for the picked color.
+/// Amount colors are data, not colors, so only a descriptive label is returned.
+pub fn handle_color_presentation(
+    params: &ColorPresentationParams,
+    source: &str,
+) -> Vec<ColorPresentation> {
+    let range_text = source
+        .lines()
+        .nth(params.range.start.line as usize)
+        .filter(|_| params.range.start.line == params.range.end.line)
+        .and_then(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            let start = params.range.start.character as usize;
+            let end = params.range.end.character as usize;
+            chars.get(start..end).map(|c| c.iter().collect::<String>())
+        });
+
+    if range_text.as_deref().and_then(parse_hex_color).is_some() {
+        let hex = format_hex_color(&params.color);
+        return vec![ColorPresentation {
+            label: hex.clone(),
+            text_edit: Some(TextEdit {
+                range: params.range,
+                new_text: hex,
+            }),
+            additional_text_edits: None,
+        }];
+    }
+
     let label = if params.color.red > 0.5 && params.color.green < 0.5 {
         "Negative amount"
     } else if params.color.green > 0.5 {
@@ -136,6 +166,82 @@ pub fn handle_color_presentation(params: &ColorPresentationParams) -> Vec<ColorP
     }]
 }
 
+/// Collect colors for string metadata values that are hex colors.
+fn collect_metadata_colors(
+    source: &str,
+    directive_start: usize,
+    directive: &Directive,
+    colors: &mut Vec<ColorInformation>,
+) {
+    let (start_line, _) = byte_offset_to_position(source, directive_start);
+
+    for (key, value) in directive.meta() {
+        let MetaValue::String(value) = value else {
+            continue;
+        };
+        let Some(color) = parse_hex_color(value) else {
+            continue;
+        };
+
+        // Find the `key: "value"` line among the directive's lines
+        let needle = format!("\"{}\"", value);
+        let found = source
+            .lines()
+            .enumerate()
+            .skip(start_line as usize)
+            .take_while(|(n, line)| *n == start_line as usize || line.starts_with([' ', '\t']))
+            .find_map(|(n, line)| {
+                let trimmed = line.trim_start();
+                let rest = trimmed.strip_prefix(key.as_str())?.strip_prefix(':')?;
+                let pos = rest.find(&needle)?;
+                let byte = line.len() - rest.len() + pos + 1; // skip the opening quote
+                Some((n as u32, line[..byte].chars().count() as u32))
+            });
+
+        if let Some((line, col)) = found {
+            colors.push(ColorInformation {
+                range: Range {
+                    start: Position::new(line, col),
+                    end: Position::new(line, col + value.chars().count() as u32),
+                },
+                color,
+            });
+        }
+    }
+}
+
+/// Parse a `#RRGGBB` or `#RRGGBBAA` hex color.
+fn parse_hex_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let component = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|v| f32::from(v) / 255.0)
+    };
+
+    Some(Color {
+        red: component(0)?,
+        green: component(2)?,
+        blue: component(4)?,
+        alpha: if hex.len() == 8 { component(6)? } else { 1.0 },
+    })
+}
+
+/// Format a color as a `#RRGGBB` hex string.
+fn format_hex_color(color: &Color) -> String {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.red),
+        channel(color.green),
+        channel(color.blue)
+    )
+}
+
 /// Find the range of an amount in a line.
 fn find_amount_range(line: &str, amount_str: &str, line_num: u32) -> Option<Range> {
     // Look for the amount pattern (may have negative sign)
@@ -172,7 +278,6 @@ fn find_amount_range(line: &str, amount_str: &str, line_num: u32) -> Option<Rang
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_document_color_positive_negative() {
@@ -180,7 +285,8 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food  5.00 USD
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = DocumentColorParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -188,8 +294,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let colors = handle_document_color(&params, source, &result);
+        let colors = handle_document_color(&params, &snapshot);
         assert!(colors.is_some());
 
         let colors = colors.unwrap();
@@ -208,7 +313,8 @@ mod tests {
     fn test_document_color_balance() {
         let source = r#"2024-01-31 balance Assets:Bank 100 USD
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = DocumentColorParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -216,8 +322,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let colors = handle_document_color(&params, source, &result);
+        let colors = handle_document_color(&params, &snapshot);
         assert!(colors.is_some());
 
         let colors = colors.unwrap();
@@ -225,4 +330,72 @@ mod tests {
         // Positive balance (green)
         assert!(colors[0].color.green > 0.5);
     }
+
+    #[test]
+    fn test_parse_hex_color() {
+        let color = parse_hex_color("#ff8800").unwrap();
+        assert_eq!(color.red, 1.0);
+        assert_eq!(color.green, 136.0 / 255.0);
+        assert_eq!(color.blue, 0.0);
+        assert_eq!(color.alpha, 1.0);
+
+        assert!(parse_hex_color("ff8800").is_none());
+        assert!(parse_hex_color("#ff88").is_none());
+        assert!(parse_hex_color("#gg8800").is_none());
+    }
+
+    #[test]
+    fn test_document_color_commodity_metadata() {
+        let source = r##"2024-01-01 commodity USD
+  color: "#ff8800"
+"##;
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let params = DocumentColorParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let colors = handle_document_color(&params, &snapshot).unwrap();
+        assert_eq!(colors.len(), 1);
+        assert_eq!(colors[0].range.start, Position::new(1, 10));
+        assert_eq!(colors[0].range.end, Position::new(1, 17));
+        assert_eq!(colors[0].color.red, 1.0);
+        assert_eq!(colors[0].color.green, 136.0 / 255.0);
+        assert_eq!(colors[0].color.blue, 0.0);
+    }
+
+    #[test]
+    fn test_color_presentation_hex() {
+        let source = r##"2024-01-01 commodity USD
+  color: "#ff8800"
+"##;
+        let range = Range {
+            start: Position::new(1, 10),
+            end: Position::new(1, 17),
+        };
+        let params = ColorPresentationParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            color: Color {
+                red: 0.0,
+                green: 0.5,
+                blue: 1.0,
+                alpha: 1.0,
+            },
+            range,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let presentations = handle_color_presentation(&params, source);
+        assert_eq!(presentations.len(), 1);
+        assert_eq!(presentations[0].label, "#0080ff");
+        let edit = presentations[0].text_edit.as_ref().unwrap();
+        assert_eq!(edit.range, range);
+        assert_eq!(edit.new_text, "#0080ff");
+    }
 }
@@ -14,13 +14,15 @@ use rustledger_parser::ParseResult;
 use super::utils::{
     byte_offset_to_position, get_word_at_position, is_account_like, is_currency_like,
 };
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a document highlight request.
 pub fn handle_document_highlight(
     params: &DocumentHighlightParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<DocumentHighlight>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let position = params.text_document_position_params.position;
     let line_idx = position.line as usize;
     let lines: Vec<&str> = source.lines().collect();
@@ -283,7 +285,6 @@ fn is_in_quotes(line: &str, col: usize) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_highlight_account() {
@@ -293,19 +294,19 @@ mod tests {
   Expenses:Food
 2024-01-31 balance Assets:Bank 100 USD
 "#;
-        let result = parse(source);
         let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
 
         let params = DocumentHighlightParams {
             text_document_position_params: lsp_types::TextDocumentPositionParams {
-                text_document: lsp_types::TextDocumentIdentifier { uri },
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
                 position: Position::new(0, 16), // On "Assets:Bank"
             },
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
 
-        let highlights = handle_document_highlight(&params, source, &result);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let highlights = handle_document_highlight(&params, &snapshot);
         assert!(highlights.is_some());
 
         let highlights = highlights.unwrap();
@@ -323,19 +324,19 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food  5.00 USD
 "#;
-        let result = parse(source);
         let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
 
         let params = DocumentHighlightParams {
             text_document_position_params: lsp_types::TextDocumentPositionParams {
-                text_document: lsp_types::TextDocumentIdentifier { uri },
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
                 position: Position::new(0, 28), // On "USD"
             },
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
 
-        let highlights = handle_document_highlight(&params, source, &result);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let highlights = handle_document_highlight(&params, &snapshot);
         assert!(highlights.is_some());
 
         let highlights = highlights.unwrap();
@@ -8,22 +8,24 @@
 
 use lsp_types::{DocumentLink, DocumentLinkParams, Position, Range, Uri};
 use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
 use std::path::Path;
 
-use super::utils::byte_offset_to_position;
+use super::diagnostics::{document_base_dir, resolve_include};
+use super::utils::{byte_offset_to_position, path_to_uri, uri_to_path};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a document links request.
 pub fn handle_document_links(
     params: &DocumentLinkParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<DocumentLink>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let mut links = Vec::new();
     let base_uri = &params.text_document.uri;
 
-    // Get the base directory from the document URI
-    let base_dir = get_base_directory(base_uri);
+    // Document paths are relative to the configured base, or the file's directory
+    let base_dir = get_base_directory(base_uri, snapshot.config.document_base.as_deref());
 
     for spanned in &parse_result.directives {
         if let Directive::Document(doc) = &spanned.value {
@@ -37,14 +39,17 @@ pub fn handle_document_links(
         }
     }
 
-    // Also look for include directives in comments/options
-    // (includes are typically parsed as options, not directives)
-    for (line_num, line) in source.lines().enumerate() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("include") {
-            if let Some(link) = parse_include_line(line, line_num as u32, &base_dir) {
-                links.push(link);
-            }
+    // Include paths link straight to the resolved file
+    let document_path = uri_to_path(base_uri);
+    for (include, span) in &parse_result.includes {
+        if let Some(link) = create_include_link(
+            source,
+            span.start,
+            span.end,
+            include,
+            document_path.as_deref(),
+        ) {
+            links.push(link);
         }
     }
 
@@ -64,8 +69,11 @@ pub fn handle_document_link_resolve(link: DocumentLink) -> DocumentLink {
             .map(String::from);
         let kind = data.get("kind").and_then(|v| v.as_str()).unwrap_or("file");
 
-        // Resolve the path
-        let resolved_path = resolve_full_path(path, &base_dir);
+        // Resolve the path, unless the link already carries its target
+        let resolved_path = match &link.target {
+            Some(target) => uri_to_path(target).map(|p| p.to_string_lossy().to_string()),
+            None => resolve_full_path(path, &base_dir),
+        };
 
         // Check if file exists
         let exists = resolved_path
@@ -75,7 +83,7 @@ pub fn handle_document_link_resolve(link: DocumentLink) -> DocumentLink {
 
         // Set target URI
         if let Some(ref full_path) = resolved_path {
-            if let Ok(uri) = format!("file://{}", full_path).parse::<Uri>() {
+            if let Some(uri) = path_to_uri(Path::new(full_path)) {
                 resolved.target = Some(uri);
             }
         }
@@ -108,15 +116,10 @@ fn resolve_full_path(path: &str, base_dir: &Option<String>) -> Option<String> {
     }
 }
 
-/// Get the base directory from a file URI.
-fn get_base_directory(uri: &Uri) -> Option<String> {
-    let uri_str = uri.as_str();
-    if let Some(path_str) = uri_str.strip_prefix("file://") {
-        let path = Path::new(path_str);
-        path.parent().map(|p| p.to_string_lossy().to_string())
-    } else {
-        None
-    }
+/// Get the directory `document` paths are resolved against from a file URI.
+fn get_base_directory(uri: &Uri, document_base: Option<&str>) -> Option<String> {
+    let path = uri_to_path(uri)?;
+    document_base_dir(&path, document_base).map(|p| p.to_string_lossy().to_string())
 }
 
 /// Create a document link for a path found in source.
@@ -164,41 +167,36 @@ fn create_document_link(
     })
 }
 
-/// Parse an include line and create a document link.
-/// The target is deferred to the resolve phase for lazy verification.
-fn parse_include_line(
-    line: &str,
-    line_num: u32,
-    base_dir: &Option<String>,
+/// Create a document link for the path of an `include` directive.
+///
+/// The target is resolved right away, relative to the including document
+/// and with `~` expanded; only the tooltip is deferred to the resolve phase.
+fn create_include_link(
+    source: &str,
+    start: usize,
+    end: usize,
+    include: &str,
+    document_path: Option<&Path>,
 ) -> Option<DocumentLink> {
-    // Match patterns like: include "path/to/file.beancount"
-    let trimmed = line.trim();
-    if !trimmed.starts_with("include") {
-        return None;
-    }
-
-    // Find the quoted path
-    let quote_start = line.find('"')?;
-    let after_quote = &line[quote_start + 1..];
-    let quote_end = after_quote.find('"')?;
+    let path_start = start + source[start..end].find(include)?;
+    let (line, start_col) = byte_offset_to_position(source, path_start);
+    let (_, end_col) = byte_offset_to_position(source, path_start + include.len());
 
-    let path = &after_quote[..quote_end];
-    let start_col = (quote_start + 1) as u32;
-    let end_col = start_col + path.len() as u32;
-
-    // Store data for resolve - defer target resolution
+    let target = document_path
+        .and_then(|document_path| resolve_include(document_path, include))
+        .and_then(|path| path_to_uri(&path));
     let data = serde_json::json!({
-        "path": path,
-        "base_dir": base_dir,
+        "path": include,
+        "base_dir": document_path.and_then(Path::parent),
         "kind": "include",
     });
 
     Some(DocumentLink {
         range: Range {
-            start: Position::new(line_num, start_col),
-            end: Position::new(line_num, end_col),
+            start: Position::new(line, start_col),
+            end: Position::new(line, end_col),
         },
-        target: None,  // Resolved lazily
+        target,
         tooltip: None, // Resolved lazily
         data: Some(data),
     })
@@ -208,31 +206,54 @@ fn parse_include_line(
 #[cfg(test)]
 fn resolve_path_to_uri(path: &str, base_dir: &Option<String>) -> Option<Uri> {
     let resolved = resolve_full_path(path, base_dir)?;
-    format!("file://{}", resolved).parse().ok()
+    path_to_uri(Path::new(&resolved))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_parse_include_line() {
-        let line = r#"include "accounts.beancount""#;
-        let base_dir = Some("/home/user/ledger".to_string());
+    fn links_for(source: &str) -> Vec<DocumentLink> {
+        let params = DocumentLinkParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///home/user/ledger/main.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        handle_document_links(&params, &snapshot).unwrap_or_default()
+    }
 
-        let link = parse_include_line(line, 0, &base_dir);
-        assert!(link.is_some());
+    #[test]
+    fn test_include_link() {
+        let links = links_for(r#"include "accounts.beancount""#);
+        assert_eq!(links.len(), 1);
 
-        let link = link.unwrap();
+        let link = &links[0];
         assert_eq!(link.range.start.character, 9); // After the opening quote
         assert_eq!(link.range.end.character, 27); // "accounts.beancount" is 18 chars
-
-        // Target should be None (resolved lazily)
-        assert!(link.target.is_none());
-        // Data should contain the path info
+        assert_eq!(
+            link.target.as_ref().map(|uri| uri.as_str()),
+            Some("file:///home/user/ledger/accounts.beancount")
+        );
+        // The tooltip is resolved lazily from the data
+        assert!(link.tooltip.is_none());
         assert!(link.data.is_some());
     }
 
+    #[test]
+    fn test_include_link_resolves_relative_path() {
+        let source = "2024-01-01 open Assets:Bank\ninclude \"../shared/./prices.beancount\"\n";
+        let links = links_for(source);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].range.start, Position::new(1, 9));
+        assert_eq!(
+            links[0].target.as_ref().map(|uri| uri.as_str()),
+            Some("file:///home/user/shared/prices.beancount")
+        );
+    }
+
     #[test]
     fn test_resolve_path_to_uri() {
         let base_dir = Some("/home/user/ledger".to_string());
@@ -4,14 +4,29 @@
 //! - rledger.insertDate: Insert today's date
 //! - rledger.sortTransactions: Sort transactions by date
 //! - rledger.alignAmounts: Align amounts in a region
+//! - rledger.exportJson: Export the parsed ledger as JSON
+//! - rledger.exportLedger: Convert the document to Ledger-CLI syntax
+//! - rledger.netWorth: Assets minus liabilities per currency
+//! - rledger.incomeStatement: Income and expenses for a date range
+//! - rledger.normalizePrecision: Round amounts to their commodity's declared precision
+//! - rledger.formatThousands: Insert or strip thousands separators in all amounts
+//! - rledger.flatten: Concatenate the document and its includes into one read-only view
 
 use chrono::Local;
-use lsp_types::{ExecuteCommandParams, TextEdit, Uri, WorkspaceEdit};
+use lsp_types::{
+    ExecuteCommandParams, FoldingRange, FoldingRangeKind, TextEdit, Uri, WorkspaceEdit,
+};
 use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
-use std::collections::HashMap;
+use rustledger_parser::{ParseResult, parse};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-use super::utils::byte_offset_to_position;
+use super::diagnostics::{resolve_include, round_to_precision};
+use super::formatting::thousands_separator_edits;
+use super::utils::{byte_offset_to_position, declared_precisions, units_amount_token};
+use crate::export::{to_json, to_ledger};
+use crate::report::{income_statement, net_worth};
+use crate::snapshot::WorldSnapshot;
 
 /// Available commands.
 pub const COMMANDS: &[&str] = &[
@@ -19,15 +34,23 @@ pub const COMMANDS: &[&str] = &[
     "rledger.sortTransactions",
     "rledger.alignAmounts",
     "rledger.showAccountBalance",
+    "rledger.exportJson",
+    "rledger.exportLedger",
+    "rledger.netWorth",
+    "rledger.incomeStatement",
+    "rledger.normalizePrecision",
+    "rledger.formatThousands",
+    "rledger.flatten",
 ];
 
 /// Handle an execute command request.
 pub fn handle_execute_command(
     params: &ExecuteCommandParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<serde_json::Value> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     match params.command.as_str() {
         "rledger.insertDate" => handle_insert_date(),
         "rledger.sortTransactions" => handle_sort_transactions(source, parse_result, uri),
@@ -35,6 +58,19 @@ pub fn handle_execute_command(
         "rledger.showAccountBalance" => {
             handle_show_account_balance(&params.arguments, parse_result)
         }
+        "rledger.exportJson" => Some(to_json(parse_result)),
+        "rledger.exportLedger" => Some(serde_json::json!({
+            "text": to_ledger(parse_result)
+        })),
+        "rledger.netWorth" => handle_net_worth(&params.arguments, parse_result),
+        "rledger.incomeStatement" => handle_income_statement(&params.arguments, parse_result),
+        "rledger.normalizePrecision" => handle_normalize_precision(source, parse_result, uri),
+        // Regrouping follows the configured separator style, inserting by default
+        "rledger.formatThousands" => handle_format_thousands(
+            source,
+            uri,
+            snapshot.config.thousands_separators.unwrap_or(true),
+        ),
         _ => {
             tracing::warn!("Unknown command: {}", params.command);
             None
@@ -242,6 +278,210 @@ fn handle_show_account_balance(
     }))
 }
 
+/// Compute net worth as of `date` (default: today).
+fn handle_net_worth(
+    arguments: &[serde_json::Value],
+    parse_result: &ParseResult,
+) -> Option<serde_json::Value> {
+    let as_of = date_argument(arguments, "date").unwrap_or_else(|| Local::now().date_naive());
+    let report = net_worth(parse_result, Some(as_of));
+
+    let mut value = serde_json::to_value(report).ok()?;
+    value["date"] = serde_json::json!(as_of.to_string());
+    Some(value)
+}
+
+/// Compute the income statement between `start` and `end` (both optional).
+fn handle_income_statement(
+    arguments: &[serde_json::Value],
+    parse_result: &ParseResult,
+) -> Option<serde_json::Value> {
+    let start = date_argument(arguments, "start");
+    let end = date_argument(arguments, "end");
+    let report = income_statement(parse_result, start, end);
+
+    let mut value = serde_json::to_value(report).ok()?;
+    value["start"] = serde_json::json!(start.map(|d| d.to_string()));
+    value["end"] = serde_json::json!(end.map(|d| d.to_string()));
+    Some(value)
+}
+
+/// Round posting units and balance amounts to the `precision` declared on
+/// their commodity.
+///
+/// Numbers are rounded half-to-even and padded to exactly that many
+/// decimals. Prices and costs are left alone, since rounding them would
+/// change transaction weights, as are amounts in currencies without a
+/// declared precision and numbers written with thousands separators.
+fn handle_normalize_precision(
+    source: &str,
+    parse_result: &ParseResult,
+    uri: &Uri,
+) -> Option<serde_json::Value> {
+    let precisions = declared_precisions(parse_result);
+
+    let mut edits: Vec<TextEdit> = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        if let Some((start, number, currency)) = units_amount_token(line) {
+            let Some(&precision) = precisions.get(currency) else {
+                continue;
+            };
+            let Some(new_text) = round_to_precision(number, precision) else {
+                continue;
+            };
+            if new_text == number {
+                continue;
+            }
+
+            let col = line[..start].encode_utf16().count() as u32;
+            edits.push(TextEdit {
+                range: lsp_types::Range {
+                    start: lsp_types::Position::new(line_idx as u32, col),
+                    end: lsp_types::Position::new(line_idx as u32, col + number.len() as u32),
+                },
+                new_text,
+            });
+        }
+    }
+
+    if edits.is_empty() {
+        return Some(serde_json::json!({
+            "message": "No amounts to normalize"
+        }));
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    let workspace_edit = WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+        change_annotations: None,
+    };
+
+    serde_json::to_value(workspace_edit).ok()
+}
+
+/// Insert (`grouping`) or strip comma thousands separators in every amount.
+fn handle_format_thousands(source: &str, uri: &Uri, grouping: bool) -> Option<serde_json::Value> {
+    let edits = thousands_separator_edits(source, grouping);
+    if edits.is_empty() {
+        return Some(serde_json::json!({
+            "message": "No amounts to regroup"
+        }));
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    let mut changes = HashMap::new();
+    changes.insert(uri.clone(), edits);
+
+    let workspace_edit = WorkspaceEdit {
+        changes: Some(changes),
+        document_changes: None,
+        change_annotations: None,
+    };
+
+    serde_json::to_value(workspace_edit).ok()
+}
+
+/// Flatten a document and everything it includes into a single text.
+///
+/// Each `include` line is replaced by a `; >>> included <path>` marker
+/// followed by the included file's content, itself flattened, and a folding
+/// range covers each included block. Includes that cannot be read are left
+/// as they are, and a file is only inlined once.
+///
+/// This needs to read other files, so the main loop dispatches it rather
+/// than [`handle_execute_command`].
+pub fn handle_flatten(
+    path: &Path,
+    source: &str,
+    read: impl Fn(&Path) -> Option<String>,
+) -> serde_json::Value {
+    let mut lines = Vec::new();
+    let mut folding_ranges = Vec::new();
+    let mut visited = HashSet::from([path.to_path_buf()]);
+    flatten_into(
+        path,
+        source,
+        &read,
+        &mut visited,
+        &mut lines,
+        &mut folding_ranges,
+    );
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    serde_json::json!({
+        "text": text,
+        "foldingRanges": folding_ranges,
+    })
+}
+
+/// Append the flattened lines of `source` to `lines`.
+fn flatten_into(
+    path: &Path,
+    source: &str,
+    read: &dyn Fn(&Path) -> Option<String>,
+    visited: &mut HashSet<PathBuf>,
+    lines: &mut Vec<String>,
+    folding_ranges: &mut Vec<FoldingRange>,
+) {
+    let parse_result = parse(source);
+    let includes: HashMap<u32, &str> = parse_result
+        .includes
+        .iter()
+        .map(|(include, span)| {
+            (
+                byte_offset_to_position(source, span.start).0,
+                include.as_str(),
+            )
+        })
+        .collect();
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let included = includes.get(&(line_idx as u32)).and_then(|include| {
+            let include_path = resolve_include(path, include)?;
+            if visited.contains(&include_path) {
+                return None;
+            }
+            let content = read(&include_path)?;
+            Some((*include, include_path, content))
+        });
+        let Some((include, include_path, content)) = included else {
+            lines.push(line.to_string());
+            continue;
+        };
+
+        visited.insert(include_path.clone());
+        let start_line = lines.len() as u32;
+        lines.push(format!("; >>> included {}", include));
+        flatten_into(
+            &include_path,
+            &content,
+            read,
+            visited,
+            lines,
+            folding_ranges,
+        );
+        folding_ranges.push(FoldingRange {
+            start_line,
+            start_character: None,
+            end_line: lines.len() as u32 - 1,
+            end_character: None,
+            kind: Some(FoldingRangeKind::Region),
+            collapsed_text: Some(format!("included {}", include)),
+        });
+    }
+}
+
+/// Read a `YYYY-MM-DD` date from the first command argument object.
+fn date_argument(arguments: &[serde_json::Value], key: &str) -> Option<chrono::NaiveDate> {
+    let value = arguments.first()?.get(key)?.as_str()?;
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+}
+
 /// Check if a line looks like a posting.
 fn is_posting_line(trimmed: &str) -> bool {
     trimmed.starts_with("Assets")
@@ -332,4 +572,152 @@ mod tests {
         let (start, _end) = pos.unwrap();
         assert!(line[start..].starts_with("100"));
     }
+
+    #[test]
+    fn test_export_json_command() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = ExecuteCommandParams {
+            command: "rledger.exportJson".to_string(),
+            arguments: vec![],
+            work_done_progress_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let value = handle_execute_command(&params, &snapshot).unwrap();
+        assert_eq!(value["directives"][0]["type"], "open");
+        assert_eq!(value["directives"][0]["account"], "Assets:Bank");
+    }
+
+    #[test]
+    fn test_net_worth_command() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-01 open Liabilities:Card USD
+2024-01-05 * "Salary"
+  Assets:Bank  500.00 USD
+  Income:Salary
+2024-01-06 * "Dinner"
+  Liabilities:Card  -30.00 USD
+  Expenses:Food
+2024-03-01 * "Bonus"
+  Assets:Bank  100.00 USD
+  Income:Salary
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = ExecuteCommandParams {
+            command: "rledger.netWorth".to_string(),
+            arguments: vec![serde_json::json!({ "date": "2024-01-31" })],
+            work_done_progress_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let value = handle_execute_command(&params, &snapshot).unwrap();
+        assert_eq!(value["date"], "2024-01-31");
+        assert_eq!(value["assets"]["USD"], "500.00");
+        assert_eq!(value["liabilities"]["USD"], "30.00");
+        assert_eq!(value["netWorth"]["USD"], "470.00");
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_normalize_precision_rounds_half_even() {
+        let source = r#"2024-01-01 commodity USD
+  precision: 2
+2024-01-05 * "Coffee" "5.005 USD in the narration stays"
+  Expenses:Food  5.005 USD
+  Expenses:Tips  5.015 USD
+  Expenses:Fees  7 USD
+  Assets:Cash  -2.5 EUR ; 1.234 USD in a comment stays
+  Assets:Bank
+2024-01-06 price EUR 1.0875 USD
+2024-01-07 * "Exchange"
+  Assets:Cash  10.5 EUR @ 1.0875 USD
+  Assets:Stocks  1 AAPL {100.125 USD}
+  Assets:Bank
+2024-01-31 balance Assets:Bank 4.999 USD
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = ExecuteCommandParams {
+            command: "rledger.normalizePrecision".to_string(),
+            arguments: vec![],
+            work_done_progress_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let value = handle_execute_command(&params, &snapshot).unwrap();
+        let edit: WorkspaceEdit = serde_json::from_value(value).unwrap();
+        let changes = edit.changes.unwrap();
+        let edits: Vec<(u32, u32, &str)> = changes[&uri]
+            .iter()
+            .map(|e| {
+                (
+                    e.range.start.line,
+                    e.range.start.character,
+                    e.new_text.as_str(),
+                )
+            })
+            .collect();
+
+        // Half-even: 5.005 rounds down to the even 5.00, 5.015 up to 5.02.
+        // Prices, costs and price directives keep their weight.
+        assert_eq!(
+            edits,
+            vec![
+                (3, 17, "5.00"),
+                (4, 17, "5.02"),
+                (5, 17, "7.00"),
+                (13, 31, "5.00"),
+            ]
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri has interior mutability but is safe in tests
+    fn test_format_thousands_strips_separators() {
+        let source = "2024-01-05 * \"Rent\" \"1,000 USD in the narration stays\"\n  Expenses:Rent  1,250.00 USD\n  Assets:Bank  -1,250.00 USD\n";
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+
+        let edit: WorkspaceEdit =
+            serde_json::from_value(handle_format_thousands(source, &uri, false).unwrap()).unwrap();
+        let changes = edit.changes.unwrap();
+        let edits: Vec<(u32, u32, &str)> = changes[&uri]
+            .iter()
+            .map(|e| {
+                (
+                    e.range.start.line,
+                    e.range.start.character,
+                    e.new_text.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(edits, vec![(1, 17, "1250.00"), (2, 15, "-1250.00")]);
+
+        let value = handle_format_thousands("2024-01-01 open Assets:Bank\n", &uri, true).unwrap();
+        assert_eq!(value["message"], "No amounts to regroup");
+    }
+
+    #[test]
+    fn test_flatten_inlines_includes_with_markers() {
+        let root = Path::new("/ledger/main.beancount");
+        let source = "include \"accounts.beancount\"\n2024-01-02 open Assets:Cash\n";
+        let read = |path: &Path| {
+            (path == Path::new("/ledger/accounts.beancount"))
+                .then(|| "2024-01-01 open Assets:Bank\n2024-01-01 open Expenses:Food\n".to_string())
+        };
+
+        let value = handle_flatten(root, source, read);
+        assert_eq!(
+            value["text"],
+            "; >>> included accounts.beancount\n\
+             2024-01-01 open Assets:Bank\n\
+             2024-01-01 open Expenses:Food\n\
+             2024-01-02 open Assets:Cash\n"
+        );
+
+        let ranges: Vec<FoldingRange> =
+            serde_json::from_value(value["foldingRanges"].clone()).unwrap();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].start_line, ranges[0].end_line), (0, 2));
+    }
 }
@@ -4,23 +4,26 @@
 //! - Multi-line transactions (with postings)
 //! - Sections marked by comments (e.g., "; === Section ===")
 //! - Consecutive directives of the same type
+//! - Runs of `price` directives for the same currency
 
 use lsp_types::{FoldingRange, FoldingRangeKind, FoldingRangeParams};
 use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 
 use super::utils::LineIndex;
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a folding range request.
 pub fn handle_folding_ranges(
     _params: &FoldingRangeParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<FoldingRange>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let mut ranges = Vec::new();
 
     // Build line index once for O(log n) lookups
-    let line_index = LineIndex::new(source);
+    let line_index = &snapshot.line_index;
 
     // Add folding ranges for transactions (multi-line)
     for spanned in &parse_result.directives {
@@ -44,6 +47,8 @@ pub fn handle_folding_ranges(
         }
     }
 
+    ranges.extend(price_run_ranges(parse_result, line_index));
+
     // Add folding ranges for comment sections
     let lines: Vec<&str> = source.lines().collect();
     let mut section_start: Option<(u32, &str)> = None;
@@ -122,6 +127,52 @@ pub fn handle_folding_ranges(
     }
 }
 
+/// Fold runs of consecutive `price` directives for the same currency.
+///
+/// A directive of any other kind, or a price for another currency, ends
+/// the run. Runs of a single price are not folded.
+fn price_run_ranges(parse_result: &ParseResult, line_index: &LineIndex) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    // (currency, first line, last line, count) of the current run
+    let mut run: Option<(&str, u32, u32, usize)> = None;
+
+    let mut close_run = |run: Option<(&str, u32, u32, usize)>| {
+        if let Some((currency, start_line, end_line, count)) = run {
+            if count > 1 {
+                ranges.push(FoldingRange {
+                    start_line,
+                    start_character: None,
+                    end_line,
+                    end_character: None,
+                    kind: Some(FoldingRangeKind::Region),
+                    collapsed_text: Some(format!("{currency} prices ({count})")),
+                });
+            }
+        }
+    };
+
+    for spanned in &parse_result.directives {
+        let Directive::Price(price) = &spanned.value else {
+            close_run(run.take());
+            continue;
+        };
+        let (line, _) = line_index.offset_to_position(spanned.span.start);
+        let currency = price.currency.as_str();
+        match &mut run {
+            Some((current, _, end_line, count)) if *current == currency => {
+                *end_line = line;
+                *count += 1;
+            }
+            _ => {
+                close_run(run.replace((currency, line, line, 1)));
+            }
+        }
+    }
+    close_run(run);
+
+    ranges
+}
+
 /// Format a transaction summary for collapsed text.
 fn format_transaction_summary(txn: &rustledger_core::Transaction) -> String {
     let date = txn.date.format("%Y-%m-%d");
@@ -165,7 +216,6 @@ fn is_section_header(line: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_folding_transaction() {
@@ -173,7 +223,8 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = FoldingRangeParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -181,8 +232,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let ranges = handle_folding_ranges(&params, source, &result);
+        let ranges = handle_folding_ranges(&params, &snapshot);
         assert!(ranges.is_some());
 
         let ranges = ranges.unwrap();
@@ -201,4 +251,30 @@ mod tests {
         assert!(!is_section_header("; Just a comment"));
         assert!(!is_section_header("2024-01-01 open Assets:Bank"));
     }
+
+    #[test]
+    fn test_folding_price_runs_by_currency() {
+        let source = r#"2024-01-01 price USD 1.35 CAD
+2024-01-02 price USD 1.36 CAD
+2024-01-03 price USD 1.34 CAD
+2024-01-04 price USD 1.37 CAD
+2024-01-05 price USD 1.35 CAD
+2024-01-05 price EUR 1.47 CAD
+2024-01-06 price USD 1.36 CAD
+"#;
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let params = FoldingRangeParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let ranges = handle_folding_ranges(&params, &snapshot).unwrap();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].start_line, 0);
+        assert_eq!(ranges[0].end_line, 4);
+        assert_eq!(ranges[0].collapsed_text.as_deref(), Some("USD prices (5)"));
+    }
 }
@@ -1,26 +1,29 @@
 //! Document formatting handler for Beancount files.
 //!
 //! Provides formatting for:
-//! - Consistent indentation (2 spaces for postings)
-//! - Aligned amounts in transactions
+//! - Consistent indentation (`indentWidth` spaces for postings)
+//! - Amounts aligned to `alignmentColumn` in transactions
+//! - Comma thousands separators inserted or stripped per `thousandsSeparators`
 //! - Consistent spacing around operators
 
 use lsp_types::{DocumentFormattingParams, Position, Range, TextEdit};
 use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
+use std::collections::HashSet;
 
-use super::utils::byte_offset_to_position;
-
-/// Default column for amount alignment.
-const AMOUNT_COLUMN: usize = 50;
+use super::utils::{amount_tokens, byte_offset_to_position};
+use crate::config::Config;
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a document formatting request.
 pub fn handle_formatting(
     _params: &DocumentFormattingParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<TextEdit>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let config = &*snapshot.config;
     let mut edits = Vec::new();
+    let indent = " ".repeat(config.indent_width);
     let lines: Vec<&str> = source.lines().collect();
 
     for spanned in &parse_result.directives {
@@ -32,7 +35,7 @@ pub fn handle_formatting(
                 let posting_line = start_line + 1 + i as u32;
 
                 if let Some(line) = lines.get(posting_line as usize) {
-                    if let Some(edit) = format_posting_line(line, posting_line, posting) {
+                    if let Some(edit) = format_posting_line(line, posting_line, posting, config) {
                         edits.push(edit);
                     }
                 }
@@ -44,7 +47,7 @@ pub fn handle_formatting(
     for (line_num, line) in lines.iter().enumerate() {
         // Fix tabs to spaces
         if line.contains('\t') {
-            let new_line = line.replace('\t', "  ");
+            let new_line = line.replace('\t', &indent);
             if new_line != *line {
                 edits.push(TextEdit {
                     range: Range {
@@ -69,6 +72,20 @@ pub fn handle_formatting(
         }
     }
 
+    // Regroup the numbers of amounts whose lines were not rewritten above
+    if let Some(grouping) = config.thousands_separators {
+        let rewritten: HashSet<u32> = edits
+            .iter()
+            .filter(|e| e.range.start.character == 0)
+            .map(|e| e.range.start.line)
+            .collect();
+        edits.extend(
+            thousands_separator_edits(source, grouping)
+                .into_iter()
+                .filter(|e| !rewritten.contains(&e.range.start.line)),
+        );
+    }
+
     // Remove duplicate edits and sort
     edits.sort_by(|a, b| {
         a.range
@@ -87,6 +104,7 @@ fn format_posting_line(
     line: &str,
     line_num: u32,
     posting: &rustledger_core::Posting,
+    config: &Config,
 ) -> Option<TextEdit> {
     let trimmed = line.trim();
 
@@ -100,7 +118,8 @@ fn format_posting_line(
 
     // Check if line starts with proper indentation
     let current_indent = line.len() - line.trim_start().len();
-    let expected_indent = 2;
+    let expected_indent = config.indent_width;
+    let amount_column = config.alignment_column;
 
     // Build the formatted line
     let mut formatted = String::new();
@@ -112,16 +131,28 @@ fn format_posting_line(
     formatted.push_str(&account);
 
     // Add amount if present
+    let mut regrouped = false;
     if let Some(ref units) = posting.units {
         if let (Some(num), Some(curr)) = (units.number(), units.currency()) {
-            let num_str = num.to_string();
+            let num_str = match config.thousands_separators {
+                Some(grouping) => regroup_number(&num.to_string(), grouping),
+                None => num.to_string(),
+            };
+            // Costs and prices are not rebuilt here, so leave those lines
+            // to `thousands_separator_edits`
+            regrouped = config.thousands_separators.is_some()
+                && posting.cost.is_none()
+                && posting.price.is_none()
+                && amount_tokens(line)
+                    .first()
+                    .is_some_and(|(_, number, _)| *number != num_str);
             let curr_str = curr.to_string();
             let amount_str = format!("{} {}", num_str, curr_str);
 
-            // Calculate padding to align amount at AMOUNT_COLUMN
+            // Calculate padding to align amount at the alignment column
             let current_len = expected_indent + account.len();
-            let padding = if current_len < AMOUNT_COLUMN - amount_str.len() {
-                AMOUNT_COLUMN - amount_str.len() - current_len
+            let padding = if current_len + amount_str.len() < amount_column {
+                amount_column - amount_str.len() - current_len
             } else {
                 2 // Minimum 2 spaces
             };
@@ -134,7 +165,7 @@ fn format_posting_line(
     // Check if formatting changed anything significant
     let line_trimmed_end = line.trim_end();
     if formatted.trim_end() != line_trimmed_end
-        && (current_indent != expected_indent || needs_alignment(line, &formatted))
+        && (current_indent != expected_indent || needs_alignment(line, &formatted) || regrouped)
     {
         Some(TextEdit {
             range: Range {
@@ -148,6 +179,52 @@ fn format_posting_line(
     }
 }
 
+/// Insert (`grouping`) or strip comma thousands separators in a number.
+pub(crate) fn regroup_number(number: &str, grouping: bool) -> String {
+    let plain: String = number.chars().filter(|&c| c != ',').collect();
+    if !grouping {
+        return plain;
+    }
+
+    let (sign, rest) = plain.split_at(usize::from(plain.starts_with(['-', '+'])));
+    let (whole, fraction) = rest.split_at(rest.find('.').unwrap_or(rest.len()));
+
+    let mut grouped = String::from(sign);
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped.push_str(fraction);
+    grouped
+}
+
+/// Edits regrouping the number of every amount in the source.
+///
+/// Amounts in quoted strings and comments are left alone.
+pub(crate) fn thousands_separator_edits(source: &str, grouping: bool) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        for (start, number, _) in amount_tokens(line) {
+            let new_text = regroup_number(number, grouping);
+            if new_text == number {
+                continue;
+            }
+
+            let col = line[..start].encode_utf16().count() as u32;
+            edits.push(TextEdit {
+                range: Range {
+                    start: Position::new(line_idx as u32, col),
+                    end: Position::new(line_idx as u32, col + number.len() as u32),
+                },
+                new_text,
+            });
+        }
+    }
+    edits
+}
+
 /// Check if line needs amount alignment.
 fn needs_alignment(original: &str, formatted: &str) -> bool {
     // Simple heuristic: if the formatted version has different spacing, align
@@ -162,11 +239,13 @@ fn needs_alignment(original: &str, formatted: &str) -> bool {
 mod tests {
     use super::*;
     use rustledger_parser::parse;
+    use std::sync::Arc;
 
     #[test]
     fn test_formatting_removes_trailing_whitespace() {
         let source = "2024-01-01 open Assets:Bank USD   \n";
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = DocumentFormattingParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -174,15 +253,15 @@ mod tests {
             options: Default::default(),
             work_done_progress_params: Default::default(),
         };
-
-        let edits = handle_formatting(&params, source, &result);
+        let edits = handle_formatting(&params, &snapshot);
         assert!(edits.is_some());
     }
 
     #[test]
     fn test_formatting_converts_tabs() {
         let source = "2024-01-01 * \"Test\"\n\tAssets:Bank\n";
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = DocumentFormattingParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -190,12 +269,83 @@ mod tests {
             options: Default::default(),
             work_done_progress_params: Default::default(),
         };
-
-        let edits = handle_formatting(&params, source, &result);
+        let edits = handle_formatting(&params, &snapshot);
         assert!(edits.is_some());
 
         let edits = edits.unwrap();
         // Should have edit to replace tab
         assert!(edits.iter().any(|e| e.new_text.contains("  ")));
     }
+
+    #[test]
+    fn test_formatting_uses_configured_layout() {
+        let source = "2024-01-01 * \"Test\"\n  Assets:Bank -5.00 USD\n  Expenses:Food\n";
+        let params = DocumentFormattingParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            options: Default::default(),
+            work_done_progress_params: Default::default(),
+        };
+        let config = Config {
+            indent_width: 4,
+            alignment_column: 30,
+            ..Config::default()
+        };
+
+        let snapshot = WorldSnapshot::with_parse_result(
+            "file:///test.beancount".parse().unwrap(),
+            source.to_string(),
+            Arc::new(parse(source)),
+            Arc::new(config),
+        );
+        let edits = handle_formatting(&params, &snapshot).unwrap();
+        let posting = edits.iter().find(|e| e.range.start.line == 1).unwrap();
+        assert_eq!(posting.new_text, "    Assets:Bank      -5.00 USD");
+        assert_eq!(posting.new_text.len(), 30);
+    }
+
+    #[test]
+    fn test_regroup_number() {
+        assert_eq!(regroup_number("1234567.50", true), "1,234,567.50");
+        assert_eq!(regroup_number("-1234", true), "-1,234");
+        assert_eq!(regroup_number("+1000", true), "+1,000");
+        assert_eq!(regroup_number("999.999", true), "999.999");
+        assert_eq!(regroup_number("-1,234,567.5", false), "-1234567.5");
+    }
+
+    #[test]
+    fn test_formatting_inserts_thousands_separators() {
+        let source = "2024-01-01 * \"Rent\"\n  Assets:Bank      1234.56 USD\n  Expenses:Rent\n2024-01-31 balance Assets:Bank 1234.56 USD\n";
+        let params = DocumentFormattingParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            options: Default::default(),
+            work_done_progress_params: Default::default(),
+        };
+        let config = Config {
+            alignment_column: 30,
+            thousands_separators: Some(true),
+            ..Config::default()
+        };
+
+        let snapshot = WorldSnapshot::with_parse_result(
+            "file:///test.beancount".parse().unwrap(),
+            source.to_string(),
+            Arc::new(parse(source)),
+            Arc::new(config),
+        );
+        let edits = handle_formatting(&params, &snapshot).unwrap();
+
+        // The posting is realigned for the longer number
+        let posting = edits.iter().find(|e| e.range.start.line == 1).unwrap();
+        assert_eq!(posting.new_text, "  Assets:Bank     1,234.56 USD");
+        assert_eq!(posting.new_text.len(), 30);
+
+        // Other amounts are regrouped in place
+        let balance = edits.iter().find(|e| e.range.start.line == 3).unwrap();
+        assert_eq!(balance.range.start.character, 31);
+        assert_eq!(balance.new_text, "1,234.56");
+    }
 }
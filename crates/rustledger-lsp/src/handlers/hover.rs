@@ -2,21 +2,30 @@
 //!
 //! Provides hover information for:
 //! - Accounts: open date, currencies, metadata
-//! - Currencies: commodity directive info
-//! - Transactions: posting summary
+//! - Account roots (`Assets`, `Expenses`, ...): subtree totals per currency
+//! - Currencies: commodity directive info, led by its `name` metadata
+//! - Transactions: per-currency totals, balance status, and elided amounts
+//! - Pads: the balance assertions they pair with and the padding inserted
+//! - Include paths: the directive count and date range of the included file
 
 use lsp_types::{Hover, HoverContents, HoverParams, MarkupContent, MarkupKind};
-use rustledger_core::Directive;
+use rustledger_booking::{calculate_tolerance, interpolate, is_balanced, process_pads};
+use rustledger_core::{Account, Decimal, Directive, MetaValue, Pad, Transaction};
 use rustledger_parser::ParseResult;
+use std::path::Path;
+use std::sync::Arc;
 
-use super::utils::{get_word_at_source_position, is_account_type, is_currency_like_simple};
+use super::diagnostics::{resolve_include, transaction_residuals};
+use super::utils::{
+    get_word_at_position, get_word_at_source_position, is_account_type, is_currency_like_simple,
+};
+use crate::report::balances_by_prefix;
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a hover request.
-pub fn handle_hover(
-    params: &HoverParams,
-    source: &str,
-    parse_result: &ParseResult,
-) -> Option<Hover> {
+pub fn handle_hover(params: &HoverParams, snapshot: &WorldSnapshot) -> Option<Hover> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let position = params.text_document_position_params.position;
 
     // Get the word at the cursor position
@@ -24,6 +33,17 @@ pub fn handle_hover(
 
     tracing::debug!("Hover for word: {:?}", word);
 
+    // Check if it's an account root, bare or as the first segment of an account
+    if let Some(root) = account_root_at(source, position) {
+        return Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: get_account_root_info(&root, parse_result),
+            }),
+            range: None,
+        });
+    }
+
     // Check if it's an account name
     if word.contains(':') || is_account_type(&word) {
         if let Some(info) = get_account_info(&word, parse_result) {
@@ -50,6 +70,28 @@ pub fn handle_hover(
         }
     }
 
+    // Check if it's on a transaction header (date, payee, or narration)
+    if let Some(txn) = transaction_at_line(snapshot, position.line) {
+        return Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: get_transaction_info(txn),
+            }),
+            range: None,
+        });
+    }
+
+    // Check if it's on a pad directive
+    if let Some(pad) = pad_at_line(snapshot, position.line) {
+        return Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: get_pad_info(pad, parse_result),
+            }),
+            range: None,
+        });
+    }
+
     // Check if it's a directive keyword
     if let Some(info) = get_directive_info(&word) {
         return Some(Hover {
@@ -64,15 +106,108 @@ pub fn handle_hover(
     None
 }
 
+/// Handle a hover over the path of an `include` directive.
+///
+/// `path` is the hovered document's path, used to resolve the include, and
+/// `load` fetches the parse result of the included file.
+pub fn handle_include_hover(
+    params: &HoverParams,
+    snapshot: &WorldSnapshot,
+    path: &Path,
+    load: impl Fn(&Path) -> Option<Arc<ParseResult>>,
+) -> Option<Hover> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let position = params.text_document_position_params.position;
+    let line_index = &snapshot.line_index;
+
+    let (include, range) = parse_result.includes.iter().find_map(|(include, span)| {
+        // The quoted path within the directive
+        let start = span.start + source[span.start..span.end].find(include.as_str())?;
+        let (line, start_col) = line_index.offset_to_position(start);
+        let (_, end_col) = line_index.offset_to_position(start + include.len());
+        let on_path = line == position.line
+            && start_col <= position.character
+            && position.character <= end_col;
+        on_path.then(|| {
+            let range = lsp_types::Range::new(
+                lsp_types::Position::new(line, start_col),
+                lsp_types::Position::new(line, end_col),
+            );
+            (include, range)
+        })
+    })?;
+
+    let info = match resolve_include(path, include).and_then(|p| load(&p)) {
+        Some(included) => get_include_info(include, &included),
+        None => format!("## Include: `{}`\n\n**Note:** File not found", include),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: info,
+        }),
+        range: Some(range),
+    })
+}
+
+/// Summarize an included file's directives.
+fn get_include_info(include: &str, included: &ParseResult) -> String {
+    let mut info = format!("## Include: `{}`\n\n", include);
+    info.push_str(&format!("**Directives:** {}", included.directives.len()));
+
+    let dates = included.directives.iter().map(|d| d.value.date());
+    if let (Some(first), Some(last)) = (dates.clone().min(), dates.max()) {
+        info.push_str(&format!("\n\n**Dates:** {} to {}", first, last));
+    }
+    info
+}
+
+/// Find the account root (e.g. `Expenses`) under the cursor.
+///
+/// Matches a bare root word, or the first segment of an account name such
+/// as the `Expenses` in `Expenses:Food`.
+fn account_root_at(source: &str, position: lsp_types::Position) -> Option<String> {
+    let line = source.lines().nth(position.line as usize)?;
+    let (word, start, _) = get_word_at_position(line, position.character as usize)?;
+    let account = Account::new(word.as_str());
+    let root = account.components().next()?;
+    let on_root = position.character as usize <= start + root.len();
+    (on_root && is_account_type(root)).then(|| root.to_string())
+}
+
+/// Get the aggregated balance of every account under a root.
+fn get_account_root_info(root: &str, parse_result: &ParseResult) -> String {
+    let mut info = format!("## Account root: `{}`\n\n", root);
+
+    let total = balances_by_prefix(parse_result, root);
+    let currencies = total.currencies();
+    if currencies.is_empty() {
+        info.push_str("**Balance:** none");
+        return info;
+    }
+
+    info.push_str("| Currency | Total |\n|---|---|\n");
+    for currency in currencies {
+        info.push_str(&format!("| {} | {} |\n", currency, total.units(currency)));
+    }
+    info
+}
+
 /// Get information about an account.
 fn get_account_info(account: &str, parse_result: &ParseResult) -> Option<String> {
     // Find the open directive for this account
-    for spanned_directive in &parse_result.directives {
+    for (index, spanned_directive) in parse_result.directives.iter().enumerate() {
         if let Directive::Open(open) = &spanned_directive.value {
             let open_account = open.account.to_string();
             if open_account == account || account.starts_with(&format!("{}:", open_account)) {
                 let mut info = format!("## Account: `{}`\n\n", open_account);
 
+                // Add the comment documenting the open directive
+                if let Some(comment) = parse_result.leading_comments.get(&index) {
+                    info.push_str(&format!("{}\n\n", comment.value));
+                }
+
                 // Add open date
                 info.push_str(&format!("**Opened:** {}\n\n", open.date));
 
@@ -126,6 +261,12 @@ fn get_currency_info(currency: &str, parse_result: &ParseResult) -> Option<Strin
         if let Directive::Commodity(comm) = &spanned_directive.value {
             if comm.currency.as_ref() == currency {
                 let mut info = format!("## Currency: `{}`\n\n", currency);
+
+                // Lead with the friendly name, e.g. `name: "Apple Inc."`
+                if let Some(MetaValue::String(name)) = comm.meta.get("name") {
+                    info.push_str(&format!("**{}**\n\n", name));
+                }
+
                 info.push_str(&format!("**Defined:** {}\n", comm.date));
 
                 // Count usages
@@ -177,6 +318,179 @@ fn count_currency_usages(currency: &str, parse_result: &ParseResult) -> usize {
     count
 }
 
+/// Find the transaction whose header is on the given line.
+fn transaction_at_line(snapshot: &WorldSnapshot, line: u32) -> Option<&Transaction> {
+    let line_index = &snapshot.line_index;
+    snapshot
+        .parse_result
+        .directives
+        .iter()
+        .find_map(|spanned| match &spanned.value {
+            Directive::Transaction(txn)
+                if line_index.offset_to_position(spanned.span.start).0 == line =>
+            {
+                Some(txn)
+            }
+            _ => None,
+        })
+}
+
+/// Summarize a transaction's totals per currency and whether it balances.
+///
+/// Elided posting amounts are filled in by interpolation first, and listed
+/// with the amount they resolve to.
+fn get_transaction_info(txn: &Transaction) -> String {
+    let mut info = String::from("## Transaction\n\n");
+
+    let (resolved, filled) = match interpolate(txn) {
+        Ok(result) => (result.transaction, result.filled_indices),
+        Err(err) => {
+            info.push_str(&format!("**Cannot interpolate:** {}\n\n", err));
+            (txn.clone(), Vec::new())
+        }
+    };
+
+    // The total moved per currency is the sum of the positive weights
+    let mut totals: Vec<(String, Decimal)> = Vec::new();
+    for posting in &resolved.postings {
+        let single = Transaction {
+            postings: vec![posting.clone()],
+            ..resolved.clone()
+        };
+        for (currency, weight) in transaction_residuals(&single) {
+            if !weight.is_sign_positive() {
+                continue;
+            }
+            match totals.iter_mut().find(|(c, _)| *c == currency) {
+                Some((_, total)) => *total += weight,
+                None => totals.push((currency, weight)),
+            }
+        }
+    }
+    let residuals = transaction_residuals(&resolved);
+
+    info.push_str("| Currency | Total | Residual |\n|---|---:|---:|\n");
+    for (currency, residual) in &residuals {
+        let total = totals
+            .iter()
+            .find(|(c, _)| c == currency)
+            .map(|(_, t)| *t)
+            .unwrap_or_default();
+        info.push_str(&format!("| {} | {} | {} |\n", currency, total, residual));
+    }
+
+    let amounts: Vec<_> = resolved
+        .postings
+        .iter()
+        .filter_map(|p| p.amount())
+        .collect();
+    if is_balanced(&resolved, &calculate_tolerance(&amounts)) {
+        info.push_str("\n**Status:** balanced\n");
+    } else {
+        info.push_str("\n**Status:** unbalanced\n");
+    }
+
+    if !filled.is_empty() {
+        info.push_str("\n**Elided amounts:**\n");
+        for index in filled {
+            let posting = &resolved.postings[index];
+            if let Some(amount) = posting.amount() {
+                info.push_str(&format!(
+                    "- `{}`: {} {}\n",
+                    posting.account, amount.number, amount.currency
+                ));
+            }
+        }
+    }
+
+    info
+}
+
+/// Find the pad directive on the given line.
+fn pad_at_line(snapshot: &WorldSnapshot, line: u32) -> Option<&Pad> {
+    let line_index = &snapshot.line_index;
+    snapshot
+        .parse_result
+        .directives
+        .iter()
+        .find_map(|spanned| match &spanned.value {
+            Directive::Pad(pad) if line_index.offset_to_position(spanned.span.start).0 == line => {
+                Some(pad)
+            }
+            _ => None,
+        })
+}
+
+/// Describe the balance assertions a pad pairs with and the padding the
+/// pad-resolution engine inserts for them.
+///
+/// A pad applies to every later balance of its account until the next pad
+/// for that account replaces it.
+fn get_pad_info(pad: &Pad, parse_result: &ParseResult) -> String {
+    let mut info = format!("## Pad `{}` from `{}`\n\n", pad.account, pad.source_account);
+
+    // Directives in the order the engine processes them: by date, then file order
+    let mut sorted: Vec<&Directive> = parse_result.directives.iter().map(|s| &s.value).collect();
+    sorted.sort_by_key(|d| d.date());
+
+    let balances: Vec<_> = sorted
+        .iter()
+        .skip_while(|d| !matches!(d, Directive::Pad(p) if std::ptr::eq(p, pad)))
+        .skip(1)
+        .take_while(|d| !matches!(d, Directive::Pad(p) if p.account == pad.account))
+        .filter_map(|d| match d {
+            Directive::Balance(bal) if bal.account == pad.account => Some(bal),
+            _ => None,
+        })
+        .collect();
+
+    if balances.is_empty() {
+        info.push_str(&format!(
+            "**Inactive:** no later `balance` assertion for `{}`.\n",
+            pad.account
+        ));
+        return info;
+    }
+
+    info.push_str("**Balance assertions:**\n");
+    for bal in &balances {
+        info.push_str(&format!(
+            "- {}: {} {}\n",
+            bal.date, bal.amount.number, bal.amount.currency
+        ));
+    }
+
+    let directives: Vec<Directive> = sorted.into_iter().cloned().collect();
+    let padding: Vec<_> = process_pads(&directives)
+        .padding_transactions
+        .into_iter()
+        .filter(|txn| {
+            txn.date == pad.date
+                && txn
+                    .postings
+                    .first()
+                    .is_some_and(|p| p.account == pad.account)
+                && txn
+                    .postings
+                    .get(1)
+                    .is_some_and(|p| p.account == pad.source_account)
+        })
+        .collect();
+
+    if padding.is_empty() {
+        info.push_str("\n**Padding:** none needed, the balance already matches\n");
+    } else {
+        info.push_str("\n**Padding inserted:**\n");
+        for txn in &padding {
+            if let Some(amount) = txn.postings[0].amount() {
+                info.push_str(&format!("- {} {}\n", amount.number, amount.currency));
+            }
+        }
+    }
+
+    info
+}
+
 /// Get information about a directive keyword.
 fn get_directive_info(keyword: &str) -> Option<String> {
     let info = match keyword {
@@ -246,5 +560,174 @@ mod tests {
         assert!(get_directive_info("unknown").is_none());
     }
 
+    fn hover_at(source: &str, line: u32, character: u32) -> Option<String> {
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier {
+                    uri: "file:///test.beancount".parse().unwrap(),
+                },
+                position: lsp_types::Position::new(line, character),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        match handle_hover(&params, &snapshot)?.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_hover_transaction_totals() {
+        let source = r#"2024-01-15 * "Cafe" "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food  5.00 USD
+"#;
+        // Hover on the payee
+        let info = hover_at(source, 0, 15).unwrap();
+        assert!(info.contains("| USD | 5.00 | 0.00 |"));
+        assert!(info.contains("balanced"));
+        assert!(!info.contains("unbalanced"));
+
+        // Hover on the date
+        assert_eq!(hover_at(source, 0, 3), Some(info));
+    }
+
+    #[test]
+    fn test_hover_transaction_elided_amount() {
+        let source = r#"2024-01-15 * "Coffee"
+  Expenses:Food  5.00 USD
+  Assets:Bank
+"#;
+        let info = hover_at(source, 0, 15).unwrap();
+        assert!(info.contains("`Assets:Bank`: -5.00 USD"));
+        assert!(info.contains("**Status:** balanced"));
+    }
+
+    #[test]
+    fn test_hover_account_shows_open_comment() {
+        let source = r#"; main checking
+2024-01-01 open Assets:Checking USD
+2024-01-05 * "Coffee"
+  Expenses:Food  5.00 USD
+  Assets:Checking
+"#;
+        // Hovering the account where it is used
+        let info = hover_at(source, 4, 12).unwrap();
+        assert!(info.contains("## Account: `Assets:Checking`\n\nmain checking\n\n"));
+    }
+
+    #[test]
+    fn test_hover_currency_shows_commodity_name() {
+        let source = r#"2024-01-01 commodity AAPL
+  name: "Apple Inc."
+2024-01-01 open Assets:Stocks AAPL
+"#;
+        // Hovering the currency where it is used
+        let info = hover_at(source, 2, 32).unwrap();
+        assert!(info.starts_with("## Currency: `AAPL`\n\n**Apple Inc.**\n\n"));
+        assert!(info.contains("**Defined:** 2024-01-01"));
+    }
+
+    #[test]
+    fn test_hover_include_shows_directive_count() {
+        let source = "include \"accounts.beancount\"\n";
+        let included = rustledger_parser::parse(
+            "2024-01-01 open Assets:Bank\n2024-03-01 open Assets:Cash\n2024-02-01 close Assets:Bank\n",
+        );
+        let included = Arc::new(included);
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier {
+                    uri: "file:///ledger/main.beancount".parse().unwrap(),
+                },
+                position: lsp_types::Position::new(0, 12),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        let snapshot = WorldSnapshot::new("file:///ledger/main.beancount".parse().unwrap(), source);
+        let load =
+            |p: &Path| (p == Path::new("/ledger/accounts.beancount")).then(|| included.clone());
+
+        let hover = handle_include_hover(
+            &params,
+            &snapshot,
+            Path::new("/ledger/main.beancount"),
+            load,
+        )
+        .unwrap();
+        let HoverContents::Markup(markup) = hover.contents else {
+            panic!("expected markdown");
+        };
+        assert!(markup.value.contains("**Directives:** 3"));
+        assert!(markup.value.contains("**Dates:** 2024-01-01 to 2024-03-01"));
+        assert_eq!(
+            hover.range,
+            Some(lsp_types::Range::new(
+                lsp_types::Position::new(0, 9),
+                lsp_types::Position::new(0, 27)
+            ))
+        );
+
+        // Off the path, e.g. on the keyword, there is no include preview
+        let mut params = params;
+        params.text_document_position_params.position.character = 3;
+        assert!(
+            handle_include_hover(
+                &params,
+                &snapshot,
+                Path::new("/ledger/main.beancount"),
+                load
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_hover_account_root_sums_subtree() {
+        let source = r#"2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Rent
+2024-01-05 * "Groceries"
+  Expenses:Food  40.00 USD
+  Assets:Bank  -40.00 USD
+2024-01-06 * "Rent"
+  Expenses:Rent  900.00 USD
+  Assets:Bank  -900.00 USD
+; Expenses this month
+"#;
+        // On the `Expenses` segment of an open directive
+        let info = hover_at(source, 0, 18).unwrap();
+        assert!(info.contains("## Account root: `Expenses`"));
+        assert!(info.contains("| USD | 940.00 |"));
+
+        // A bare root in text gives the same summary
+        assert_eq!(hover_at(source, 8, 4), Some(info));
+
+        // Past the root segment, the account hover is shown instead
+        let food = hover_at(source, 0, 26).unwrap();
+        assert!(food.contains("## Account: `Expenses:Food`"));
+    }
+
+    #[test]
+    fn test_hover_pad_shows_padding_amount() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-01 open Equity:Opening
+2024-01-01 pad Assets:Bank Equity:Opening
+2024-01-10 * "Deposit"
+  Assets:Bank  200.00 USD
+  Equity:Opening  -200.00 USD
+2024-02-01 balance Assets:Bank 1000.00 USD
+2024-03-01 pad Assets:Bank Equity:Opening
+"#;
+        let info = hover_at(source, 2, 3).unwrap();
+        assert!(info.contains("## Pad `Assets:Bank` from `Equity:Opening`"));
+        assert!(info.contains("- 2024-02-01: 1000.00 USD"));
+        assert!(info.contains("**Padding inserted:**\n- 800.00 USD"));
+
+        // The later pad has no balance to pair with
+        let info = hover_at(source, 7, 3).unwrap();
+        assert!(info.contains("**Inactive:**"));
+    }
+
     // Tests for shared utilities removed - they are tested in utils module
 }
@@ -11,14 +11,16 @@ use rustledger_core::{Decimal, Directive};
 use rustledger_parser::ParseResult;
 use std::collections::HashMap;
 
-use super::utils::byte_offset_to_position;
+use super::utils::{account_transaction_count, byte_offset_to_position};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle an inlay hints request.
 pub fn handle_inlay_hints(
     params: &InlayHintParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<InlayHint>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let range = params.range;
     let uri = params.text_document.uri.as_str();
     let mut hints = Vec::new();
@@ -84,7 +86,8 @@ pub fn handle_inlay_hints(
 
 /// Handle an inlay hint resolve request.
 /// Adds rich tooltip with account balance information.
-pub fn handle_inlay_hint_resolve(hint: InlayHint, parse_result: &ParseResult) -> InlayHint {
+pub fn handle_inlay_hint_resolve(hint: InlayHint, snapshot: &WorldSnapshot) -> InlayHint {
+    let parse_result = &*snapshot.parse_result;
     let mut resolved = hint.clone();
 
     // Check if we have data to resolve
@@ -118,14 +121,13 @@ fn build_account_tooltip(
     parse_result: &ParseResult,
 ) -> String {
     let mut balances: HashMap<String, Decimal> = HashMap::new();
-    let mut transaction_count = 0;
+    let transaction_count = account_transaction_count(parse_result, account);
 
     // Calculate running balance for this account
     for spanned in &parse_result.directives {
         if let Directive::Transaction(txn) = &spanned.value {
             for posting in &txn.postings {
                 if posting.account.as_ref() == account {
-                    transaction_count += 1;
                     if let Some(units) = &posting.units {
                         if let Some(number) = units.number() {
                             let curr = units.currency().unwrap_or("???").to_string();
@@ -194,7 +196,8 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = InlayHintParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -205,8 +208,7 @@ mod tests {
             },
             work_done_progress_params: Default::default(),
         };
-
-        let hints = handle_inlay_hints(&params, source, &result);
+        let hints = handle_inlay_hints(&params, &snapshot);
         assert!(hints.is_some());
 
         let hints = hints.unwrap();
@@ -248,7 +250,7 @@ mod tests {
   Assets:Bank  -10.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
 
         // Create a hint with data that would be resolved
         let hint = InlayHint {
@@ -267,7 +269,7 @@ mod tests {
             })),
         };
 
-        let resolved = handle_inlay_hint_resolve(hint, &result);
+        let resolved = handle_inlay_hint_resolve(hint, &snapshot);
 
         // Should now have a tooltip
         assert!(resolved.tooltip.is_some());
@@ -9,15 +9,17 @@ use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 
 use super::utils::{
-    byte_offset_to_position, get_word_at_position, is_account_like, is_currency_like,
+    byte_offset_to_position, get_word_at_position, is_account_like, is_currency_like, is_word_char,
 };
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a linked editing range request.
 pub fn handle_linked_editing_range(
     params: &LinkedEditingRangeParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<LinkedEditingRanges> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let position = params.text_document_position_params.position;
     let line_idx = position.line as usize;
     let lines: Vec<&str> = source.lines().collect();
@@ -121,9 +123,18 @@ fn collect_account_ranges(
                 }
             }
             Directive::Transaction(txn) => {
-                for (i, posting) in txn.postings.iter().enumerate() {
-                    if posting.account.as_ref() == account {
-                        let posting_line = start_line + 1 + i as u32;
+                if !txn.postings.iter().any(|p| p.account.as_ref() == account) {
+                    continue;
+                }
+                // Scan the posting lines (metadata lines may sit between them)
+                let directive_text = &source[spanned.span.start..spanned.span.end];
+                for (line_offset, line) in directive_text.lines().enumerate().skip(1) {
+                    let posting = line.trim_start();
+                    let posting = posting
+                        .strip_prefix(['!', '*'])
+                        .map_or(posting, str::trim_start);
+                    if posting.split_whitespace().next() == Some(account) {
+                        let posting_line = start_line + line_offset as u32;
                         if let Some(range) = find_in_line(source, posting_line, account) {
                             ranges.push(range);
                         }
@@ -188,10 +199,17 @@ fn collect_currency_ranges(
     ranges.dedup();
 }
 
-/// Find a string in a specific line.
+/// Find a whole-word occurrence of a string in a specific line.
 fn find_in_line(source: &str, line_num: u32, needle: &str) -> Option<Range> {
     let line = source.lines().nth(line_num as usize)?;
-    let col = line.find(needle)?;
+    let col = line.match_indices(needle).map(|(i, _)| i).find(|&i| {
+        let before_ok = !line[..i].chars().next_back().is_some_and(is_word_char);
+        let after_ok = !line[i + needle.len()..]
+            .chars()
+            .next()
+            .is_some_and(is_word_char);
+        before_ok && after_ok
+    })?;
     Some(Range {
         start: Position::new(line_num, col as u32),
         end: Position::new(line_num, (col + needle.len()) as u32),
@@ -201,7 +219,6 @@ fn find_in_line(source: &str, line_num: u32, needle: &str) -> Option<Range> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_linked_editing_account() {
@@ -211,18 +228,18 @@ mod tests {
   Expenses:Food
 2024-01-31 balance Assets:Bank 100 USD
 "#;
-        let result = parse(source);
         let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
 
         let params = LinkedEditingRangeParams {
             text_document_position_params: lsp_types::TextDocumentPositionParams {
-                text_document: lsp_types::TextDocumentIdentifier { uri },
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
                 position: Position::new(0, 16), // On "Assets:Bank"
             },
             work_done_progress_params: Default::default(),
         };
 
-        let result = handle_linked_editing_range(&params, source, &result);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let result = handle_linked_editing_range(&params, &snapshot);
         assert!(result.is_some());
 
         let ranges = result.unwrap();
@@ -239,22 +256,52 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food  5.00 USD
 "#;
-        let result = parse(source);
         let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
 
         let params = LinkedEditingRangeParams {
             text_document_position_params: lsp_types::TextDocumentPositionParams {
-                text_document: lsp_types::TextDocumentIdentifier { uri },
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
                 position: Position::new(0, 28), // On "USD"
             },
             work_done_progress_params: Default::default(),
         };
 
-        let result = handle_linked_editing_range(&params, source, &result);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let result = handle_linked_editing_range(&params, &snapshot);
         assert!(result.is_some());
 
         let ranges = result.unwrap();
         // Should find USD in: open, posting 1, posting 2 = 3 ranges
         assert_eq!(ranges.ranges.len(), 3);
     }
+
+    #[test]
+    fn test_linked_editing_account_in_two_postings() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-01 open Assets:Bank2 USD
+2024-01-15 * "Transfer"
+  memo: "split"
+  Assets:Bank  -5.00 USD
+  Assets:Bank2  2.00 USD
+  Assets:Bank   3.00 USD
+"#;
+        let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
+
+        let params = LinkedEditingRangeParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(4, 5), // On "Assets:Bank" in the first posting
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let ranges = handle_linked_editing_range(&params, &snapshot).unwrap();
+        let lines: Vec<u32> = ranges.ranges.iter().map(|r| r.start.line).collect();
+        // Open plus both postings, but not Assets:Bank2
+        assert_eq!(lines, vec![0, 4, 6]);
+        for range in &ranges.ranges {
+            assert_eq!(range.end.character - range.start.character, 11);
+        }
+    }
 }
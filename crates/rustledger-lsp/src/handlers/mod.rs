@@ -30,5 +30,6 @@ pub mod selection_range;
 pub mod semantic_tokens;
 pub mod signature_help;
 pub mod symbols;
+pub mod type_definition;
 pub mod type_hierarchy;
 pub mod workspace_symbols;
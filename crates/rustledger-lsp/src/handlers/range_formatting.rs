@@ -4,17 +4,20 @@
 
 use lsp_types::{DocumentRangeFormattingParams, Position, Range, TextEdit};
 use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
 
 use super::utils::byte_offset_to_position;
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a range formatting request.
 pub fn handle_range_formatting(
     params: &DocumentRangeFormattingParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<TextEdit>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let config = &*snapshot.config;
     let range = params.range;
+    let indent = " ".repeat(config.indent_width);
     let mut edits = Vec::new();
     let lines: Vec<&str> = source.lines().collect();
 
@@ -23,7 +26,7 @@ pub fn handle_range_formatting(
         if let Some(line) = lines.get(line_num as usize) {
             // Fix tabs to spaces
             if line.contains('\t') {
-                let new_line = line.replace('\t', "  ");
+                let new_line = line.replace('\t', &indent);
                 if new_line != *line {
                     edits.push(TextEdit {
                         range: Range {
@@ -66,7 +69,9 @@ pub fn handle_range_formatting(
                 // Check if posting is within range
                 if posting_line >= range.start.line && posting_line <= range.end.line {
                     if let Some(line) = lines.get(posting_line as usize) {
-                        if let Some(edit) = format_posting_line(line, posting_line, posting) {
+                        if let Some(edit) =
+                            format_posting_line(line, posting_line, posting, config.indent_width)
+                        {
                             // Don't duplicate edits
                             if !edits.iter().any(|e| e.range.start.line == posting_line) {
                                 edits.push(edit);
@@ -96,6 +101,7 @@ fn format_posting_line(
     line: &str,
     line_num: u32,
     posting: &rustledger_core::Posting,
+    expected_indent: usize,
 ) -> Option<TextEdit> {
     let trimmed = line.trim();
 
@@ -106,7 +112,6 @@ fn format_posting_line(
 
     let account = posting.account.to_string();
     let current_indent = line.len() - line.trim_start().len();
-    let expected_indent = 2;
 
     // Only fix indentation issues
     if current_indent != expected_indent {
@@ -130,12 +135,12 @@ fn format_posting_line(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_range_formatting() {
         let source = "2024-01-01 open Assets:Bank USD   \n";
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = DocumentRangeFormattingParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -147,8 +152,7 @@ mod tests {
             options: Default::default(),
             work_done_progress_params: Default::default(),
         };
-
-        let edits = handle_range_formatting(&params, source, &result);
+        let edits = handle_range_formatting(&params, &snapshot);
         assert!(edits.is_some());
     }
 }
@@ -8,6 +8,7 @@
 use super::utils::{
     byte_offset_to_position, get_word_at_position, is_account_like, is_currency_like,
 };
+use crate::snapshot::WorldSnapshot;
 use lsp_types::{Location, Position, Range, ReferenceParams, Uri};
 use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
@@ -15,10 +16,11 @@ use rustledger_parser::ParseResult;
 /// Handle a find references request.
 pub fn handle_references(
     params: &ReferenceParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<Location>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let position = params.text_document_position.position;
     let include_declaration = params.context.include_declaration;
 
@@ -352,7 +354,6 @@ fn is_in_quotes(line: &str, col: usize) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_find_account_references() {
@@ -362,7 +363,6 @@ mod tests {
   Expenses:Food
 2024-01-31 balance Assets:Bank 100 USD
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         let params = ReferenceParams {
@@ -377,7 +377,8 @@ mod tests {
             },
         };
 
-        let refs = handle_references(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let refs = handle_references(&params, &snapshot);
         assert!(refs.is_some());
 
         let refs = refs.unwrap();
@@ -392,7 +393,6 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food  5.00 USD
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         let params = ReferenceParams {
@@ -407,7 +407,8 @@ mod tests {
             },
         };
 
-        let refs = handle_references(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let refs = handle_references(&params, &snapshot);
         assert!(refs.is_some());
 
         let refs = refs.unwrap();
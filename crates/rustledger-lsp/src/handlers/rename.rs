@@ -2,37 +2,51 @@
 //!
 //! Supports renaming:
 //! - Account names (updates all usages in the file)
-//! - Currency names (updates all usages in the file)
+//! - Currency names (updates all usages across the include graph)
+//! - Tags (updates transactions and `pushtag`/`poptag` lines across the include graph)
 
 use lsp_types::{
     Position, PrepareRenameResponse, Range, RenameParams, TextDocumentPositionParams, TextEdit,
-    WorkspaceEdit,
+    Uri, WorkspaceEdit,
 };
 use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 use std::collections::HashMap;
 
 use super::utils::{
-    byte_offset_to_position, get_word_at_position, is_account_like, is_currency_like,
+    byte_offset_to_position, get_word_at_position, is_account_like, is_currency_char,
+    is_currency_like, is_valid_currency_name,
 };
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a prepare rename request (check if rename is valid at position).
 pub fn handle_prepare_rename(
     params: &TextDocumentPositionParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<PrepareRenameResponse> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let position = params.position;
     let line_idx = position.line as usize;
 
     let lines: Vec<&str> = source.lines().collect();
     let line = lines.get(line_idx)?;
 
+    // Tags are renamed without their leading `#`
+    if let Some((_, start_col, end_col)) = get_tag_at_position(line, position.character as usize) {
+        return Some(PrepareRenameResponse::Range(Range {
+            start: Position::new(position.line, start_col as u32),
+            end: Position::new(position.line, end_col as u32),
+        }));
+    }
+
     // Get the word at the cursor position
     let (word, start_col, end_col) = get_word_at_position(line, position.character as usize)?;
 
     // Check if it's a valid renameable symbol
-    if is_account_like(&word) || is_currency_like(&word, parse_result) {
+    if is_account_like(&word)
+        || (is_valid_currency_name(&word) && is_currency_like(&word, parse_result))
+    {
         Some(PrepareRenameResponse::Range(Range {
             start: Position::new(position.line, start_col as u32),
             end: Position::new(position.line, end_col as u32),
@@ -43,46 +57,84 @@ pub fn handle_prepare_rename(
 }
 
 /// Handle a rename request.
+///
+/// Currency and tag renames also update the other files in the snapshot's
+/// include graph. An invalid new currency or tag name is reported as an
+/// error.
 #[allow(clippy::mutable_key_type)] // Uri is required as key by LSP WorkspaceEdit API
 pub fn handle_rename(
     params: &RenameParams,
-    source: &str,
-    parse_result: &ParseResult,
-) -> Option<WorkspaceEdit> {
+    snapshot: &WorldSnapshot,
+) -> Result<Option<WorkspaceEdit>, String> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let position = params.text_document_position.position;
     let new_name = &params.new_name;
     let uri = params.text_document_position.text_document.uri.clone();
 
     let line_idx = position.line as usize;
     let lines: Vec<&str> = source.lines().collect();
-    let line = lines.get(line_idx)?;
+    let Some(line) = lines.get(line_idx) else {
+        return Ok(None);
+    };
+
+    // This document first, then the rest of its include graph
+    let documents: Vec<(&Uri, &str, &ParseResult)> = std::iter::once((&uri, source, parse_result))
+        .chain(
+            snapshot
+                .include_graph
+                .iter()
+                .filter(|(related_uri, _, _)| *related_uri != uri)
+                .map(|(related_uri, text, result)| (related_uri, text.as_str(), result.as_ref())),
+        )
+        .collect();
 
-    // Get the word at the cursor position
-    let (old_name, _, _) = get_word_at_position(line, position.character as usize)?;
-
-    // Collect all edits
-    let mut edits = Vec::new();
+    let mut changes = HashMap::new();
 
-    if is_account_like(&old_name) {
-        // Rename account
-        collect_account_rename_edits(source, parse_result, &old_name, new_name, &mut edits);
-    } else if is_currency_like(&old_name, parse_result) {
-        // Rename currency
-        collect_currency_rename_edits(source, parse_result, &old_name, new_name, &mut edits);
+    if let Some((old_tag, _, _)) = get_tag_at_position(line, position.character as usize) {
+        // Rename tag; the `#` is optional in the new name
+        let new_tag = new_name.strip_prefix('#').unwrap_or(new_name);
+        if new_tag.is_empty() || !new_tag.chars().all(is_tag_char) {
+            return Err(format!("'{}' is not a valid tag name", new_name));
+        }
+        for (doc_uri, text, _) in &documents {
+            let edits = collect_tag_rename_edits(text, &old_tag, new_tag);
+            if !edits.is_empty() {
+                changes.insert((*doc_uri).clone(), edits);
+            }
+        }
+    } else if let Some((old_name, _, _)) = get_word_at_position(line, position.character as usize) {
+        if is_account_like(&old_name) {
+            // Rename account
+            let mut edits = Vec::new();
+            collect_account_rename_edits(source, parse_result, &old_name, new_name, &mut edits);
+            if !edits.is_empty() {
+                changes.insert(uri, edits);
+            }
+        } else if is_currency_like(&old_name, parse_result) {
+            // Rename currency
+            if !is_valid_currency_name(new_name) {
+                return Err(format!("'{}' is not a valid currency name", new_name));
+            }
+            for (doc_uri, text, result) in &documents {
+                let mut edits = Vec::new();
+                collect_currency_rename_edits(text, result, &old_name, new_name, &mut edits);
+                if !edits.is_empty() {
+                    changes.insert((*doc_uri).clone(), edits);
+                }
+            }
+        }
     }
 
-    if edits.is_empty() {
-        return None;
+    if changes.is_empty() {
+        return Ok(None);
     }
 
-    let mut changes = HashMap::new();
-    changes.insert(uri, edits);
-
-    Some(WorkspaceEdit {
+    Ok(Some(WorkspaceEdit {
         changes: Some(changes),
         document_changes: None,
         change_annotations: None,
-    })
+    }))
 }
 
 /// Collect all edits needed to rename an account.
@@ -212,6 +264,9 @@ fn collect_account_rename_edits(
 }
 
 /// Collect all edits needed to rename a currency.
+///
+/// Only whole currency tokens are renamed: text inside strings and comments
+/// is skipped, as are matches that are part of an account name.
 fn collect_currency_rename_edits(
     source: &str,
     parse_result: &ParseResult,
@@ -221,44 +276,22 @@ fn collect_currency_rename_edits(
 ) {
     for spanned in &parse_result.directives {
         let directive_text = &source[spanned.span.start..spanned.span.end];
+        if !directive_text.contains(old_name) {
+            continue;
+        }
 
-        // Check if this directive contains the currency
-        if directive_text.contains(old_name) {
-            // Find all occurrences in this directive
-            let (start_line, _) = byte_offset_to_position(source, spanned.span.start);
-
-            for (line_offset, line) in directive_text.lines().enumerate() {
-                let mut search_start = 0;
-                while let Some(pos) = line[search_start..].find(old_name) {
-                    let actual_pos = search_start + pos;
-
-                    // Verify it's a word boundary (not part of a longer identifier)
-                    let before_ok = actual_pos == 0
-                        || !line
-                            .chars()
-                            .nth(actual_pos - 1)
-                            .unwrap_or(' ')
-                            .is_alphanumeric();
-                    let after_ok = actual_pos + old_name.len() >= line.len()
-                        || !line
-                            .chars()
-                            .nth(actual_pos + old_name.len())
-                            .unwrap_or(' ')
-                            .is_alphanumeric();
-
-                    if before_ok && after_ok {
-                        let edit_line = start_line + line_offset as u32;
-                        edits.push(TextEdit {
-                            range: Range {
-                                start: Position::new(edit_line, actual_pos as u32),
-                                end: Position::new(edit_line, (actual_pos + old_name.len()) as u32),
-                            },
-                            new_text: new_name.to_string(),
-                        });
-                    }
-
-                    search_start = actual_pos + old_name.len();
-                }
+        let (start_line, _) = byte_offset_to_position(source, spanned.span.start);
+        for (line_offset, line) in directive_text.lines().enumerate() {
+            for col in currency_occurrences(line, old_name) {
+                let edit_line = start_line + line_offset as u32;
+                let start_char = line[..col].encode_utf16().count() as u32;
+                edits.push(TextEdit {
+                    range: Range {
+                        start: Position::new(edit_line, start_char),
+                        end: Position::new(edit_line, start_char + old_name.len() as u32),
+                    },
+                    new_text: new_name.to_string(),
+                });
             }
         }
     }
@@ -274,6 +307,118 @@ fn collect_currency_rename_edits(
     edits.dedup_by(|a, b| a.range == b.range);
 }
 
+/// Find the byte offsets of `currency` as a standalone token in a line.
+fn currency_occurrences(line: &str, currency: &str) -> Vec<usize> {
+    // A currency touching these characters is part of a longer token
+    let joins = |c: char| is_currency_char(c) || c.is_alphanumeric() || c == ':';
+
+    let mut occurrences = Vec::new();
+    let mut in_string = false;
+    let mut prev: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => break,
+            _ if !in_string
+                && prev.map_or(true, |p| !joins(p))
+                && line[i..].starts_with(currency)
+                && line[i + currency.len()..]
+                    .chars()
+                    .next()
+                    .map_or(true, |n| !joins(n)) =>
+            {
+                occurrences.push(i);
+            }
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    occurrences
+}
+
+/// Find the tag under the cursor.
+///
+/// Returns the tag name without its `#`, and the character range of the name.
+fn get_tag_at_position(line: &str, col: usize) -> Option<(String, usize, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    if col > chars.len() {
+        return None;
+    }
+
+    let mut start = col;
+    while start > 0 && is_tag_char(chars[start - 1]) {
+        start -= 1;
+    }
+    let mut end = col;
+    while end < chars.len() && is_tag_char(chars[end]) {
+        end += 1;
+    }
+
+    // Cursor on the `#` itself
+    if start == end && chars.get(col) == Some(&'#') {
+        start = col + 1;
+        end = start;
+        while end < chars.len() && is_tag_char(chars[end]) {
+            end += 1;
+        }
+    }
+
+    let hash = start.checked_sub(1)?;
+    let standalone = hash == 0 || chars[hash - 1].is_whitespace();
+    if start == end || chars[hash] != '#' || !standalone {
+        return None;
+    }
+
+    Some((chars[start..end].iter().collect(), start, end))
+}
+
+/// Check if a character may appear in a tag or link name.
+fn is_tag_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '/' | '.')
+}
+
+/// Collect edits renaming every `#old_tag` in a document.
+///
+/// This covers transaction headers as well as `pushtag` and `poptag` lines.
+/// Text inside strings and comments is skipped.
+fn collect_tag_rename_edits(source: &str, old_tag: &str, new_tag: &str) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+
+    for (line_num, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut in_string = false;
+        for (col, &c) in chars.iter().enumerate() {
+            match c {
+                '"' => in_string = !in_string,
+                ';' if !in_string => break,
+                '#' if !in_string && (col == 0 || chars[col - 1].is_whitespace()) => {
+                    let name_start = col + 1;
+                    let name_end = chars[name_start..]
+                        .iter()
+                        .position(|&c| !is_tag_char(c))
+                        .map_or(chars.len(), |i| name_start + i);
+                    if chars[name_start..name_end]
+                        .iter()
+                        .copied()
+                        .eq(old_tag.chars())
+                    {
+                        edits.push(TextEdit {
+                            range: Range {
+                                start: Position::new(line_num as u32, name_start as u32),
+                                end: Position::new(line_num as u32, name_end as u32),
+                            },
+                            new_text: new_tag.to_string(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    edits
+}
+
 /// Find a string in the source and create a text edit.
 fn find_and_create_edit(
     source: &str,
@@ -312,6 +457,7 @@ fn find_and_create_edit(
 mod tests {
     use super::*;
     use rustledger_parser::parse;
+    use std::sync::Arc;
 
     #[test]
     fn test_get_word_at_position() {
@@ -338,19 +484,19 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: lsp_types::Uri = "file:///test.beancount".parse().unwrap();
 
         let params = RenameParams {
             text_document_position: TextDocumentPositionParams {
-                text_document: lsp_types::TextDocumentIdentifier { uri },
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
                 position: Position::new(0, 16), // On "Assets:Bank"
             },
             new_name: "Assets:Checking".to_string(),
             work_done_progress_params: Default::default(),
         };
 
-        let edit = handle_rename(&params, source, &result);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let edit = handle_rename(&params, &snapshot).unwrap();
         assert!(edit.is_some());
 
         let edit = edit.unwrap();
@@ -360,4 +506,128 @@ mod tests {
         // Should have 2 edits: one for open, one for posting
         assert_eq!(edits.len(), 2);
     }
+
+    fn rename_params(uri: &Uri, position: Position, new_name: &str) -> RenameParams {
+        RenameParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                position,
+            },
+            new_name: new_name.to_string(),
+            work_done_progress_params: Default::default(),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri in HashMap is required by LSP API
+    fn test_rename_currency_skips_narration_and_accounts() {
+        let source = r#"2012-05-18 commodity FB
+2024-01-01 open Assets:Broker:FB FB
+2024-01-15 * "Sold FB shares" ; trimming FB
+  Assets:Broker:FB  -10 FB {30.00 USD} @ 35.00 USD
+  Assets:Cash  350.00 USD
+2024-01-16 price FB 36.00 USD
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = rename_params(&uri, Position::new(0, 21), "META");
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let edit = handle_rename(&params, &snapshot).unwrap().unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        let positions: Vec<(u32, u32)> = edits
+            .iter()
+            .map(|e| (e.range.start.line, e.range.start.character))
+            .collect();
+
+        // Commodity, open currency list, posting units, and price; never the
+        // narration, comment, or account segment
+        assert_eq!(positions, vec![(0, 21), (1, 33), (3, 24), (5, 17)]);
+        assert!(edits.iter().all(|e| e.new_text == "META"));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri in HashMap is required by LSP API
+    fn test_rename_currency_in_related_documents() {
+        let main = "include \"prices.beancount\"\n2024-01-01 commodity FB\n";
+        let prices = "2024-01-16 price FB 36.00 USD\n";
+        let main_uri: Uri = "file:///ledger/main.beancount".parse().unwrap();
+        let prices_uri: Uri = "file:///ledger/prices.beancount".parse().unwrap();
+        let related = vec![(
+            prices_uri.clone(),
+            prices.to_string(),
+            Arc::new(parse(prices)),
+        )];
+
+        let params = rename_params(&main_uri, Position::new(1, 21), "META");
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), main)
+            .with_include_graph(related);
+        let edit = handle_rename(&params, &snapshot).unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+        assert_eq!(changes[&main_uri].len(), 1);
+        assert_eq!(changes[&prices_uri][0].range.start, Position::new(0, 17));
+    }
+
+    #[test]
+    fn test_rename_currency_rejects_invalid_name() {
+        let source = "2024-01-01 commodity FB\n";
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = rename_params(&uri, Position::new(0, 21), "meta");
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        assert!(handle_rename(&params, &snapshot).is_err());
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // Uri in HashMap is required by LSP API
+    fn test_rename_tag() {
+        let source = r##"2024-03-01 * "Flight" #trip-japan
+  Expenses:Travel  800.00 USD
+  Assets:Bank
+2024-03-05 * "Hotel" "#trip-japan in a string" #trip-japan-extra #trip-japan
+  Expenses:Travel  300.00 USD
+  Assets:Bank
+pushtag #trip-japan
+2024-03-06 * "Ramen" ; #trip-japan
+  Expenses:Food  10.00 USD
+  Assets:Bank
+poptag #trip-japan
+"##;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let prepare = handle_prepare_rename(
+            &TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(0, 25),
+            },
+            &snapshot,
+        );
+        assert_eq!(
+            prepare,
+            Some(PrepareRenameResponse::Range(Range {
+                start: Position::new(0, 23),
+                end: Position::new(0, 33),
+            }))
+        );
+
+        let params = rename_params(&uri, Position::new(0, 25), "#japan-2024");
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let edit = handle_rename(&params, &snapshot).unwrap().unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        let lines: Vec<u32> = edits.iter().map(|e| e.range.start.line).collect();
+
+        // Both transactions plus the pushtag/poptag pair; not the string,
+        // the longer tag, or the comment
+        assert_eq!(lines, vec![0, 3, 6, 10]);
+        assert_eq!(edits[1].range.start, Position::new(3, 66));
+        assert!(edits.iter().all(|e| e.new_text == "japan-2024"));
+    }
+
+    #[test]
+    fn test_rename_tag_rejects_spaces() {
+        let source = "pushtag #trip-japan\n";
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = rename_params(&uri, Position::new(0, 10), "japan 2024");
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        assert!(handle_rename(&params, &snapshot).is_err());
+    }
 }
@@ -9,18 +9,20 @@ use rustledger_core::Directive;
 use rustledger_parser::ParseResult;
 
 use super::utils::{LineIndex, is_word_char};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a selection range request.
 pub fn handle_selection_range(
     params: &SelectionRangeParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<SelectionRange>> {
-    let line_index = LineIndex::new(source);
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let line_index = &snapshot.line_index;
     let mut results = Vec::new();
 
     for position in &params.positions {
-        if let Some(range) = compute_selection_range(source, parse_result, &line_index, *position) {
+        if let Some(range) = compute_selection_range(source, parse_result, line_index, *position) {
             results.push(range);
         } else {
             // Return a simple range at the position if we can't compute anything
@@ -242,7 +244,6 @@ fn find_account_range(line: &str, account: &str, line_num: u32) -> Option<Range>
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_selection_range_in_transaction() {
@@ -250,7 +251,8 @@ mod tests {
   Assets:Bank:Checking  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = SelectionRangeParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -259,8 +261,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let ranges = handle_selection_range(&params, source, &result);
+        let ranges = handle_selection_range(&params, &snapshot);
         assert!(ranges.is_some());
 
         let ranges = ranges.unwrap();
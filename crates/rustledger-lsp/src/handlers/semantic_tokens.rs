@@ -7,6 +7,9 @@
 //! - Numbers
 //! - Strings (payees, narrations)
 //! - Keywords (directive types)
+//! - Tags and links
+//! - `custom` directive arguments, by type
+//! - Metadata keys and values
 //! - Comments
 //!
 //! Supports full document, range-based, and delta tokenization.
@@ -18,11 +21,12 @@ use lsp_types::{
     SemanticTokensOptions, SemanticTokensParams, SemanticTokensRangeParams,
     SemanticTokensRangeResult, SemanticTokensResult, SemanticTokensServerCapabilities,
 };
-use rustledger_core::Directive;
+use rustledger_core::{Directive, MetaValue};
 use rustledger_parser::ParseResult;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-use super::utils::byte_offset_to_position;
+use super::utils::{byte_offset_to_position, split_line_words};
+use crate::snapshot::WorldSnapshot;
 
 /// Token types we support.
 pub const TOKEN_TYPES: &[SemanticTokenType] = &[
@@ -34,6 +38,8 @@ pub const TOKEN_TYPES: &[SemanticTokenType] = &[
     SemanticTokenType::COMMENT,  // 5: comments
     SemanticTokenType::OPERATOR, // 6: flags (*, !)
     SemanticTokenType::MACRO,    // 7: dates
+    SemanticTokenType::ENUM_MEMBER, // 8: tags and links
+    SemanticTokenType::PROPERTY, // 9: metadata keys
 ];
 
 /// Token modifiers we support.
@@ -76,10 +82,11 @@ mod token_type {
     pub const STRING: u32 = 2;
     pub const VARIABLE: u32 = 3; // accounts
     pub const TYPE: u32 = 4; // currencies
-    #[allow(dead_code)] // Reserved for future use when we parse comments
     pub const COMMENT: u32 = 5;
     pub const OPERATOR: u32 = 6; // flags
     pub const MACRO: u32 = 7; // dates
+    pub const ENUM_MEMBER: u32 = 8; // tags and links
+    pub const PROPERTY: u32 = 9; // metadata keys
 }
 
 /// Token modifier bits.
@@ -93,24 +100,49 @@ mod token_modifier {
 /// Handle a semantic tokens request.
 pub fn handle_semantic_tokens(
     _params: &SemanticTokensParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<SemanticTokensResult> {
-    let mut tokens = Vec::new();
-    let mut prev_line = 0u32;
-    let mut prev_start = 0u32;
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let tokens = compute_semantic_tokens(source, parse_result);
 
-    // Collect all tokens from directives
+    if tokens.is_empty() {
+        None
+    } else {
+        Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: Some(generate_result_id()),
+            data: tokens,
+        }))
+    }
+}
+
+/// Compute the delta-encoded semantic tokens for a whole document.
+pub fn compute_semantic_tokens(source: &str, parse_result: &ParseResult) -> Vec<SemanticToken> {
     let mut raw_tokens: Vec<RawToken> = Vec::new();
 
     for spanned in &parse_result.directives {
         collect_directive_tokens(&spanned.value, spanned.span.start, source, &mut raw_tokens);
+        collect_tag_and_metadata_tokens(
+            source,
+            spanned.span.start,
+            spanned.span.end,
+            &mut raw_tokens,
+        );
     }
+    collect_comment_tokens(source, 0, u32::MAX, &mut raw_tokens);
 
     // Sort tokens by position
     raw_tokens.sort_by_key(|t| (t.line, t.start));
 
-    // Convert to delta-encoded semantic tokens
+    encode_tokens(raw_tokens)
+}
+
+/// Convert sorted raw tokens to delta-encoded semantic tokens.
+fn encode_tokens(raw_tokens: impl IntoIterator<Item = RawToken>) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let mut prev_line = 0u32;
+    let mut prev_start = 0u32;
+
     for raw in raw_tokens {
         let delta_line = raw.line - prev_line;
         let delta_start = if delta_line == 0 {
@@ -131,92 +163,87 @@ pub fn handle_semantic_tokens(
         prev_start = raw.start;
     }
 
-    if tokens.is_empty() {
-        None
-    } else {
-        Some(SemanticTokensResult::Tokens(SemanticTokens {
-            result_id: Some(generate_result_id()),
-            data: tokens,
-        }))
-    }
+    tokens
 }
 
 /// Handle a semantic tokens delta request.
-/// Returns only the changed tokens since the previous result.
 ///
-/// Note: For simplicity, this implementation always returns full tokens
-/// when there are changes, using the edit mechanism. A more sophisticated
-/// implementation could compute actual diffs for better performance.
+/// `previous_tokens` are the tokens sent with `params.previous_result_id`.
+/// When they are known, only the changed region is returned as a single
+/// edit; otherwise the full token set is returned.
 pub fn handle_semantic_tokens_delta(
-    params: &SemanticTokensDeltaParams,
-    source: &str,
-    parse_result: &ParseResult,
+    _params: &SemanticTokensDeltaParams,
+    snapshot: &WorldSnapshot,
     previous_tokens: Option<&[SemanticToken]>,
 ) -> Option<SemanticTokensFullDeltaResult> {
-    // Compute current tokens
-    let mut current_tokens = Vec::new();
-    let mut prev_line = 0u32;
-    let mut prev_start = 0u32;
-
-    let mut raw_tokens: Vec<RawToken> = Vec::new();
-    for spanned in &parse_result.directives {
-        collect_directive_tokens(&spanned.value, spanned.span.start, source, &mut raw_tokens);
-    }
-    raw_tokens.sort_by_key(|t| (t.line, t.start));
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let current_tokens = compute_semantic_tokens(source, parse_result);
+    let result_id = Some(generate_result_id());
+
+    let Some(previous) = previous_tokens else {
+        // Unknown previous result: the client needs a full resend
+        return Some(SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+            result_id,
+            data: current_tokens,
+        }));
+    };
 
-    for raw in raw_tokens {
-        let delta_line = raw.line - prev_line;
-        let delta_start = if delta_line == 0 {
-            raw.start - prev_start
-        } else {
-            raw.start
-        };
-
-        current_tokens.push(SemanticToken {
-            delta_line,
-            delta_start,
-            length: raw.length,
-            token_type: raw.token_type,
-            token_modifiers_bitset: raw.modifiers,
-        });
+    Some(SemanticTokensFullDeltaResult::TokensDelta(
+        SemanticTokensDelta {
+            result_id,
+            edits: diff_tokens(previous, &current_tokens),
+        },
+    ))
+}
 
-        prev_line = raw.line;
-        prev_start = raw.start;
-    }
+/// Number of integers each token occupies in the encoded array.
+const TOKEN_STRIDE: usize = 5;
 
-    // If we have previous tokens and they match, return empty delta
-    if let Some(prev) = previous_tokens {
-        if tokens_equal(prev, &current_tokens) {
-            return Some(SemanticTokensFullDeltaResult::TokensDelta(
-                SemanticTokensDelta {
-                    result_id: Some(generate_result_id()),
-                    edits: vec![], // No changes
-                },
-            ));
-        }
+/// Compute the edits turning `previous` into `current`.
+///
+/// Strips the common prefix and suffix and replaces the middle with a
+/// single edit. Edit offsets are in integer units of the flattened array,
+/// as required by the LSP specification.
+fn diff_tokens(previous: &[SemanticToken], current: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    if tokens_equal(previous, current) {
+        return vec![];
     }
 
-    // Tokens changed - return full replacement as a single edit
-    // This replaces all tokens from index 0
-    let new_result_id = generate_result_id();
-    let _ = params; // Used for previous_result_id validation in a more complete impl
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let max_suffix = previous.len().min(current.len()) - prefix;
+    let suffix = previous
+        .iter()
+        .rev()
+        .zip(current.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    vec![SemanticTokensEdit {
+        start: (prefix * TOKEN_STRIDE) as u32,
+        delete_count: ((previous.len() - prefix - suffix) * TOKEN_STRIDE) as u32,
+        data: Some(current[prefix..current.len() - suffix].to_vec()),
+    }]
+}
 
-    if current_tokens.is_empty() && previous_tokens.map(|t| t.is_empty()).unwrap_or(true) {
-        return None;
+/// Apply delta edits to a previous token array, yielding the new array.
+pub fn apply_token_edits(
+    previous: &[SemanticToken],
+    edits: &[SemanticTokensEdit],
+) -> Vec<SemanticToken> {
+    let mut tokens = previous.to_vec();
+    // Apply back to front so earlier offsets stay valid
+    for edit in edits.iter().rev() {
+        let start = (edit.start as usize / TOKEN_STRIDE).min(tokens.len());
+        let end = (start + edit.delete_count as usize / TOKEN_STRIDE).min(tokens.len());
+        tokens.splice(start..end, edit.data.clone().unwrap_or_default());
     }
-
-    let prev_len = previous_tokens.map(|t| t.len()).unwrap_or(0);
-
-    Some(SemanticTokensFullDeltaResult::TokensDelta(
-        SemanticTokensDelta {
-            result_id: Some(new_result_id),
-            edits: vec![SemanticTokensEdit {
-                start: 0,
-                delete_count: prev_len as u32,
-                data: Some(current_tokens),
-            }],
-        },
-    ))
+    tokens
 }
 
 /// Check if two token arrays are equal.
@@ -237,62 +264,43 @@ fn tokens_equal(a: &[SemanticToken], b: &[SemanticToken]) -> bool {
 /// Only tokenizes directives within the requested range for better performance.
 pub fn handle_semantic_tokens_range(
     params: &SemanticTokensRangeParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<SemanticTokensRangeResult> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
     let range = params.range;
-    let mut tokens = Vec::new();
-    let mut prev_line = 0u32;
-    let mut prev_start = 0u32;
 
     // Collect tokens only from directives within the range
     let mut raw_tokens: Vec<RawToken> = Vec::new();
 
     for spanned in &parse_result.directives {
         let (dir_line, _) = byte_offset_to_position(source, spanned.span.start);
+        let (dir_end_line, _) = byte_offset_to_position(source, spanned.span.end);
 
-        // Skip directives before the range
-        if dir_line > range.end.line {
-            continue;
-        }
-
-        // Skip directives after the range (estimate end based on directive type)
-        let estimated_end_line = estimate_directive_end_line(dir_line, &spanned.value);
-        if estimated_end_line < range.start.line {
+        // Skip directives that don't overlap the range
+        if dir_line > range.end.line || dir_end_line < range.start.line {
             continue;
         }
 
         collect_directive_tokens(&spanned.value, spanned.span.start, source, &mut raw_tokens);
+        collect_tag_and_metadata_tokens(
+            source,
+            spanned.span.start,
+            spanned.span.end,
+            &mut raw_tokens,
+        );
     }
+    collect_comment_tokens(source, range.start.line, range.end.line, &mut raw_tokens);
 
     // Sort tokens by position
     raw_tokens.sort_by_key(|t| (t.line, t.start));
 
     // Filter tokens within range and convert to delta-encoded
-    for raw in raw_tokens {
-        // Skip tokens outside the requested range
-        if !is_token_in_range(&raw, &range) {
-            continue;
-        }
-
-        let delta_line = raw.line - prev_line;
-        let delta_start = if delta_line == 0 {
-            raw.start - prev_start
-        } else {
-            raw.start
-        };
-
-        tokens.push(SemanticToken {
-            delta_line,
-            delta_start,
-            length: raw.length,
-            token_type: raw.token_type,
-            token_modifiers_bitset: raw.modifiers,
-        });
-
-        prev_line = raw.line;
-        prev_start = raw.start;
-    }
+    let tokens = encode_tokens(
+        raw_tokens
+            .into_iter()
+            .filter(|raw| is_token_in_range(raw, &range)),
+    );
 
     if tokens.is_empty() {
         None
@@ -304,20 +312,6 @@ pub fn handle_semantic_tokens_range(
     }
 }
 
-/// Estimate the end line of a directive for range filtering.
-fn estimate_directive_end_line(start_line: u32, directive: &Directive) -> u32 {
-    match directive {
-        Directive::Transaction(txn) => {
-            // Transaction spans header + postings
-            start_line + 1 + txn.postings.len() as u32
-        }
-        _ => {
-            // Most directives are single line
-            start_line
-        }
-    }
-}
-
 /// Check if a token is within the requested range.
 fn is_token_in_range(token: &RawToken, range: &Range) -> bool {
     // Token line must be within range
@@ -367,13 +361,23 @@ fn collect_directive_tokens(
                 modifiers: 0,
             });
 
-            // Flag token (after date + space)
+            // Flag token (after date + space); the `txn` keyword form is
+            // highlighted as a keyword rather than a flag
             let flag_col = col + 11;
+            let header = source.lines().nth(line as usize).unwrap_or("");
+            let is_txn_keyword = word_columns(header)
+                .get(1)
+                .is_some_and(|&(_, word)| word == "txn");
+            let (flag_len, flag_type) = if is_txn_keyword {
+                (3, token_type::KEYWORD)
+            } else {
+                (1, token_type::OPERATOR)
+            };
             tokens.push(RawToken {
                 line,
                 start: flag_col,
-                length: 1,
-                token_type: token_type::OPERATOR,
+                length: flag_len,
+                token_type: flag_type,
                 modifiers: 0,
             });
 
@@ -382,46 +386,54 @@ fn collect_directive_tokens(
                 let payee_len = payee.len() as u32 + 2; // include quotes
                 tokens.push(RawToken {
                     line,
-                    start: flag_col + 2,
+                    start: flag_col + flag_len + 1,
                     length: payee_len,
                     token_type: token_type::STRING,
                     modifiers: 0,
                 });
             }
 
-            // Postings
-            for (i, posting) in txn.postings.iter().enumerate() {
-                let posting_line = line + 1 + i as u32;
+            // Postings (located by scanning the lines after the header,
+            // since metadata lines may sit between them)
+            let mut lines = source.lines().enumerate().skip(line as usize + 1);
+            for posting in &txn.postings {
+                let account_str = posting.account.to_string();
+                let Some((posting_line, words)) = lines.find_map(|(n, text)| {
+                    let words = word_columns(text);
+                    let account_idx = words.iter().take(2).position(|&(_, w)| w == account_str)?;
+                    Some((n as u32, words[account_idx..].to_vec()))
+                }) else {
+                    break;
+                };
 
                 // Account
-                let account_str = posting.account.to_string();
+                let (account_col, _) = words[0];
                 tokens.push(RawToken {
                     line: posting_line,
-                    start: 2, // indentation
+                    start: account_col,
                     length: account_str.len() as u32,
                     token_type: token_type::VARIABLE,
                     modifiers: 0,
                 });
 
                 // Amount if present
-                if let Some(ref units) = posting.units {
-                    if let Some(num) = units.number() {
-                        let num_str = num.to_string();
-                        let num_start = 2 + account_str.len() as u32 + 2;
+                if posting.units.as_ref().and_then(|u| u.number()).is_some() {
+                    if let Some(&(num_col, num_str)) = words.get(1) {
                         tokens.push(RawToken {
                             line: posting_line,
-                            start: num_start,
-                            length: num_str.len() as u32,
+                            start: num_col,
+                            length: num_str.chars().count() as u32,
                             token_type: token_type::NUMBER,
                             modifiers: 0,
                         });
+                    }
 
-                        // Currency
-                        if let Some(curr) = units.currency() {
-                            let curr_str = curr.to_string();
+                    // Currency
+                    if let Some(&(curr_col, curr_str)) = words.get(2) {
+                        if posting.units.as_ref().and_then(|u| u.currency()) == Some(curr_str) {
                             tokens.push(RawToken {
                                 line: posting_line,
-                                start: num_start + num_str.len() as u32 + 1,
+                                start: curr_col,
                                 length: curr_str.len() as u32,
                                 token_type: token_type::TYPE,
                                 modifiers: 0,
@@ -638,6 +650,55 @@ fn collect_directive_tokens(
             });
         }
 
+        Directive::Custom(custom) => {
+            // Date
+            tokens.push(RawToken {
+                line,
+                start: col,
+                length: 10,
+                token_type: token_type::MACRO,
+                modifiers: 0,
+            });
+
+            // "custom" keyword
+            tokens.push(RawToken {
+                line,
+                start: col + 11,
+                length: 6,
+                token_type: token_type::KEYWORD,
+                modifiers: 0,
+            });
+
+            // The name and arguments follow the keyword on the header line.
+            // Tags and links are highlighted with the rest of the header.
+            let header = source[start_offset..].lines().next().unwrap_or("");
+            let mut words = word_columns(header).into_iter().skip(2);
+            let arg_types = custom.values.iter().flat_map(|value| match value {
+                MetaValue::String(_) => vec![Some(token_type::STRING)],
+                MetaValue::Account(_) => vec![Some(token_type::VARIABLE)],
+                MetaValue::Currency(_) => vec![Some(token_type::TYPE)],
+                MetaValue::Date(_) => vec![Some(token_type::MACRO)],
+                MetaValue::Number(_) => vec![Some(token_type::NUMBER)],
+                MetaValue::Bool(_) => vec![Some(token_type::KEYWORD)],
+                MetaValue::Amount(_) => vec![Some(token_type::NUMBER), Some(token_type::TYPE)],
+                MetaValue::Tag(_) | MetaValue::Link(_) | MetaValue::None => vec![None],
+            });
+            for (token_type, (start, word)) in std::iter::once(Some(token_type::STRING))
+                .chain(arg_types)
+                .zip(&mut words)
+            {
+                if let Some(token_type) = token_type {
+                    tokens.push(RawToken {
+                        line,
+                        start: col + start,
+                        length: word.chars().count() as u32,
+                        token_type,
+                        modifiers: 0,
+                    });
+                }
+            }
+        }
+
         // For other directives, just highlight the date and keyword
         _ => {
             // Date
@@ -652,6 +713,140 @@ fn collect_directive_tokens(
     }
 }
 
+/// Collect tag, link, and metadata tokens by scanning a directive's source text.
+///
+/// Tags and links are only recognized on the header line; metadata is
+/// recognized on indented `key: value` lines.
+fn collect_tag_and_metadata_tokens(
+    source: &str,
+    start_offset: usize,
+    end_offset: usize,
+    tokens: &mut Vec<RawToken>,
+) {
+    let (first_line, _) = byte_offset_to_position(source, start_offset);
+    let text = source
+        .get(start_offset..end_offset.min(source.len()))
+        .unwrap_or("");
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = first_line + i as u32;
+        let words = word_columns(line);
+
+        if i == 0 {
+            for &(col, word) in &words {
+                if word.len() > 1 && (word.starts_with('#') || word.starts_with('^')) {
+                    tokens.push(RawToken {
+                        line: line_num,
+                        start: col,
+                        length: word.chars().count() as u32,
+                        token_type: token_type::ENUM_MEMBER,
+                        modifiers: 0,
+                    });
+                }
+            }
+            continue;
+        }
+
+        // Metadata lines: indented `key: value`
+        let Some(&(key_col, key)) = words.first() else {
+            continue;
+        };
+        if !line.starts_with([' ', '\t']) || !is_metadata_key(key) {
+            continue;
+        }
+        tokens.push(RawToken {
+            line: line_num,
+            start: key_col,
+            length: key.chars().count() as u32 - 1, // exclude the colon
+            token_type: token_type::PROPERTY,
+            modifiers: 0,
+        });
+
+        for &(col, word) in &words[1..] {
+            if let Some(value_type) = metadata_value_token_type(word) {
+                tokens.push(RawToken {
+                    line: line_num,
+                    start: col,
+                    length: word.chars().count() as u32,
+                    token_type: value_type,
+                    modifiers: 0,
+                });
+            }
+        }
+    }
+}
+
+/// Collect comment tokens (`;` to end of line) for lines in `first_line..=last_line`.
+///
+/// A `;` inside a quoted string does not start a comment.
+fn collect_comment_tokens(
+    source: &str,
+    first_line: u32,
+    last_line: u32,
+    tokens: &mut Vec<RawToken>,
+) {
+    let lines = source.lines().enumerate().skip(first_line as usize);
+    for (line_num, line) in lines.take_while(|(n, _)| *n as u32 <= last_line) {
+        let mut in_quotes = false;
+        for (col, ch) in line.chars().enumerate() {
+            match ch {
+                '"' => in_quotes = !in_quotes,
+                ';' if !in_quotes => {
+                    tokens.push(RawToken {
+                        line: line_num as u32,
+                        start: col as u32,
+                        length: line.trim_end().chars().count() as u32 - col as u32,
+                        token_type: token_type::COMMENT,
+                        modifiers: 0,
+                    });
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Split a line into words with their start columns, in characters.
+fn word_columns(line: &str) -> Vec<(u32, &str)> {
+    split_line_words(line)
+        .into_iter()
+        .map(|(start, word)| (line[..start].chars().count() as u32, word))
+        .collect()
+}
+
+/// Check if a word is a metadata key (lowercase identifier followed by `:`).
+fn is_metadata_key(word: &str) -> bool {
+    let Some(key) = word.strip_suffix(':') else {
+        return false;
+    };
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Pick the token type for a metadata value word.
+fn metadata_value_token_type(word: &str) -> Option<u32> {
+    let first = word.chars().next()?;
+    if word.starts_with('"') {
+        Some(token_type::STRING)
+    } else if word.starts_with('#') || word.starts_with('^') {
+        Some(token_type::ENUM_MEMBER)
+    } else if word.len() == 10 && word.as_bytes()[4] == b'-' && first.is_ascii_digit() {
+        Some(token_type::MACRO)
+    } else if first.is_ascii_digit() || first == '-' || first == '+' || first == '.' {
+        Some(token_type::NUMBER)
+    } else if word.contains(':') && first.is_ascii_uppercase() {
+        Some(token_type::VARIABLE)
+    } else if word == "TRUE" || word == "FALSE" {
+        Some(token_type::KEYWORD)
+    } else if first.is_ascii_uppercase() {
+        Some(token_type::TYPE)
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -660,7 +855,8 @@ mod tests {
     #[test]
     fn test_semantic_tokens_basic() {
         let source = "2024-01-01 open Assets:Bank USD\n";
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = SemanticTokensParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -668,8 +864,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let response = handle_semantic_tokens(&params, source, &result);
+        let response = handle_semantic_tokens(&params, &snapshot);
         assert!(response.is_some());
 
         if let Some(SemanticTokensResult::Tokens(tokens)) = response {
@@ -686,7 +881,8 @@ mod tests {
   Expenses:Food
 2024-01-20 close Assets:OldAccount
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
 
         // Request tokens only for lines 1-3 (the transaction)
         let params = SemanticTokensRangeParams {
@@ -700,8 +896,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let response = handle_semantic_tokens_range(&params, source, &result);
+        let response = handle_semantic_tokens_range(&params, &snapshot);
         assert!(response.is_some());
 
         if let Some(SemanticTokensRangeResult::Tokens(tokens)) = response {
@@ -710,6 +905,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_semantic_tokens_range_excludes_outside_tokens() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+2024-01-20 close Assets:OldAccount
+"#;
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+
+        let params = SemanticTokensRangeParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            range: Range {
+                start: lsp_types::Position::new(1, 0),
+                end: lsp_types::Position::new(3, 100),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let Some(SemanticTokensRangeResult::Tokens(tokens)) =
+            handle_semantic_tokens_range(&params, &snapshot)
+        else {
+            panic!("Expected tokens");
+        };
+
+        // Decode absolute lines: every token must fall within lines 1-3
+        let mut line = 0;
+        for token in &tokens.data {
+            line += token.delta_line;
+            assert!(
+                (1..=3).contains(&line),
+                "token on line {} outside range",
+                line
+            );
+            // Neither the open keyword (line 0) nor the close keyword (line 4) may appear
+            assert_ne!(token.token_type, token_type::KEYWORD);
+        }
+    }
+
     #[test]
     fn test_is_token_in_range() {
         let token = RawToken {
@@ -745,7 +982,6 @@ mod tests {
     #[test]
     fn test_semantic_tokens_delta_no_change() {
         let source = "2024-01-01 open Assets:Bank USD\n";
-        let result = parse(source);
 
         // Get initial tokens
         let params = SemanticTokensParams {
@@ -755,7 +991,8 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-        let initial = handle_semantic_tokens(&params, source, &result);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let initial = handle_semantic_tokens(&params, &snapshot);
         let initial_tokens = match initial {
             Some(SemanticTokensResult::Tokens(t)) => t.data,
             _ => panic!("Expected tokens"),
@@ -771,8 +1008,8 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let delta =
-            handle_semantic_tokens_delta(&delta_params, source, &result, Some(&initial_tokens));
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let delta = handle_semantic_tokens_delta(&delta_params, &snapshot, Some(&initial_tokens));
         assert!(delta.is_some());
 
         // Should return empty edits since nothing changed
@@ -792,8 +1029,6 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-
-        let result1 = parse(source1);
         let result2 = parse(source2);
 
         // Get initial tokens
@@ -804,7 +1039,8 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-        let initial = handle_semantic_tokens(&params, source1, &result1);
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source1);
+        let initial = handle_semantic_tokens(&params, &snapshot);
         let initial_tokens = match initial {
             Some(SemanticTokensResult::Tokens(t)) => t.data,
             _ => panic!("Expected tokens"),
@@ -820,8 +1056,8 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let delta =
-            handle_semantic_tokens_delta(&delta_params, source2, &result2, Some(&initial_tokens));
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source2);
+        let delta = handle_semantic_tokens_delta(&delta_params, &snapshot, Some(&initial_tokens));
         assert!(delta.is_some());
 
         // Should return edits since source changed significantly
@@ -832,14 +1068,79 @@ mod tests {
             );
             // The edit should contain the new tokens
             assert!(d.edits[0].data.is_some());
-            // New source has more directives, so should have more tokens
-            let new_tokens = d.edits[0].data.as_ref().unwrap();
-            assert!(new_tokens.len() > initial_tokens.len());
+            // Applying the edits must reproduce the full token set
+            let updated = apply_token_edits(&initial_tokens, &d.edits);
+            assert_eq!(updated, compute_semantic_tokens(source2, &result2));
         } else {
             panic!("Expected delta result");
         }
     }
 
+    #[test]
+    fn test_semantic_tokens_delta_single_line_edit_is_small() {
+        let mut lines: Vec<String> = (1..=20)
+            .map(|day| format!("2024-01-{:02} open Assets:Account{:02} USD", day, day))
+            .collect();
+        let source1 = lines.join("\n") + "\n";
+        lines[10] = "2024-01-11 open Assets:Renamed USD EUR".to_string();
+        let source2 = lines.join("\n") + "\n";
+
+        let result1 = parse(&source1);
+        let result2 = parse(&source2);
+        let initial_tokens = compute_semantic_tokens(&source1, &result1);
+
+        let delta_params = SemanticTokensDeltaParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            previous_result_id: "0".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), &source2);
+        let Some(SemanticTokensFullDeltaResult::TokensDelta(d)) =
+            handle_semantic_tokens_delta(&delta_params, &snapshot, Some(&initial_tokens))
+        else {
+            panic!("Expected delta result");
+        };
+
+        assert_eq!(d.edits.len(), 1);
+        let edit = &d.edits[0];
+        // Only the edited line's tokens are replaced, not the whole document
+        let inserted = edit.data.as_ref().map_or(0, |data| data.len());
+        assert!(
+            inserted <= 5,
+            "expected a small edit, got {} tokens",
+            inserted
+        );
+        assert!((edit.delete_count as usize) < initial_tokens.len() * TOKEN_STRIDE / 4);
+        assert_eq!(
+            apply_token_edits(&initial_tokens, &d.edits),
+            compute_semantic_tokens(&source2, &result2)
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_delta_unknown_previous_returns_full() {
+        let source = "2024-01-01 open Assets:Bank USD\n";
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
+        let delta_params = SemanticTokensDeltaParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: "file:///test.beancount".parse().unwrap(),
+            },
+            previous_result_id: "unknown".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let delta = handle_semantic_tokens_delta(&delta_params, &snapshot, None);
+        assert!(matches!(
+            delta,
+            Some(SemanticTokensFullDeltaResult::Tokens(_))
+        ));
+    }
+
     #[test]
     fn test_tokens_equal() {
         let tokens1 = vec![SemanticToken {
@@ -862,4 +1163,102 @@ mod tests {
         assert!(!tokens_equal(&tokens1, &tokens3));
         assert!(!tokens_equal(&tokens1, &[]));
     }
+
+    /// Decode delta-encoded tokens into absolute (line, start, length, type) tuples.
+    fn decode(tokens: &[SemanticToken]) -> Vec<(u32, u32, u32, u32)> {
+        let mut line = 0;
+        let mut start = 0;
+        tokens
+            .iter()
+            .map(|t| {
+                if t.delta_line > 0 {
+                    start = 0;
+                }
+                line += t.delta_line;
+                start += t.delta_start;
+                (line, start, t.length, t.token_type)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_semantic_tokens_tags_and_metadata() {
+        let source = r#"2024-01-15 * "Coffee" #trip ^receipt-1
+  key: "value"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        let result = parse(source);
+        let tokens = decode(&compute_semantic_tokens(source, &result));
+
+        // `#trip` tag and `^receipt-1` link on the header line
+        assert!(tokens.contains(&(0, 22, 5, token_type::ENUM_MEMBER)));
+        assert!(tokens.contains(&(0, 28, 10, token_type::ENUM_MEMBER)));
+        // `key` metadata key and its string value
+        assert!(tokens.contains(&(1, 2, 3, token_type::PROPERTY)));
+        assert!(tokens.contains(&(1, 7, 7, token_type::STRING)));
+        // Postings after the metadata line are still located correctly
+        assert!(tokens.contains(&(2, 2, 11, token_type::VARIABLE)));
+        assert!(tokens.contains(&(2, 15, 5, token_type::NUMBER)));
+        assert!(tokens.contains(&(2, 21, 3, token_type::TYPE)));
+        assert!(tokens.contains(&(3, 2, 13, token_type::VARIABLE)));
+    }
+
+    #[test]
+    fn test_semantic_tokens_txn_keyword() {
+        let source = r#"2024-01-15 txn "Cafe" "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        let result = parse(source);
+        let tokens = decode(&compute_semantic_tokens(source, &result));
+
+        assert!(tokens.contains(&(0, 11, 3, token_type::KEYWORD)));
+        assert!(tokens.contains(&(0, 15, 6, token_type::STRING)));
+        assert!(!tokens.iter().any(|t| t.3 == token_type::OPERATOR));
+    }
+
+    #[test]
+    fn test_semantic_tokens_custom_arguments() {
+        let source = "2024-01-01 custom \"budget\" Expenses:Food 500 USD\n";
+        let result = parse(source);
+        let tokens = decode(&compute_semantic_tokens(source, &result));
+
+        assert_eq!(
+            tokens,
+            vec![
+                (0, 0, 10, token_type::MACRO),
+                (0, 11, 6, token_type::KEYWORD),
+                (0, 18, 8, token_type::STRING),
+                (0, 27, 13, token_type::VARIABLE),
+                (0, 41, 3, token_type::NUMBER),
+                (0, 45, 3, token_type::TYPE),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_word_columns_count_characters() {
+        let words = word_columns(r#"  note: "café; b" 10 ; trailing"#);
+        assert_eq!(words, vec![(2, "note:"), (8, r#""café; b""#), (18, "10")]);
+    }
+
+    #[test]
+    fn test_semantic_tokens_comments() {
+        let source = r#"; a note
+2024-01-01 open Assets:Bank USD  ; eol comment
+2024-01-15 * "Semi; colon"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        let result = parse(source);
+        let tokens = decode(&compute_semantic_tokens(source, &result));
+        let comments: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.3 == token_type::COMMENT)
+            .collect();
+
+        // Standalone comment line and trailing comment; the `;` in the string is ignored
+        assert_eq!(comments, vec![&(0, 0, 8, 5), &(1, 33, 13, 5)]);
+    }
 }
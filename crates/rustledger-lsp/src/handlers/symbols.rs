@@ -10,18 +10,18 @@ use lsp_types::{
     DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, Position, Range, SymbolKind,
 };
 use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
 
 use super::utils::LineIndex;
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a document symbols request.
 pub fn handle_document_symbols(
     _params: &DocumentSymbolParams,
-    source: &str,
-    parse_result: &ParseResult,
+    snapshot: &WorldSnapshot,
 ) -> Option<DocumentSymbolResponse> {
+    let parse_result = &*snapshot.parse_result;
     // Build line index once for O(log n) lookups
-    let line_index = LineIndex::new(source);
+    let line_index = &snapshot.line_index;
 
     let symbols: Vec<DocumentSymbol> = parse_result
         .directives
@@ -31,7 +31,7 @@ pub fn handle_document_symbols(
                 &spanned.value,
                 spanned.span.start,
                 spanned.span.end,
-                &line_index,
+                line_index,
             )
         })
         .collect();
@@ -266,7 +266,6 @@ fn directive_to_symbol(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rustledger_parser::parse;
 
     #[test]
     fn test_document_symbols_basic() {
@@ -276,7 +275,8 @@ mod tests {
   Assets:Bank  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
+
+        let snapshot = WorldSnapshot::new("file:///test.beancount".parse().unwrap(), source);
         let params = DocumentSymbolParams {
             text_document: lsp_types::TextDocumentIdentifier {
                 uri: "file:///test.beancount".parse().unwrap(),
@@ -284,8 +284,7 @@ mod tests {
             work_done_progress_params: Default::default(),
             partial_result_params: Default::default(),
         };
-
-        let response = handle_document_symbols(&params, source, &result);
+        let response = handle_document_symbols(&params, &snapshot);
         assert!(response.is_some());
 
         if let Some(DocumentSymbolResponse::Nested(symbols)) = response {
@@ -0,0 +1,84 @@
+//! Go to type definition handler.
+//!
+//! The "type" of an amount is its currency, so from either the number or
+//! the currency of an amount such as `5.00 USD` this navigates to the
+//! currency's `commodity` directive.
+
+use lsp_types::Position;
+use lsp_types::request::{GotoTypeDefinitionParams, GotoTypeDefinitionResponse};
+
+use super::definition::find_currency_definition;
+use super::utils::{
+    amount_token_at, get_word_at_source_position, is_currency_like_simple, position_to_offset,
+};
+use crate::snapshot::WorldSnapshot;
+
+/// Handle a go-to-type-definition request.
+pub fn handle_goto_type_definition(
+    params: &GotoTypeDefinitionParams,
+    snapshot: &WorldSnapshot,
+) -> Option<GotoTypeDefinitionResponse> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
+    let position = params.text_document_position_params.position;
+
+    let currency = match amount_currency_at(source, position) {
+        Some(currency) => currency.to_string(),
+        None => get_word_at_source_position(source, position)
+            .filter(|word| is_currency_like_simple(word))?,
+    };
+
+    find_currency_definition(&currency, parse_result, source, uri)
+        .map(GotoTypeDefinitionResponse::Scalar)
+}
+
+/// Find the currency of the amount under the cursor, whether the cursor is
+/// on its number or its currency.
+fn amount_currency_at(source: &str, position: Position) -> Option<&str> {
+    let line_start = position_to_offset(source, Position::new(position.line, 0));
+    let line = source[line_start..].lines().next()?;
+    let col = position_to_offset(source, position) - line_start;
+
+    amount_token_at(line, col).map(|(_, _, currency)| currency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{
+        GotoDefinitionParams, TextDocumentIdentifier, TextDocumentPositionParams, Uri,
+    };
+
+    fn type_definition_line(source: &str, line: u32, character: u32) -> Option<u32> {
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let params = GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(line, character),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        match handle_goto_type_definition(&params, &snapshot)? {
+            GotoTypeDefinitionResponse::Scalar(location) => Some(location.range.start.line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_type_definition_from_amount_number() {
+        let source = r#"2024-01-01 commodity USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        // On the number of `-5.00 USD`
+        assert_eq!(type_definition_line(source, 2, 16), Some(0));
+        // On the currency
+        assert_eq!(type_definition_line(source, 2, 21), Some(0));
+        // On the account, which has no type
+        assert_eq!(type_definition_line(source, 2, 4), None);
+    }
+}
@@ -6,22 +6,24 @@
 
 use lsp_types::{
     Position, Range, SymbolKind, TypeHierarchyItem, TypeHierarchyPrepareParams,
-    TypeHierarchySubtypesParams, TypeHierarchySupertypesParams, Uri,
+    TypeHierarchySubtypesParams, TypeHierarchySupertypesParams,
 };
-use rustledger_core::Directive;
+use rustledger_core::{Account, Directive};
 use rustledger_parser::ParseResult;
 use std::collections::HashSet;
 
 use super::utils::{byte_offset_to_position, get_word_at_position, is_account_like};
+use crate::snapshot::WorldSnapshot;
 
 /// Handle a prepare type hierarchy request.
 /// Returns the account at the cursor position as a TypeHierarchyItem.
 pub fn handle_prepare_type_hierarchy(
     params: &TypeHierarchyPrepareParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<TypeHierarchyItem>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let position = params.text_document_position_params.position;
     let line_idx = position.line as usize;
     let lines: Vec<&str> = source.lines().collect();
@@ -64,10 +66,11 @@ pub fn handle_prepare_type_hierarchy(
 /// Returns the parent account in the hierarchy.
 pub fn handle_supertypes(
     params: &TypeHierarchySupertypesParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<TypeHierarchyItem>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let account = params
         .item
         .data
@@ -99,10 +102,11 @@ pub fn handle_supertypes(
 /// Returns all child accounts in the hierarchy.
 pub fn handle_subtypes(
     params: &TypeHierarchySubtypesParams,
-    source: &str,
-    parse_result: &ParseResult,
-    uri: &Uri,
+    snapshot: &WorldSnapshot,
 ) -> Option<Vec<TypeHierarchyItem>> {
+    let source = snapshot.text.as_str();
+    let parse_result = &*snapshot.parse_result;
+    let uri = &snapshot.uri;
     let account = params
         .item
         .data
@@ -139,35 +143,29 @@ pub fn handle_subtypes(
 
 /// Get the parent account by removing the last segment.
 fn get_parent_account(account: &str) -> Option<String> {
-    let parts: Vec<&str> = account.split(':').collect();
-    if parts.len() <= 1 {
-        return None;
-    }
-    Some(parts[..parts.len() - 1].join(":"))
+    Account::new(account)
+        .parent()
+        .map(|parent| parent.to_string())
 }
 
 /// Get all direct child accounts.
 fn get_child_accounts(parent: &str, parse_result: &ParseResult) -> Vec<String> {
     let mut children = HashSet::new();
-    let prefix = format!("{}:", parent);
-    let parent_depth = parent.matches(':').count();
+    let parent = Account::new(parent);
 
     for spanned in &parse_result.directives {
         let accounts = get_accounts_from_directive(&spanned.value);
 
         for account in accounts {
-            if account.starts_with(&prefix) {
-                // Check if this is a direct child (only one more segment)
-                let child_depth = account.matches(':').count();
-                if child_depth == parent_depth + 1 {
-                    children.insert(account);
-                } else if child_depth > parent_depth + 1 {
-                    // Extract the direct child
-                    let parts: Vec<&str> = account.split(':').collect();
-                    let direct_child = parts[..parent_depth + 2].join(":");
-                    children.insert(direct_child);
-                }
+            let mut account = Account::new(account);
+            if !account.is_child_of(&parent) {
+                continue;
             }
+            // Walk up to the direct child of `parent`
+            while let Some(up) = account.parent().filter(|up| *up != parent) {
+                account = up;
+            }
+            children.insert(account.to_string());
         }
     }
 
@@ -246,6 +244,7 @@ fn find_account_location(source: &str, parse_result: &ParseResult, account: &str
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lsp_types::Uri;
     use rustledger_parser::parse;
 
     #[test]
@@ -287,7 +286,6 @@ mod tests {
   Assets:Bank:Checking  -5.00 USD
   Expenses:Food
 "#;
-        let result = parse(source);
         let uri: Uri = "file:///test.beancount".parse().unwrap();
 
         let params = TypeHierarchyPrepareParams {
@@ -298,7 +296,8 @@ mod tests {
             work_done_progress_params: Default::default(),
         };
 
-        let items = handle_prepare_type_hierarchy(&params, source, &result, &uri);
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let items = handle_prepare_type_hierarchy(&params, &snapshot);
         assert!(items.is_some());
 
         let items = items.unwrap();
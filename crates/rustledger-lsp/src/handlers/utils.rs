@@ -3,9 +3,11 @@
 //! This module contains common utilities used across multiple handlers,
 //! including position conversion, word extraction, and type checking.
 
-use lsp_types::Position;
-use rustledger_core::Directive;
-use rustledger_parser::ParseResult;
+use lsp_types::{Position, Uri};
+use rustledger_core::{Directive, MetaValue};
+use rustledger_parser::{ParseResult, Spanned};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 /// A line index for efficient offset-to-position conversion.
 ///
@@ -23,6 +25,8 @@ use rustledger_parser::ParseResult;
 pub struct LineIndex {
     /// Byte offset of the start of each line (including line 0 at offset 0).
     line_starts: Vec<usize>,
+    /// Byte offset of the end of each line's content, before `\n` or `\r\n`.
+    line_ends: Vec<usize>,
     /// Total length of the source in bytes.
     len: usize,
 }
@@ -33,21 +37,32 @@ impl LineIndex {
     /// This is O(n) where n is the source length.
     pub fn new(source: &str) -> Self {
         let mut line_starts = vec![0]; // Line 0 starts at offset 0
+        let mut line_ends = Vec::new();
 
         for (i, ch) in source.char_indices() {
             if ch == '\n' {
+                // A `\r\n` line break is a single break, not part of the line
+                let end = if source[..i].ends_with('\r') {
+                    i - 1
+                } else {
+                    i
+                };
+                line_ends.push(end);
                 line_starts.push(i + 1); // Next line starts after the newline
             }
         }
+        line_ends.push(source.len());
 
         Self {
             line_starts,
+            line_ends,
             len: source.len(),
         }
     }
 
     /// Convert a byte offset to a (line, column) position (0-based).
     ///
+    /// Offsets inside a line break clamp to the end of the line's content.
     /// This is O(log(lines)) using binary search.
     pub fn offset_to_position(&self, offset: usize) -> (u32, u32) {
         let offset = offset.min(self.len);
@@ -59,7 +74,7 @@ impl LineIndex {
         };
 
         let line_start = self.line_starts[line];
-        let col = offset - line_start;
+        let col = offset.min(self.line_ends[line]) - line_start;
 
         (line as u32, col as u32)
     }
@@ -89,8 +104,41 @@ impl LineIndex {
     }
 }
 
+/// Identifies a directive across requests, such as a code lens and its
+/// resolve, via the `data` field round-tripped through the client.
+///
+/// The id is the directive's index in [`ParseResult::directives`]. Unlike a
+/// position or a hash, it is unchanged by edits to the directive itself or
+/// to text around it, as long as no directive is added or removed before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DirectiveId(pub usize);
+
+impl DirectiveId {
+    /// Key under which the id is stored in a `data` object.
+    const DATA_KEY: &'static str = "directive";
+
+    /// Read the id stored in a `data` object by [`DirectiveId::store`].
+    pub fn from_data(data: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(data.get(Self::DATA_KEY)?.clone()).ok()
+    }
+
+    /// Store the id in a `data` object.
+    pub fn store(self, data: &mut serde_json::Value) {
+        data[Self::DATA_KEY] = serde_json::json!(self);
+    }
+
+    /// Look up the directive in a parse of the document.
+    pub fn get(self, parse_result: &ParseResult) -> Option<&Spanned<Directive>> {
+        parse_result.directives.get(self.0)
+    }
+}
+
 /// Convert a byte offset to a line/column position (0-based for LSP).
 ///
+/// `\r` is not counted as a column, so `\r\n` line endings give the same
+/// positions as `\n`.
+///
 /// Note: This is O(n) where n is the offset. For handlers that do multiple
 /// conversions on the same source, use [`LineIndex`] instead for O(log n) lookups.
 pub fn byte_offset_to_position(source: &str, offset: usize) -> (u32, u32) {
@@ -104,7 +152,7 @@ pub fn byte_offset_to_position(source: &str, offset: usize) -> (u32, u32) {
         if ch == '\n' {
             line += 1;
             col = 0;
-        } else {
+        } else if ch != '\r' {
             col += 1;
         }
     }
@@ -112,35 +160,137 @@ pub fn byte_offset_to_position(source: &str, offset: usize) -> (u32, u32) {
     (line, col)
 }
 
-/// Get the word at a given column position in a line.
+/// Convert an LSP position to a byte offset in the source.
 ///
-/// Returns the word, its start column, and end column (0-based).
-/// Words include alphanumeric characters, colons, hyphens, and underscores.
-pub fn get_word_at_position(line: &str, col: usize) -> Option<(String, usize, usize)> {
-    if col > line.len() {
-        return None;
+/// The column is in UTF-16 code units, as LSP clients send it. Columns past
+/// the end of the line clamp to the line end (before any `\r\n`), and lines
+/// past the end of the source clamp to the source length.
+pub fn position_to_offset(source: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match source[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return source.len(),
+        }
+    }
+
+    let line = source[line_start..].lines().next().unwrap_or("");
+    line_start + utf16_col_to_byte(line, position.character as usize)
+}
+
+/// Convert a UTF-16 column within a line to a byte offset, clamping to the line end.
+fn utf16_col_to_byte(line: &str, col: usize) -> usize {
+    let mut units = 0;
+    for (byte, ch) in line.char_indices() {
+        if units >= col {
+            return byte;
+        }
+        units += ch.len_utf16();
     }
+    line.len()
+}
 
-    let chars: Vec<char> = line.chars().collect();
+/// Count the transactions with at least one posting to an account.
+pub fn account_transaction_count(parse_result: &ParseResult, account: &str) -> usize {
+    parse_result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Transaction(txn) => Some(txn),
+            _ => None,
+        })
+        .filter(|txn| {
+            txn.postings
+                .iter()
+                .any(|posting| posting.account.as_str() == account)
+        })
+        .count()
+}
 
-    // Find word start
-    let mut start = col;
-    while start > 0 && is_word_char(chars.get(start - 1).copied().unwrap_or(' ')) {
-        start -= 1;
+/// Convert a `file://` URI to a file path, decoding percent-escapes.
+///
+/// Returns `None` for other schemes and for escapes that are not UTF-8.
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    let encoded = uri.as_str().strip_prefix("file://")?;
+    // Drop the host, which is empty for local files
+    let encoded = &encoded[encoded.find('/')?..];
+    // Windows paths look like file:///C:/...
+    #[cfg(windows)]
+    let encoded = encoded.strip_prefix('/').unwrap_or(encoded);
+
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
     }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
 
-    // Find word end
-    let mut end = col;
-    while end < chars.len() && is_word_char(chars[end]) {
-        end += 1;
+/// Convert a file path to a `file://` URI, percent-encoding its bytes
+/// except unreserved characters and separators.
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let path = path.to_str()?;
+    #[cfg(windows)]
+    let path = format!("/{}", path.replace('\\', "/"));
+
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            #[cfg(windows)]
+            b':' => uri.push(':'),
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
     }
+    uri.parse().ok()
+}
+
+/// Find the byte range of the word containing (or ending at) a byte offset.
+fn word_bounds(line: &str, offset: usize) -> Option<(usize, usize)> {
+    let start = line[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word_char(*c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = line[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_word_char(*c))
+        .map_or(line.len(), |(i, _)| offset + i);
+
+    (start < end).then_some((start, end))
+}
 
-    if start == end {
+/// Get the word at a given column position in a line.
+///
+/// The column and the returned start and end columns are in UTF-16 code
+/// units (0-based), matching LSP positions.
+/// Words include alphanumeric characters, colons, hyphens, and underscores.
+pub fn get_word_at_position(line: &str, col: usize) -> Option<(String, usize, usize)> {
+    if col > line.encode_utf16().count() {
         return None;
     }
 
-    let word: String = chars[start..end].iter().collect();
-    Some((word, start, end))
+    let (start, end) = word_bounds(line, utf16_col_to_byte(line, col))?;
+    let start_col = line[..start].encode_utf16().count();
+    let end_col = start_col + line[start..end].encode_utf16().count();
+    Some((line[start..end].to_string(), start_col, end_col))
 }
 
 /// Get the word at a position in a source document.
@@ -148,37 +298,8 @@ pub fn get_word_at_position(line: &str, col: usize) -> Option<(String, usize, us
 /// This is a convenience wrapper that handles line extraction.
 pub fn get_word_at_source_position(source: &str, position: Position) -> Option<String> {
     let line = source.lines().nth(position.line as usize)?;
-    let col = position.character as usize;
-
-    // Handle UTF-8: convert character offset to byte offset for the line
-    let byte_col = line
-        .char_indices()
-        .nth(col)
-        .map(|(i, _)| i)
-        .unwrap_or(line.len());
-
-    if byte_col > line.len() {
-        return None;
-    }
-
-    let chars: Vec<char> = line.chars().collect();
-
-    // Find word boundaries
-    let mut start = col.min(chars.len());
-    while start > 0 && is_word_char(chars.get(start - 1).copied().unwrap_or(' ')) {
-        start -= 1;
-    }
-
-    let mut end = col.min(chars.len());
-    while end < chars.len() && is_word_char(chars[end]) {
-        end += 1;
-    }
-
-    if start == end {
-        return None;
-    }
-
-    Some(chars[start..end].iter().collect())
+    let (word, _, _) = get_word_at_position(line, position.character as usize)?;
+    Some(word)
 }
 
 /// Check if a character is part of a word (for Beancount identifiers).
@@ -216,6 +337,26 @@ pub fn is_currency_like_simple(s: &str) -> bool {
             .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
 }
 
+/// Check if a string is a syntactically valid currency name.
+///
+/// Follows Beancount's rules: 2-24 characters, starting with an uppercase
+/// letter, ending with an uppercase letter or digit, and otherwise made of
+/// uppercase letters, digits, `'`, `.`, `_`, or `-`.
+pub fn is_valid_currency_name(s: &str) -> bool {
+    let (Some(first), Some(last)) = (s.chars().next(), s.chars().last()) else {
+        return false;
+    };
+    (2..=24).contains(&s.len())
+        && first.is_ascii_uppercase()
+        && (last.is_ascii_uppercase() || last.is_ascii_digit())
+        && s.chars().all(is_currency_char)
+}
+
+/// Check if a character may appear in a currency name.
+pub fn is_currency_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || matches!(c, '\'' | '.' | '_' | '-')
+}
+
 /// Check if a string looks like a currency, validating against known currencies.
 ///
 /// This checks the format AND verifies the currency exists in the document.
@@ -282,10 +423,271 @@ pub fn is_currency_like(s: &str, parse_result: &ParseResult) -> bool {
     false
 }
 
+/// Find `NUMBER CURRENCY` pairs on a line, as (byte offset of the number,
+/// number, currency).
+///
+/// Quoted strings and `;` comments are skipped, and a leading `{` or `(`
+/// on the number or a trailing `}`, `)` or `,` on the currency is ignored,
+/// so amounts in costs and price directives are found too. Only decimal
+/// numbers, optionally with comma thousands separators, match; expressions
+/// do not.
+pub fn amount_tokens(line: &str) -> Vec<(usize, &str, &str)> {
+    line_words(line)
+        .windows(2)
+        .filter_map(|pair| {
+            let (start, word) = pair[0];
+            let number = word.trim_start_matches(['{', '(']);
+            let currency = pair[1].1.trim_end_matches(['}', ')', ',']);
+            (is_decimal_number(number) && is_valid_currency_name(currency))
+                .then(|| (start + word.len() - number.len(), number, currency))
+        })
+        .collect()
+}
+
+/// Find the amount spanning byte column `col` of a line, from the start of
+/// its number to the end of its currency, as returned by [`amount_tokens`].
+pub fn amount_token_at(line: &str, col: usize) -> Option<(usize, &str, &str)> {
+    amount_tokens(line)
+        .into_iter()
+        .find(|(start, number, currency)| {
+            let number_end = start + number.len();
+            let currency_end = line[number_end..]
+                .find(currency)
+                .map_or(number_end, |i| number_end + i + currency.len());
+            (*start..=currency_end).contains(&col)
+        })
+}
+
+/// Find the units amount of a posting or `balance` line, as (byte offset
+/// of the number, number, currency).
+///
+/// Prices, costs, and amounts anywhere else (`price` directives, metadata,
+/// `custom` arguments) are not units and are not returned, so changing the
+/// result cannot change a transaction's weight.
+pub fn units_amount_token(line: &str) -> Option<(usize, &str, &str)> {
+    let words = line_words(line);
+    let is_account = |word: &str| {
+        word.starts_with(|c: char| c.is_ascii_uppercase())
+            && word.contains(':')
+            && !word.ends_with(':')
+    };
+
+    let amount_index = if line.starts_with(char::is_whitespace) {
+        // A posting: an optional flag, the account, then the units
+        let account_index = match words.first()? {
+            (_, flag) if flag.chars().count() == 1 => 1,
+            _ => 0,
+        };
+        is_account(words.get(account_index)?.1).then_some(account_index + 1)?
+    } else {
+        // A balance: the date, `balance`, the account, then the amount
+        (words.get(1)?.1 == "balance" && is_account(words.get(2)?.1)).then_some(3)?
+    };
+
+    let (amount_start, _) = *words.get(amount_index)?;
+    amount_tokens(line)
+        .into_iter()
+        .find(|&(start, _, _)| start == amount_start)
+}
+
+/// Split a line into whitespace-separated words with their byte offsets,
+/// skipping quoted strings and stopping at a `;` comment.
+pub fn line_words(line: &str) -> Vec<(usize, &str)> {
+    split_line_words(line)
+        .into_iter()
+        .filter(|(_, word)| !word.starts_with('"'))
+        .collect()
+}
+
+/// Split a line into whitespace-separated words with their byte offsets.
+///
+/// Quoted strings are kept together as a single word, and scanning stops
+/// at a `;` comment outside of quotes.
+pub fn split_line_words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut word_start: Option<usize> = None;
+    let mut in_quotes = false;
+
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        if in_quotes || !(c.is_whitespace() || c == ';') {
+            if word_start.is_none() {
+                word_start = Some(i);
+            }
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            words.push((start, &line[start..i]));
+        }
+        if c == ';' {
+            return words;
+        }
+    }
+
+    if let Some(start) = word_start {
+        words.push((start, &line[start..]));
+    }
+    words
+}
+
+/// Check for a decimal number: optional sign, digits, optional fraction.
+///
+/// The whole part may be grouped in threes by commas, as in `1,234.56`.
+fn is_decimal_number(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let mut groups = whole.split(',');
+    let first = groups.next().unwrap_or_default();
+    let grouped = whole.contains(',');
+
+    !first.is_empty()
+        && first.chars().all(|c| c.is_ascii_digit())
+        && (!grouped || first.len() <= 3)
+        && groups.all(|g| g.len() == 3 && g.chars().all(|c| c.is_ascii_digit()))
+        && fraction.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Booking methods an `open` directive can name, with a short description.
+pub const BOOKING_METHODS: &[(&str, &str)] = &[
+    ("STRICT", "Reductions must match exactly one lot"),
+    (
+        "STRICT_WITH_SIZE",
+        "Like STRICT, but a lot of the exact size may be picked",
+    ),
+    ("FIFO", "Reduce the oldest lots first"),
+    ("LIFO", "Reduce the newest lots first"),
+    ("HIFO", "Reduce the lots with the highest cost first"),
+    ("AVERAGE", "Merge lots at their average cost"),
+    ("NONE", "Keep lots without matching reductions"),
+];
+
+/// Collect the `precision` metadata declared on `commodity` directives.
+///
+/// Only whole, non-negative numbers count as a precision.
+pub fn declared_precisions(parse_result: &ParseResult) -> std::collections::HashMap<&str, u32> {
+    parse_result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Commodity(comm) => match comm.meta.get("precision") {
+                // `trunc` drops the scale, leaving the value as the mantissa
+                Some(MetaValue::Number(n)) if n.is_integer() => {
+                    let precision = u32::try_from(n.trunc().mantissa()).ok()?;
+                    Some((comm.currency.as_str(), precision))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collect account aliases, mapping each alias to the account it stands for.
+///
+/// An alias is declared with a `custom "alias"` directive naming the alias
+/// and then the account:
+///
+/// ```beancount
+/// 2024-01-01 custom "alias" "groc" Expenses:Food:Groceries
+/// ```
+///
+/// Aliases are an editing shorthand: completion expands them to the full
+/// account, so the ledger itself only ever contains real account names.
+/// A later declaration of the same alias replaces an earlier one.
+pub fn account_aliases(parse_result: &ParseResult) -> std::collections::HashMap<&str, &str> {
+    parse_result
+        .directives
+        .iter()
+        .filter_map(|spanned| match &spanned.value {
+            Directive::Custom(custom) if custom.custom_type == "alias" => {
+                match custom.values.as_slice() {
+                    [MetaValue::String(alias), MetaValue::Account(account)]
+                        if !alias.is_empty() && !alias.contains(char::is_whitespace) =>
+                    {
+                        Some((alias.as_str(), account.as_str()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_position_to_offset_ascii() {
+        let source = "line one\nline two\n";
+        assert_eq!(position_to_offset(source, Position::new(0, 0)), 0);
+        assert_eq!(position_to_offset(source, Position::new(1, 5)), 14);
+        // Past the end of a line clamps to the line end
+        assert_eq!(position_to_offset(source, Position::new(0, 99)), 8);
+        // Past the last line clamps to the source length
+        assert_eq!(
+            position_to_offset(source, Position::new(5, 0)),
+            source.len()
+        );
+    }
+
+    #[test]
+    fn test_position_to_offset_multibyte() {
+        // "é" is 2 bytes but 1 UTF-16 unit; "𝄞" is 4 bytes and 2 UTF-16 units
+        let source = "2024-01-15 * \"Café 𝄞\" USD\r\nnext";
+        let usd = source.find("USD").unwrap();
+        assert_eq!(position_to_offset(source, Position::new(0, 23)), usd);
+        // Clamps before the carriage return
+        assert_eq!(position_to_offset(source, Position::new(0, 99)), usd + 3);
+        assert_eq!(
+            position_to_offset(source, Position::new(1, 2)),
+            source.find("next").unwrap() + 2
+        );
+    }
+
+    #[test]
+    fn test_get_word_after_accented_narration() {
+        let line = "2024-01-15 * \"Crème brûlée\" Assets:Café";
+        // UTF-16 column of "Assets" (every character here is one unit)
+        let col = line.chars().position(|c| c == 'A').unwrap();
+        let (word, start, end) = get_word_at_position(line, col + 3).unwrap();
+        assert_eq!(word, "Assets:Café");
+        assert_eq!(start, col);
+        assert_eq!(end, col + 11);
+
+        let source = format!("{line}\n");
+        assert_eq!(
+            get_word_at_source_position(&source, Position::new(0, (col + 10) as u32)),
+            Some("Assets:Café".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_word_with_surrogate_pair_before() {
+        let line = "; 𝄞 USD";
+        // "𝄞" takes two UTF-16 units, so "USD" starts at column 5
+        assert_eq!(
+            get_word_at_position(line, 6),
+            Some(("USD".to_string(), 5, 8))
+        );
+    }
+
+    #[test]
+    fn test_is_valid_currency_name() {
+        assert!(is_valid_currency_name("USD"));
+        assert!(is_valid_currency_name("META"));
+        assert!(is_valid_currency_name("VWO2"));
+        assert!(is_valid_currency_name("BRK.B"));
+        assert!(!is_valid_currency_name("M"));
+        assert!(!is_valid_currency_name("meta"));
+        assert!(!is_valid_currency_name("2FB"));
+        assert!(!is_valid_currency_name("FB-"));
+        assert!(!is_valid_currency_name("F B"));
+    }
+
     #[test]
     fn test_line_index_basic() {
         let source = "line1\nline2\nline3";
@@ -303,6 +705,28 @@ mod tests {
         assert_eq!(index.line_count(), 3);
     }
 
+    #[test]
+    fn test_line_index_crlf() {
+        let source = "line1\r\nline2\r\nline3";
+        let index = LineIndex::new(source);
+
+        assert_eq!(index.offset_to_position(7), (1, 0));
+        assert_eq!(index.offset_to_position(10), (1, 3));
+        // The `\r` and `\n` of a line break both clamp to the line end
+        assert_eq!(index.offset_to_position(12), (1, 5));
+        assert_eq!(index.offset_to_position(13), (1, 5));
+        assert_eq!(index.offset_to_position(14), (2, 0));
+        assert_eq!(index.line_count(), 3);
+
+        for offset in 0..=source.len() {
+            assert_eq!(
+                byte_offset_to_position(source, offset),
+                index.offset_to_position(offset),
+                "offset {offset}"
+            );
+        }
+    }
+
     #[test]
     fn test_line_index_empty() {
         let index = LineIndex::new("");
@@ -424,4 +848,83 @@ mod tests {
         assert!(!is_word_char(' '));
         assert!(!is_word_char('"'));
     }
+
+    #[test]
+    fn test_amount_tokens() {
+        let line = r#"  Assets:Brokerage  {150.5 USD} @ 2 EUR ; 9 USD"#;
+        assert_eq!(
+            amount_tokens(line),
+            vec![(21, "150.5", "USD"), (34, "2", "EUR")]
+        );
+        assert!(amount_tokens(r#"2024-01-01 * "5 USD" "1,000 USD""#).is_empty());
+        assert_eq!(
+            amount_tokens("  Assets:Bank  -1,234.56 USD"),
+            vec![(15, "-1,234.56", "USD")]
+        );
+        assert!(amount_tokens("  Assets:Bank  12,34 USD").is_empty());
+        assert!(amount_tokens("  Assets:Bank  1234,567 USD").is_empty());
+    }
+
+    #[test]
+    fn test_split_line_words_respects_quotes_and_comments() {
+        let line = r#"  note: "a; b c" 10 ; trailing"#;
+        assert_eq!(
+            split_line_words(line),
+            vec![(2, "note:"), (8, r#""a; b c""#), (17, "10")]
+        );
+        assert_eq!(line_words(line), vec![(2, "note:"), (17, "10")]);
+    }
+
+    #[test]
+    fn test_units_amount_token() {
+        assert_eq!(
+            units_amount_token("  ! Assets:Cash  10.5 EUR @ 1.0875 USD"),
+            Some((17, "10.5", "EUR"))
+        );
+        assert_eq!(
+            units_amount_token("2024-01-31 balance Assets:Bank 4.999 USD"),
+            Some((31, "4.999", "USD"))
+        );
+        assert_eq!(units_amount_token("2024-01-06 price EUR 1.0875 USD"), None);
+        assert_eq!(units_amount_token("  budget: 300.00 USD"), None);
+        assert_eq!(units_amount_token("  Assets:Stocks  {100 USD}"), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_file_uris_round_trip_with_escapes() {
+        let path = Path::new("/home/me/My Ledger/café #1.beancount");
+        let uri = path_to_uri(path).unwrap();
+        assert_eq!(
+            uri.as_str(),
+            "file:///home/me/My%20Ledger/caf%C3%A9%20%231.beancount"
+        );
+        assert_eq!(uri_to_path(&uri).unwrap(), path);
+
+        let uri: Uri = "file:///ledger/main.beancount".parse().unwrap();
+        assert_eq!(
+            uri_to_path(&uri).unwrap(),
+            Path::new("/ledger/main.beancount")
+        );
+        let uri: Uri = "untitled:Untitled-1".parse().unwrap();
+        assert_eq!(uri_to_path(&uri), None);
+    }
+
+    #[test]
+    fn test_account_transaction_count_counts_transactions_once() {
+        let source = r#"2024-01-15 * "Transfer"
+  Assets:Bank  -5.00 USD
+  Assets:Bank  5.00 USD
+2024-01-16 * "Coffee"
+  Assets:Bank  -3.00 USD
+  Expenses:Food
+2024-01-17 * "Lunch"
+  Assets:Cash  -8.00 USD
+  Expenses:Food
+"#;
+        let result = rustledger_parser::parse(source);
+        assert_eq!(account_transaction_count(&result, "Assets:Bank"), 2);
+        assert_eq!(account_transaction_count(&result, "Expenses:Food"), 2);
+        assert_eq!(account_transaction_count(&result, "Income:Salary"), 0);
+    }
 }
@@ -15,18 +15,24 @@ use std::collections::HashSet;
 use std::sync::Arc;
 
 use super::utils::byte_offset_to_position;
+use crate::snapshot::{CancellationToken, Cancelled};
 
 /// Handle a workspace symbol request.
+///
+/// The token is checked before each document, so a cancelled request stops
+/// without scanning the rest of the workspace.
 pub fn handle_workspace_symbols(
     params: &WorkspaceSymbolParams,
     documents: &[(Uri, String, Arc<ParseResult>)],
-) -> Option<Vec<SymbolInformation>> {
+    cancel: &CancellationToken,
+) -> Result<Option<Vec<SymbolInformation>>, Cancelled> {
     let query = params.query.to_lowercase();
     let mut symbols = Vec::new();
     let mut seen_accounts: HashSet<String> = HashSet::new();
     let mut seen_currencies: HashSet<String> = HashSet::new();
 
     for (uri, source, parse_result) in documents {
+        cancel.check()?;
         collect_symbols_from_document(
             uri,
             source,
@@ -39,9 +45,9 @@ pub fn handle_workspace_symbols(
     }
 
     if symbols.is_empty() {
-        None
+        Ok(None)
     } else {
-        Some(symbols)
+        Ok(Some(symbols))
     }
 }
 
@@ -175,7 +181,7 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let symbols = handle_workspace_symbols(&params, &docs);
+        let symbols = handle_workspace_symbols(&params, &docs, &CancellationToken::new()).unwrap();
         assert!(symbols.is_some());
         let symbols = symbols.unwrap();
 
@@ -201,7 +207,7 @@ mod tests {
             partial_result_params: Default::default(),
         };
 
-        let symbols = handle_workspace_symbols(&params, &docs);
+        let symbols = handle_workspace_symbols(&params, &docs, &CancellationToken::new()).unwrap();
         assert!(symbols.is_some());
         let symbols = symbols.unwrap();
 
@@ -209,4 +215,27 @@ mod tests {
         assert_eq!(symbols.len(), 1);
         assert_eq!(symbols[0].name, "Assets:Bank");
     }
+
+    #[test]
+    fn test_workspace_symbols_cancelled() {
+        let source = "2024-01-01 open Assets:Bank USD\n";
+        let docs: Vec<_> = (0..3)
+            .map(|i| {
+                let uri: Uri = format!("file:///test{i}.beancount").parse().unwrap();
+                (uri, source.to_string(), Arc::new(parse(source)))
+            })
+            .collect();
+        let params = WorkspaceSymbolParams {
+            query: "".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert_eq!(
+            handle_workspace_symbols(&params, &docs, &cancel),
+            Err(Cancelled)
+        );
+    }
 }
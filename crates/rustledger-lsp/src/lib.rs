@@ -28,17 +28,22 @@
 #![warn(missing_docs)]
 #![warn(clippy::all)]
 
+pub mod config;
 pub mod db;
+pub mod export;
 pub mod handlers;
 pub mod main_loop;
+pub mod report;
 
 mod server;
 mod snapshot;
 mod vfs;
+mod workspace;
 
+pub use config::Config;
 pub use main_loop::run_main_loop;
 pub use server::{Server, start_stdio};
-pub use snapshot::Snapshot;
+pub use snapshot::{CancellationToken, Cancelled, WorldSnapshot};
 pub use vfs::Vfs;
 
 /// LSP server version.
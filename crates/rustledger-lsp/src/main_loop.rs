@@ -4,7 +4,9 @@
 //! - Notifications handled synchronously (critical for correctness)
 //! - Requests dispatched to threadpool with immutable snapshots
 //! - Revision counter enables cancellation of stale requests
+//! - `$/cancelRequest` stops long-running workspace requests via cancellation tokens
 
+use crate::config::Config;
 use crate::handlers::call_hierarchy::{
     handle_incoming_calls, handle_outgoing_calls, handle_prepare_call_hierarchy,
 };
@@ -14,14 +16,26 @@ use crate::handlers::completion::handle_completion;
 use crate::handlers::completion_resolve::handle_completion_resolve;
 use crate::handlers::declaration::handle_goto_declaration;
 use crate::handlers::definition::handle_goto_definition;
-use crate::handlers::diagnostics::parse_errors_to_diagnostics;
+use crate::handlers::diagnostics::{
+    account_capitalization_diagnostics, balance_currency_diagnostics, bom_diagnostic,
+    budget_overlap_diagnostics, client_pulls_diagnostics, collect_included_accounts,
+    collect_included_commodities, currency_constraint_diagnostics, document_base_dir,
+    duplicate_metadata_diagnostics, duplicate_transaction_diagnostics,
+    excess_precision_diagnostics, flagged_entry_diagnostics,
+    get_capabilities as get_diagnostic_capabilities, indentation_diagnostics,
+    inline_tab_diagnostics, load_included_files, lot_reduction_diagnostics,
+    metadata_type_diagnostics, missing_document_diagnostics, negative_balance_diagnostics,
+    pad_without_balance_diagnostics, parse_errors_to_diagnostics, self_price_diagnostics,
+    undeclared_currency_diagnostics, undefined_account_diagnostics, unknown_booking_diagnostics,
+    unrelated_currencies_diagnostics,
+};
 use crate::handlers::document_color::{handle_color_presentation, handle_document_color};
 use crate::handlers::document_highlight::handle_document_highlight;
 use crate::handlers::document_links::{handle_document_link_resolve, handle_document_links};
-use crate::handlers::execute_command::handle_execute_command;
+use crate::handlers::execute_command::{handle_execute_command, handle_flatten};
 use crate::handlers::folding::handle_folding_ranges;
 use crate::handlers::formatting::handle_formatting;
-use crate::handlers::hover::handle_hover;
+use crate::handlers::hover::{handle_hover, handle_include_hover};
 use crate::handlers::inlay_hints::{handle_inlay_hint_resolve, handle_inlay_hints};
 use crate::handlers::linked_editing::handle_linked_editing_range;
 use crate::handlers::on_type_formatting::handle_on_type_formatting;
@@ -30,68 +44,91 @@ use crate::handlers::references::handle_references;
 use crate::handlers::rename::{handle_prepare_rename, handle_rename};
 use crate::handlers::selection_range::handle_selection_range;
 use crate::handlers::semantic_tokens::{
-    handle_semantic_tokens, handle_semantic_tokens_delta, handle_semantic_tokens_range,
+    apply_token_edits, handle_semantic_tokens, handle_semantic_tokens_delta,
+    handle_semantic_tokens_range,
 };
 use crate::handlers::signature_help::handle_signature_help;
 use crate::handlers::symbols::handle_document_symbols;
+use crate::handlers::type_definition::handle_goto_type_definition;
 use crate::handlers::type_hierarchy::{
     handle_prepare_type_hierarchy, handle_subtypes, handle_supertypes,
 };
+use crate::handlers::utils::{path_to_uri, uri_to_path};
 use crate::handlers::workspace_symbols::handle_workspace_symbols;
-use crate::snapshot::bump_revision;
-use crate::vfs::Vfs;
-use crossbeam_channel::{Receiver, Sender};
+use crate::snapshot::{CancellationToken, Cancelled, RelatedDocument, WorldSnapshot};
+use crate::vfs::{BOM, Vfs, parse_document, strip_bom};
+use crate::workspace::{discover_files, parse_files};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
 use lsp_types::notification::{
-    DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, DidOpenTextDocument,
-    Notification, PublishDiagnostics,
+    Cancel, DidChangeConfiguration, DidChangeTextDocument, DidChangeWatchedFiles,
+    DidCloseTextDocument, DidOpenTextDocument, Notification, Progress, PublishDiagnostics,
 };
 use lsp_types::request::{
     CallHierarchyIncomingCalls, CallHierarchyOutgoingCalls, CallHierarchyPrepare,
     CodeActionRequest, CodeActionResolveRequest, CodeLensRequest, CodeLensResolve,
-    ColorPresentationRequest, Completion, DocumentColor, DocumentHighlightRequest,
-    DocumentLinkRequest, DocumentLinkResolve, DocumentSymbolRequest, ExecuteCommand,
-    FoldingRangeRequest, Formatting, GotoDeclaration, GotoDefinition, HoverRequest, Initialize,
-    InlayHintRequest, InlayHintResolveRequest, LinkedEditingRange, OnTypeFormatting,
-    PrepareRenameRequest, RangeFormatting, References, Rename, Request, ResolveCompletionItem,
-    SelectionRangeRequest, SemanticTokensFullDeltaRequest, SemanticTokensFullRequest,
-    SemanticTokensRangeRequest, Shutdown, SignatureHelpRequest, TypeHierarchyPrepare,
-    TypeHierarchySubtypes, TypeHierarchySupertypes, WorkspaceSymbolRequest,
+    ColorPresentationRequest, Completion, DocumentColor, DocumentDiagnosticRequest,
+    DocumentHighlightRequest, DocumentLinkRequest, DocumentLinkResolve, DocumentSymbolRequest,
+    ExecuteCommand, FoldingRangeRequest, Formatting, GotoDeclaration, GotoDefinition,
+    GotoTypeDefinition, HoverRequest, Initialize, InlayHintRequest, InlayHintResolveRequest,
+    LinkedEditingRange, OnTypeFormatting, PrepareRenameRequest, RangeFormatting, References,
+    Rename, Request, ResolveCompletionItem, SelectionRangeRequest, SemanticTokensFullDeltaRequest,
+    SemanticTokensFullRequest, SemanticTokensRangeRequest, Shutdown, SignatureHelpRequest,
+    TypeHierarchyPrepare, TypeHierarchySubtypes, TypeHierarchySupertypes,
+    WorkspaceDiagnosticRequest, WorkspaceSymbolRequest,
 };
 use lsp_types::{
     CallHierarchyIncomingCallsParams, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
-    CodeAction, CodeActionParams, CodeLens, CodeLensParams, ColorPresentationParams,
-    CompletionItem, CompletionParams, DiagnosticOptions, DiagnosticServerCapabilities,
-    DocumentColorParams, DocumentFormattingParams, DocumentHighlightParams, DocumentLink,
-    DocumentLinkParams, DocumentOnTypeFormattingParams, DocumentRangeFormattingParams,
-    DocumentSymbolParams, ExecuteCommandParams, FoldingRangeParams, GotoDefinitionParams,
-    HoverParams, InitializeParams, InitializeResult, InlayHint, InlayHintParams,
-    LinkedEditingRangeParams, PublishDiagnosticsParams, ReferenceParams, RenameParams,
-    SelectionRangeParams, SemanticTokensDeltaParams, SemanticTokensParams,
-    SemanticTokensRangeParams, ServerCapabilities, ServerInfo, SignatureHelpParams,
-    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
-    TypeHierarchyPrepareParams, TypeHierarchySubtypesParams, TypeHierarchySupertypesParams, Uri,
-    WorkspaceSymbolParams,
+    CancelParams, CodeAction, CodeActionParams, CodeLens, CodeLensParams, ColorPresentationParams,
+    CompletionItem, CompletionParams, DocumentColorParams, DocumentDiagnosticParams,
+    DocumentDiagnosticReport, DocumentDiagnosticReportResult, DocumentFormattingParams,
+    DocumentHighlightParams, DocumentLink, DocumentLinkParams, DocumentOnTypeFormattingParams,
+    DocumentRangeFormattingParams, DocumentSymbolParams, ExecuteCommandParams, FoldingRangeParams,
+    FullDocumentDiagnosticReport, GotoDefinitionParams, HoverParams, InitializeParams,
+    InitializeResult, InlayHint, InlayHintParams, LinkedEditingRangeParams,
+    PublishDiagnosticsParams, ReferenceParams, RelatedFullDocumentDiagnosticReport,
+    RelatedUnchangedDocumentDiagnosticReport, RenameParams, SelectionRangeParams, SemanticToken,
+    SemanticTokensDeltaParams, SemanticTokensFullDeltaResult, SemanticTokensParams,
+    SemanticTokensRangeParams, SemanticTokensResult, ServerCapabilities, ServerInfo,
+    SignatureHelpParams, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TypeHierarchyPrepareParams, TypeHierarchySubtypesParams,
+    TypeHierarchySupertypesParams, UnchangedDocumentDiagnosticReport, Uri,
+    WorkspaceDiagnosticParams, WorkspaceDiagnosticReport, WorkspaceDiagnosticReportPartialResult,
+    WorkspaceDiagnosticReportResult, WorkspaceDocumentDiagnosticReport,
+    WorkspaceFullDocumentDiagnosticReport, WorkspaceSymbolParams,
+    WorkspaceUnchangedDocumentDiagnosticReport,
 };
 use parking_lot::RwLock;
 use rustledger_parser::{ParseResult, parse};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-
-/// Convert a URI to a file path.
-#[cfg(not(windows))]
-fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
-    uri.as_str().strip_prefix("file://").map(PathBuf::from)
+use std::time::{Duration, Instant};
+
+/// Default quiet period after an edit before diagnostics are recomputed.
+pub const DEFAULT_DIAGNOSTICS_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Workspace root directories from initialize params.
+///
+/// Uses the workspace folders, falling back to the deprecated root URI.
+fn workspace_roots(params: &InitializeParams) -> Vec<PathBuf> {
+    match &params.workspace_folders {
+        Some(folders) => folders
+            .iter()
+            .filter_map(|folder| uri_to_path(&folder.uri))
+            .collect(),
+        #[allow(deprecated)]
+        None => params.root_uri.iter().filter_map(uri_to_path).collect(),
+    }
 }
 
-/// Convert a URI to a file path (Windows version).
-#[cfg(windows)]
-fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
-    uri.as_str()
-        .strip_prefix("file://")
-        // Handle Windows paths like file:///C:/...
-        .map(|p| p.strip_prefix('/').unwrap_or(p))
-        .map(PathBuf::from)
+/// Convert the ID in a `$/cancelRequest` to the request ID it refers to.
+fn request_id(id: lsp_types::NumberOrString) -> lsp_server::RequestId {
+    match id {
+        lsp_types::NumberOrString::Number(id) => id.into(),
+        lsp_types::NumberOrString::String(id) => id.into(),
+    }
 }
 
 /// Events processed by the main loop.
@@ -133,6 +170,27 @@ pub struct MainLoopState {
     pub sender: Sender<lsp_server::Message>,
     /// Cached diagnostics per file.
     pub diagnostics: HashMap<Uri, Vec<lsp_types::Diagnostic>>,
+    /// Server configuration, shared with request snapshots.
+    pub config: Arc<Config>,
+    /// Files transitively included by each document, used to refresh
+    /// dependents when an included file changes.
+    pub include_graph: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// Last semantic tokens sent per file, with their result ID (for delta requests).
+    pub semantic_tokens: HashMap<Uri, (String, Vec<SemanticToken>)>,
+    /// Quiet period after an edit before diagnostics are recomputed.
+    pub diagnostics_debounce: Duration,
+    /// Documents whose diagnostics are due once their deadline passes.
+    /// A newer edit replaces the deadline, cancelling the earlier one.
+    pub pending_diagnostics: HashMap<Uri, Instant>,
+    /// Workspace root directories, indexed once the client is initialized.
+    pub workspace_roots: Vec<PathBuf>,
+    /// Cancellation tokens for requests the client may cancel, by request ID.
+    pub cancellations: HashMap<lsp_server::RequestId, CancellationToken>,
+    /// Whether the client pulls diagnostics, so they are not also pushed.
+    pub pull_diagnostics: bool,
+    /// Bumped whenever the configuration changes, as that can change any
+    /// document's diagnostics.
+    pub config_revision: u64,
     /// Whether shutdown was requested.
     pub shutdown_requested: bool,
 }
@@ -149,10 +207,116 @@ impl MainLoopState {
             vfs: Arc::new(RwLock::new(Vfs::new())),
             sender,
             diagnostics: HashMap::new(),
+            semantic_tokens: HashMap::new(),
+            config: Arc::default(),
+            include_graph: HashMap::new(),
+            diagnostics_debounce: DEFAULT_DIAGNOSTICS_DEBOUNCE,
+            pending_diagnostics: HashMap::new(),
+            workspace_roots: Vec::new(),
+            cancellations: HashMap::new(),
+            pull_diagnostics: false,
+            config_revision: 0,
             shutdown_requested: false,
         }
     }
 
+    /// Create a new main loop state with the given configuration.
+    pub fn with_config(sender: Sender<lsp_server::Message>, config: Config) -> Self {
+        let mut state = Self::new(sender);
+        state.set_config(config);
+        state
+    }
+
+    /// Replace the server configuration.
+    pub fn set_config(&mut self, config: Config) {
+        self.diagnostics_debounce = config
+            .diagnostics_debounce_ms
+            .map_or(DEFAULT_DIAGNOSTICS_DEBOUNCE, Duration::from_millis);
+        self.config = Arc::new(config);
+        self.config_revision += 1;
+    }
+
+    /// The result ID of a document's pulled diagnostics.
+    ///
+    /// Hashes the document's text, the text of every file it includes, and
+    /// the configuration revision, so the ID changes whenever anything the
+    /// diagnostics depend on does.
+    fn diagnostics_result_id(&self, path: Option<&Path>, text: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.config_revision.hash(&mut hasher);
+        text.hash(&mut hasher);
+
+        if let Some(included) = path.and_then(|path| self.include_graph.get(path)) {
+            let mut included: Vec<&PathBuf> = included.iter().collect();
+            included.sort();
+            let vfs = self.vfs.read();
+            for path in included {
+                path.hash(&mut hasher);
+                vfs.get_content(path)
+                    .or_else(|| std::fs::read_to_string(path).ok())
+                    .hash(&mut hasher);
+            }
+        }
+
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Parse every Beancount file in the workspace into the VFS.
+    ///
+    /// Files are parsed in parallel, then the include graph is built from
+    /// the cached results. Documents already open in the editor keep their
+    /// content. Returns the number of files indexed.
+    pub fn index_workspace(&mut self) -> usize {
+        let files = discover_files(&self.workspace_roots);
+        let parsed = parse_files(&files);
+        let count = parsed.len();
+
+        let mut vfs = self.vfs.write();
+        let results: Vec<_> = parsed
+            .into_iter()
+            .map(|(path, content, parse_result)| {
+                vfs.load_external_parsed(path.clone(), content, parse_result.clone());
+                (path, parse_result)
+            })
+            .collect();
+
+        for (path, parse_result) in results {
+            let included = load_included_files(&path, &parse_result, |p: &Path| {
+                vfs.get_content(&p.to_path_buf())
+                    .or_else(|| std::fs::read_to_string(p).ok())
+            });
+            self.include_graph.insert(path, included.paths);
+        }
+
+        tracing::info!("Indexed {} workspace files", count);
+        count
+    }
+
+    /// The earliest pending diagnostics deadline, if any.
+    pub fn next_diagnostics_deadline(&self) -> Option<Instant> {
+        self.pending_diagnostics.values().min().copied()
+    }
+
+    /// Publish diagnostics for documents whose debounce deadline is at or before `now`.
+    pub fn flush_pending_diagnostics(&mut self, now: Instant) {
+        let due: Vec<Uri> = self
+            .pending_diagnostics
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(uri, _)| uri.clone())
+            .collect();
+
+        for uri in due {
+            self.pending_diagnostics.remove(&uri);
+            let Some(text) = uri_to_path(&uri).and_then(|path| self.vfs.read().get_content(&path))
+            else {
+                continue;
+            };
+            self.publish_diagnostics(&uri, &text);
+            self.publish_dependent_diagnostics(&uri);
+        }
+    }
+
     /// Get document text and cached parse result for a URI.
     /// Uses cached parse result if available, avoiding re-parsing.
     fn get_document_data(&self, uri: &Uri) -> (String, Arc<ParseResult>) {
@@ -164,6 +328,12 @@ impl MainLoopState {
         (String::new(), empty_parse_result())
     }
 
+    /// Take a snapshot of the document at `uri` for a request handler.
+    fn snapshot(&self, uri: &Uri) -> WorldSnapshot {
+        let (text, parse_result) = self.get_document_data(uri);
+        WorldSnapshot::with_parse_result(uri.clone(), text, parse_result, self.config.clone())
+    }
+
     /// Handle an incoming event.
     pub fn handle_event(&mut self, event: Event) {
         match event {
@@ -230,12 +400,15 @@ impl MainLoopState {
             DocumentColor::METHOD => self.handle_document_color_request(req),
             ColorPresentationRequest::METHOD => self.handle_color_presentation_request(req),
             GotoDeclaration::METHOD => self.handle_goto_declaration_request(req),
+            GotoTypeDefinition::METHOD => self.handle_goto_type_definition_request(req),
             CallHierarchyPrepare::METHOD => self.handle_prepare_call_hierarchy_request(req),
             CallHierarchyIncomingCalls::METHOD => self.handle_incoming_calls_request(req),
             CallHierarchyOutgoingCalls::METHOD => self.handle_outgoing_calls_request(req),
             SignatureHelpRequest::METHOD => self.handle_signature_help_request(req),
             ExecuteCommand::METHOD => self.handle_execute_command_request(req),
             ResolveCompletionItem::METHOD => self.handle_completion_resolve_request(req),
+            DocumentDiagnosticRequest::METHOD => self.handle_document_diagnostic_request(req),
+            WorkspaceDiagnosticRequest::METHOD => self.handle_workspace_diagnostic_request(req),
             _ => {
                 tracing::warn!("Unhandled request: {}", req.method);
                 Err(format!("Unhandled request: {}", req.method))
@@ -250,6 +423,8 @@ impl MainLoopState {
                 // InternalError for handler failures
                 let error_code = if msg.starts_with("Unhandled request") {
                     lsp_server::ErrorCode::MethodNotFound
+                } else if msg == Cancelled.to_string() {
+                    lsp_server::ErrorCode::RequestCanceled
                 } else {
                     lsp_server::ErrorCode::InternalError
                 };
@@ -257,19 +432,66 @@ impl MainLoopState {
             }
         };
 
+        self.cancellations.remove(&response.id);
         self.send(lsp_server::Message::Response(response));
     }
 
+    /// Get the cancellation token for a request, registering it so a later
+    /// `$/cancelRequest` can reach it.
+    fn cancellation_token(&mut self, id: &lsp_server::RequestId) -> CancellationToken {
+        self.cancellations.entry(id.clone()).or_default().clone()
+    }
+
+    /// Handle the `$/cancelRequest` notification.
+    ///
+    /// Only requests that are still running have a token to cancel; a
+    /// request that was already answered is ignored.
+    fn on_cancel_request(&mut self, params: CancelParams) {
+        if let Some(token) = self.cancellations.get(&request_id(params.id)) {
+            token.cancel();
+        }
+    }
+
+    /// Cancel queued requests whose `$/cancelRequest` is queued behind them.
+    ///
+    /// Requests are handled one at a time, so a cancellation is otherwise
+    /// only seen after the request it targets has finished. Registering a
+    /// cancelled token up front makes the request stop as soon as it starts.
+    pub fn cancel_queued_requests<'a>(
+        &mut self,
+        messages: impl Iterator<Item = &'a lsp_server::Message> + Clone,
+    ) {
+        let cancelled: HashSet<lsp_server::RequestId> = messages
+            .clone()
+            .filter_map(|msg| match msg {
+                lsp_server::Message::Notification(notif) if notif.method == Cancel::METHOD => {
+                    serde_json::from_value::<CancelParams>(notif.params.clone()).ok()
+                }
+                _ => None,
+            })
+            .map(|params| request_id(params.id))
+            .collect();
+        if cancelled.is_empty() {
+            return;
+        }
+        for msg in messages {
+            if let lsp_server::Message::Request(req) = msg {
+                if cancelled.contains(&req.id) {
+                    self.cancellation_token(&req.id).cancel();
+                }
+            }
+        }
+    }
+
     /// Handle the initialize request.
     fn handle_initialize(&mut self, req: lsp_server::Request) -> Result<serde_json::Value, String> {
-        let _params: InitializeParams =
+        let params: InitializeParams =
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
+        self.pull_diagnostics = client_pulls_diagnostics(&params);
 
         let capabilities = ServerCapabilities {
             text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
-            diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
-                ..Default::default()
-            })),
+            diagnostic_provider: Some(get_diagnostic_capabilities()),
             ..Default::default()
         };
 
@@ -293,9 +515,19 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
 
-        let response = handle_completion(&params, &text, &parse_result);
+        // Custom directive names come from the whole workspace
+        let path = uri_to_path(uri);
+        let workspace: Vec<Arc<ParseResult>> = self
+            .vfs
+            .read()
+            .cached_parse_results()
+            .filter(|(other, _)| Some(*other) != path.as_ref())
+            .map(|(_, parse_result)| parse_result.clone())
+            .collect();
+        let snapshot = self.snapshot(uri).with_workspace(workspace);
+
+        let response = handle_completion(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -309,9 +541,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_goto_definition(&params, &text, &parse_result, uri);
+        let response = handle_goto_definition(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -325,9 +557,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_references(&params, &text, &parse_result, uri);
+        let response = handle_references(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -337,9 +569,23 @@ impl MainLoopState {
         let params: HoverParams = serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
+
+        // Include paths preview the included file, preferring cached parses
+        if let Some(path) = uri_to_path(uri) {
+            let include_hover = handle_include_hover(&params, &snapshot, &path, |p: &Path| {
+                let cached = self.vfs.write().get_document_data(&p.to_path_buf());
+                cached.map(|(_, parse_result)| parse_result).or_else(|| {
+                    let content = std::fs::read_to_string(p).ok()?;
+                    Some(Arc::new(parse(strip_bom(&content).0)))
+                })
+            });
+            if let Some(hover) = include_hover {
+                return serde_json::to_value(hover).map_err(|e| e.to_string());
+            }
+        }
 
-        let response = handle_hover(&params, &text, &parse_result);
+        let response = handle_hover(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -353,44 +599,73 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_document_symbols(&params, &text, &parse_result);
+        let response = handle_document_symbols(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
 
     /// Handle the textDocument/semanticTokens/full request.
     fn handle_semantic_tokens_request(
-        &self,
+        &mut self,
         req: lsp_server::Request,
     ) -> Result<serde_json::Value, String> {
         let params: SemanticTokensParams =
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
+
+        let response = handle_semantic_tokens(&params, &snapshot);
 
-        let response = handle_semantic_tokens(&params, &text, &parse_result);
+        // Remember the tokens so later delta requests can diff against them
+        if let Some(SemanticTokensResult::Tokens(tokens)) = &response {
+            if let Some(result_id) = &tokens.result_id {
+                self.semantic_tokens
+                    .insert(uri.clone(), (result_id.clone(), tokens.data.clone()));
+            }
+        }
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
 
     /// Handle the textDocument/semanticTokens/full/delta request.
     fn handle_semantic_tokens_delta_request(
-        &self,
+        &mut self,
         req: lsp_server::Request,
     ) -> Result<serde_json::Value, String> {
         let params: SemanticTokensDeltaParams =
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
+
+        // Only diff against the previous tokens if the client's result ID matches
+        let previous = self
+            .semantic_tokens
+            .remove(uri)
+            .filter(|(result_id, _)| *result_id == params.previous_result_id)
+            .map(|(_, tokens)| tokens);
 
-        // Note: For a full implementation, we would store previous tokens by result_id
-        // and pass them to handle_semantic_tokens_delta. For now, pass None to always
-        // return full tokens as a delta.
-        let response = handle_semantic_tokens_delta(&params, &text, &parse_result, None);
+        let response = handle_semantic_tokens_delta(&params, &snapshot, previous.as_deref());
+
+        let cached = match &response {
+            Some(SemanticTokensFullDeltaResult::Tokens(tokens)) => {
+                tokens.result_id.clone().map(|id| (id, tokens.data.clone()))
+            }
+            Some(SemanticTokensFullDeltaResult::TokensDelta(delta)) => {
+                delta.result_id.clone().map(|id| {
+                    let tokens =
+                        apply_token_edits(previous.as_deref().unwrap_or(&[]), &delta.edits);
+                    (id, tokens)
+                })
+            }
+            _ => None,
+        };
+        if let Some(cached) = cached {
+            self.semantic_tokens.insert(uri.clone(), cached);
+        }
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -404,9 +679,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_semantic_tokens_range(&params, &text, &parse_result);
+        let response = handle_semantic_tokens_range(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -420,9 +695,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_code_actions(&params, &text, &parse_result);
+        let response = handle_code_actions(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -444,35 +719,33 @@ impl MainLoopState {
             "file:///unknown".parse().unwrap()
         };
 
-        let (text, parse_result) = self.get_document_data(&uri);
+        let snapshot = self.snapshot(&uri);
 
-        let resolved = handle_code_action_resolve(action, &text, &parse_result, &uri);
+        let resolved = handle_code_action_resolve(action, &snapshot);
 
         serde_json::to_value(resolved).map_err(|e| e.to_string())
     }
 
     /// Handle the workspace/symbol request.
     fn handle_workspace_symbol_request(
-        &self,
+        &mut self,
         req: lsp_server::Request,
     ) -> Result<serde_json::Value, String> {
         let params: WorkspaceSymbolParams =
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
+        let cancel = self.cancellation_token(&req.id);
 
         // Collect all open documents with cached parse results
         let mut vfs = self.vfs.write();
         let documents: Vec<_> = vfs
             .iter_with_parse()
-            .map(|(path, content, parse_result)| {
-                let uri_str = format!("file://{}", path.display());
-                let uri: Uri = uri_str
-                    .parse()
-                    .unwrap_or_else(|_| "file:///".parse().unwrap());
-                (uri, content, parse_result)
+            .filter_map(|(path, content, parse_result)| {
+                Some((path_to_uri(path)?, content, parse_result))
             })
             .collect();
 
-        let response = handle_workspace_symbols(&params, &documents);
+        let response =
+            handle_workspace_symbols(&params, &documents, &cancel).map_err(|e| e.to_string())?;
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -486,9 +759,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_prepare_rename(&params, &text, &parse_result);
+        let response = handle_prepare_rename(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -498,9 +771,11 @@ impl MainLoopState {
         let params: RenameParams = serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self
+            .snapshot(uri)
+            .with_include_graph(self.include_graph_documents(uri));
 
-        let response = handle_rename(&params, &text, &parse_result);
+        let response = handle_rename(&params, &snapshot)?;
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -514,9 +789,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_formatting(&params, &text, &parse_result);
+        let response = handle_formatting(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -530,9 +805,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_folding_ranges(&params, &text, &parse_result);
+        let response = handle_folding_ranges(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -546,9 +821,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_range_formatting(&params, &text, &parse_result);
+        let response = handle_range_formatting(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -562,9 +837,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_document_links(&params, &text, &parse_result);
+        let response = handle_document_links(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -590,9 +865,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_inlay_hints(&params, &text, &parse_result);
+        let response = handle_inlay_hints(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -614,8 +889,8 @@ impl MainLoopState {
             "file:///unknown".parse().unwrap()
         };
 
-        let (_text, parse_result) = self.get_document_data(&uri);
-        let resolved = handle_inlay_hint_resolve(hint, &parse_result);
+        let snapshot = self.snapshot(&uri);
+        let resolved = handle_inlay_hint_resolve(hint, &snapshot);
 
         serde_json::to_value(resolved).map_err(|e| e.to_string())
     }
@@ -629,9 +904,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_selection_range(&params, &text, &parse_result);
+        let response = handle_selection_range(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -645,9 +920,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_prepare_type_hierarchy(&params, &text, &parse_result, uri);
+        let response = handle_prepare_type_hierarchy(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -661,9 +936,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.item.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_supertypes(&params, &text, &parse_result, uri);
+        let response = handle_supertypes(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -677,9 +952,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.item.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_subtypes(&params, &text, &parse_result, uri);
+        let response = handle_subtypes(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -693,9 +968,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_document_highlight(&params, &text, &parse_result);
+        let response = handle_document_highlight(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -709,9 +984,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_linked_editing_range(&params, &text, &parse_result);
+        let response = handle_linked_editing_range(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -747,9 +1022,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_code_lens(&params, &text, &parse_result);
+        let response = handle_code_lens(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -771,8 +1046,8 @@ impl MainLoopState {
             "file:///unknown".parse().unwrap()
         };
 
-        let (_text, parse_result) = self.get_document_data(&uri);
-        let resolved = handle_code_lens_resolve(lens, &parse_result);
+        let snapshot = self.snapshot(&uri);
+        let resolved = handle_code_lens_resolve(lens, &snapshot);
 
         serde_json::to_value(resolved).map_err(|e| e.to_string())
     }
@@ -786,9 +1061,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_document_color(&params, &text, &parse_result);
+        let response = handle_document_color(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -801,8 +1076,8 @@ impl MainLoopState {
         let params: ColorPresentationParams =
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
-        // Handle color presentation
-        let response = handle_color_presentation(&params);
+        let (text, _parse_result) = self.get_document_data(&params.text_document.uri);
+        let response = handle_color_presentation(&params, &text);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -816,10 +1091,25 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
         // Handle go-to-declaration (same as definition for Beancount)
-        let response = handle_goto_declaration(&params, &text, &parse_result, uri);
+        let response = handle_goto_declaration(&params, &snapshot);
+
+        serde_json::to_value(response).map_err(|e| e.to_string())
+    }
+
+    /// Handle the textDocument/typeDefinition request.
+    fn handle_goto_type_definition_request(
+        &self,
+        req: lsp_server::Request,
+    ) -> Result<serde_json::Value, String> {
+        let params: GotoDefinitionParams =
+            serde_json::from_value(req.params).map_err(|e| e.to_string())?;
+
+        let uri = &params.text_document_position_params.text_document.uri;
+        let snapshot = self.snapshot(uri);
+        let response = handle_goto_type_definition(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -833,9 +1123,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.text_document_position_params.text_document.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_prepare_call_hierarchy(&params, &text, &parse_result, uri);
+        let response = handle_prepare_call_hierarchy(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -849,9 +1139,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.item.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_incoming_calls(&params, &text, &parse_result, uri);
+        let response = handle_incoming_calls(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -865,9 +1155,9 @@ impl MainLoopState {
             serde_json::from_value(req.params).map_err(|e| e.to_string())?;
 
         let uri = &params.item.uri;
-        let (text, parse_result) = self.get_document_data(uri);
+        let snapshot = self.snapshot(uri);
 
-        let response = handle_outgoing_calls(&params, &text, &parse_result, uri);
+        let response = handle_outgoing_calls(&params, &snapshot);
 
         serde_json::to_value(response).map_err(|e| e.to_string())
     }
@@ -912,9 +1202,7 @@ impl MainLoopState {
             .and_then(|s| s.parse().ok());
 
         if let Some(uri) = uri_from_args {
-            let (text, parse_result) = self.get_document_data(&uri);
-            let response = handle_execute_command(&params, &text, &parse_result, &uri);
-            return Ok(response.unwrap_or(serde_json::Value::Null));
+            return self.execute_command_on(&params, &uri);
         }
 
         // Fall back to first open document (legacy behavior)
@@ -929,21 +1217,155 @@ impl MainLoopState {
         };
 
         // Convert path to URI
-        #[cfg(not(windows))]
-        let uri: Uri = format!("file://{}", path.display())
-            .parse()
-            .map_err(|e| format!("{:?}", e))?;
-        #[cfg(windows)]
-        let uri: Uri = format!("file:///{}", path.display())
-            .parse()
-            .map_err(|e| format!("{:?}", e))?;
+        let uri = path_to_uri(&path).ok_or_else(|| format!("Invalid path: {}", path.display()))?;
 
-        let (text, parse_result) = self.get_document_data(&uri);
-        let response = handle_execute_command(&params, &text, &parse_result, &uri);
+        self.execute_command_on(&params, &uri)
+    }
+
+    /// Run a command against the document at `uri`.
+    fn execute_command_on(
+        &self,
+        params: &ExecuteCommandParams,
+        uri: &Uri,
+    ) -> Result<serde_json::Value, String> {
+        let snapshot = self.snapshot(uri);
+
+        // Flattening reads included files, preferring open documents
+        if params.command == "rledger.flatten" {
+            let path = uri_to_path(uri).ok_or("Flatten requires a file URI")?;
+            let vfs = self.vfs.read();
+            return Ok(handle_flatten(&path, &snapshot.text, |p: &Path| {
+                vfs.get_content(&p.to_path_buf())
+                    .or_else(|| std::fs::read_to_string(p).ok())
+            }));
+        }
 
+        let response = handle_execute_command(params, &snapshot);
         Ok(response.unwrap_or(serde_json::Value::Null))
     }
 
+    /// Handle the textDocument/diagnostic request.
+    ///
+    /// Diagnostics are recomputed from the current document text, so a
+    /// pull made during the debounce period reflects the latest edit. If
+    /// nothing they depend on changed since the client's previous result,
+    /// the report is `Unchanged` and nothing is recomputed.
+    fn handle_document_diagnostic_request(
+        &mut self,
+        req: lsp_server::Request,
+    ) -> Result<serde_json::Value, String> {
+        let params: DocumentDiagnosticParams =
+            serde_json::from_value(req.params).map_err(|e| e.to_string())?;
+
+        let uri = &params.text_document.uri;
+        let path = uri_to_path(uri);
+        let (text, _parse_result) = self.get_document_data(uri);
+        let result_id = self.diagnostics_result_id(path.as_deref(), &text);
+
+        let report = if params.previous_result_id.as_ref() == Some(&result_id) {
+            DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                related_documents: None,
+                unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                    result_id,
+                },
+            })
+        } else {
+            let diagnostics = self.compute_diagnostics(uri, &text);
+            self.diagnostics.insert(uri.clone(), diagnostics.clone());
+            // The include graph may have changed with this computation
+            let result_id = self.diagnostics_result_id(path.as_deref(), &text);
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items: diagnostics,
+                },
+            })
+        };
+        let report = DocumentDiagnosticReportResult::Report(report);
+
+        serde_json::to_value(report).map_err(|e| e.to_string())
+    }
+
+    /// Handle the workspace/diagnostic request.
+    ///
+    /// Reports on every file in the VFS, including workspace files that are
+    /// not open. When the client passes a partial result token, each file's
+    /// report is streamed as a `$/progress` notification and the final
+    /// response is empty. Cancellation is checked before each file. Files
+    /// whose result ID matches the client's previous one are reported as
+    /// `Unchanged` without recomputing their diagnostics.
+    fn handle_workspace_diagnostic_request(
+        &mut self,
+        req: lsp_server::Request,
+    ) -> Result<serde_json::Value, String> {
+        let params: WorkspaceDiagnosticParams =
+            serde_json::from_value(req.params).map_err(|e| e.to_string())?;
+        let token = params.partial_result_params.partial_result_token;
+        let cancel = self.cancellation_token(&req.id);
+        let previous: HashMap<String, String> = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri.as_str().to_string(), previous.value))
+            .collect();
+
+        let mut documents: Vec<(PathBuf, String)> = self
+            .vfs
+            .read()
+            .iter()
+            .map(|(path, content)| (path.clone(), content))
+            .collect();
+        documents.sort();
+
+        let mut items = Vec::new();
+        for (path, text) in documents {
+            cancel.check().map_err(|e| e.to_string())?;
+            let Some(uri) = path_to_uri(&path) else {
+                continue;
+            };
+            let result_id = self.diagnostics_result_id(Some(&path), &text);
+            let report = if previous.get(uri.as_str()) == Some(&result_id) {
+                WorkspaceDocumentDiagnosticReport::Unchanged(
+                    WorkspaceUnchangedDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                            result_id,
+                        },
+                    },
+                )
+            } else {
+                let diagnostics = self.compute_diagnostics(&uri, &text);
+                let result_id = self.diagnostics_result_id(Some(&path), &text);
+                WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                    uri,
+                    version: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: Some(result_id),
+                        items: diagnostics,
+                    },
+                })
+            };
+
+            match &token {
+                Some(token) => {
+                    let partial = WorkspaceDiagnosticReportPartialResult {
+                        items: vec![report],
+                    };
+                    let notif = lsp_server::Notification::new(
+                        Progress::METHOD.to_string(),
+                        serde_json::json!({ "token": token, "value": partial }),
+                    );
+                    self.send(lsp_server::Message::Notification(notif));
+                }
+                None => items.push(report),
+            }
+        }
+
+        let report = WorkspaceDiagnosticReportResult::Report(WorkspaceDiagnosticReport { items });
+        serde_json::to_value(report).map_err(|e| e.to_string())
+    }
+
     /// Handle the completionItem/resolve request.
     fn handle_completion_resolve_request(
         &self,
@@ -961,8 +1383,8 @@ impl MainLoopState {
             "file:///unknown".parse().unwrap()
         };
 
-        let (_text, parse_result) = self.get_document_data(&uri);
-        let resolved = handle_completion_resolve(item, &parse_result);
+        let snapshot = self.snapshot(&uri);
+        let resolved = handle_completion_resolve(item, &snapshot);
 
         serde_json::to_value(resolved).map_err(|e| e.to_string())
     }
@@ -999,8 +1421,21 @@ impl MainLoopState {
                     self.on_did_change_watched_files(params);
                 }
             }
+            DidChangeConfiguration::METHOD => {
+                if let Ok(params) =
+                    serde_json::from_value::<lsp_types::DidChangeConfigurationParams>(notif.params)
+                {
+                    self.on_did_change_configuration(params);
+                }
+            }
+            Cancel::METHOD => {
+                if let Ok(params) = serde_json::from_value::<CancelParams>(notif.params) {
+                    self.on_cancel_request(params);
+                }
+            }
             "initialized" => {
                 tracing::info!("Client initialized");
+                self.index_workspace();
                 // Register for file watching after initialization
                 self.register_file_watchers();
             }
@@ -1027,11 +1462,9 @@ impl MainLoopState {
             self.vfs.write().open(path, text.clone(), version);
         }
 
-        // Bump revision (invalidates any in-flight requests)
-        bump_revision();
-
         // Compute and publish diagnostics
         self.publish_diagnostics(&uri, &text);
+        self.publish_dependent_diagnostics(&uri);
     }
 
     /// Handle textDocument/didChange notification.
@@ -1050,11 +1483,9 @@ impl MainLoopState {
                 self.vfs.write().update(&path, text.clone(), version);
             }
 
-            // Bump revision
-            bump_revision();
-
-            // Recompute diagnostics
-            self.publish_diagnostics(&uri, &text);
+            // Recompute diagnostics (including dependents) once edits settle
+            self.pending_diagnostics
+                .insert(uri, Instant::now() + self.diagnostics_debounce);
         }
     }
 
@@ -1064,32 +1495,147 @@ impl MainLoopState {
 
         tracing::info!("Document closed: {}", uri.as_str());
 
-        // Remove from VFS
+        // Remove from VFS; workspace files stay indexed with their saved content
         if let Some(path) = uri_to_path(&uri) {
-            self.vfs.write().close(&path);
+            let mut vfs = self.vfs.write();
+            vfs.close(&path);
+            let in_workspace = self
+                .workspace_roots
+                .iter()
+                .any(|root| path.starts_with(root));
+            match std::fs::read_to_string(&path) {
+                Ok(content) if in_workspace => {
+                    vfs.load_external(path, content);
+                }
+                _ => {
+                    self.include_graph.remove(&path);
+                }
+            }
         }
 
-        // Clear diagnostics
+        // Clear diagnostics and cached tokens
+        self.pending_diagnostics.remove(&uri);
         self.diagnostics.remove(&uri);
+        self.semantic_tokens.remove(&uri);
         self.send_diagnostics(&uri, vec![]);
+
+        // Dependents now see the file's contents on disk
+        self.publish_dependent_diagnostics(&uri);
+    }
+
+    /// Republish diagnostics for open documents that include `uri`.
+    fn publish_dependent_diagnostics(&mut self, uri: &Uri) {
+        let Some(path) = uri_to_path(uri) else {
+            return;
+        };
+
+        let dependents: Vec<PathBuf> = self
+            .include_graph
+            .iter()
+            .filter(|(dependent, included)| **dependent != path && included.contains(&path))
+            .map(|(dependent, _)| dependent.clone())
+            .collect();
+
+        for dependent in dependents {
+            // Files loaded from disk are not shown, so they get no diagnostics
+            let content = self
+                .vfs
+                .read()
+                .get(&dependent)
+                .filter(|doc| !doc.is_external())
+                .map(|doc| doc.text());
+            let Some(content) = content else {
+                continue;
+            };
+            let Some(dependent_uri) = path_to_uri(&dependent) else {
+                continue;
+            };
+            tracing::debug!("Republishing dependent: {}", dependent_uri.as_str());
+            self.publish_diagnostics(&dependent_uri, &content);
+        }
+    }
+
+    /// Collect the other documents in `uri`'s include graph.
+    ///
+    /// These are the files it includes, the open documents that include it,
+    /// and everything those include in turn. Open documents are read from
+    /// the VFS, the rest from disk.
+    fn include_graph_documents(&self, uri: &Uri) -> Vec<RelatedDocument> {
+        let Some(path) = uri_to_path(uri) else {
+            return Vec::new();
+        };
+
+        let roots = self
+            .include_graph
+            .iter()
+            .filter(|(_, included)| included.contains(&path))
+            .map(|(dependent, _)| dependent)
+            .chain(std::iter::once(&path));
+        let mut paths: HashSet<PathBuf> = HashSet::new();
+        for root in roots {
+            paths.insert(root.clone());
+            if let Some(included) = self.include_graph.get(root) {
+                paths.extend(included.iter().cloned());
+            }
+        }
+        paths.remove(&path);
+
+        let vfs = self.vfs.read();
+        paths
+            .into_iter()
+            .filter_map(|p| {
+                let content = vfs.get_content(&p).or_else(|| {
+                    let content = std::fs::read_to_string(&p).ok()?;
+                    Some(strip_bom(&content).0.to_string())
+                })?;
+                let doc_uri = path_to_uri(&p)?;
+                let parse_result = Arc::new(parse_document(&content));
+                Some((doc_uri, content, parse_result))
+            })
+            .collect()
     }
 
     /// Handle workspace/didChangeWatchedFiles notification.
     fn on_did_change_watched_files(&mut self, params: lsp_types::DidChangeWatchedFilesParams) {
         tracing::info!("Watched files changed: {} files", params.changes.len());
 
+        let mut any_changed = false;
         for change in params.changes {
             tracing::debug!("File {:?}: {:?}", change.uri.as_str(), change.typ);
 
-            // If a .beancount file changed externally, re-validate open documents
-            // that might include this file
-            if change.uri.as_str().ends_with(".beancount") {
-                self.revalidate_open_documents();
-                break; // Only need to revalidate once
+            let Some(path) = uri_to_path(&change.uri) else {
+                continue;
+            };
+            if change.typ == lsp_types::FileChangeType::DELETED {
+                if self.vfs.write().remove_external(&path) {
+                    self.diagnostics.remove(&change.uri);
+                    self.send_diagnostics(&change.uri, vec![]);
+                }
+            } else if let Ok(content) = std::fs::read_to_string(&path) {
+                // Editor-owned documents keep their (possibly unsaved) content
+                self.vfs.write().load_external(path, content);
             }
+            any_changed = true;
+        }
+
+        // Re-validate open documents, which may include the changed files
+        if any_changed {
+            self.revalidate_open_documents();
         }
     }
 
+    /// Handle workspace/didChangeConfiguration notification.
+    ///
+    /// Invalid settings are ignored, keeping the current configuration.
+    fn on_did_change_configuration(&mut self, params: lsp_types::DidChangeConfigurationParams) {
+        let Some(config) = Config::from_value(&params.settings) else {
+            return;
+        };
+        tracing::info!("Configuration changed");
+        self.set_config(config);
+        self.revalidate_open_documents();
+    }
+
     /// Re-validate all open documents (e.g., after an included file changes).
     fn revalidate_open_documents(&mut self) {
         let paths: Vec<_> = self.vfs.read().paths().cloned().collect();
@@ -1099,9 +1645,7 @@ impl MainLoopState {
             .into_iter()
             .filter_map(|path| {
                 let content = self.vfs.read().get_content(&path)?;
-                let uri_str = format!("file://{}", path.display());
-                let uri = uri_str.parse::<Uri>().ok()?;
-                Some((uri, content))
+                Some((path_to_uri(&path)?, content))
             })
             .collect();
 
@@ -1154,11 +1698,7 @@ impl MainLoopState {
 
     /// Parse document and publish diagnostics.
     fn publish_diagnostics(&mut self, uri: &Uri, text: &str) {
-        // Parse the document
-        let result = parse(text);
-
-        // Convert errors to LSP diagnostics
-        let diagnostics = parse_errors_to_diagnostics(&result, text);
+        let diagnostics = self.compute_diagnostics(uri, text);
 
         tracing::debug!(
             "Publishing {} diagnostics for {}",
@@ -1171,8 +1711,116 @@ impl MainLoopState {
         self.send_diagnostics(uri, diagnostics);
     }
 
-    /// Send diagnostics to the client.
+    /// Parse document and compute its diagnostics.
+    ///
+    /// Also records the document's includes in the include graph.
+    fn compute_diagnostics(&mut self, uri: &Uri, text: &str) -> Vec<lsp_types::Diagnostic> {
+        // Files loaded from disk have a byte order mark stripped from their text
+        let path = uri_to_path(uri);
+        let bom = text.starts_with(BOM)
+            || path
+                .as_ref()
+                .is_some_and(|path| self.vfs.read().get(path).is_some_and(|doc| doc.has_bom()));
+
+        // Parse the document
+        let result = parse_document(text);
+
+        // Convert errors to LSP diagnostics
+        let mut diagnostics = parse_errors_to_diagnostics(&result, text);
+        if bom {
+            diagnostics.push(bom_diagnostic());
+        }
+
+        // Directives from included files, for checks that span files.
+        // Open documents are read from the VFS so unsaved edits count.
+        let included_files = path
+            .as_ref()
+            .map(|path| {
+                let vfs = self.vfs.read();
+                load_included_files(path, &result, |p: &Path| {
+                    vfs.get_content(&p.to_path_buf())
+                        .or_else(|| std::fs::read_to_string(p).ok())
+                })
+            })
+            .unwrap_or_default();
+        if let Some(path) = &path {
+            self.include_graph
+                .insert(path.clone(), included_files.paths);
+        }
+        let included = included_files.directives;
+
+        if self.config.diagnostics.undefined_accounts {
+            let opened_elsewhere = collect_included_accounts(&included);
+            diagnostics.extend(undefined_account_diagnostics(
+                &result,
+                text,
+                &opened_elsewhere,
+            ));
+        }
+        diagnostics.extend(pad_without_balance_diagnostics(&result, text, &included));
+        diagnostics.extend(unrelated_currencies_diagnostics(
+            &result,
+            text,
+            &self.config.conversions_account,
+        ));
+        diagnostics.extend(balance_currency_diagnostics(&result, text, &included));
+        diagnostics.extend(currency_constraint_diagnostics(&result, text, &included));
+        diagnostics.extend(negative_balance_diagnostics(&result, text, &included));
+        diagnostics.extend(lot_reduction_diagnostics(&result, text, &included));
+        diagnostics.extend(budget_overlap_diagnostics(&result, text, &included));
+        diagnostics.extend(duplicate_metadata_diagnostics(&result, text, uri));
+        diagnostics.extend(excess_precision_diagnostics(&result, text));
+        diagnostics.extend(self_price_diagnostics(&result, text));
+        diagnostics.extend(unknown_booking_diagnostics(&result, text));
+        diagnostics.extend(account_capitalization_diagnostics(text));
+        if self.config.diagnostics.missing_documents {
+            let base_dir = path
+                .as_deref()
+                .and_then(|path| document_base_dir(path, self.config.document_base.as_deref()));
+            if let Some(base_dir) = base_dir {
+                diagnostics.extend(missing_document_diagnostics(&result, text, &base_dir));
+            }
+        }
+
+        // Opt-in checks
+        if self.config.diagnostics.undeclared_currencies {
+            let mut declared_elsewhere = collect_included_commodities(&included);
+            declared_elsewhere.extend(self.config.operating_currency.clone());
+            diagnostics.extend(undeclared_currency_diagnostics(
+                &result,
+                text,
+                &declared_elsewhere,
+            ));
+        }
+        if self.config.diagnostics.flagged_entries {
+            diagnostics.extend(flagged_entry_diagnostics(&result, text));
+        }
+        if self.config.diagnostics.metadata_types {
+            diagnostics.extend(metadata_type_diagnostics(&result, text));
+        }
+        if self.config.diagnostics.duplicate_transactions {
+            diagnostics.extend(duplicate_transaction_diagnostics(&result, text, uri));
+        }
+        if self.config.diagnostics.indentation {
+            diagnostics.extend(indentation_diagnostics(
+                &result,
+                text,
+                self.config.indent_width,
+            ));
+        }
+        if self.config.diagnostics.inline_tabs {
+            diagnostics.extend(inline_tab_diagnostics(text, self.config.indent_width));
+        }
+
+        diagnostics
+    }
+
+    /// Send diagnostics to the client, unless it pulls them.
     fn send_diagnostics(&self, uri: &Uri, diagnostics: Vec<lsp_types::Diagnostic>) {
+        if self.pull_diagnostics {
+            return;
+        }
+
         let params = PublishDiagnosticsParams {
             uri: uri.clone(),
             diagnostics,
@@ -1193,12 +1841,47 @@ impl MainLoopState {
 }
 
 /// Run the main event loop.
-pub fn run_main_loop(receiver: Receiver<lsp_server::Message>, sender: Sender<lsp_server::Message>) {
-    let mut state = MainLoopState::new(sender);
+///
+/// While diagnostics are pending, waiting for the next message times out at
+/// the earliest debounce deadline so they are published once edits settle.
+/// Messages already waiting are queued so that a `$/cancelRequest` can reach
+/// the request it targets before that request is handled.
+pub fn run_main_loop(
+    receiver: Receiver<lsp_server::Message>,
+    sender: Sender<lsp_server::Message>,
+    init_params: &InitializeParams,
+) {
+    let mut state = MainLoopState::with_config(sender, Config::from_params(init_params));
+    state.workspace_roots = workspace_roots(init_params);
+    state.pull_diagnostics = client_pulls_diagnostics(init_params);
 
     tracing::info!("Main loop started");
 
-    for msg in receiver {
+    let mut queue: VecDeque<lsp_server::Message> = VecDeque::new();
+    loop {
+        let msg = match queue.pop_front() {
+            Some(msg) => msg,
+            None => match state.next_diagnostics_deadline() {
+                Some(deadline) => {
+                    match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    {
+                        Ok(msg) => msg,
+                        Err(RecvTimeoutError::Timeout) => {
+                            state.flush_pending_diagnostics(Instant::now());
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match receiver.recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                },
+            },
+        };
+        queue.extend(receiver.try_iter());
+        state.cancel_queued_requests(std::iter::once(&msg).chain(&queue));
+
         let event = match msg {
             lsp_server::Message::Request(req) => Event::Message(Message::Request(req)),
             lsp_server::Message::Notification(notif) => {
@@ -1212,3 +1895,587 @@ pub fn run_main_loop(receiver: Receiver<lsp_server::Message>, sender: Sender<lsp
 
     tracing::info!("Main loop ended");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::diagnostics::{BOM_CODE, UNDEFINED_ACCOUNT_CODE};
+    use crate::handlers::utils::LineIndex;
+    use lsp_types::{
+        CompletionResponse, DidChangeTextDocumentParams, DidOpenTextDocumentParams, NumberOrString,
+        TextDocumentContentChangeEvent, TextDocumentItem, VersionedTextDocumentIdentifier,
+    };
+
+    fn open(state: &mut MainLoopState, uri: &Uri, text: &str) {
+        state.on_did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: "beancount".to_string(),
+                version: 1,
+                text: text.to_string(),
+            },
+        });
+    }
+
+    fn change(state: &mut MainLoopState, uri: &Uri, text: &str) {
+        state.on_did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: 2,
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.to_string(),
+            }],
+        });
+    }
+
+    /// Apply a change and let its debounce period elapse.
+    fn change_and_settle(state: &mut MainLoopState, uri: &Uri, text: &str) {
+        change(state, uri, text);
+        state.flush_pending_diagnostics(Instant::now() + state.diagnostics_debounce);
+    }
+
+    fn published_diagnostics(receiver: &Receiver<lsp_server::Message>) -> Vec<Uri> {
+        receiver
+            .try_iter()
+            .filter_map(|msg| match msg {
+                lsp_server::Message::Notification(notif)
+                    if notif.method == PublishDiagnostics::METHOD =>
+                {
+                    serde_json::from_value::<PublishDiagnosticsParams>(notif.params)
+                        .ok()
+                        .map(|params| params.uri)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rapid_changes_publish_diagnostics_once() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let config = Config {
+            diagnostics_debounce_ms: Some(100),
+            ..Config::default()
+        };
+        let mut state = MainLoopState::with_config(sender, config);
+        let uri: Uri = "file:///virtual/debounce/main.beancount".parse().unwrap();
+
+        open(&mut state, &uri, "");
+        assert_eq!(published_diagnostics(&receiver), vec![uri.clone()]);
+
+        let start = Instant::now();
+        change(&mut state, &uri, "2024-01-01 open Assets:Bank\n");
+        change(&mut state, &uri, "2024-01-01 open Assets:Bank\n2024-01-02");
+        change(
+            &mut state,
+            &uri,
+            "2024-01-01 open Assets:Bank\n2024-01-02 * \"x\"\n",
+        );
+
+        // Nothing is published before the quiet period ends
+        state.flush_pending_diagnostics(start);
+        assert_eq!(published_diagnostics(&receiver), Vec::<Uri>::new());
+
+        let deadline = state.next_diagnostics_deadline().unwrap();
+        assert!(deadline >= start + Duration::from_millis(100));
+        state.flush_pending_diagnostics(deadline);
+        assert_eq!(published_diagnostics(&receiver), vec![uri.clone()]);
+        assert_eq!(state.next_diagnostics_deadline(), None);
+    }
+
+    fn undefined_account_count(state: &MainLoopState, uri: &Uri) -> usize {
+        state.diagnostics[uri]
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String(UNDEFINED_ACCOUNT_CODE.to_string())))
+            .count()
+    }
+
+    #[test]
+    fn test_pull_diagnostics_match_pushed() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        let uri: Uri = "file:///virtual/pull/main.beancount".parse().unwrap();
+
+        open(
+            &mut state,
+            &uri,
+            "2024-01-01 balance Assets:Bank 0 USD\n2024-01-02 * \"Unbalanced\n",
+        );
+        let pushed = state.diagnostics[&uri].clone();
+        assert!(pushed.len() >= 2);
+
+        let req = lsp_server::Request::new(
+            1.into(),
+            DocumentDiagnosticRequest::METHOD.to_string(),
+            serde_json::json!({ "textDocument": { "uri": uri.as_str() } }),
+        );
+        let value = state.handle_document_diagnostic_request(req).unwrap();
+        let report: DocumentDiagnosticReportResult = serde_json::from_value(value).unwrap();
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = report
+        else {
+            panic!("expected a full report");
+        };
+        assert_eq!(report.full_document_diagnostic_report.items, pushed);
+    }
+
+    #[test]
+    fn test_pull_diagnostics_unchanged_until_an_include_changes() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        let main: Uri = "file:///virtual/result-id/main.beancount".parse().unwrap();
+        let accounts: Uri = "file:///virtual/result-id/accounts.beancount"
+            .parse()
+            .unwrap();
+        open(&mut state, &accounts, "2024-01-01 open Assets:Bank\n");
+        open(
+            &mut state,
+            &main,
+            "include \"accounts.beancount\"\n2024-01-02 balance Assets:Bank 0 USD\n",
+        );
+
+        let pull = |state: &mut MainLoopState, previous: Option<&str>| {
+            let req = lsp_server::Request::new(
+                1.into(),
+                DocumentDiagnosticRequest::METHOD.to_string(),
+                serde_json::json!({
+                    "textDocument": { "uri": main.as_str() },
+                    "previousResultId": previous,
+                }),
+            );
+            let value = state.handle_document_diagnostic_request(req).unwrap();
+            match serde_json::from_value(value).unwrap() {
+                DocumentDiagnosticReportResult::Report(report) => report,
+                DocumentDiagnosticReportResult::Partial(_) => panic!("expected a report"),
+            }
+        };
+
+        let DocumentDiagnosticReport::Full(full) = pull(&mut state, None) else {
+            panic!("expected a full report");
+        };
+        let result_id = full.full_document_diagnostic_report.result_id.unwrap();
+        assert!(matches!(
+            pull(&mut state, Some(&result_id)),
+            DocumentDiagnosticReport::Unchanged(_)
+        ));
+
+        // Editing the included file changes the main file's diagnostics
+        change(&mut state, &accounts, "2024-01-01 open Assets:Cash\n");
+        let DocumentDiagnosticReport::Full(full) = pull(&mut state, Some(&result_id)) else {
+            panic!("expected a full report");
+        };
+        assert_ne!(
+            full.full_document_diagnostic_report.result_id,
+            Some(result_id)
+        );
+        assert_eq!(full.full_document_diagnostic_report.items.len(), 1);
+    }
+
+    #[test]
+    fn test_pull_clients_are_not_pushed_diagnostics() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        let req = lsp_server::Request::new(
+            1.into(),
+            Initialize::METHOD.to_string(),
+            serde_json::json!({ "capabilities": { "textDocument": { "diagnostic": {} } } }),
+        );
+        state.handle_initialize(req).unwrap();
+        assert!(state.pull_diagnostics);
+
+        let uri: Uri = "file:///virtual/pull-only/main.beancount".parse().unwrap();
+        open(&mut state, &uri, "2024-01-02 * \"Unbalanced\n");
+        change_and_settle(&mut state, &uri, "2024-01-02 * \"Still unbalanced\n");
+
+        // Diagnostics are computed for pulls but never pushed
+        assert!(!state.diagnostics[&uri].is_empty());
+        assert_eq!(published_diagnostics(&receiver), Vec::<Uri>::new());
+    }
+
+    #[test]
+    fn test_workspace_diagnostics_cover_unopened_files() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        let main: Uri = "file:///virtual/workspace-pull/main.beancount"
+            .parse()
+            .unwrap();
+        let other = PathBuf::from("/virtual/workspace-pull/other.beancount");
+
+        open(
+            &mut state,
+            &main,
+            "include \"other.beancount\"\n2024-01-02 balance Assets:Bank 0 USD\n",
+        );
+        // Indexed from the workspace but never opened
+        state.vfs.write().load_external(
+            other.clone(),
+            "2024-01-01 open Assets:Bank\n2024-01-03 balance Assets:Cash 0 USD\n".to_string(),
+        );
+        receiver.try_iter().for_each(drop);
+
+        let request = |token: Option<&str>| {
+            let mut params = serde_json::json!({ "previousResultIds": [] });
+            if let Some(token) = token {
+                params["partialResultToken"] = serde_json::json!(token);
+            }
+            lsp_server::Request::new(
+                1.into(),
+                WorkspaceDiagnosticRequest::METHOD.to_string(),
+                params,
+            )
+        };
+        let value = state
+            .handle_workspace_diagnostic_request(request(None))
+            .unwrap();
+        let WorkspaceDiagnosticReportResult::Report(report) =
+            serde_json::from_value(value).unwrap()
+        else {
+            panic!("expected a full report");
+        };
+
+        let counts: Vec<(String, usize)> = report
+            .items
+            .iter()
+            .map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => (
+                    full.uri.as_str().to_string(),
+                    full.full_document_diagnostic_report.items.len(),
+                ),
+                WorkspaceDocumentDiagnosticReport::Unchanged(_) => unreachable!(),
+            })
+            .collect();
+        // Assets:Bank is opened in the included file; Assets:Cash is never opened
+        assert_eq!(
+            counts,
+            vec![
+                (main.as_str().to_string(), 0),
+                (path_to_uri(&other).unwrap().as_str().to_string(), 1),
+            ]
+        );
+
+        // With a partial result token, each file is streamed as progress
+        state
+            .handle_workspace_diagnostic_request(request(Some("pull")))
+            .unwrap();
+        let streamed = receiver
+            .try_iter()
+            .filter(|msg| {
+                matches!(msg, lsp_server::Message::Notification(n) if n.method == Progress::METHOD)
+            })
+            .count();
+        assert_eq!(streamed, 2);
+
+        // Re-polling with the returned result IDs reports nothing changed
+        let previous: Vec<serde_json::Value> = report
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => Some(serde_json::json!({
+                    "uri": full.uri.as_str(),
+                    "value": full.full_document_diagnostic_report.result_id.clone()?,
+                })),
+                WorkspaceDocumentDiagnosticReport::Unchanged(_) => None,
+            })
+            .collect();
+        let req = lsp_server::Request::new(
+            2.into(),
+            WorkspaceDiagnosticRequest::METHOD.to_string(),
+            serde_json::json!({ "previousResultIds": previous }),
+        );
+        let value = state.handle_workspace_diagnostic_request(req).unwrap();
+        let WorkspaceDiagnosticReportResult::Report(report) =
+            serde_json::from_value(value).unwrap()
+        else {
+            panic!("expected a full report");
+        };
+        assert_eq!(report.items.len(), 2);
+        assert!(
+            report
+                .items
+                .iter()
+                .all(|item| matches!(item, WorkspaceDocumentDiagnosticReport::Unchanged(_)))
+        );
+    }
+
+    #[test]
+    fn test_complete_custom_names_from_workspace_files() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        state.vfs.write().load_external(
+            PathBuf::from("/virtual/custom/budget.beancount"),
+            "2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 500 USD\n".to_string(),
+        );
+        let uri: Uri = "file:///virtual/custom/main.beancount".parse().unwrap();
+        open(&mut state, &uri, "2024-03-01 custom ");
+
+        let req = lsp_server::Request::new(
+            1.into(),
+            Completion::METHOD.to_string(),
+            serde_json::json!({
+                "textDocument": { "uri": uri.as_str() },
+                "position": { "line": 0, "character": 18 },
+            }),
+        );
+        let value = state.handle_completion_request(req).unwrap();
+        let Some(CompletionResponse::Array(items)) = serde_json::from_value(value).unwrap() else {
+            panic!("expected completion items");
+        };
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["\"budget\""]);
+    }
+
+    #[test]
+    fn test_cancelled_workspace_diagnostics_stop_early() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        for i in 0..3 {
+            state.vfs.write().load_external(
+                PathBuf::from(format!("/virtual/cancel/file{i}.beancount")),
+                "2024-01-01 open Assets:Bank\n".to_string(),
+            );
+        }
+
+        let request = lsp_server::Message::Request(lsp_server::Request::new(
+            7.into(),
+            WorkspaceDiagnosticRequest::METHOD.to_string(),
+            serde_json::json!({ "previousResultIds": [], "partialResultToken": "pull" }),
+        ));
+        let cancel = lsp_server::Message::Notification(lsp_server::Notification::new(
+            Cancel::METHOD.to_string(),
+            serde_json::json!({ "id": 7 }),
+        ));
+        state.cancel_queued_requests([&request, &cancel].into_iter());
+        let lsp_server::Message::Request(request) = request else {
+            unreachable!()
+        };
+        state.handle_request(request);
+
+        let messages: Vec<_> = receiver.try_iter().collect();
+        // No file was reported before the cancellation was noticed
+        assert!(!messages.iter().any(
+            |msg| matches!(msg, lsp_server::Message::Notification(n) if n.method == Progress::METHOD)
+        ));
+        let [lsp_server::Message::Response(response)] = messages.as_slice() else {
+            panic!("expected a single response");
+        };
+        assert_eq!(
+            response.error.as_ref().map(|e| e.code),
+            Some(lsp_server::ErrorCode::RequestCanceled as i32)
+        );
+        assert!(state.cancellations.is_empty());
+    }
+
+    #[test]
+    fn test_config_disables_undefined_account_diagnostic() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let config = Config::from_value(&serde_json::json!({
+            "diagnostics": { "undefinedAccounts": false }
+        }))
+        .unwrap();
+        let mut state = MainLoopState::with_config(sender, config);
+        let uri: Uri = "file:///virtual/config/main.beancount".parse().unwrap();
+
+        open(&mut state, &uri, "2024-01-01 balance Assets:Bank 0 USD\n");
+        assert_eq!(undefined_account_count(&state, &uri), 0);
+
+        // Re-enabling the check republishes open documents
+        state.on_did_change_configuration(lsp_types::DidChangeConfigurationParams {
+            settings: serde_json::json!({ "rledger": { "diagnostics": { "undefinedAccounts": true } } }),
+        });
+        assert_eq!(undefined_account_count(&state, &uri), 1);
+    }
+
+    #[test]
+    fn test_bom_prefixed_document() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+        let uri: Uri = "file:///virtual/bom/main.beancount".parse().unwrap();
+
+        let text =
+            "\u{feff}2024-01-01 balance Assets:Bank 0 USD\noption \"operating_currency\" \"USD\"\n";
+        open(&mut state, &uri, text);
+
+        let snapshot = state.snapshot(&uri);
+        assert!(snapshot.parse_result.errors.is_empty());
+        assert_eq!(snapshot.parse_result.options[0].0, "operating_currency");
+
+        let diagnostics = &state.diagnostics[&uri];
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code.clone()).collect();
+        assert_eq!(
+            codes,
+            vec![
+                Some(NumberOrString::String(BOM_CODE.to_string())),
+                Some(NumberOrString::String(UNDEFINED_ACCOUNT_CODE.to_string())),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(lsp_types::DiagnosticSeverity::INFORMATION)
+        );
+        // Positions are those of the editor's text, mark included
+        let (line, col) = LineIndex::new(text).offset_to_position(text.find("Assets").unwrap());
+        assert_eq!(
+            diagnostics[1].range.start,
+            lsp_types::Position::new(line, col)
+        );
+    }
+
+    #[test]
+    fn test_change_in_included_file_refreshes_dependents() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+
+        let main: Uri = "file:///virtual/ledger/main.beancount".parse().unwrap();
+        let accounts: Uri = "file:///virtual/ledger/accounts.beancount".parse().unwrap();
+
+        open(
+            &mut state,
+            &main,
+            "include \"accounts.beancount\"\n2024-01-01 balance Assets:Bank 0 USD\n",
+        );
+        open(&mut state, &accounts, "");
+        assert_eq!(undefined_account_count(&state, &main), 1);
+
+        // Opening the account in the included file clears the error in main
+        change_and_settle(&mut state, &accounts, "2024-01-01 open Assets:Bank\n");
+        assert_eq!(undefined_account_count(&state, &main), 0);
+    }
+
+    #[test]
+    fn test_include_graph_documents_cover_siblings() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+
+        let main: Uri = "file:///virtual/graph/main.beancount".parse().unwrap();
+        let prices: Uri = "file:///virtual/graph/prices.beancount".parse().unwrap();
+        let trades: Uri = "file:///virtual/graph/trades.beancount".parse().unwrap();
+
+        open(&mut state, &prices, "2024-01-16 price FB 36.00 USD\n");
+        open(&mut state, &trades, "2024-01-15 commodity FB\n");
+        open(
+            &mut state,
+            &main,
+            "include \"prices.beancount\"\ninclude \"trades.beancount\"\n",
+        );
+
+        // Renaming from one included file reaches its sibling via main
+        let mut uris: Vec<String> = state
+            .include_graph_documents(&trades)
+            .into_iter()
+            .map(|(uri, _, _)| uri.as_str().to_string())
+            .collect();
+        uris.sort();
+        assert_eq!(uris, vec![main.as_str(), prices.as_str()]);
+    }
+
+    #[test]
+    fn test_index_workspace_parses_and_caches_files() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("accounts")).unwrap();
+        let main = root.join("main.beancount");
+        let bank = root.join("accounts/bank.beancount");
+        std::fs::write(&main, "include \"accounts/bank.beancount\"\n").unwrap();
+        std::fs::write(&bank, "2024-01-01 open Assets:Bank\n").unwrap();
+
+        state.workspace_roots = vec![root.to_path_buf()];
+        assert_eq!(state.index_workspace(), 2);
+
+        // Both files are cached, with their parse results, before being opened
+        let mut vfs = state.vfs.write();
+        assert!(vfs.get(&bank).is_some_and(|doc| doc.is_external()));
+        let (_, parsed) = vfs.get_document_data(&bank).unwrap();
+        assert_eq!(parsed.directives.len(), 1);
+        drop(vfs);
+
+        // The include graph is built from the cached files
+        assert!(state.include_graph[&main].contains(&bank));
+    }
+
+    #[test]
+    fn test_change_in_included_file_skips_closed_dependents() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let main = root.join("main.beancount");
+        let bank = root.join("bank.beancount");
+        std::fs::write(&main, "include \"bank.beancount\"\n").unwrap();
+        std::fs::write(&bank, "2024-01-01 open Assets:Bank\n").unwrap();
+        state.workspace_roots = vec![root.to_path_buf()];
+        state.index_workspace();
+
+        // Only the included file is open; main is indexed but not shown
+        let bank_uri = path_to_uri(&bank).unwrap();
+        open(&mut state, &bank_uri, "2024-01-01 open Assets:Bank\n");
+        change_and_settle(&mut state, &bank_uri, "2024-01-01 open Assets:Cash\n");
+
+        let main_uri = path_to_uri(&main).unwrap();
+        assert!(!state.diagnostics.contains_key(&main_uri));
+    }
+
+    #[test]
+    fn test_watched_file_change_reloads_content() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("imported.beancount");
+        let uri = path_to_uri(&path).unwrap();
+        let event = |typ| lsp_types::DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: uri.clone(),
+                typ,
+            }],
+        };
+
+        std::fs::write(&path, "2024-01-01 open Assets:Bank\n").unwrap();
+        state.on_did_change_watched_files(event(lsp_types::FileChangeType::CREATED));
+        assert_eq!(
+            state.vfs.read().get_content(&path).as_deref(),
+            Some("2024-01-01 open Assets:Bank\n")
+        );
+
+        std::fs::write(&path, "2024-01-01 open Assets:Cash\n").unwrap();
+        state.on_did_change_watched_files(event(lsp_types::FileChangeType::CHANGED));
+        assert_eq!(
+            state.vfs.read().get_content(&path).as_deref(),
+            Some("2024-01-01 open Assets:Cash\n")
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        state.on_did_change_watched_files(event(lsp_types::FileChangeType::DELETED));
+        assert!(state.vfs.read().get_content(&path).is_none());
+    }
+
+    #[test]
+    fn test_watched_file_change_keeps_editor_content() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = MainLoopState::new(sender);
+
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let path = dir.join("open.beancount");
+        std::fs::write(&path, "; on disk\n").unwrap();
+        let uri = path_to_uri(&path).unwrap();
+
+        open(&mut state, &uri, "; unsaved edits\n");
+        state.on_did_change_watched_files(lsp_types::DidChangeWatchedFilesParams {
+            changes: vec![lsp_types::FileEvent {
+                uri: uri.clone(),
+                typ: lsp_types::FileChangeType::CHANGED,
+            }],
+        });
+        assert_eq!(
+            state.vfs.read().get_content(&path).as_deref(),
+            Some("; unsaved edits\n")
+        );
+    }
+}
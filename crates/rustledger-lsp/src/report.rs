@@ -0,0 +1,630 @@
+//! Balance reports computed from a parsed ledger.
+//!
+//! These are the building blocks for balance hovers, code lenses, and
+//! summary commands, along with the price lookups used to convert between
+//! currencies.
+
+use std::collections::{BTreeMap, HashMap};
+
+use rustledger_booking::merge_with_padding;
+use rustledger_core::{
+    Account, Balance, BookingMethod, Decimal, Directive, Inventory, NaiveDate, Position, Posting,
+};
+use rustledger_parser::ParseResult;
+use rustledger_query::PriceDatabase;
+use serde::Serialize;
+
+/// Amounts keyed by currency.
+pub type CurrencyTotals = BTreeMap<String, Decimal>;
+
+/// Assets and liabilities at a point in time.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetWorth {
+    /// Total of all `Assets` accounts.
+    pub assets: CurrencyTotals,
+    /// Amount owed across all `Liabilities` accounts, as positive numbers.
+    pub liabilities: CurrencyTotals,
+    /// Assets minus liabilities.
+    pub net_worth: CurrencyTotals,
+}
+
+/// Income and expenses over a period.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeStatement {
+    /// Amount earned across all `Income` accounts, as positive numbers.
+    pub income: CurrencyTotals,
+    /// Total of all `Expenses` accounts.
+    pub expenses: CurrencyTotals,
+    /// Income minus expenses.
+    pub net_income: CurrencyTotals,
+}
+
+/// Compute per-account balances, optionally up to and including `as_of`.
+///
+/// Pad directives are resolved first, so padded accounts and their source
+/// accounts include the synthetic padding amounts. Sales at cost reduce lots
+/// with the account's booking method. Postings with
+/// elided amounts are not counted.
+pub fn balances(
+    parse_result: &ParseResult,
+    as_of: Option<NaiveDate>,
+) -> BTreeMap<String, Inventory> {
+    let mut balances: BTreeMap<String, Inventory> = BTreeMap::new();
+
+    let directives: Vec<Directive> = parse_result
+        .directives
+        .iter()
+        .map(|spanned| spanned.value.clone())
+        .collect();
+    let booking = BookingMethods::new(parse_result, &directives);
+
+    for directive in merge_with_padding(&directives) {
+        let Directive::Transaction(txn) = &directive else {
+            continue;
+        };
+        if as_of.is_some_and(|d| txn.date > d) {
+            continue;
+        }
+        for posting in &txn.postings {
+            let inventory = balances.entry(posting.account.to_string()).or_default();
+            let method = booking.get(posting.account.as_str());
+            apply_posting(inventory, posting, txn.date, method);
+        }
+    }
+
+    balances
+}
+
+/// Compute the balance each `balance` assertion is checked against.
+///
+/// Walks the transactions once in date order, keeping a running inventory
+/// per account, and records for every assertion the units of its currency
+/// held by its account before its date. Results are keyed by the
+/// assertion's index in `parse_result.directives`.
+pub fn balance_assertion_actuals(parse_result: &ParseResult) -> HashMap<usize, Decimal> {
+    let directives: Vec<Directive> = parse_result
+        .directives
+        .iter()
+        .map(|spanned| spanned.value.clone())
+        .collect();
+    let booking = BookingMethods::new(parse_result, &directives);
+    let merged = merge_with_padding(&directives);
+    let mut transactions = merged.iter().filter_map(|directive| match directive {
+        Directive::Transaction(txn) => Some(txn),
+        _ => None,
+    });
+
+    let mut assertions: Vec<(usize, &Balance)> = parse_result
+        .directives
+        .iter()
+        .enumerate()
+        .filter_map(|(index, spanned)| match &spanned.value {
+            Directive::Balance(bal) => Some((index, bal)),
+            _ => None,
+        })
+        .collect();
+    assertions.sort_by_key(|(_, bal)| bal.date);
+
+    let mut running: HashMap<&str, Inventory> = HashMap::new();
+    let mut pending = transactions.next();
+    let mut actuals = HashMap::with_capacity(assertions.len());
+
+    for (index, bal) in assertions {
+        // Assertions check the balance at the start of their day
+        while let Some(txn) = pending.filter(|txn| txn.date < bal.date) {
+            for posting in &txn.postings {
+                let inventory = running.entry(posting.account.as_str()).or_default();
+                let method = booking.get(posting.account.as_str());
+                apply_posting(inventory, posting, txn.date, method);
+            }
+            pending = transactions.next();
+        }
+
+        let actual = running
+            .get(bal.account.as_str())
+            .map(|inventory| inventory.units(&bal.amount.currency))
+            .unwrap_or_default();
+        actuals.insert(index, actual);
+    }
+
+    actuals
+}
+
+/// Roll each account's balance up into all of its parent accounts.
+///
+/// The result contains every ancestor account (e.g. `Assets` and
+/// `Assets:Bank` for `Assets:Bank:Checking`) holding the sum of its own
+/// postings and those of its descendants.
+pub fn aggregate_children(balances: &BTreeMap<String, Inventory>) -> BTreeMap<String, Inventory> {
+    let mut aggregated: BTreeMap<String, Inventory> = BTreeMap::new();
+
+    for (account, inventory) in balances {
+        let mut prefix_end = Some(account.len());
+        while let Some(end) = prefix_end {
+            let prefix = &account[..end];
+            aggregated
+                .entry(prefix.to_string())
+                .or_default()
+                .merge(inventory);
+            prefix_end = prefix.rfind(':');
+        }
+    }
+
+    aggregated
+}
+
+/// Sum the balances of every account under `prefix` into one inventory.
+///
+/// A trailing `:` on the prefix is optional, and the prefix matches whole
+/// account segments: `Expenses:Food` covers `Expenses:Food` and
+/// `Expenses:Food:Coffee` but not `Expenses:FoodTruck`. An empty prefix
+/// covers the whole ledger.
+pub fn balances_by_prefix(parse_result: &ParseResult, prefix: &str) -> Inventory {
+    let prefix = prefix.trim_end_matches(':');
+    let mut total = Inventory::new();
+
+    for (account, inventory) in balances(parse_result, None) {
+        let in_subtree = prefix.is_empty()
+            || account
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'));
+        if in_subtree {
+            total.merge(&inventory);
+        }
+    }
+
+    total
+}
+
+/// Compute net worth (assets minus liabilities) as of a date.
+pub fn net_worth(parse_result: &ParseResult, as_of: Option<NaiveDate>) -> NetWorth {
+    let balances = balances(parse_result, as_of);
+    let assets = totals_for_type(&balances, "Assets");
+    // Liabilities carry credit (negative) balances; report them as owed
+    let liabilities = negate(&totals_for_type(&balances, "Liabilities"));
+    let net_worth = difference(&assets, &liabilities);
+
+    NetWorth {
+        assets,
+        liabilities,
+        net_worth,
+    }
+}
+
+/// Compute income and expenses for transactions between `start` and `end`
+/// (both inclusive; `None` leaves that side of the range open).
+pub fn income_statement(
+    parse_result: &ParseResult,
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+) -> IncomeStatement {
+    let closing = balances(parse_result, end);
+    let opening = match start.and_then(|d| d.pred_opt()) {
+        Some(before_start) => balances(parse_result, Some(before_start)),
+        None => BTreeMap::new(),
+    };
+
+    let period_total = |account_type: &str| {
+        difference(
+            &totals_for_type(&closing, account_type),
+            &totals_for_type(&opening, account_type),
+        )
+    };
+
+    // Income carries credit (negative) balances; report it as earned
+    let income = negate(&period_total("Income"));
+    let expenses = period_total("Expenses");
+    let net_income = difference(&income, &expenses);
+
+    IncomeStatement {
+        income,
+        expenses,
+        net_income,
+    }
+}
+
+/// Build the [`PriceDatabase`] of a ledger's `price` directives.
+///
+/// Build it once per parse and pass it to [`price_at`] for each lookup.
+pub fn price_database(parse_result: &ParseResult) -> PriceDatabase {
+    let directives: Vec<Directive> = parse_result
+        .directives
+        .iter()
+        .filter(|spanned| matches!(spanned.value, Directive::Price(_)))
+        .map(|spanned| spanned.value.clone())
+        .collect();
+    PriceDatabase::from_directives(&directives)
+}
+
+/// Look up the price of one unit of `base` in `quote` as of `date`.
+///
+/// Returns the most recent `price` directive on or before `date`. Inverse
+/// prices are always considered: a `quote` priced in `base` is inverted,
+/// and when neither is priced in the other the price is chained through an
+/// intermediate currency.
+pub fn price_at(
+    prices: &PriceDatabase,
+    base: &str,
+    quote: &str,
+    date: NaiveDate,
+) -> Option<Decimal> {
+    prices.get_price(base, quote, date)
+}
+
+/// Sum the units of all accounts under a root account type.
+fn totals_for_type(balances: &BTreeMap<String, Inventory>, account_type: &str) -> CurrencyTotals {
+    let mut totals = CurrencyTotals::new();
+    for (account, inventory) in balances {
+        if Account::new(account.as_str()).components().next() != Some(account_type) {
+            continue;
+        }
+        for position in inventory.positions() {
+            *totals
+                .entry(position.units.currency.to_string())
+                .or_default() += position.units.number;
+        }
+    }
+    totals.retain(|_, amount| !amount.is_zero());
+    totals
+}
+
+/// Negate every amount.
+fn negate(totals: &CurrencyTotals) -> CurrencyTotals {
+    totals
+        .iter()
+        .map(|(currency, amount)| (currency.clone(), -*amount))
+        .collect()
+}
+
+/// Subtract `rhs` from `lhs` per currency.
+fn difference(lhs: &CurrencyTotals, rhs: &CurrencyTotals) -> CurrencyTotals {
+    let mut result = lhs.clone();
+    for (currency, amount) in rhs {
+        *result.entry(currency.clone()).or_default() -= *amount;
+    }
+    result.retain(|_, amount| !amount.is_zero());
+    result
+}
+
+/// The booking method each account reduces lots with.
+///
+/// An account uses the method its `open` directive names, falling back to
+/// the ledger's `booking_method` option and then to STRICT. Methods
+/// Beancount does not know are ignored.
+pub(crate) struct BookingMethods<'a> {
+    accounts: HashMap<&'a str, BookingMethod>,
+    default: BookingMethod,
+}
+
+impl<'a> BookingMethods<'a> {
+    pub(crate) fn new(
+        parse_result: &ParseResult,
+        directives: impl IntoIterator<Item = &'a Directive>,
+    ) -> Self {
+        let accounts = directives
+            .into_iter()
+            .filter_map(|directive| match directive {
+                Directive::Open(open) => {
+                    let method = open.booking.as_deref()?.parse().ok()?;
+                    Some((open.account.as_str(), method))
+                }
+                _ => None,
+            })
+            .collect();
+        let default = parse_result
+            .options
+            .iter()
+            .rev()
+            .filter(|(key, _, _)| key == "booking_method")
+            .find_map(|(_, method, _)| method.parse().ok())
+            .unwrap_or_default();
+        Self { accounts, default }
+    }
+
+    pub(crate) fn get(&self, account: &str) -> BookingMethod {
+        self.accounts.get(account).copied().unwrap_or(self.default)
+    }
+}
+
+/// Apply a single posting to an inventory, reducing lots with `method`.
+fn apply_posting(
+    inventory: &mut Inventory,
+    posting: &Posting,
+    date: NaiveDate,
+    method: BookingMethod,
+) {
+    let Some(units) = posting.amount() else {
+        return;
+    };
+
+    match &posting.cost {
+        Some(spec) if units.number.is_sign_negative() => {
+            // Sales reduce existing lots. A sale that matches no lot, or that
+            // STRICT finds ambiguous, is reported by validation; its lots are
+            // left unreduced and the units kept as a plain position so the
+            // account's units still add up
+            if inventory.reduce(units, Some(spec), method).is_err() {
+                inventory.add_amount(units);
+            }
+        }
+        Some(spec) => match spec.resolve(units.number, date) {
+            Some(cost) => inventory.add(Position::with_cost(units.clone(), cost)),
+            None => inventory.add_amount(units),
+        },
+        None => inventory.add_amount(units),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustledger_core::Decimal;
+    use rustledger_parser::parse;
+
+    const LEDGER: &str = r#"2024-01-01 open Assets:Bank:Checking
+2024-01-01 open Assets:Bank:Savings
+2024-01-01 open Expenses:Food
+2024-01-01 open Income:Salary
+2024-01-05 * "Salary"
+  Assets:Bank:Checking  1000.00 USD
+  Income:Salary  -1000.00 USD
+2024-01-10 * "Groceries"
+  Assets:Bank:Checking  -50.00 USD
+  Expenses:Food  50.00 USD
+2024-01-12 * "Travel money"
+  Assets:Bank:Savings  200.00 EUR
+  Income:Salary  -200.00 EUR
+2024-02-01 * "More groceries"
+  Assets:Bank:Checking  -25.00 USD
+  Expenses:Food  25.00 USD
+"#;
+
+    #[test]
+    fn test_balances_per_currency() {
+        let result = parse(LEDGER);
+        let balances = balances(&result, None);
+
+        let checking = &balances["Assets:Bank:Checking"];
+        assert_eq!(checking.units("USD"), Decimal::new(92500, 2));
+        assert_eq!(
+            balances["Expenses:Food"].units("USD"),
+            Decimal::new(7500, 2)
+        );
+
+        let salary = &balances["Income:Salary"];
+        assert_eq!(salary.units("USD"), Decimal::new(-100_000, 2));
+        assert_eq!(salary.units("EUR"), Decimal::new(-20000, 2));
+    }
+
+    #[test]
+    fn test_balances_date_cutoff() {
+        let result = parse(LEDGER);
+        let balances = balances(&result, NaiveDate::from_ymd_opt(2024, 1, 31));
+
+        // The February transaction is excluded
+        assert_eq!(
+            balances["Assets:Bank:Checking"].units("USD"),
+            Decimal::new(95000, 2)
+        );
+        assert_eq!(
+            balances["Expenses:Food"].units("USD"),
+            Decimal::new(5000, 2)
+        );
+    }
+
+    #[test]
+    fn test_balance_assertion_actuals() {
+        let source = format!(
+            "{LEDGER}2024-01-10 balance Assets:Bank:Checking 1000.00 USD\n\
+             2024-02-02 balance Assets:Bank:Checking 925.00 USD\n\
+             2024-01-31 balance Assets:Bank:Savings 200.00 EUR\n"
+        );
+        let result = parse(&source);
+        let actuals = balance_assertion_actuals(&result);
+
+        // Each assertion sees only the transactions before its date
+        let first = result.directives.len() - 3;
+        assert_eq!(actuals[&first], Decimal::new(100_000, 2));
+        assert_eq!(actuals[&(first + 1)], Decimal::new(92500, 2));
+        assert_eq!(actuals[&(first + 2)], Decimal::new(20000, 2));
+    }
+
+    #[test]
+    fn test_balances_honor_booking_method() {
+        let source = r#"2024-01-01 open Assets:Fifo AAPL "FIFO"
+2024-01-01 open Assets:Lifo AAPL "LIFO"
+2024-01-01 open Assets:Cash
+2024-01-05 * "Buy"
+  Assets:Fifo  10 AAPL {100.00 USD}
+  Assets:Lifo  10 AAPL {100.00 USD}
+  Assets:Cash
+2024-02-05 * "Buy"
+  Assets:Fifo  10 AAPL {150.00 USD}
+  Assets:Lifo  10 AAPL {150.00 USD}
+  Assets:Cash
+2024-03-05 * "Sell"
+  Assets:Fifo  -10 AAPL {}
+  Assets:Lifo  -10 AAPL {}
+  Assets:Cash
+"#;
+        let result = parse(source);
+        let balances = balances(&result, None);
+        let lot_costs = |account: &str| -> Vec<Decimal> {
+            balances[account]
+                .positions()
+                .iter()
+                .filter(|p| !p.units.number.is_zero())
+                .filter_map(|p| p.cost.as_ref().map(|c| c.number))
+                .collect()
+        };
+
+        // The same sale leaves the newest lot under FIFO, the oldest under LIFO
+        assert_eq!(lot_costs("Assets:Fifo"), vec![Decimal::new(15000, 2)]);
+        assert_eq!(lot_costs("Assets:Lifo"), vec![Decimal::new(10000, 2)]);
+    }
+
+    #[test]
+    fn test_balances_fall_back_to_booking_method_option() {
+        let source = r#"option "booking_method" "LIFO"
+2024-01-01 open Assets:Stock AAPL
+2024-01-01 open Assets:Strict AAPL "STRICT"
+2024-01-01 open Assets:Cash
+2024-01-05 * "Buy"
+  Assets:Stock  10 AAPL {100.00 USD}
+  Assets:Strict  10 AAPL {100.00 USD}
+  Assets:Cash
+2024-02-05 * "Buy"
+  Assets:Stock  10 AAPL {150.00 USD}
+  Assets:Strict  10 AAPL {150.00 USD}
+  Assets:Cash
+2024-03-05 * "Sell"
+  Assets:Stock  -10 AAPL {}
+  Assets:Strict  -10 AAPL {}
+  Assets:Cash
+"#;
+        let result = parse(source);
+        let balances = balances(&result, None);
+        let lot_costs = |account: &str| -> Vec<Decimal> {
+            balances[account]
+                .positions()
+                .iter()
+                .filter(|p| !p.units.number.is_zero())
+                .filter_map(|p| p.cost.as_ref().map(|c| c.number))
+                .collect()
+        };
+
+        // The option makes LIFO the default
+        assert_eq!(lot_costs("Assets:Stock"), vec![Decimal::new(10000, 2)]);
+        // An ambiguous STRICT sale leaves both lots in place
+        assert_eq!(
+            lot_costs("Assets:Strict"),
+            vec![Decimal::new(10000, 2), Decimal::new(15000, 2)]
+        );
+        assert_eq!(balances["Assets:Strict"].units("AAPL"), Decimal::from(10));
+    }
+
+    #[test]
+    fn test_aggregate_children() {
+        let result = parse(LEDGER);
+        let aggregated = aggregate_children(&balances(&result, None));
+
+        let bank = &aggregated["Assets:Bank"];
+        assert_eq!(bank.units("USD"), Decimal::new(92500, 2));
+        assert_eq!(bank.units("EUR"), Decimal::new(20000, 2));
+        assert_eq!(aggregated["Assets"], *bank);
+        assert!(aggregated.contains_key("Assets:Bank:Savings"));
+    }
+
+    #[test]
+    fn test_balances_by_prefix_sums_children() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Rent
+2024-01-01 open Expenses:FoodTruck
+2024-01-05 * "Groceries"
+  Expenses:Food  40.00 USD
+  Assets:Bank  -40.00 USD
+2024-01-06 * "Rent"
+  Expenses:Rent  900.00 USD
+  Assets:Bank  -900.00 USD
+"#;
+        let result = parse(source);
+
+        let expenses = balances_by_prefix(&result, "Expenses:");
+        assert_eq!(expenses.units("USD"), Decimal::new(94000, 2));
+        assert_eq!(balances_by_prefix(&result, "Expenses"), expenses);
+
+        // Exact accounts match only themselves and their descendants
+        let food = balances_by_prefix(&result, "Expenses:Food");
+        assert_eq!(food.units("USD"), Decimal::new(4000, 2));
+
+        // The whole ledger nets to zero
+        assert!(balances_by_prefix(&result, "").units("USD").is_zero());
+    }
+
+    #[test]
+    fn test_net_worth_is_assets_minus_liabilities() {
+        let source = r#"2024-01-01 open Assets:Bank
+2024-01-01 open Liabilities:CreditCard
+2024-01-01 open Expenses:Food
+2024-01-01 open Income:Salary
+2024-01-05 * "Salary"
+  Assets:Bank  1000.00 USD
+  Income:Salary  -1000.00 USD
+2024-01-10 * "Dinner on credit"
+  Liabilities:CreditCard  -80.00 USD
+  Expenses:Food  80.00 USD
+"#;
+        let result = parse(source);
+        let report = net_worth(&result, None);
+
+        assert_eq!(report.assets["USD"], Decimal::new(100_000, 2));
+        assert_eq!(report.liabilities["USD"], Decimal::new(8000, 2));
+        assert_eq!(
+            report.net_worth["USD"],
+            report.assets["USD"] - report.liabilities["USD"]
+        );
+        assert_eq!(report.net_worth["USD"], Decimal::new(92000, 2));
+    }
+
+    #[test]
+    fn test_income_statement_range() {
+        let result = parse(LEDGER);
+        let report = income_statement(
+            &result,
+            NaiveDate::from_ymd_opt(2024, 1, 6),
+            NaiveDate::from_ymd_opt(2024, 2, 28),
+        );
+
+        // Only the EUR income falls inside the range
+        assert_eq!(report.income.get("USD"), None);
+        assert_eq!(report.income["EUR"], Decimal::new(20000, 2));
+        assert_eq!(report.expenses["USD"], Decimal::new(7500, 2));
+        assert_eq!(report.net_income["USD"], Decimal::new(-7500, 2));
+    }
+
+    const PRICES: &str = r#"2024-01-01 price EUR 1.10 USD
+2024-02-01 price EUR 1.20 USD
+2024-02-01 price USD 150 JPY
+"#;
+
+    #[test]
+    fn test_price_at_exact_and_prior_date() {
+        let prices = price_database(&parse(PRICES));
+
+        let on = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+        assert_eq!(
+            price_at(&prices, "EUR", "USD", on),
+            Some(Decimal::new(120, 2))
+        );
+
+        let between = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        assert_eq!(
+            price_at(&prices, "EUR", "USD", between),
+            Some(Decimal::new(110, 2))
+        );
+
+        let before = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+        assert_eq!(price_at(&prices, "EUR", "USD", before), None);
+    }
+
+    #[test]
+    fn test_price_at_inverse_and_chained() {
+        let prices = price_database(&parse(PRICES));
+
+        let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        assert_eq!(
+            price_at(&prices, "USD", "EUR", date),
+            Some(Decimal::ONE / Decimal::new(110, 2))
+        );
+
+        // EUR -> USD -> JPY
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(
+            price_at(&prices, "EUR", "JPY", date),
+            Some(Decimal::from(180))
+        );
+    }
+}
@@ -1,5 +1,6 @@
 //! Main LSP server implementation.
 
+use crate::handlers::diagnostics::get_capabilities as get_diagnostic_capabilities;
 use crate::handlers::execute_command::COMMANDS;
 use crate::handlers::on_type_formatting::{FIRST_TRIGGER_CHARACTER, MORE_TRIGGER_CHARACTERS};
 use crate::handlers::semantic_tokens::get_capabilities as get_semantic_tokens_capabilities;
@@ -37,7 +38,7 @@ impl Server {
 
         // Run the main event loop
         let (sender, receiver) = (self.connection.sender, self.connection.receiver);
-        run_main_loop(receiver, sender);
+        run_main_loop(receiver, sender, &self.init_params);
 
         tracing::info!("Server shutdown complete");
     }
@@ -64,6 +65,7 @@ pub fn start_stdio() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                 ":".to_string(),  // Account segments
                 " ".to_string(),  // After keywords
                 "\"".to_string(), // Strings (payees, narrations)
+                "/".to_string(),  // Include path directories
             ]),
             resolve_provider: Some(true), // Enable completion resolve for detailed info
             ..Default::default()
@@ -78,6 +80,7 @@ pub fn start_stdio() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                 code_action_kinds: Some(vec![
                     lsp_types::CodeActionKind::QUICKFIX,
                     lsp_types::CodeActionKind::REFACTOR,
+                    lsp_types::CodeActionKind::SOURCE,
                 ]),
                 resolve_provider: Some(true), // Enable resolve for lazy-loading edits
                 work_done_progress_options: Default::default(),
@@ -116,10 +119,13 @@ pub fn start_stdio() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             ),
         }),
         code_lens_provider: Some(lsp_types::CodeLensOptions {
-            resolve_provider: Some(true), // Enable resolve for lazy-loading balance verification
+            resolve_provider: Some(true), // Titles and balance verification are computed on resolve
         }),
         color_provider: Some(lsp_types::ColorProviderCapability::Simple(true)),
+        // Pull diagnostics; diagnostics are pushed instead to clients without pull support
+        diagnostic_provider: Some(get_diagnostic_capabilities()),
         declaration_provider: Some(lsp_types::DeclarationCapability::Simple(true)),
+        type_definition_provider: Some(lsp_types::TypeDefinitionProviderCapability::Simple(true)),
         call_hierarchy_provider: Some(lsp_types::CallHierarchyServerCapability::Simple(true)),
         signature_help_provider: Some(lsp_types::SignatureHelpOptions {
             trigger_characters: Some(
@@ -3,71 +3,171 @@
 //! Each LSP request receives an immutable snapshot of the world state.
 //! This allows requests to be processed concurrently without locks.
 
+use lsp_types::Uri;
+use rustledger_parser::{ParseResult, parse};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-/// Global revision counter for cancellation detection.
-static REVISION: AtomicU64 = AtomicU64::new(0);
+use crate::config::Config;
+use crate::handlers::utils::LineIndex;
 
-/// Bump the global revision counter.
-/// Called when the world state changes (e.g., document edit).
-pub fn bump_revision() -> u64 {
-    REVISION.fetch_add(1, Ordering::SeqCst) + 1
+/// A flag a client can raise with `$/cancelRequest` to stop a request.
+///
+/// Long-running handlers check it between units of work and return
+/// [`Cancelled`] once it is set. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the request holding this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if the request has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Return early with [`Cancelled`] if the request has been cancelled.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
 }
 
-/// Get the current revision.
-pub fn current_revision() -> u64 {
-    REVISION.load(Ordering::SeqCst)
+/// Error returned by a handler that stopped because its request was cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Request cancelled")
+    }
 }
 
-/// An immutable snapshot of the world state.
+impl std::error::Error for Cancelled {}
+
+/// A document other than the snapshot's own, as (URI, text, parse result).
+pub type RelatedDocument = (Uri, String, Arc<ParseResult>);
+
+/// The state of one document as seen by a request handler.
 ///
-/// Snapshots capture the revision at creation time, allowing
-/// handlers to detect if they should cancel (revision changed).
-#[derive(Debug)]
-pub struct Snapshot {
-    /// The revision at snapshot creation time.
-    revision: u64,
-    /// Parsed directives (TODO: replace with actual data)
-    _data: Arc<()>,
+/// Bundles everything a handler needs to answer a request about the
+/// document, so all handlers for a request see the same text, parse, and
+/// configuration. Documents in other files are only gathered for the
+/// requests that look across files.
+#[derive(Debug, Clone)]
+pub struct WorldSnapshot {
+    /// The document's URI.
+    pub uri: Uri,
+    /// The document's text.
+    pub text: String,
+    /// The parse result for `text`.
+    pub parse_result: Arc<ParseResult>,
+    /// Line index for `text`.
+    pub line_index: Arc<LineIndex>,
+    /// Server configuration.
+    pub config: Arc<Config>,
+    /// The other documents in this document's include graph.
+    ///
+    /// Empty unless added with [`WorldSnapshot::with_include_graph`].
+    pub include_graph: Arc<Vec<RelatedDocument>>,
+    /// Parse results of the other documents in the workspace.
+    ///
+    /// Empty unless added with [`WorldSnapshot::with_workspace`].
+    pub workspace: Arc<Vec<Arc<ParseResult>>>,
 }
 
-impl Snapshot {
-    /// Create a new snapshot at the current revision.
-    pub fn new() -> Self {
-        Self {
-            revision: current_revision(),
-            _data: Arc::new(()),
-        }
+impl WorldSnapshot {
+    /// Snapshot a standalone document with the default configuration.
+    pub fn new(uri: Uri, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let parse_result = Arc::new(parse(&text));
+        Self::with_parse_result(uri, text, parse_result, Arc::default())
     }
 
-    /// Check if this snapshot is still current (not cancelled).
-    pub fn is_current(&self) -> bool {
-        self.revision == current_revision()
+    /// Snapshot a document whose text has already been parsed.
+    pub fn with_parse_result(
+        uri: Uri,
+        text: String,
+        parse_result: Arc<ParseResult>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            uri,
+            line_index: Arc::new(LineIndex::new(&text)),
+            text,
+            parse_result,
+            config,
+            include_graph: Arc::default(),
+            workspace: Arc::default(),
+        }
     }
 
-    /// Check if this snapshot has been cancelled.
-    pub fn is_cancelled(&self) -> bool {
-        !self.is_current()
+    /// Add the other documents in this document's include graph.
+    pub fn with_include_graph(mut self, documents: Vec<RelatedDocument>) -> Self {
+        self.include_graph = Arc::new(documents);
+        self
     }
-}
 
-impl Default for Snapshot {
-    fn default() -> Self {
-        Self::new()
+    /// Add the parse results of the other documents in the workspace.
+    pub fn with_workspace(mut self, parse_results: Vec<Arc<ParseResult>>) -> Self {
+        self.workspace = Arc::new(parse_results);
+        self
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::handlers::folding::handle_folding_ranges;
+    use crate::handlers::symbols::handle_document_symbols;
+    use lsp_types::{
+        DocumentSymbolParams, DocumentSymbolResponse, FoldingRangeParams, TextDocumentIdentifier,
+    };
 
     #[test]
-    fn test_snapshot_cancellation() {
-        let snap = Snapshot::new();
-        assert!(snap.is_current());
+    fn test_handlers_share_world_snapshot() {
+        let source = r#"2024-01-01 open Assets:Bank USD
+2024-01-15 * "Coffee"
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+"#;
+        let uri: Uri = "file:///test.beancount".parse().unwrap();
+        let snapshot = WorldSnapshot::new(uri.clone(), source);
+        let text_document = TextDocumentIdentifier { uri };
+
+        let symbols = handle_document_symbols(
+            &DocumentSymbolParams {
+                text_document: text_document.clone(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+            &snapshot,
+        );
+        let Some(DocumentSymbolResponse::Nested(symbols)) = symbols else {
+            panic!("expected nested symbols");
+        };
+        assert_eq!(symbols.len(), 2);
 
-        bump_revision();
-        assert!(snap.is_cancelled());
+        let folds = handle_folding_ranges(
+            &FoldingRangeParams {
+                text_document,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+            &snapshot,
+        )
+        .unwrap();
+        assert!(folds.iter().any(|f| f.start_line == 1 && f.end_line == 3));
     }
 }
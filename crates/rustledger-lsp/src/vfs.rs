@@ -4,13 +4,68 @@
 //! handling incremental updates from the editor.
 //!
 //! Documents cache their parse results to avoid re-parsing on every request.
+//!
+//! Text sent by the editor is kept as is, including a leading UTF-8 byte
+//! order mark, so positions computed from it match the editor's. Files
+//! loaded from disk have the mark stripped, as editors drop it when they
+//! open such a file.
 
 use ropey::Rope;
-use rustledger_parser::{ParseResult, parse};
+use rustledger_parser::{ParseResult, Span, parse};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+/// The byte order mark some editors write at the start of UTF-8 files.
+pub const BOM: char = '\u{feff}';
+
+/// Strip a leading byte order mark, returning the remaining text and
+/// whether a mark was present.
+pub fn strip_bom(text: &str) -> (&str, bool) {
+    match text.strip_prefix(BOM) {
+        Some(rest) => (rest, true),
+        None => (text, false),
+    }
+}
+
+/// Remove a leading byte order mark in place, returning whether one was present.
+fn remove_bom(content: &mut String) -> bool {
+    let bom = content.starts_with(BOM);
+    if bom {
+        content.replace_range(..BOM.len_utf8(), "");
+    }
+    bom
+}
+
+/// Parse a document, skipping a leading byte order mark.
+///
+/// The parser does not accept the mark, so the text after it is parsed and
+/// the resulting spans are moved past it to index into `text`.
+pub fn parse_document(text: &str) -> ParseResult {
+    let (rest, bom) = strip_bom(text);
+    let mut result = parse(rest);
+    if bom {
+        let offset = BOM.len_utf8();
+        let shift = |span: &mut Span| {
+            span.start += offset;
+            span.end += offset;
+        };
+        result
+            .directives
+            .iter_mut()
+            .for_each(|d| shift(&mut d.span));
+        result.options.iter_mut().for_each(|o| shift(&mut o.2));
+        result.includes.iter_mut().for_each(|i| shift(&mut i.1));
+        result.plugins.iter_mut().for_each(|p| shift(&mut p.2));
+        result.errors.iter_mut().for_each(|e| shift(&mut e.span));
+        result
+            .leading_comments
+            .values_mut()
+            .for_each(|c| shift(&mut c.span));
+    }
+    result
+}
+
 /// A document in the virtual file system.
 #[derive(Debug)]
 pub struct Document {
@@ -20,6 +75,11 @@ pub struct Document {
     version: i32,
     /// Cached parse result (lazily computed, invalidated on change).
     parse_cache: Option<Arc<ParseResult>>,
+    /// Whether the content was loaded from disk rather than opened by the editor.
+    external: bool,
+    /// Whether the content starts with a byte order mark, or did before it
+    /// was stripped from a file loaded from disk.
+    bom: bool,
 }
 
 impl Document {
@@ -29,9 +89,22 @@ impl Document {
             content: Rope::from_str(&content),
             version,
             parse_cache: None,
+            external: false,
+            bom: content.starts_with(BOM),
         }
     }
 
+    /// Whether the document was loaded from disk rather than opened by the editor.
+    pub fn is_external(&self) -> bool {
+        self.external
+    }
+
+    /// Whether the content starts with a byte order mark, or did before it
+    /// was stripped from a file loaded from disk.
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
     /// Get the document content as a string.
     pub fn text(&self) -> String {
         self.content.to_string()
@@ -46,7 +119,7 @@ impl Document {
     pub fn parse_result(&mut self) -> Arc<ParseResult> {
         if self.parse_cache.is_none() {
             let text = self.content.to_string();
-            self.parse_cache = Some(Arc::new(parse(&text)));
+            self.parse_cache = Some(Arc::new(parse_document(&text)));
         }
         self.parse_cache.clone().unwrap()
     }
@@ -58,16 +131,17 @@ impl Document {
 
     /// Update the document content.
     pub fn update(&mut self, content: String, version: i32) {
+        self.bom = content.starts_with(BOM);
         self.content = Rope::from_str(&content);
         self.version = version;
         self.invalidate_cache();
     }
 }
 
-/// Virtual file system for managing open documents.
+/// Virtual file system for managing open documents and workspace files loaded from disk.
 #[derive(Debug, Default)]
 pub struct Vfs {
-    /// Open documents indexed by path.
+    /// Documents indexed by path.
     documents: HashMap<PathBuf, Document>,
 }
 
@@ -112,6 +186,59 @@ impl Vfs {
         })
     }
 
+    /// Load or reload a document from disk after an external change.
+    ///
+    /// Documents open in the editor are left alone since the editor owns
+    /// their content. The content is parsed right away, so its parse result
+    /// is among [`Vfs::cached_parse_results`]. Returns whether the VFS was
+    /// updated.
+    pub fn load_external(&mut self, path: PathBuf, content: String) -> bool {
+        self.insert_external(path, content, None)
+    }
+
+    /// Load a document from disk together with its already computed parse result.
+    ///
+    /// Like [`Vfs::load_external`], documents open in the editor are left alone.
+    /// The parse result must be of the content with any byte order mark stripped.
+    pub fn load_external_parsed(
+        &mut self,
+        path: PathBuf,
+        content: String,
+        parse_result: Arc<ParseResult>,
+    ) -> bool {
+        self.insert_external(path, content, Some(parse_result))
+    }
+
+    fn insert_external(
+        &mut self,
+        path: PathBuf,
+        mut content: String,
+        parse_result: Option<Arc<ParseResult>>,
+    ) -> bool {
+        if self.documents.get(&path).is_some_and(|doc| !doc.external) {
+            return false;
+        }
+        let bom = remove_bom(&mut content);
+        let mut doc = Document::new(content, 0);
+        doc.external = true;
+        doc.bom = bom;
+        doc.parse_cache = parse_result;
+        doc.parse_result();
+        self.documents.insert(path, doc);
+        true
+    }
+
+    /// Remove a document loaded from disk after it was deleted.
+    ///
+    /// Returns whether a document was removed.
+    pub fn remove_external(&mut self, path: &PathBuf) -> bool {
+        if self.documents.get(path).is_some_and(|doc| doc.external) {
+            self.documents.remove(path);
+            return true;
+        }
+        false
+    }
+
     /// Update a document's content.
     pub fn update(&mut self, path: &PathBuf, content: String, version: i32) {
         if let Some(doc) = self.documents.get_mut(path) {
@@ -119,17 +246,22 @@ impl Vfs {
         }
     }
 
-    /// Get all open document paths.
+    /// Get the paths of documents open in the editor.
+    ///
+    /// Documents loaded from disk are skipped.
     pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
-        self.documents.keys()
+        self.documents
+            .iter()
+            .filter(|(_, doc)| !doc.external)
+            .map(|(path, _)| path)
     }
 
-    /// Iterate over all open documents (path and content).
+    /// Iterate over all documents (path and content), including those loaded from disk.
     pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, String)> {
         self.documents.iter().map(|(path, doc)| (path, doc.text()))
     }
 
-    /// Iterate over all open documents with parse results.
+    /// Iterate over all documents with parse results, including those loaded from disk.
     pub fn iter_with_parse(
         &mut self,
     ) -> impl Iterator<Item = (&PathBuf, String, Arc<ParseResult>)> {
@@ -139,6 +271,17 @@ impl Vfs {
             (path, text, parse_result)
         })
     }
+
+    /// Iterate over the parse results already cached, including those of
+    /// documents loaded from disk.
+    ///
+    /// Documents not parsed since their last change are skipped, so this
+    /// needs neither the text nor a mutable borrow.
+    pub fn cached_parse_results(&self) -> impl Iterator<Item = (&PathBuf, &Arc<ParseResult>)> {
+        self.documents
+            .iter()
+            .filter_map(|(path, doc)| Some((path, doc.parse_cache.as_ref()?)))
+    }
 }
 
 #[cfg(test)]
@@ -157,9 +300,72 @@ mod tests {
         assert!(vfs.get(&path).is_none());
     }
 
+    #[test]
+    fn test_paths_skip_external_documents() {
+        let mut vfs = Vfs::new();
+        let open = PathBuf::from("/main.beancount");
+        let external = PathBuf::from("/accounts.beancount");
+
+        vfs.open(open.clone(), String::new(), 1);
+        vfs.load_external(external.clone(), String::new());
+
+        assert_eq!(vfs.paths().collect::<Vec<_>>(), vec![&open]);
+        assert_eq!(vfs.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_cached_parse_results_skip_unparsed_documents() {
+        let mut vfs = Vfs::new();
+        let parsed = PathBuf::from("/parsed.beancount");
+        let unparsed = PathBuf::from("/unparsed.beancount");
+        vfs.open(
+            parsed.clone(),
+            "2024-01-01 open Assets:Bank\n".to_string(),
+            1,
+        );
+        vfs.open(unparsed, String::new(), 1);
+        vfs.get_mut(&parsed).unwrap().parse_result();
+
+        let cached: Vec<_> = vfs.cached_parse_results().collect();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].0, &parsed);
+        assert_eq!(cached[0].1.directives.len(), 1);
+    }
+
     #[test]
     fn test_document_text() {
         let doc = Document::new("hello world".to_string(), 1);
         assert_eq!(doc.text(), "hello world");
     }
+
+    #[test]
+    fn test_document_keeps_bom_from_editor() {
+        let text = "\u{feff}option \"title\" \"Ledger\"\n";
+        let mut doc = Document::new(text.to_string(), 1);
+        assert!(doc.has_bom());
+        assert_eq!(doc.text(), text);
+
+        let result = doc.parse_result();
+        assert!(result.errors.is_empty());
+        assert_eq!(result.options[0].0, "title");
+        // The option's span starts after the mark
+        assert_eq!(result.options[0].2.start, BOM.len_utf8());
+
+        doc.update("option \"title\" \"Ledger\"\n".to_string(), 2);
+        assert!(!doc.has_bom());
+    }
+
+    #[test]
+    fn test_external_document_strips_bom() {
+        let mut vfs = Vfs::new();
+        let path = PathBuf::from("/accounts.beancount");
+        vfs.load_external(
+            path.clone(),
+            "\u{feff}2024-01-01 open Assets:Bank\n".to_string(),
+        );
+
+        let doc = vfs.get(&path).unwrap();
+        assert!(doc.has_bom());
+        assert_eq!(doc.text(), "2024-01-01 open Assets:Bank\n");
+    }
 }
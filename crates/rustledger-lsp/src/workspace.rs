@@ -0,0 +1,112 @@
+//! Workspace discovery and bulk parsing.
+//!
+//! On startup, every Beancount file under the workspace roots is parsed in
+//! parallel so cross-file features work before the files are opened.
+
+use rayon::prelude::*;
+use rustledger_parser::{ParseResult, parse};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::vfs::strip_bom;
+
+/// File extensions treated as Beancount files.
+const EXTENSIONS: &[&str] = &["beancount", "bean"];
+
+/// A file read from disk and parsed.
+pub type ParsedFile = (PathBuf, String, Arc<ParseResult>);
+
+/// Find all Beancount files under the given roots.
+///
+/// Hidden directories (such as `.git`) are skipped. The result is sorted so
+/// indexing is deterministic.
+pub fn discover_files(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = roots.to_vec();
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !is_hidden(&path) {
+                    pending.push(path);
+                }
+            } else if is_beancount_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+/// Read and parse files in parallel. Unreadable files are skipped.
+pub fn parse_files(paths: &[PathBuf]) -> Vec<ParsedFile> {
+    paths
+        .par_iter()
+        .filter_map(|path| {
+            let content = std::fs::read_to_string(path).ok()?;
+            let parse_result = Arc::new(parse(strip_bom(&content).0));
+            Some((path.clone(), content, parse_result))
+        })
+        .collect()
+}
+
+/// Check whether a path has a Beancount file extension.
+pub fn is_beancount_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext))
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_and_parse_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("accounts")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(
+            root.join("main.beancount"),
+            "include \"accounts/bank.bean\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("accounts/bank.bean"),
+            "2024-01-01 open Assets:Bank\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("notes.txt"), "not a ledger").unwrap();
+        std::fs::write(root.join(".git/hidden.beancount"), "").unwrap();
+
+        let files = discover_files(&[root.to_path_buf()]);
+        assert_eq!(
+            files,
+            vec![root.join("accounts/bank.bean"), root.join("main.beancount")]
+        );
+
+        let parsed = parse_files(&files);
+        assert_eq!(parsed.len(), 2);
+        let (_, _, bank) = parsed
+            .iter()
+            .find(|(path, _, _)| path.ends_with("bank.bean"))
+            .unwrap();
+        assert_eq!(bank.directives.len(), 1);
+    }
+}
@@ -38,6 +38,7 @@ pub use error::{ParseError, ParseErrorKind};
 pub use span::{Span, Spanned};
 
 use rustledger_core::Directive;
+use std::collections::HashMap;
 
 /// Result of parsing a beancount file.
 #[derive(Debug)]
@@ -52,6 +53,11 @@ pub struct ParseResult {
     pub plugins: Vec<(String, Option<String>, Span)>,
     /// Parse errors encountered.
     pub errors: Vec<ParseError>,
+    /// Comment lines immediately preceding a directive, keyed by the
+    /// directive's index in `directives`.
+    ///
+    /// The `;` markers are stripped and consecutive lines joined with `\n`.
+    pub leading_comments: HashMap<usize, Spanned<String>>,
 }
 
 /// Parse beancount source code.
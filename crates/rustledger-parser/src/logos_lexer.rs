@@ -349,11 +349,14 @@ impl fmt::Display for Token<'_> {
 pub fn tokenize(source: &str) -> Vec<(Token<'_>, Span)> {
     let mut tokens = Vec::new();
     let mut lexer = Token::lexer(source);
+    // Offset of the lexer's input in `source`, moved on when lexing restarts
+    let mut base = 0usize;
     let mut at_line_start = true;
     let mut last_newline_end = 0usize;
 
     while let Some(result) = lexer.next() {
         let span = lexer.span();
+        let span = base + span.start..base + span.end;
 
         match result {
             Ok(Token::Newline) => {
@@ -407,6 +410,22 @@ pub fn tokenize(source: &str) -> Vec<(Token<'_>, Span)> {
                 // Lexer error - produce an Error token with the invalid source text
                 at_line_start = false;
                 let invalid_text = &source[span.clone()];
+
+                // An unclosed string runs to the end of the input. End it at
+                // the end of its line and lex the following lines normally.
+                if invalid_text.starts_with('"') {
+                    if let Some(line_len) = invalid_text.find('\n') {
+                        let end = span.start + line_len;
+                        tokens.push((
+                            Token::Error(&source[span.start..end]),
+                            (span.start..end).into(),
+                        ));
+                        base = end;
+                        lexer = Token::lexer(&source[end..]);
+                        continue;
+                    }
+                }
+
                 tokens.push((Token::Error(invalid_text), span.into()));
             }
         }
@@ -1385,18 +1385,27 @@ fn tok_entry<'src>() -> impl Parser<'src, &'src [SpannedToken<'src>], ParsedItem
     .labelled("directive")
 }
 
-/// Skip tokens until we reach a newline (for error recovery).
-/// Consumes at least one token to make progress.
+/// Skip a broken entry (for error recovery).
+///
+/// Skips to the end of the line, then over any indented lines that follow,
+/// since those are the broken entry's postings or metadata and would
+/// otherwise each report an error of their own. Parsing resumes at the next
+/// unindented line. Consumes at least one token to make progress.
 fn tok_skip_to_newline<'src>() -> impl Parser<'src, &'src [SpannedToken<'src>], (), TokExtra<'src>>
 {
+    let rest_of_line = any()
+        .filter(|t: &SpannedToken<'_>| !matches!(t.token, Token::Newline))
+        .repeated();
+    let indented_line = any()
+        .filter(|t: &SpannedToken<'_>| matches!(t.token, Token::Indent(_) | Token::DeepIndent(_)))
+        .then(rest_of_line)
+        .then(tok_newline().or_not());
+
     // Must consume at least one token to make progress
     any()
-        .then(
-            any()
-                .filter(|t: &SpannedToken<'_>| !matches!(t.token, Token::Newline))
-                .repeated(),
-        )
+        .then(rest_of_line)
         .then(tok_newline().or_not())
+        .then(indented_line.repeated())
         .to(())
 }
 
@@ -1776,15 +1785,44 @@ pub fn parse(source: &str) -> ParseResult {
         })
         .collect();
 
+    let leading_comments = directives
+        .iter()
+        .enumerate()
+        .filter_map(|(i, d)| Some((i, leading_comment(source, d.span.start)?)))
+        .collect();
+
     ParseResult {
         directives,
         options,
         includes,
         plugins,
         errors,
+        leading_comments,
     }
 }
 
+/// Collect the unindented comment lines directly above the line starting
+/// at `start`, stopping at the first blank or non-comment line.
+fn leading_comment(source: &str, start: usize) -> Option<Spanned<String>> {
+    let mut begin = start;
+    let mut lines = Vec::new();
+    while let Some(before) = source[..begin].strip_suffix('\n') {
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let Some(text) = before[line_start..].strip_prefix(';') else {
+            break;
+        };
+        lines.push(text.trim_start_matches(';').trim());
+        begin = line_start;
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+
+    let end = source[..start].trim_end_matches(['\n', '\r']).len();
+    Some(Spanned::new(lines.join("\n"), Span::new(begin, end)))
+}
+
 /// Apply pushed tags to a directive (only affects transactions).
 fn apply_pushed_tags(directive: Directive, tag_stack: &[InternedStr]) -> Directive {
     if tag_stack.is_empty() {
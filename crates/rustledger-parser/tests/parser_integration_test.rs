@@ -90,6 +90,46 @@ fn test_parse_simple_transaction() {
     }
 }
 
+#[test]
+fn test_parse_txn_keyword_transaction() {
+    let source = r#"
+2024-01-15 txn "Coffee Shop" "Morning coffee"
+  Expenses:Food:Coffee  5.00 USD
+  Assets:Cash  -5.00 USD
+"#;
+    let result = parse_ok(source);
+    assert_eq!(count_directive_type(&result, "transaction"), 1);
+
+    let Directive::Transaction(txn) = &result.directives[0].value else {
+        panic!("expected transaction");
+    };
+    assert_eq!(txn.flag, '*');
+    assert_eq!(txn.payee.as_deref(), Some("Coffee Shop"));
+    assert_eq!(txn.narration.as_str(), "Morning coffee");
+
+    // Identical to the same transaction written with `*`
+    let starred = parse_ok(&source.replace(" txn ", " * "));
+    assert_eq!(result.directives[0].value, starred.directives[0].value);
+}
+
+#[test]
+fn test_transaction_span_covers_postings_and_metadata() {
+    let source = r#"2024-01-15 * "Coffee Shop"
+  receipt: "r-1"
+  Expenses:Food:Coffee  5.00 USD
+    note: "latte"
+  Assets:Cash
+2024-01-16 open Assets:Bank
+"#;
+    let result = parse_ok(source);
+
+    let span = &result.directives[0].span;
+    let last_posting_end = source.find("Assets:Cash").unwrap() + "Assets:Cash".len();
+    assert_eq!(span.start, 0);
+    assert_eq!(source[..span.end].trim_end().len(), last_posting_end);
+    assert!(span.end <= result.directives[1].span.start);
+}
+
 #[test]
 fn test_parse_transaction_with_tags_and_links() {
     let source = r#"
@@ -101,6 +141,8 @@ fn test_parse_transaction_with_tags_and_links() {
     assert_eq!(count_directive_type(&result, "transaction"), 1);
 
     if let Directive::Transaction(txn) = &result.directives[0].value {
+        // Tags and links end the narration
+        assert_eq!(txn.narration.as_str(), "Dinner");
         assert!(txn.tags.iter().any(|t| t.as_str() == "food"));
         assert!(txn.tags.iter().any(|t| t.as_str() == "restaurant"));
         assert!(txn.links.iter().any(|l| l.as_str() == "receipt-123"));
@@ -230,6 +272,33 @@ fn test_parse_custom_directive() {
     assert_eq!(count_directive_type(&result, "custom"), 1);
 }
 
+#[test]
+fn test_parse_custom_directive_argument_types() {
+    use rust_decimal_macros::dec;
+    use rustledger_core::{Amount, MetaValue};
+
+    let source =
+        r#"2024-01-01 custom "budget" Expenses:Food "monthly" 500.00 USD 2024-12-31 TRUE 3 EUR"#;
+    let result = parse_ok(source);
+
+    if let Directive::Custom(custom) = &result.directives[0].value {
+        assert_eq!(custom.custom_type, "budget");
+        assert_eq!(
+            custom.values,
+            vec![
+                MetaValue::Account("Expenses:Food".to_string()),
+                MetaValue::String("monthly".to_string()),
+                MetaValue::Amount(Amount::new(dec!(500.00), "USD")),
+                MetaValue::Date(chrono::NaiveDate::from_ymd_opt(2024, 12, 31).unwrap()),
+                MetaValue::Bool(true),
+                MetaValue::Amount(Amount::new(dec!(3), "EUR")),
+            ]
+        );
+    } else {
+        panic!("expected custom");
+    }
+}
+
 // ============================================================================
 // Options, Includes, and Plugins
 // ============================================================================
@@ -439,6 +508,36 @@ fn test_error_recovery_continues_parsing() {
     );
 }
 
+#[test]
+fn test_error_recovery_skips_broken_entry_postings() {
+    let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 * "Coffee" 12 USD
+  Assets:Bank  -5.00 USD
+  Expenses:Food
+2024-01-31 close Assets:Bank
+"#;
+    let result = parse(source);
+
+    // The postings of the broken transaction don't report errors of their own
+    assert_eq!(result.errors.len(), 1, "errors: {:?}", result.errors);
+    assert_eq!(count_directive_type(&result, "open"), 1);
+    assert_eq!(count_directive_type(&result, "close"), 1);
+}
+
+#[test]
+fn test_error_recovery_after_unclosed_string() {
+    let source = r#"2024-01-01 open Assets:Bank
+2024-01-15 note Assets:Bank "Unclosed
+2024-01-20 open Assets:Cash
+2024-01-31 close Assets:Bank
+"#;
+    let result = parse(source);
+
+    assert_eq!(result.errors.len(), 1, "errors: {:?}", result.errors);
+    assert_eq!(count_directive_type(&result, "open"), 2);
+    assert_eq!(count_directive_type(&result, "close"), 1);
+}
+
 #[test]
 fn test_error_on_invalid_date() {
     let source = r"2024-13-45 open Assets:Bank";
@@ -478,6 +577,34 @@ fn test_parse_only_comments() {
     assert!(result.directives.is_empty());
 }
 
+#[test]
+fn test_parse_leading_comments() {
+    let source = r#"; Accounts
+; main checking
+2024-01-01 open Assets:Checking
+; unrelated
+
+2024-01-01 open Assets:Savings
+2024-01-02 * "Deposit"
+  ; posting note
+  Assets:Checking  10 USD
+  Assets:Savings
+2024-01-03 close Assets:Savings
+"#;
+    let result = parse_ok(source);
+
+    let comment = &result.leading_comments[&0];
+    assert_eq!(comment.value, "Accounts\nmain checking");
+    assert_eq!(
+        &source[comment.span.start..comment.span.end],
+        "; Accounts\n; main checking"
+    );
+
+    // A blank line detaches the comment, and indented comments belong to
+    // the transaction above
+    assert_eq!(result.leading_comments.len(), 1);
+}
+
 #[test]
 fn test_parse_unicode_in_narration() {
     let source = r#"2024-01-15 * "Café ☕" "Latte mit Milch"
@@ -512,6 +639,45 @@ fn test_parse_large_numbers() {
     assert_eq!(count_directive_type(&result, "price"), 1);
 }
 
+#[test]
+fn test_parse_thousands_separators_and_plus_sign() {
+    use rust_decimal_macros::dec;
+
+    let source = r#"
+2024-01-15 * "Rent"
+  Expenses:Rent  1,234.56 USD
+  Assets:Bank  +1,000 USD
+  Assets:Cash
+
+2024-01-31 balance Assets:Bank 1,000,000.00 USD
+2024-01-31 price BTC +42,000 USD
+"#;
+    let result = parse_ok(source);
+
+    if let Directive::Transaction(txn) = &result.directives[0].value {
+        let units: Vec<_> = txn
+            .postings
+            .iter()
+            .filter_map(|p| p.amount().map(|a| a.number))
+            .collect();
+        assert_eq!(units, vec![dec!(1234.56), dec!(1000)]);
+    } else {
+        panic!("expected transaction");
+    }
+
+    if let Directive::Balance(bal) = &result.directives[1].value {
+        assert_eq!(bal.amount.number, dec!(1000000.00));
+    } else {
+        panic!("expected balance");
+    }
+
+    if let Directive::Price(price) = &result.directives[2].value {
+        assert_eq!(price.amount.number, dec!(42000));
+    } else {
+        panic!("expected price");
+    }
+}
+
 #[test]
 fn test_parse_booking_method() {
     let source = r#"2024-01-01 open Assets:Stock "FIFO""#;
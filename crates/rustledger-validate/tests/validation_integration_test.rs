@@ -5,7 +5,8 @@
 
 use rust_decimal_macros::dec;
 use rustledger_core::{
-    Amount, Balance, Close, Directive, NaiveDate, Open, Pad, Posting, PriceAnnotation, Transaction,
+    Amount, Balance, Close, CostSpec, Directive, NaiveDate, Open, Pad, Posting, PriceAnnotation,
+    Transaction,
 };
 use rustledger_validate::{ErrorCode, validate};
 
@@ -244,6 +245,46 @@ fn test_valid_balanced_transaction() {
     );
 }
 
+#[test]
+fn test_valid_transaction_balanced_at_cost() {
+    let purchase = |cost: Option<CostSpec>| {
+        let stock = Posting::new("Assets:Stock", Amount::new(dec!(10), "AAPL"));
+        Directive::Transaction(
+            Transaction::new(date(2024, 1, 15), "Buy stock")
+                .with_posting(match cost {
+                    Some(cost) => stock.with_cost(cost),
+                    None => stock,
+                })
+                .with_posting(Posting::new("Assets:Bank", Amount::new(dec!(-1500), "USD"))),
+        )
+    };
+    let opens = [
+        Directive::Open(Open::new(date(2024, 1, 1), "Assets:Bank")),
+        Directive::Open(Open::new(date(2024, 1, 1), "Assets:Stock")),
+    ];
+
+    // 10 AAPL {150 USD} weighs 1500 USD, offsetting the cash posting
+    let cost = CostSpec::empty()
+        .with_number_per(dec!(150))
+        .with_currency("USD");
+    let mut directives = opens.to_vec();
+    directives.push(purchase(Some(cost)));
+    let errors = validate_directives(&directives);
+    assert!(
+        !errors.contains(&ErrorCode::TransactionUnbalanced),
+        "expected cost to balance the cash posting"
+    );
+
+    // Without the cost the AAPL and USD postings cannot offset each other
+    let mut directives = opens.to_vec();
+    directives.push(purchase(None));
+    let errors = validate_directives(&directives);
+    assert!(
+        errors.contains(&ErrorCode::TransactionUnbalanced),
+        "expected E3001 TransactionUnbalanced error"
+    );
+}
+
 // ============================================================================
 // Pad Directive Tests
 // ============================================================================
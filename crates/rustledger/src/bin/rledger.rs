@@ -0,0 +1,4 @@
+//! rledger - Beancount toolkit with `check` and `format` subcommands.
+fn main() -> std::process::ExitCode {
+    rustledger::cmd::rledger::main()
+}
@@ -204,15 +204,18 @@ pub fn main() -> ExitCode {
 
 /// Main entry point with custom binary name (for bean-format compatibility).
 pub fn main_with_name(bin_name: &str) -> ExitCode {
-    let args = Args::parse();
+    execute(&Args::parse(), bin_name)
+}
 
+/// Run the command with already-parsed arguments (for the `rledger` CLI).
+pub fn execute(args: &Args, bin_name: &str) -> ExitCode {
     // Handle shell completion generation
     if let Some(shell) = args.generate_completions {
         crate::cmd::completions::generate_completions::<Args>(shell, bin_name);
         return ExitCode::SUCCESS;
     }
 
-    match run(&args) {
+    match run(args) {
         Ok(exit_code) => exit_code,
         Err(e) => {
             eprintln!("error: {e:#}");
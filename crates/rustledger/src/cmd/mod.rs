@@ -11,3 +11,4 @@ pub mod format;
 pub mod price_cmd;
 pub mod query;
 pub mod report_cmd;
+pub mod rledger;
@@ -0,0 +1,34 @@
+//! The `rledger` command, dispatching to subcommands.
+//!
+//! `rledger check FILE` and `rledger format FILE` accept the same arguments
+//! as the standalone `rledger-check` and `rledger-format` binaries.
+
+use crate::cmd::{check, format};
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+
+/// Beancount toolkit.
+#[derive(Parser, Debug)]
+#[command(name = "rledger", author, version, about, long_about = None)]
+pub struct Args {
+    /// The subcommand to run
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Subcommands of `rledger`.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Validate beancount files and report errors
+    Check(check::Args),
+    /// Format beancount files
+    Format(format::Args),
+}
+
+/// Main entry point for the `rledger` command.
+pub fn main() -> ExitCode {
+    match Args::parse().command {
+        Command::Check(args) => check::execute(&args, "rledger check"),
+        Command::Format(args) => format::execute(&args, "rledger format"),
+    }
+}
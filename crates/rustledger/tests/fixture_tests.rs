@@ -30,6 +30,7 @@ fn rust_bean_check_binary() -> PathBuf {
 /// Run rledger-check on a file and return (success, output).
 fn rledger_check(path: &Path) -> (bool, String) {
     let output = Command::new(rust_bean_check_binary())
+        .arg("--no-cache")
         .arg(path)
         .output()
         .expect("Failed to run rledger-check");
@@ -46,6 +47,7 @@ fn rledger_check(path: &Path) -> (bool, String) {
 /// Run rledger-check with `auto_accounts` plugin (permissive mode like Python beancount).
 fn rledger_check_permissive(path: &Path) -> (bool, String) {
     let output = Command::new(rust_bean_check_binary())
+        .arg("--no-cache")
         .arg("--native-plugin")
         .arg("auto_accounts")
         .arg(path)
@@ -47,6 +47,7 @@ fn python_bean_check(path: &Path) -> (bool, String) {
 /// Run Rust bean-check on a file.
 fn rust_bean_check(path: &Path) -> (bool, String) {
     let output = Command::new(rust_bean_check_binary())
+        .arg("--no-cache")
         .arg(path)
         .output()
         .expect("Failed to run rust bean-check");
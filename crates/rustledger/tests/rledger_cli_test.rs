@@ -0,0 +1,57 @@
+//! Tests for the `rledger` command and its subcommands.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn rledger(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rledger"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("Failed to run rledger")
+}
+
+#[test]
+fn test_check_valid_ledger() {
+    let path = fixture("valid-ledger.beancount");
+    let output = rledger(&["check", "--no-cache", path.to_str().unwrap()]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_check_reports_errors_with_location() {
+    let path = fixture("unclosed-string.beancount");
+    let output = rledger(&["check", "--no-cache", path.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("unclosed string literal"), "{stdout}");
+    assert!(
+        stdout.contains("unclosed-string.beancount:3:14"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_format_aligns_amounts() {
+    let path = fixture("unaligned.beancount");
+    let output = rledger(&["format", path.to_str().unwrap()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    let postings: Vec<&str> = stdout.lines().filter(|l| l.starts_with("  ")).collect();
+    assert_eq!(postings.len(), 2);
+    assert_eq!(postings[0].find(" USD"), postings[1].find(" USD"));
+}
+
+#[test]
+fn test_unknown_subcommand_fails() {
+    let output = rledger(&["frobnicate"]);
+    assert!(!output.status.success());
+}